@@ -0,0 +1,215 @@
+use std::{
+    alloc,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+};
+
+use crate::{AlignedBytesMut, AllocError, InnerBuffer};
+
+/// A pool of recycled allocations.
+///
+/// Allocating and freeing a buffer for every chunk is expensive when reading millions of small
+/// chunks: Every fresh allocation costs page faults when it's first written to, and large
+/// allocations are returned to the operating system when they're freed. Buffers allocated by
+/// [`BufferPool::get`] are returned to the pool when every view of the buffer (every
+/// [`AlignedBytesMut`] and [`AlignedBytes`](crate::AlignedBytes)) has been dropped, so the next
+/// call to `get` can reuse the allocation.
+///
+/// Allocations are grouped by size class: The length of each allocation is rounded up to the next
+/// power of two, so that buffers of similar lengths can share allocations.
+///
+/// `BufferPool` is cheap to clone: All clones share the same pool. Buffers which outlive the pool
+/// are deallocated as normal.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+impl BufferPool {
+    /// Creates a new, empty `BufferPool`. The pool keeps at most `max_free_buffers_per_size_class`
+    /// unused allocations of each size class. Buffers returned to a full size class are
+    /// deallocated.
+    pub fn new(max_free_buffers_per_size_class: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(HashMap::new()),
+                max_free_buffers_per_size_class,
+                n_recycled: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns an `AlignedBytesMut` which views the first `len` bytes of a buffer aligned to
+    /// `align`. The buffer is reused from the pool if possible. Otherwise a new buffer is
+    /// allocated. Like [`AlignedBytesMut::with_capacity`], the contents of the buffer are not
+    /// initialised.
+    ///
+    /// 'align' must not be zero, and must be a power of two.
+    pub fn get(&self, len: usize, align: usize) -> AlignedBytesMut {
+        self.try_get(len, align).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Like [`BufferPool::get`], except that failing to allocate a new buffer returns an error,
+    /// instead of aborting the process.
+    pub fn try_get(&self, len: usize, align: usize) -> Result<AlignedBytesMut, AllocError> {
+        assert_ne!(len, 0);
+        let size_class = len
+            .checked_next_power_of_two()
+            .ok_or(AllocError { size: len, align })?;
+        let layout = AllocError::layout(size_class, align)?;
+        let recycled = self
+            .inner
+            .free
+            .lock()
+            .unwrap()
+            .get_mut(&(layout.size(), layout.align()))
+            .and_then(Vec::pop);
+        let mut inner_buf = match recycled {
+            Some(FreeBuffer(buf)) => {
+                self.inner.n_recycled.fetch_add(1, Relaxed);
+                InnerBuffer {
+                    buf,
+                    layout,
+                    #[cfg(feature = "external-memory")]
+                    external_memory: None,
+                    pool: None,
+                    is_hugetlb: false,
+                    initialised: false,
+                }
+            }
+            None => InnerBuffer::try_new(layout.size(), layout.align(), alloc::alloc)?,
+        };
+        inner_buf.pool = Some(Arc::downgrade(&self.inner));
+        Ok(AlignedBytesMut {
+            buf: Arc::new(inner_buf),
+            range: 0..len,
+        })
+    }
+
+    /// Returns the number of calls to [`BufferPool::get`] which reused an allocation.
+    pub fn n_recycled(&self) -> usize {
+        self.inner.n_recycled.load(Relaxed)
+    }
+}
+
+/// An unused allocation, owned by the pool.
+#[derive(Debug)]
+struct FreeBuffer(*mut u8);
+
+// SAFETY: The pool has exclusive ownership of each free allocation.
+unsafe impl Send for FreeBuffer {}
+
+#[derive(Debug)]
+pub(crate) struct PoolInner {
+    /// The unused allocations, keyed by `(size, align)`.
+    free: Mutex<HashMap<(usize, usize), Vec<FreeBuffer>>>,
+    max_free_buffers_per_size_class: usize,
+    n_recycled: AtomicUsize,
+}
+
+impl PoolInner {
+    /// Called when an `InnerBuffer` allocated by this pool is dropped. Returns `true` if the pool
+    /// has taken ownership of the allocation, or `false` if the caller must deallocate it.
+    pub(crate) fn put(&self, buf: *mut u8, layout: alloc::Layout) -> bool {
+        let mut free = self.free.lock().unwrap();
+        let size_class = free.entry((layout.size(), layout.align())).or_default();
+        if size_class.len() < self.max_free_buffers_per_size_class {
+            size_class.push(FreeBuffer(buf));
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        for ((size, align), buffers) in self.free.get_mut().unwrap().drain() {
+            let layout = alloc::Layout::from_size_align(size, align).unwrap();
+            for FreeBuffer(buf) in buffers {
+                unsafe { alloc::dealloc(buf, layout) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_reuses_allocations() {
+        let pool = BufferPool::new(2);
+        let mut buffer = pool.get(1000, 512);
+        assert_eq!(buffer.len(), 1000);
+        assert_eq!(buffer.capacity(), 1024);
+        let ptr = buffer.as_mut_ptr();
+        drop(buffer);
+
+        // Similar lengths share a size class:
+        let mut buffer = pool.get(600, 512);
+        assert_eq!(buffer.as_mut_ptr(), ptr);
+        assert_eq!(pool.n_recycled(), 1);
+
+        // Different size classes don't share allocations:
+        let mut other_buffer = pool.get(2000, 512);
+        assert_ne!(other_buffer.as_mut_ptr(), ptr);
+        assert_eq!(pool.n_recycled(), 1);
+
+        // Allocations are only returned to the pool when every view has been dropped:
+        let frozen = buffer.freeze().unwrap();
+        let view = frozen.slice(0..10).unwrap();
+        drop(frozen);
+        assert_ne!(pool.get(1000, 512).as_mut_ptr(), ptr);
+        drop(view);
+        assert_eq!(pool.get(1000, 512).as_mut_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_buffers_can_outlive_the_pool() {
+        let pool = BufferPool::new(2);
+        let buffer = pool.get(1000, 512);
+        drop(pool);
+        drop(buffer);
+    }
+
+    /// Returns the number of minor page faults caused by the current thread.
+    #[cfg(target_os = "linux")]
+    fn minor_page_faults() -> u64 {
+        let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+        // The second field (the command name) may contain spaces, so skip past it. `minflt` is
+        // the 10th field.
+        let (_, fields) = stat.rsplit_once(") ").unwrap();
+        fields.split(' ').nth(7).unwrap().parse().unwrap()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pool_reduces_page_faults() {
+        const LEN: usize = 16 * 1024 * 1024;
+        const N_ITERATIONS: usize = 8;
+
+        let count_page_faults = |allocate: &dyn Fn() -> AlignedBytesMut| {
+            let before = minor_page_faults();
+            for _ in 0..N_ITERATIONS {
+                let mut buffer = allocate();
+                buffer.fill(1);
+            }
+            minor_page_faults() - before
+        };
+
+        let without_pool = count_page_faults(&|| AlignedBytesMut::with_capacity(LEN, 4096));
+        let pool = BufferPool::new(1);
+        let with_pool = count_page_faults(&|| pool.get(LEN, 4096));
+        assert_eq!(pool.n_recycled(), N_ITERATIONS - 1);
+        // Without the pool, every iteration faults every page of the fresh allocation. With the
+        // pool, only the first iteration does.
+        assert!(
+            with_pool * 2 < without_pool,
+            "Minor page faults: without pool = {without_pool}, with pool = {with_pool}"
+        );
+    }
+}
@@ -2,7 +2,17 @@
 #![doc = include_str!("../README.md")]
 
 use anyhow;
-use std::{alloc, ops::Range, slice, sync::Arc};
+#[cfg(feature = "external-memory")]
+use std::any::Any;
+use std::{alloc, mem::MaybeUninit, ops::Range, slice, sync::Arc};
+
+/// The size of a huge page used by [`AlignedBytesMut::new_huge`]. This is the default huge page
+/// size on x86-64 and aarch64 Linux.
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+mod buffer_pool;
+pub use buffer_pool::BufferPool;
+use buffer_pool::PoolInner;
 
 /// A mutable aligned buffer.
 #[derive(Debug)]
@@ -21,13 +31,166 @@ impl AlignedBytesMut {
     ///
     /// Aligns the start and end of the buffer with `align`.
     /// 'align' must not be zero, and must be a power of two.
-    /// `len` is the length of the underlying buffer, in bytes.
+    /// `len` is the length of the underlying buffer, in bytes. `new` is intended for lengths which
+    /// are a multiple of `align`, in which case `len == capacity`. (If `len` is not a multiple of
+    /// `align` then `new` behaves like [`AlignedBytesMut::with_capacity`].)
     pub fn new(len: usize, align: usize) -> Self {
-        let inner_buf = InnerBuffer::new(len, align);
-        Self {
+        Self::with_capacity(len, align)
+    }
+
+    /// Creates a new `AlignedBytesMut` which views the first `requested_len` bytes of an
+    /// underlying buffer whose [capacity](AlignedBytesMut::capacity) is `requested_len` rounded up
+    /// to a multiple of `align`. For example, this can be used to allocate a buffer for a read
+    /// whose length isn't aligned: Read into the whole capacity, and the view will only contain
+    /// the bytes that were requested.
+    ///
+    /// 'align' must not be zero, and must be a power of two.
+    pub fn with_capacity(requested_len: usize, align: usize) -> Self {
+        Self::try_new(requested_len, align).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Like [`AlignedBytesMut::new`], except that failing to allocate the buffer (e.g. because
+    /// `len` is larger than the available memory) returns an error, instead of aborting the
+    /// process.
+    ///
+    /// 'align' must not be zero, and must be a power of two.
+    pub fn try_new(len: usize, align: usize) -> Result<Self, AllocError> {
+        let inner_buf = InnerBuffer::try_new(len, align, alloc::alloc)?;
+        Ok(Self {
+            buf: Arc::new(inner_buf),
+            range: 0..len,
+        })
+    }
+
+    /// Like [`AlignedBytesMut::new`], except that the whole underlying buffer (including any
+    /// padding) is initialised to zero.
+    pub fn zeroed(len: usize, align: usize) -> Self {
+        Self::try_zeroed(len, align).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Like [`AlignedBytesMut::zeroed`], except that failing to allocate the buffer returns an
+    /// error, instead of aborting the process.
+    pub fn try_zeroed(len: usize, align: usize) -> Result<Self, AllocError> {
+        let mut inner_buf = InnerBuffer::try_new(len, align, alloc::alloc_zeroed)?;
+        inner_buf.initialised = true;
+        Ok(Self {
             buf: Arc::new(inner_buf),
             range: 0..len,
+        })
+    }
+
+    /// Creates a new `AlignedBytesMut` of `len` bytes, backed by huge pages (see
+    /// [`HUGE_PAGE_SIZE`]). For multi-gigabyte buffers, huge pages reduce the number of page faults
+    /// (and the pressure on the TLB) by a factor of 512. The underlying buffer is aligned to
+    /// `HUGE_PAGE_SIZE`, and its capacity is `len` rounded up to a multiple of `HUGE_PAGE_SIZE`.
+    ///
+    /// The buffer is allocated with `mmap(MAP_HUGETLB)`, which requires huge pages to have been
+    /// reserved (see `/proc/sys/vm/nr_hugepages`). If that fails (or if we're not on Linux) then
+    /// `new_huge` falls back to a normal allocation, and asks for transparent huge pages using
+    /// `madvise(MADV_HUGEPAGE)`. Use [`AlignedBytesMut::is_hugetlb`] to find out which happened.
+    pub fn new_huge(len: usize) -> Self {
+        Self::try_new_huge(len).unwrap_or_else(|err| err.handle())
+    }
+
+    /// Like [`AlignedBytesMut::new_huge`], except that failing to allocate the buffer returns an
+    /// error, instead of aborting the process.
+    pub fn try_new_huge(len: usize) -> Result<Self, AllocError> {
+        let inner_buf = InnerBuffer::try_new_huge(len)?;
+        Ok(Self {
+            buf: Arc::new(inner_buf),
+            range: 0..len,
+        })
+    }
+
+    /// Returns `true` if the underlying buffer was allocated by [`AlignedBytesMut::new_huge`]
+    /// using `mmap(MAP_HUGETLB)`.
+    pub fn is_hugetlb(&self) -> bool {
+        self.buf.is_hugetlb
+    }
+
+    /// Set every byte in this view to `value`. Bytes outside of this view are not modified.
+    pub fn fill(&mut self, value: u8) {
+        let len = self.len();
+        unsafe { std::ptr::write_bytes(self.as_mut_ptr(), value, len) };
+    }
+
+    /// Returns the total size of the underlying buffer, in bytes. For buffers allocated by `new`
+    /// or `with_capacity`, this is a multiple of the alignment.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Creates a new `AlignedBytesMut` which is a view of `range` of `memory`. This allows
+    /// IO operations to write directly into memory which was allocated elsewhere.
+    ///
+    /// Each `AlignedBytesMut` created by `from_external_memory` has its own (non-owning) backing
+    /// buffer. So, unlike the views created by [`AlignedBytesMut::split_to`], each of these views
+    /// can be [frozen](AlignedBytesMut::freeze) independently.
+    ///
+    /// The start of `range` must be aligned to `align` (which must be a power of two).
+    ///
+    /// # Safety
+    /// The caller must ensure that the `range`s of all the live views of `memory` never overlap.
+    /// In debug builds, `from_external_memory` returns an error if `range` overlaps with a view
+    /// which is still alive (e.g. a view which is still being read into).
+    #[cfg(feature = "external-memory")]
+    pub unsafe fn from_external_memory(
+        memory: &Arc<ExternalMemory>,
+        range: Range<usize>,
+        align: usize,
+    ) -> anyhow::Result<Self> {
+        if range.is_empty() || range.end > memory.len {
+            return Err(anyhow::format_err!(
+                "range {range:?} must not be empty, and must be within the external memory, \
+                    which is {} bytes long",
+                memory.len
+            ));
+        }
+        let buf = memory.ptr.add(range.start);
+        let layout = alloc::Layout::from_size_align(range.len(), align)?;
+        if (buf as usize) % align != 0 {
+            return Err(anyhow::format_err!(
+                "The start of range {range:?} is not aligned to {align} bytes"
+            ));
         }
+        memory.register_view(&range)?;
+        let inner_buf = InnerBuffer {
+            buf,
+            layout,
+            external_memory: Some(Arc::clone(memory)),
+            pool: None,
+            is_hugetlb: false,
+            initialised: false,
+        };
+        Ok(Self {
+            buf: Arc::new(inner_buf),
+            range: 0..range.len(),
+        })
+    }
+
+    /// Creates a new `AlignedBytesMut` which takes ownership of `vec`, and views all of
+    /// `vec`'s initialised bytes (`vec.len()` bytes, not `vec.capacity()`). This allows IO
+    /// operations to write directly into a `Vec<u8>` which the caller already has (e.g. from a
+    /// memory pool). The `Vec`'s memory is freed when the last view of it is dropped.
+    ///
+    /// A `Vec<u8>` is only guaranteed to be aligned to 1 byte, so the returned `AlignedBytesMut`
+    /// claims an alignment of 1 byte. IO backends which use `O_DIRECT` require more than that.
+    ///
+    /// # Errors
+    /// Returns an error if `vec` is empty.
+    #[cfg(feature = "external-memory")]
+    pub fn from_vec(mut vec: Vec<u8>) -> anyhow::Result<Self> {
+        let ptr = vec.as_mut_ptr();
+        let len = vec.len();
+        // SAFETY: Moving `vec` into the `ExternalMemory` doesn't move the memory that `vec` points
+        // to. And the only view of `vec` is the one that we create below, which covers all of it.
+        let mut view = unsafe {
+            let memory = Arc::new(ExternalMemory::new(ptr, len, vec));
+            Self::from_external_memory(&memory, 0..len, 1)?
+        };
+        // The bytes of a `Vec` are always initialised.
+        Arc::get_mut(&mut view.buf).unwrap().initialised = true;
+        Ok(view)
     }
 
     /// Returns the length of the `range` requested by the user. The `range` is a view into the
@@ -36,12 +199,44 @@ impl AlignedBytesMut {
         self.range.len()
     }
 
+    /// Returns true if the `range` requested by the user has a length of zero bytes. Views are
+    /// almost never empty, because the methods which create views reject empty ranges.
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
     /// Returns a mutable pointer to the underlying buffer offset by `self.range.start`.
     pub fn as_mut_ptr(&mut self) -> *mut u8 {
         let ptr = self.buf.as_mut_ptr();
         unsafe { ptr.offset(self.range.start as isize) }
     }
 
+    /// Returns a mutable slice of the `range` view of the underlying buffer, whose bytes may not
+    /// be initialised. This works for every buffer, and is the safe way to write into buffers
+    /// created by `new` or `with_capacity`. (No other `AlignedBytesMut` can view the bytes in
+    /// `range`, so the slice can't alias.)
+    pub fn as_uninit_mut_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        let len = self.len();
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr().cast(), len) }
+    }
+
+    /// Returns a mutable slice of the `range` view of the underlying buffer.
+    ///
+    /// # Panics
+    /// If the underlying buffer may not be initialised. Only buffers created by
+    /// [`AlignedBytesMut::zeroed`], [`AlignedBytesMut::new_huge`] (when backed by `MAP_HUGETLB`)
+    /// and `AlignedBytesMut::from_vec` are known to be initialised. For other buffers, use
+    /// [`AlignedBytesMut::as_uninit_mut_slice`].
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        assert!(
+            self.buf.initialised,
+            "This buffer may not be initialised, so it can't be viewed as a &mut [u8]. Use \
+                as_uninit_mut_slice instead."
+        );
+        let len = self.len();
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), len) }
+    }
+
     /// Split this view of the underlying buffer into two views at the given index.
     ///
     /// This does not allocate a new buffer. Instead, both `AlignedBytesMut` objects reference
@@ -72,6 +267,43 @@ impl AlignedBytesMut {
     /// other.range      :     [2,      6)
     /// ```
     pub fn split_to(&mut self, idx: usize) -> anyhow::Result<Self> {
+        self.check_split_idx(idx)?;
+        let new_range = self.range.start..idx;
+        self.range.start = idx;
+        Ok(AlignedBytesMut {
+            buf: self.buf.clone(),
+            range: new_range,
+        })
+    }
+
+    /// Split this view of the underlying buffer into two views at the given index. This is the
+    /// mirror image of [`AlignedBytesMut::split_to`] (and matches the semantics of
+    /// `bytes::BytesMut::split_off`), which is handy when filling buffers front-to-back.
+    ///
+    /// `idx` indexes into the backing buffer, and has the same requirements as for `split_to`.
+    ///
+    /// Afterwards, `self` contains `[range.start, idx)`. The returned `AlignedBytesMut`
+    /// contains elements `[idx, range.end)`.
+    ///
+    /// After calling `split_off(6)` on the example from `split_to`:
+    ///
+    /// ```text
+    /// Underlying buffer:  0 1 2 3 4 5 6 7 8 9
+    /// self.range       :     [2,      6)
+    /// other.range      :             [6,  8)
+    /// ```
+    pub fn split_off(&mut self, idx: usize) -> anyhow::Result<Self> {
+        self.check_split_idx(idx)?;
+        let new_range = idx..self.range.end;
+        self.range.end = idx;
+        Ok(AlignedBytesMut {
+            buf: self.buf.clone(),
+            range: new_range,
+        })
+    }
+
+    /// Check that `idx` is a valid index for `split_to` and `split_off`.
+    fn check_split_idx(&self, idx: usize) -> anyhow::Result<()> {
         if !self.range.contains(&idx) {
             Err(anyhow::format_err!(
                 "idx {idx} is not contained in this buffer's range {:?}",
@@ -85,12 +317,7 @@ impl AlignedBytesMut {
                 self.buf.alignment()
             ))
         } else {
-            let new_range = self.range.start..idx;
-            self.range.start = idx;
-            Ok(AlignedBytesMut {
-                buf: self.buf.clone(),
-                range: new_range,
-            })
+            Ok(())
         }
     }
 
@@ -113,6 +340,12 @@ impl AlignedBytesMut {
 }
 
 /// Immutable.
+///
+/// # Cloning
+/// `clone` is cheap: It returns another view of the _same_ underlying buffer (by cloning an
+/// `Arc`), not a copy of the bytes. To get an independent buffer which can be mutated without
+/// affecting (or being affected by) any other view, use [`AlignedBytes::clone_contents`], which
+/// copies the bytes.
 #[derive(Debug, Clone)]
 pub struct AlignedBytes {
     buf: Arc<InnerBuffer>,
@@ -141,6 +374,28 @@ impl AlignedBytes {
         self
     }
 
+    /// Returns a new `AlignedBytes` which views `range` of `self`, without modifying `self`. Unlike
+    /// [`AlignedBytes::set_slice`], `range` is relative to the start of `self`'s current view. The
+    /// new `AlignedBytes` shares the underlying buffer with `self`.
+    ///
+    /// Returns an error if `range` is empty, or if `range` extends beyond the end of `self`'s
+    /// current view.
+    pub fn slice(&self, range: Range<usize>) -> anyhow::Result<Self> {
+        if range.is_empty() {
+            Err(anyhow::format_err!("range {range:?} must not be empty"))
+        } else if range.end > self.len() {
+            Err(anyhow::format_err!(
+                "range {range:?} extends beyond the end of this view, which is {} bytes long",
+                self.len()
+            ))
+        } else {
+            Ok(AlignedBytes {
+                buf: self.buf.clone(),
+                range: self.range.start + range.start..self.range.start + range.end,
+            })
+        }
+    }
+
     /// Resets this `AlignedBytes` range to be equal to the total extent of the underlying buffer.
     pub fn reset_slice(&mut self) -> &Self {
         self.range = 0..self.buf.len();
@@ -153,6 +408,12 @@ impl AlignedBytes {
         self.range.len()
     }
 
+    /// Returns true if the `range` requested by the user has a length of zero bytes. Views are
+    /// almost never empty, because the methods which create views reject empty ranges.
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
     /// Returns a constant pointer to `self.range.start` of the underlying buffer.
     pub fn as_ptr(&self) -> *const u8 {
         let ptr = self.buf.as_ptr();
@@ -163,6 +424,256 @@ impl AlignedBytes {
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
     }
+
+    /// Copies the bytes of this view into a newly-allocated buffer whose start is aligned to
+    /// `align`, and returns a mutable handle to the copy. Unlike [`Clone::clone`] (which shares
+    /// the underlying buffer), the copy is completely independent of `self`: It's the only view
+    /// of its buffer, so it can be mutated (and frozen) without affecting any other view.
+    ///
+    /// 'align' must not be zero, and must be a power of two.
+    ///
+    /// ## Panics
+    /// Panics if `self` is empty.
+    pub fn clone_contents(&self, align: usize) -> AlignedBytesMut {
+        let mut copy = AlignedBytesMut::with_capacity(self.len(), align);
+        // SAFETY: `copy` was allocated with at least `self.len()` bytes, and doesn't overlap with
+        // `self`'s buffer.
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.as_ptr(), copy.as_mut_ptr(), self.len());
+        }
+        copy
+    }
+
+    /// Copies the `range` view of the underlying buffer into a new `Vec<u8>`. This is the
+    /// recommended way to pass the bytes to APIs which need an owned `Vec<u8>`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.as_slice().to_vec()
+    }
+
+    /// Like [`AlignedBytes::to_vec`], but consumes `self`, so the underlying buffer is freed
+    /// straight away if `self` was the only view of it.
+    ///
+    /// `into_vec` always copies, even if `self` is the only view: The underlying buffer was
+    /// allocated with a larger alignment than `u8`, so it can't be handed to a `Vec<u8>` (which
+    /// would free it with the wrong layout).
+    pub fn into_vec(self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    /// Returns `true` if `self` is the only view of the underlying buffer. For example, a pool of
+    /// buffers can use `is_unique` to find out if a buffer that it previously handed out has since
+    /// been dropped by everyone else.
+    pub fn is_unique(&self) -> bool {
+        Arc::strong_count(&self.buf) == 1
+    }
+
+    /// Returns a [`bytes::Bytes`] which views the same bytes as `self`, without copying. The
+    /// `Bytes` holds a clone of `self`, so the underlying (aligned) buffer stays alive until the
+    /// `Bytes` (and every other view of the buffer) has been dropped. The `Bytes` starts at
+    /// `self.as_ptr()`, so it has the same alignment as `self`.
+    #[cfg(feature = "bytes")]
+    pub fn to_bytes(&self) -> bytes::Bytes {
+        bytes::Bytes::from_owner(self.clone())
+    }
+}
+
+impl AsRef<[u8]> for AlignedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+/// Two `AlignedBytes` are equal if their views hold the same bytes, regardless of their backing
+/// buffers. So two identical chunks which were read independently (into separate buffers) are
+/// equal, and two views of the same buffer are only equal if they view the same bytes. To test
+/// whether two views share the same backing buffer, compare [`AlignedBytes::as_ptr`] instead.
+impl PartialEq for AlignedBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for AlignedBytes {}
+
+/// Hashes the bytes in the view (like `[u8]`), consistent with `PartialEq`. Hashing reads every
+/// byte, so hashing large chunks isn't cheap.
+impl std::hash::Hash for AlignedBytes {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+    }
+}
+
+/// Zero-copy. See [`AlignedBytes::to_bytes`].
+#[cfg(feature = "bytes")]
+impl From<AlignedBytes> for bytes::Bytes {
+    fn from(aligned_bytes: AlignedBytes) -> Self {
+        bytes::Bytes::from_owner(aligned_bytes)
+    }
+}
+
+/// The error returned by the fallible constructors (such as [`AlignedBytesMut::try_new`]) when a
+/// buffer can't be allocated (e.g. because the system is out of memory, or because `size` is too
+/// large for any allocation to succeed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError {
+    /// The number of bytes which we tried to allocate.
+    pub size: usize,
+    /// The alignment of the allocation, in bytes.
+    pub align: usize,
+}
+
+impl AllocError {
+    /// The layout of an allocation of at least `len` bytes, aligned to `align`.
+    fn layout(len: usize, align: usize) -> Result<alloc::Layout, Self> {
+        assert!(align.is_power_of_two(), "align must be a power of two");
+        alloc::Layout::from_size_align(len, align)
+            .map(|layout| layout.pad_to_align())
+            .map_err(|_| Self { size: len, align })
+    }
+
+    /// Abort the process, like the infallible constructors do when they fail to allocate.
+    fn handle(self) -> ! {
+        match alloc::Layout::from_size_align(self.size, self.align) {
+            Ok(layout) => alloc::handle_alloc_error(layout),
+            Err(_) => panic!("failed to create Layout! {self}"),
+        }
+    }
+}
+
+impl From<alloc::Layout> for AllocError {
+    fn from(layout: alloc::Layout) -> Self {
+        Self {
+            size: layout.size(),
+            align: layout.align(),
+        }
+    }
+}
+
+impl std::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to allocate {} bytes aligned to {} bytes",
+            self.size, self.align
+        )
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// A region of memory which was allocated outside of `lsio_aligned_bytes`. For example, host
+/// memory which has been pinned for fast transfers to a GPU (e.g. allocated by `cudaHostAlloc`).
+///
+/// Use [`AlignedBytesMut::from_external_memory`] to create views into this memory.
+#[cfg(feature = "external-memory")]
+pub struct ExternalMemory {
+    ptr: *mut u8,
+    len: usize,
+
+    /// `owner` is dropped when the `ExternalMemory` and all the views into it have been dropped.
+    _owner: Box<dyn Any + Send + Sync>,
+
+    /// The ranges of the views which are currently alive. Used to detect overlapping views.
+    #[cfg(debug_assertions)]
+    live_views: std::sync::Mutex<Vec<Range<usize>>>,
+}
+
+#[cfg(feature = "external-memory")]
+unsafe impl Send for ExternalMemory {}
+#[cfg(feature = "external-memory")]
+unsafe impl Sync for ExternalMemory {}
+
+#[cfg(feature = "external-memory")]
+impl ExternalMemory {
+    /// Wraps `len` bytes of memory, starting at `ptr`.
+    ///
+    /// `owner` will be dropped when the `ExternalMemory` and all the views into it have been
+    /// dropped. So `owner`'s `Drop` implementation is a good place to free the memory (e.g. by
+    /// calling `cudaFreeHost`).
+    ///
+    /// # Safety
+    /// `ptr` must point to `len` bytes of memory, which must remain valid until `owner` is
+    /// dropped. Nothing other than the views created by
+    /// [`AlignedBytesMut::from_external_memory`] may read or write this memory whilst `owner` is
+    /// alive.
+    pub unsafe fn new(ptr: *mut u8, len: usize, owner: impl Any + Send + Sync) -> Self {
+        Self {
+            ptr,
+            len,
+            _owner: Box::new(owner),
+            #[cfg(debug_assertions)]
+            live_views: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the length of the external memory, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the external memory has a length of zero bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// In debug builds, check that `range` doesn't overlap any live view, and record `range` as a
+    /// live view. This is a no-op in release builds.
+    fn register_view(&self, range: &Range<usize>) -> anyhow::Result<()> {
+        #[cfg(debug_assertions)]
+        {
+            let mut live_views = self.live_views.lock().unwrap();
+            if let Some(other) = live_views.iter().find(|other| ranges_overlap(range, other)) {
+                return Err(anyhow::format_err!(
+                    "range {range:?} overlaps with range {other:?} of the same ExternalMemory, \
+                        which is still in use (e.g. by a read which is still in flight)"
+                ));
+            }
+            live_views.push(range.clone());
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = range;
+        Ok(())
+    }
+
+    /// In debug builds, forget the view of the `len` bytes starting at `buf`. This is a no-op in
+    /// release builds.
+    fn unregister_view(&self, buf: *const u8, len: usize) {
+        #[cfg(debug_assertions)]
+        {
+            let start = buf as usize - self.ptr as usize;
+            let range = start..start + len;
+            // This is called from `Drop`, so don't panic if another thread panicked whilst
+            // holding the lock.
+            let mut live_views = self
+                .live_views
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            // Remove exactly one view, so that a view can't unregister any other view.
+            if let Some(i) = live_views.iter().position(|view| *view == range) {
+                live_views.swap_remove(i);
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = (buf, len);
+    }
+}
+
+/// Returns `true` if the (non-empty, half-open) ranges `a` and `b` have at least one byte in
+/// common. Adjacent ranges (e.g. `0..5` and `5..10`) don't overlap.
+#[cfg(all(feature = "external-memory", debug_assertions))]
+fn ranges_overlap(a: &Range<usize>, b: &Range<usize>) -> bool {
+    debug_assert!(!a.is_empty() && !b.is_empty());
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(feature = "external-memory")]
+impl std::fmt::Debug for ExternalMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExternalMemory")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug)]
@@ -172,19 +683,87 @@ struct InnerBuffer {
     /// `layout.size()` gives the number of bytes _actually_ allocated, which will be
     /// a multiple of `align`.
     layout: alloc::Layout,
+
+    /// If this is `Some` then `buf` points into `ExternalMemory`, which we must not deallocate.
+    #[cfg(feature = "external-memory")]
+    external_memory: Option<Arc<ExternalMemory>>,
+
+    /// If this is `Some` then `buf` was allocated by a [`BufferPool`], and will be returned to the
+    /// pool (if the pool still exists) instead of being deallocated.
+    pool: Option<std::sync::Weak<PoolInner>>,
+
+    /// If `true` then `buf` was allocated by `mmap(MAP_HUGETLB)`, so must be freed by `munmap`.
+    is_hugetlb: bool,
+
+    /// If `true` then every byte of `buf` is known to be initialised (e.g. because `buf` was
+    /// allocated by `alloc_zeroed`), so `buf` can be viewed as a `&mut [u8]`.
+    initialised: bool,
 }
 
 impl InnerBuffer {
-    fn new(len: usize, align: usize) -> Self {
+    /// Allocate at least `len` bytes using `allocate` (e.g. [`alloc::alloc`] or
+    /// [`alloc::alloc_zeroed`]). Returns an error if the allocation fails.
+    fn try_new(
+        len: usize,
+        align: usize,
+        allocate: unsafe fn(alloc::Layout) -> *mut u8,
+    ) -> Result<Self, AllocError> {
         assert_ne!(len, 0);
-        let layout = alloc::Layout::from_size_align(len, align)
-            .expect("failed to create Layout!")
-            .pad_to_align();
-        let buf = unsafe { alloc::alloc(layout) };
+        let layout = AllocError::layout(len, align)?;
+        let buf = unsafe { allocate(layout) };
         if buf.is_null() {
-            alloc::handle_alloc_error(layout);
+            return Err(AllocError::from(layout));
+        }
+        Ok(Self {
+            buf,
+            layout,
+            #[cfg(feature = "external-memory")]
+            external_memory: None,
+            pool: None,
+            is_hugetlb: false,
+            initialised: false,
+        })
+    }
+
+    /// Allocate at least `len` bytes of huge pages. See [`AlignedBytesMut::new_huge`].
+    fn try_new_huge(len: usize) -> Result<Self, AllocError> {
+        assert_ne!(len, 0);
+        let layout = AllocError::layout(len, HUGE_PAGE_SIZE)?;
+        #[cfg(target_os = "linux")]
+        {
+            let buf = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    layout.size(),
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB,
+                    -1,
+                    0,
+                )
+            };
+            if buf != libc::MAP_FAILED {
+                return Ok(Self {
+                    buf: buf.cast(),
+                    layout,
+                    #[cfg(feature = "external-memory")]
+                    external_memory: None,
+                    pool: None,
+                    is_hugetlb: true,
+                    // Anonymous mappings are zero-filled by the kernel.
+                    initialised: true,
+                });
+            }
+        }
+        // No huge pages are reserved. So fall back to a normal allocation (which is aligned to
+        // the huge page size, so the kernel can back it with transparent huge pages).
+        let inner_buf = Self::try_new(len, HUGE_PAGE_SIZE, alloc::alloc)?;
+        #[cfg(target_os = "linux")]
+        unsafe {
+            // This is only a hint, so we ignore errors (e.g. if transparent huge pages are
+            // disabled).
+            libc::madvise(inner_buf.buf.cast(), inner_buf.len(), libc::MADV_HUGEPAGE);
         }
-        Self { buf, layout }
+        Ok(inner_buf)
     }
 
     /// Returns the total size of the underlying buffer.
@@ -208,6 +787,21 @@ impl InnerBuffer {
 
 impl Drop for InnerBuffer {
     fn drop(&mut self) {
+        #[cfg(feature = "external-memory")]
+        if let Some(external_memory) = &self.external_memory {
+            external_memory.unregister_view(self.buf, self.layout.size());
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        if self.is_hugetlb {
+            unsafe { libc::munmap(self.buf.cast(), self.layout.size()) };
+            return;
+        }
+        if let Some(pool) = self.pool.as_ref().and_then(std::sync::Weak::upgrade) {
+            if pool.put(self.buf, self.layout) {
+                return;
+            }
+        }
         unsafe { alloc::dealloc(self.buf, self.layout) };
     }
 }
@@ -248,4 +842,393 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_write_and_read_via_mut_slice() {
+        const LEN: usize = 16;
+        let mut aligned_buf = AlignedBytesMut::new(LEN, 8);
+        for (i, byte) in aligned_buf.as_uninit_mut_slice().iter_mut().enumerate() {
+            byte.write(i as u8);
+        }
+        let slice = aligned_buf.freeze().unwrap();
+        assert_eq!(
+            slice.as_slice(),
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+        );
+
+        // Only the view is mutable.
+        let mut aligned_buf = AlignedBytesMut::zeroed(LEN, 8);
+        let mut first = aligned_buf.split_to(8).unwrap();
+        first.as_mut_slice().fill(1);
+        assert_eq!(first.as_mut_slice().len(), 8);
+        assert_eq!(aligned_buf.as_mut_slice(), [0; 8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "may not be initialised")]
+    fn test_as_mut_slice_panics_for_uninitialised_buffers() {
+        AlignedBytesMut::new(16, 8).as_mut_slice();
+    }
+
+    #[test]
+    fn test_slice() {
+        const LEN: usize = 16;
+        let mut buf = AlignedBytesMut::new(LEN, 8);
+        for i in 0..LEN {
+            unsafe { *buf.as_mut_ptr().add(i) = i as u8 };
+        }
+        let mut buf = buf.freeze().unwrap();
+        buf.set_slice(4..12);
+
+        // `slice` is relative to the current view, and doesn't modify `buf`.
+        let sub_view = buf.slice(2..5).unwrap();
+        assert_eq!(sub_view.as_slice(), [6, 7, 8]);
+        assert_eq!(buf.as_slice(), [4, 5, 6, 7, 8, 9, 10, 11]);
+
+        // Slicing a slice:
+        assert_eq!(sub_view.slice(1..3).unwrap().as_slice(), [7, 8]);
+
+        // Ranges which escape the current view (or are empty) are errors:
+        assert!(buf.slice(0..9).is_err());
+        assert!(sub_view.slice(2..4).is_err());
+        assert!(buf.slice(3..3).is_err());
+    }
+
+    #[test]
+    fn test_is_unique() {
+        let buf = AlignedBytesMut::new(64, 64).freeze().unwrap();
+        assert!(buf.is_unique());
+        let view = buf.slice(0..8).unwrap();
+        assert!(!buf.is_unique());
+        assert!(!view.is_unique());
+        drop(view);
+        assert!(buf.is_unique());
+    }
+
+    #[test]
+    fn test_eq_and_hash_compare_contents() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let mut a = AlignedBytesMut::zeroed(16, 8);
+        a.fill(7);
+        let a = a.freeze().unwrap();
+        let mut b = AlignedBytesMut::zeroed(32, 64);
+        b.fill(7);
+        let b = b.freeze().unwrap().slice(0..16).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, a.slice(0..8).unwrap());
+        let c = AlignedBytesMut::zeroed(16, 8).freeze().unwrap();
+        assert_ne!(a, c);
+        let hasher = RandomState::new();
+        assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+        assert_ne!(hasher.hash_one(&a), hasher.hash_one(&c));
+    }
+
+    #[test]
+    fn test_clone_contents() {
+        let mut buf = AlignedBytesMut::zeroed(64, 8);
+        buf.fill(7);
+        let buf = buf.freeze().unwrap().slice(8..24).unwrap();
+        let mut copy = buf.clone_contents(512);
+        assert_eq!(copy.len(), 16);
+        assert_eq!(copy.as_mut_ptr() as usize % 512, 0);
+        // Mutating the copy doesn't affect the original.
+        copy.fill(1);
+        let copy = copy.freeze().unwrap();
+        assert_eq!(copy.as_slice(), &[1; 16]);
+        assert_eq!(buf.as_slice(), &[7; 16]);
+    }
+
+    #[test]
+    fn test_to_vec_and_into_vec() {
+        let mut buf = AlignedBytesMut::zeroed(16, 8);
+        buf.fill(7);
+        let mut buf = buf.freeze().unwrap();
+        buf.set_slice(4..8);
+        assert_eq!(buf.to_vec(), vec![7; 4]);
+        assert_eq!(buf.into_vec(), vec![7; 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn test_to_bytes() {
+        let mut buf = AlignedBytesMut::zeroed(1024, 512);
+        for i in 0..4 {
+            unsafe { *buf.as_mut_ptr().add(512 + i) = i as u8 + 1 };
+        }
+        let mut buf = buf.freeze().unwrap();
+        buf.set_slice(512..516);
+        let bytes = buf.to_bytes();
+        assert_eq!(bytes.as_ptr(), buf.as_ptr());
+        assert_eq!(bytes.as_ptr() as usize % 512, 0);
+        assert_eq!(&bytes[..], &[1, 2, 3, 4]);
+        assert!(!buf.is_unique());
+
+        // The `Bytes` keeps the buffer alive.
+        let bytes_from_buf = bytes::Bytes::from(buf);
+        drop(bytes);
+        assert_eq!(&bytes_from_buf[..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_new_huge() {
+        const MIB: usize = 1024 * 1024;
+        let mut buf = AlignedBytesMut::new_huge(3 * MIB);
+        assert_eq!(buf.len(), 3 * MIB);
+        assert_eq!(buf.capacity(), 4 * MIB);
+        assert_eq!(buf.as_mut_ptr() as usize % HUGE_PAGE_SIZE, 0);
+        buf.fill(42);
+        let buf = buf.freeze().unwrap();
+        assert!(buf.as_slice().iter().all(|&x| x == 42));
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut buf = AlignedBytesMut::zeroed(256, 64);
+        let mut tail = buf.split_off(192).unwrap();
+        let mut middle = buf.split_off(64).unwrap();
+        assert_eq!((buf.range.clone(), middle.range.clone()), (0..64, 64..192));
+        assert_eq!(tail.range, 192..256);
+
+        // The views cover the original buffer, with no overlap:
+        buf.fill(1);
+        middle.fill(2);
+        tail.fill(3);
+        drop((buf, middle));
+        let mut buf = tail.freeze().unwrap();
+        buf.reset_slice();
+        assert_eq!(buf.as_slice()[..64], [1; 64]);
+        assert_eq!(buf.as_slice()[64..192], [2; 128]);
+        assert_eq!(buf.as_slice()[192..], [3; 64]);
+
+        // `split_off` has the same checks as `split_to`:
+        let mut buf = AlignedBytesMut::new(128, 64);
+        assert!(buf.split_off(0).is_err());
+        assert!(buf.split_off(32).is_err());
+        assert!(buf.split_off(128).is_err());
+        assert_eq!(buf.len(), 128);
+    }
+
+    #[test]
+    fn test_zeroed_and_fill() {
+        let buf = AlignedBytesMut::zeroed(100, 64);
+        let mut buf = buf.freeze().unwrap();
+        buf.reset_slice();
+        assert_eq!(buf.as_slice(), [0; 128]);
+        let mut buf = AlignedBytesMut::try_zeroed(100, 64).unwrap();
+        assert_eq!(buf.as_mut_slice(), [0; 100]);
+
+        // `fill` only modifies the view:
+        let mut buf = AlignedBytesMut::zeroed(128, 64);
+        let mut first_half = buf.split_to(64).unwrap();
+        first_half.fill(42);
+        drop(first_half);
+        let mut buf = buf.freeze().unwrap();
+        buf.reset_slice();
+        assert_eq!(buf.as_slice()[..64], [42; 64]);
+        assert_eq!(buf.as_slice()[64..], [0; 64]);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let buf = AlignedBytesMut::with_capacity(100, 64);
+        assert_eq!(buf.len(), 100);
+        assert!(!buf.is_empty());
+        assert_eq!(buf.capacity(), 128);
+        assert!(!buf.freeze().unwrap().is_empty());
+
+        // When the length is aligned, `new` gives `len == capacity`:
+        let buf = AlignedBytesMut::new(128, 64);
+        assert_eq!(buf.len(), buf.capacity());
+    }
+
+    #[test]
+    fn test_try_new_returns_alloc_errors() {
+        let buf = AlignedBytesMut::try_new(100, 64).unwrap();
+        assert_eq!(buf.len(), 100);
+        assert_eq!(buf.capacity(), 128);
+
+        // Far larger than the address space, so the allocation fails (even if the kernel
+        // overcommits memory).
+        let err = AlignedBytesMut::try_new(1 << 60, 4096).unwrap_err();
+        assert_eq!(
+            err,
+            AllocError {
+                size: 1 << 60,
+                align: 4096
+            }
+        );
+        assert!(AlignedBytesMut::try_new_huge(1 << 60).is_err());
+        assert!(AlignedBytesMut::try_zeroed(1 << 60, 4096).is_err());
+        // Too large for a `Layout`.
+        assert!(AlignedBytesMut::try_new(usize::MAX - 10, 4096).is_err());
+        assert!(AlignedBytesMut::try_zeroed(usize::MAX - 10, 4096).is_err());
+        assert!(BufferPool::new(1).try_get(1 << 60, 4096).is_err());
+        assert!(BufferPool::new(1).try_get(usize::MAX - 10, 4096).is_err());
+    }
+
+    #[cfg(feature = "external-memory")]
+    #[test]
+    fn test_from_vec() {
+        assert!(AlignedBytesMut::from_vec(Vec::new()).is_err());
+
+        let mut vec = Vec::with_capacity(100);
+        vec.extend(0..10u8);
+        let ptr = vec.as_ptr();
+        let mut buf = AlignedBytesMut::from_vec(vec).unwrap();
+        assert_eq!(buf.len(), 10);
+        assert_eq!(buf.as_mut_ptr() as *const u8, ptr);
+        unsafe { *buf.as_mut_ptr() = 42 };
+        let buf = buf.freeze().unwrap();
+        assert_eq!(buf.as_slice(), [42, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[cfg(feature = "external-memory")]
+    #[test]
+    fn test_external_memory() {
+        use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+        /// Frees the memory (and records that it has been freed) when dropped.
+        struct Owner {
+            ptr: *mut u8,
+            layout: alloc::Layout,
+            freed: Arc<AtomicBool>,
+        }
+        unsafe impl Send for Owner {}
+        unsafe impl Sync for Owner {}
+        impl Drop for Owner {
+            fn drop(&mut self) {
+                unsafe { alloc::dealloc(self.ptr, self.layout) };
+                self.freed.store(true, Relaxed);
+            }
+        }
+
+        const LEN: usize = 1024;
+        const ALIGN: usize = 512;
+        let layout = alloc::Layout::from_size_align(LEN, ALIGN).unwrap();
+        let ptr = unsafe { alloc::alloc(layout) };
+        let freed = Arc::new(AtomicBool::new(false));
+        let owner = Owner {
+            ptr,
+            layout,
+            freed: Arc::clone(&freed),
+        };
+        let memory = Arc::new(unsafe { ExternalMemory::new(ptr, LEN, owner) });
+
+        // Create two non-overlapping views:
+        let mut view_0 =
+            unsafe { AlignedBytesMut::from_external_memory(&memory, 0..ALIGN, ALIGN) }.unwrap();
+        let mut view_1 =
+            unsafe { AlignedBytesMut::from_external_memory(&memory, ALIGN..LEN, ALIGN) }.unwrap();
+        assert_eq!(view_1.as_mut_ptr(), unsafe { ptr.add(ALIGN) });
+
+        // Unaligned and out-of-bounds views must fail:
+        assert!(
+            unsafe { AlignedBytesMut::from_external_memory(&memory, 1..ALIGN, ALIGN) }.is_err()
+        );
+        assert!(
+            unsafe { AlignedBytesMut::from_external_memory(&memory, 0..LEN + 1, ALIGN) }.is_err()
+        );
+
+        // In debug builds, views which overlap a live view must fail:
+        #[cfg(debug_assertions)]
+        assert!(unsafe { AlignedBytesMut::from_external_memory(&memory, 0..LEN, ALIGN) }.is_err());
+
+        unsafe {
+            view_0.as_mut_ptr().write_bytes(0, ALIGN);
+            view_1.as_mut_ptr().write_bytes(1, LEN - ALIGN);
+        }
+
+        // Each view can be frozen independently:
+        let view_0 = view_0.freeze().unwrap();
+        let view_1 = view_1.freeze().unwrap();
+        assert!(view_0.as_slice().iter().all(|&x| x == 0));
+        assert!(view_1.as_slice().iter().all(|&x| x == 1));
+
+        // Once a view has been dropped, its range can be viewed again:
+        drop(view_0);
+        let view_0 =
+            unsafe { AlignedBytesMut::from_external_memory(&memory, 0..ALIGN, ALIGN) }.unwrap();
+
+        // The memory is only freed when the `ExternalMemory` and all its views are dropped:
+        drop(memory);
+        drop(view_0);
+        assert!(!freed.load(Relaxed));
+        drop(view_1);
+        assert!(freed.load(Relaxed));
+    }
+
+    /// A non-empty range within `0..1_000`.
+    #[cfg(all(feature = "external-memory", debug_assertions))]
+    fn non_empty_range() -> impl proptest::strategy::Strategy<Value = Range<usize>> {
+        use proptest::prelude::*;
+        (0..1_000_usize)
+            .prop_flat_map(|start| (Just(start), start + 1..=1_000))
+            .prop_map(|(start, end)| start..end)
+    }
+
+    #[cfg(all(feature = "external-memory", debug_assertions))]
+    proptest::proptest! {
+        #[test]
+        fn test_ranges_overlap(a in non_empty_range(), b in non_empty_range()) {
+            // The definition of overlapping: at least one byte in common.
+            let expected = a.clone().any(|i| b.contains(&i));
+            proptest::prop_assert_eq!(ranges_overlap(&a, &b), expected);
+            proptest::prop_assert_eq!(ranges_overlap(&b, &a), expected);
+        }
+
+        #[test]
+        fn test_adjacent_ranges_dont_overlap(a in non_empty_range(), len in 1..1_000_usize) {
+            let b = a.end..a.end + len;
+            proptest::prop_assert!(!ranges_overlap(&a, &b));
+            proptest::prop_assert!(!ranges_overlap(&b, &a));
+        }
+
+        #[test]
+        fn test_nested_ranges_overlap(
+            outer in non_empty_range(),
+            (start, len) in (0..1_000_usize, 1..1_000_usize),
+        ) {
+            // A non-empty range within `outer`:
+            let start = outer.start + start % outer.len();
+            let inner = start..start + 1 + len % (outer.end - start);
+            proptest::prop_assert!(ranges_overlap(&outer, &inner));
+            proptest::prop_assert!(ranges_overlap(&inner, &outer));
+        }
+
+        #[test]
+        fn test_identical_ranges_overlap(a in non_empty_range()) {
+            proptest::prop_assert!(ranges_overlap(&a, &a.clone()));
+        }
+    }
+
+    #[cfg(all(feature = "external-memory", debug_assertions))]
+    #[test]
+    fn test_external_memory_views_must_not_overlap() {
+        let mut vec = vec![0_u8; 64];
+        let ptr = vec.as_mut_ptr();
+        let memory = Arc::new(unsafe { ExternalMemory::new(ptr, 64, vec) });
+        let view = |range: Range<usize>| unsafe {
+            AlignedBytesMut::from_external_memory(&memory, range, 1)
+        };
+
+        let first = view(0..5).unwrap();
+        // Adjacent views don't overlap:
+        let second = view(5..10).unwrap();
+        let third = view(10..64).unwrap();
+        // Identical, nested, enclosing, and straddling views do overlap:
+        for range in [0..5, 1..4, 5..6, 9..10, 0..64, 4..6, 9..11] {
+            assert!(view(range.clone()).is_err(), "range={range:?}");
+        }
+
+        // Dropping a view only unregisters that view:
+        drop(first);
+        let first = view(0..5).unwrap();
+        assert!(view(5..10).is_err());
+        drop(second);
+        assert!(view(4..6).is_err());
+        let second = view(5..10).unwrap();
+        drop((first, second, third));
+        assert!(view(0..64).is_ok());
+    }
 }
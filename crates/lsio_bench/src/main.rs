@@ -8,10 +8,17 @@ use std::{
     time::{Duration, Instant},
 };
 
-use clap::{error::ErrorKind, CommandFactory, Parser};
-use indicatif::{ProgressBar, ProgressStyle};
-use lsio_io::{Completion, Reader};
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser,
+    ValueEnum,
+};
+use hdrhistogram::Histogram;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use lsio_aligned_bytes::BufferPool;
+use lsio_io::{Completion, Output, Reader};
+use lsio_std::StdReader;
 use lsio_uring::IoUring;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 
 const FILENAME_PREFIX: &str = "lsio_bench_";
 const MEBIBYTE: f64 = (1024 * 1024) as _;
@@ -36,13 +43,118 @@ struct Args {
     #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
     blocksize: Option<u64>,
 
-    /// The number of worker threads that lsio_uring uses:
+    /// Leave this many bytes unread between consecutive chunks, to simulate reading a subset of a
+    /// strided array. Each chunk is `--blocksize` bytes long and starts `blocksize + gap` bytes
+    /// after the start of the previous chunk, so each file holds fewer chunks than it would
+    /// without gaps. Only chunks which fit entirely within the file are read.
+    #[arg(long, default_value_t = 0)]
+    gap: u64,
+
+    /// The IO backends to benchmark, separated by commas (e.g. `--backend uring,std`). Each
+    /// backend reads exactly the same chunks, in the same order, starting with a cold page cache.
+    /// If more than one backend is benchmarked, then their results are printed side-by-side at
+    /// the end.
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Backend::Uring])]
+    backend: Vec<Backend>,
+
+    /// The number of worker threads that lsio_uring uses. Only applies to `--backend uring`.
     #[arg(short = 'w', long, default_value_t = 4, value_parser = clap::value_parser!(u64).range(1..1024))]
     nr_worker_threads: u64,
+
+    /// The number of worker threads that lsio_std uses. Each thread performs one blocking read at
+    /// a time, so lsio_std needs more threads than lsio_uring to keep the storage busy. Only
+    /// applies to `--backend std`.
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(1..1024))]
+    std_worker_threads: u64,
+
+    /// Register this many buffers (each the size of one chunk) with io_uring, and read into them
+    /// using `ReadFixed`. Compare against a run without this option to measure the benefit of
+    /// registered buffers. By default, no buffers are registered. Only applies to
+    /// `--backend uring`.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    fixed_buffers: Option<u16>,
+
+    /// Allocate buffers from a pool which recycles the buffers of dropped chunks, keeping at most
+    /// this many free buffers per size class. Compare against a run without this option to
+    /// measure the benefit of recycling buffers. By default, no pool is used. Only applies to
+    /// `--backend uring`.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    buffer_pool: Option<u64>,
+
+    /// Allocate the buffers of chunks of at least this many bytes from huge pages. Compare the
+    /// number of page faults against a run without this option to measure the benefit of huge
+    /// pages. By default, huge pages aren't used. Only applies to `--backend uring`.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    huge_pages: Option<u64>,
+
+    /// Open the files as io_uring "fixed files", which saves the kernel from looking up the file
+    /// descriptor for every read. This is most useful when reading thousands of files. Only
+    /// applies to `--backend uring`.
+    #[arg(long)]
+    fixed_files: bool,
+
+    /// Open the files with `O_DIRECT`, bypassing the page cache. This is the default. Only
+    /// applies to `--backend uring`.
+    #[arg(long, overrides_with = "no_direct")]
+    direct: bool,
+
+    /// Open the files without `O_DIRECT`, so reads go through the page cache. Only applies to
+    /// `--backend uring`.
+    #[arg(long, overrides_with = "direct")]
+    no_direct: bool,
+
+    /// Split the files into this many groups of consecutive files, and submit each group using
+    /// `get_ranges_in_group`, so that each group is only read once the previous group has
+    /// finished. Shows one progress bar per group (counting the bytes read so far), so it's
+    /// obvious when one group stalls. Must not be more than `--nrfiles`. By default, the files
+    /// aren't grouped.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    groups: u32,
+
+    /// Read the chunks of each file (and the files themselves) in random order, instead of in
+    /// order. Random reads are more representative of reading Zarr chunks.
+    #[arg(long)]
+    random: bool,
+
+    /// The seed for `--random`. By default, a random seed is used. The seed is printed, so runs
+    /// can be reproduced.
+    #[arg(long, requires = "random")]
+    seed: Option<u64>,
+}
+
+/// An IO backend which can be benchmarked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// `lsio_uring::IoUring`.
+    Uring,
+    /// `lsio_std::StdReader`.
+    Std,
+}
+
+impl Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Uring => "uring",
+            Self::Std => "std",
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    check_backend_specific_args(&args, &matches);
+    if args.groups > args.nrfiles {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            format!(
+                "--groups {} is more than the number of files ({})",
+                args.groups, args.nrfiles
+            ),
+        )
+        .exit();
+    }
 
     let directory = check_directory_or_use_temp_dir(&args.directory);
 
@@ -52,18 +164,77 @@ fn main() -> std::io::Result<()> {
 
     create_files_if_necessary(&filenames, args.filesize)?;
 
-    clear_page_cache(&directory);
-
-    read_files(
-        &filenames,
-        args.filesize,
-        args.blocksize,
-        args.nr_worker_threads as usize,
-    );
+    let workload = Workload::new(filenames, &args);
+    let mut results = Vec::with_capacity(args.backend.len());
+    for &backend in &args.backend {
+        clear_page_cache(&directory);
+        println!("Benchmarking the {} backend...", backend.name());
+        let result = match backend {
+            Backend::Uring => {
+                let (mut uring, buffer_pool) = build_io_uring(&args, workload.blocksize);
+                let result = read_files(&mut uring, &workload, backend);
+                result.print();
+                for (i, stats) in uring.worker_stats().iter().enumerate() {
+                    println!(
+                        "Worker thread {i}: {} SQEs submitted, {} CQEs processed, {} reads into \
+                            registered buffers",
+                        stats.sqes_submitted(),
+                        stats.cqes_processed(),
+                        stats.fixed_buffer_reads()
+                    );
+                }
+                if let Some(buffer_pool) = &buffer_pool {
+                    println!("Buffers recycled by the pool: {}", buffer_pool.n_recycled());
+                }
+                result
+            }
+            Backend::Std => {
+                let mut reader = StdReader::new(args.std_worker_threads as usize);
+                let result = read_files(&mut reader, &workload, backend);
+                result.print();
+                result
+            }
+        };
+        results.push(result);
+    }
+    if results.len() > 1 {
+        print_comparison(&results);
+    }
 
     Ok(())
 }
 
+/// Exit with an error if an argument which only applies to one backend is set on the command line,
+/// but that backend isn't being benchmarked.
+fn check_backend_specific_args(args: &Args, matches: &ArgMatches) {
+    let backend_specific_args = [
+        (Backend::Uring, "nr_worker_threads"),
+        (Backend::Uring, "fixed_buffers"),
+        (Backend::Uring, "buffer_pool"),
+        (Backend::Uring, "huge_pages"),
+        (Backend::Uring, "fixed_files"),
+        (Backend::Uring, "direct"),
+        (Backend::Uring, "no_direct"),
+        (Backend::Std, "std_worker_threads"),
+    ];
+    for (backend, id) in backend_specific_args {
+        if !args.backend.contains(&backend)
+            && matches.value_source(id) == Some(ValueSource::CommandLine)
+        {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--{} only applies to --backend {}",
+                    id.replace('_', "-"),
+                    backend.name()
+                ),
+            )
+            .exit();
+        }
+    }
+}
+
 fn check_directory_or_use_temp_dir(directory: &Option<PathBuf>) -> PathBuf {
     // Check directory exists. Or use temp_dir.
     if let Some(directory) = directory.as_deref() {
@@ -115,39 +286,190 @@ fn get_filesize(filename: &Path) -> std::io::Result<u64> {
     Ok(File::open(&filename)?.metadata()?.len())
 }
 
+fn get_group_progress_bar_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{prefix:>10} {bar:40.green/blue} {bytes:>10}/{total_bytes:10} {msg}",
+    )
+    .unwrap()
+    .progress_chars("##-")
+}
+
 fn get_progress_bar_style() -> ProgressStyle {
     ProgressStyle::with_template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
         .unwrap()
         .progress_chars("##-")
 }
 
-fn read_files(
-    filenames: &[PathBuf],
+/// The chunks to read, which are identical for every backend.
+struct Workload {
+    filenames: Vec<PathBuf>,
+    /// The byte range and `user_data` of each chunk, for each file.
+    chunks_per_file: Vec<Vec<(Range<isize>, u64)>>,
+    n_chunks: u64,
+    blocksize: u64,
     filesize: u64,
-    blocksize: Option<u64>,
-    n_worker_threads: usize,
-) {
-    let blocksize = if let Some(bs) = blocksize {
-        bs
-    } else {
-        filesize
-    };
+    n_groups: usize,
+}
 
-    // Calculate chunks
-    let n_chunks = filesize / blocksize;
-    let chunks: Vec<Range<isize>> = (0..n_chunks)
-        .map(|chunk_i| {
-            let chunk_start = (chunk_i * blocksize) as isize;
-            let chunk_end = chunk_start + (blocksize as isize);
-            chunk_start..chunk_end
-        })
-        .collect();
-    assert_eq!(chunks.len(), n_chunks as _);
+impl Workload {
+    fn new(mut filenames: Vec<PathBuf>, args: &Args) -> Self {
+        let filesize = args.filesize;
+        let blocksize = args.blocksize.unwrap_or(filesize);
+
+        // Calculate chunks. The last chunk doesn't need a gap after it.
+        let stride = blocksize + args.gap;
+        let n_chunks = (filesize + args.gap) / stride;
+        if n_chunks == 0 {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::ValueValidation,
+                format!("A chunk of {blocksize} bytes doesn't fit in a file of {filesize} bytes"),
+            )
+            .exit();
+        }
+        let chunks: Vec<Range<isize>> = (0..n_chunks)
+            .map(|chunk_i| {
+                let chunk_start = (chunk_i * stride) as isize;
+                let chunk_end = chunk_start + (blocksize as isize);
+                chunk_start..chunk_end
+            })
+            .collect();
+        assert_eq!(chunks.len(), n_chunks as _);
+
+        // Define user_data (so we can identify the chunks!). The user_data of chunk `chunk_i` of
+        // file `file_i` is `file_i * n_chunks + chunk_i`.
+        let mut chunks_per_file: Vec<Vec<(Range<isize>, u64)>> = (0..filenames.len() as u64)
+            .map(|file_i| {
+                let user_data = (0..n_chunks).map(|chunk_i| file_i * n_chunks + chunk_i);
+                chunks.iter().cloned().zip(user_data).collect()
+            })
+            .collect();
+
+        // If `--random`, shuffle the order of the files, and the order of the chunks within each
+        // file. Each chunk keeps its `user_data`.
+        if args.random {
+            let seed = args.seed.unwrap_or_else(rand::random);
+            println!("Reading chunks in random order, with --seed {seed}");
+            let mut rng = StdRng::seed_from_u64(seed);
+            filenames.shuffle(&mut rng);
+            for chunks in &mut chunks_per_file {
+                chunks.shuffle(&mut rng);
+            }
+        }
+
+        Self {
+            filenames,
+            chunks_per_file,
+            n_chunks,
+            blocksize,
+            filesize,
+            n_groups: args.groups as usize,
+        }
+    }
+
+    /// The group of the file at index `file_i` of `filenames`. Each group holds consecutive files.
+    fn group_of(&self, file_i: usize) -> usize {
+        file_i * self.n_groups / self.filenames.len()
+    }
+
+    /// The number of bytes read from each group.
+    fn bytes_per_group(&self) -> Vec<u64> {
+        let mut bytes_per_group = vec![0; self.n_groups];
+        for file_i in 0..self.filenames.len() {
+            bytes_per_group[self.group_of(file_i)] += self.n_chunks * self.blocksize;
+        }
+        bytes_per_group
+    }
+}
 
-    // Define user_data (so we can identify the chunks!)
-    let user_data: Vec<u64> = (0..n_chunks as u64).collect();
+/// The results of benchmarking one backend.
+struct BenchResult {
+    backend: Backend,
+    total_secs: f64,
+    total_bytes: f64,
+    /// The latency of each chunk, in microseconds.
+    latencies: Histogram<u64>,
+    minor_page_faults: u64,
+}
+
+impl BenchResult {
+    fn mebibytes_per_sec(&self) -> f64 {
+        self.total_bytes / self.total_secs / MEBIBYTE
+    }
 
-    let mut uring = IoUring::new(n_worker_threads);
+    fn print(&self) {
+        println!("Total runtime: {} secs", self.total_secs);
+        println!("Total mebibytes: {} MiB", self.total_bytes / MEBIBYTE);
+        println!(
+            "Total bandwidth = {} mebibytes per sec",
+            self.mebibytes_per_sec()
+        );
+        let latencies = &self.latencies;
+        println!(
+            "Latency per chunk (from submitting its file to receiving the chunk), in microseconds: \
+                min = {}, mean = {:.0}, p50 = {}, p90 = {}, p99 = {}, max = {}",
+            latencies.min(),
+            latencies.mean(),
+            latencies.value_at_quantile(0.5),
+            latencies.value_at_quantile(0.9),
+            latencies.value_at_quantile(0.99),
+            latencies.max(),
+        );
+        println!("Minor page faults: {}", self.minor_page_faults);
+    }
+}
+
+/// Print the results of each backend side-by-side. Latencies are in microseconds.
+fn print_comparison(results: &[BenchResult]) {
+    println!();
+    println!(
+        "{:<8} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+        "backend", "MiB/sec", "mean µs", "p50 µs", "p90 µs", "p99 µs", "max µs", "minor faults"
+    );
+    for result in results {
+        let latencies = &result.latencies;
+        println!(
+            "{:<8} {:>12.1} {:>10.0} {:>10} {:>10} {:>10} {:>10} {:>12}",
+            result.backend.name(),
+            result.mebibytes_per_sec(),
+            latencies.mean(),
+            latencies.value_at_quantile(0.5),
+            latencies.value_at_quantile(0.9),
+            latencies.value_at_quantile(0.99),
+            latencies.max(),
+            result.minor_page_faults,
+        );
+    }
+}
+
+/// Build an `IoUring` from the io_uring-specific arguments. Also returns the buffer pool (if any),
+/// so that its statistics can be printed.
+fn build_io_uring(args: &Args, blocksize: u64) -> (IoUring, Option<BufferPool>) {
+    let mut builder = IoUring::builder(args.nr_worker_threads as usize)
+        .fixed_files(args.fixed_files)
+        .direct_io(!args.no_direct);
+    if let Some(n_fixed_buffers) = args.fixed_buffers {
+        println!("Registering {n_fixed_buffers} fixed buffers of {blocksize} bytes each.");
+        builder = builder.fixed_buffers(n_fixed_buffers as usize, blocksize as usize);
+    }
+    let buffer_pool = args.buffer_pool.map(|n| BufferPool::new(n as usize));
+    if let Some(buffer_pool) = &buffer_pool {
+        builder = builder.buffer_pool(buffer_pool.clone());
+    }
+    if let Some(threshold) = args.huge_pages {
+        println!("Allocating chunks of at least {threshold} bytes from huge pages.");
+        builder = builder.huge_pages(threshold as usize);
+    }
+    (builder.build(), buffer_pool)
+}
+
+fn read_files<R: Reader + Completion>(
+    reader: &mut R,
+    workload: &Workload,
+    backend: Backend,
+) -> BenchResult {
+    let n_chunks = workload.n_chunks;
+    let filenames = &workload.filenames;
 
     // Set up progress bar:
     let n_files = filenames.len() as u64;
@@ -156,37 +478,99 @@ fn read_files(
     let pb = ProgressBar::new(n_total_chunks);
     pb.set_style(get_progress_bar_style());
 
+    // If the files are grouped, then show one progress bar per group below the overall bar.
+    let multi_progress = MultiProgress::new();
+    let group_pbs: Vec<ProgressBar> = if workload.n_groups > 1 {
+        let pb = multi_progress.add(pb.clone());
+        let group_pbs = workload
+            .bytes_per_group()
+            .into_iter()
+            .enumerate()
+            .map(|(group_i, n_bytes)| {
+                let group_pb = multi_progress.add(ProgressBar::new(n_bytes));
+                group_pb.set_style(get_group_progress_bar_style());
+                group_pb.set_prefix(format!("group {group_i}"));
+                group_pb
+            })
+            .collect();
+        pb.tick();
+        group_pbs
+    } else {
+        Vec::new()
+    };
+
+    let minor_page_faults_before = minor_page_faults();
     let started = Instant::now();
 
-    // Submit all the get_ranges requests:
-    for filename in filenames {
-        uring
-            .get_ranges(&filename, chunks.clone(), user_data.clone())
-            .unwrap();
+    // Submit all the get_ranges requests, and record when each file's chunks were submitted. Each
+    // chunk's file is at index `user_data / n_chunks` of `submitted_at`.
+    let mut submitted_at = Vec::with_capacity(filenames.len());
+    if workload.n_groups > 1 {
+        for (file_i, (filename, chunks)) in
+            filenames.iter().zip(&workload.chunks_per_file).enumerate()
+        {
+            let (chunks, user_data) = chunks.iter().cloned().unzip();
+            let group_id = workload.group_of(file_i) as u64;
+            submitted_at.push(Instant::now());
+            reader
+                .get_ranges_in_group(group_id, filename, chunks, user_data)
+                .unwrap();
+        }
+    } else if workload.blocksize == workload.filesize {
+        // `n_chunks` is 1, and the user_data of each chunk is the index of its file.
+        submitted_at.resize(filenames.len(), Instant::now());
+        reader.get_whole_files(filenames).unwrap();
+    } else {
+        for (filename, chunks) in filenames.iter().zip(&workload.chunks_per_file) {
+            let (chunks, user_data) = chunks.iter().cloned().unzip();
+            submitted_at.push(Instant::now());
+            reader.get_ranges(filename, chunks, user_data).unwrap();
+        }
     }
 
-    // Collect results
+    // Collect results, and the latency of each chunk (in microseconds):
+    let mut latencies = Histogram::<u64>::new(3).unwrap();
     for _ in 0..n_total_chunks {
-        match uring
-            .completion()
-            .recv_timeout(Duration::from_millis(10000))
-        {
-            Ok(_) => pb.inc(1),
+        match reader.recv_timeout(Duration::from_millis(10000)) {
+            // Dropping the output frees (or recycles) the chunk's buffer.
+            Ok(Ok(Output::Chunk(chunk))) => {
+                let file_i = (chunk.user_data / n_chunks) as usize;
+                let latency = submitted_at[file_i].elapsed().as_micros() as u64;
+                latencies.record(latency).unwrap();
+                pb.inc(1);
+                if let Some(group_pb) = group_pbs.get(workload.group_of(file_i)) {
+                    group_pb.inc(chunk.buffer.len() as u64);
+                    if group_pb.position() == group_pb.length().unwrap() {
+                        group_pb.finish_with_message("done");
+                    }
+                }
+            }
+            Ok(output) => panic!("Unexpected output! {output:?}"),
             Err(e) => panic!("Error collecting chunk! {e:?}"),
         }
     }
     pb.finish();
+    if !group_pbs.is_empty() && !multi_progress.is_hidden() {
+        // `MultiProgress` leaves the cursor at the end of the last group's bar.
+        println!();
+    }
 
-    // Calculate bandwidth
-    let total_secs = started.elapsed().as_secs_f64();
-    let total_bytes = (filesize * n_files) as f64;
-    let bytes_per_sec = total_bytes / total_secs;
-    println!("Total runtime: {} secs", total_secs);
-    println!("Total mebibytes: {} MiB", total_bytes / MEBIBYTE);
-    println!(
-        "Total bandwidth = {} mebibytes per sec",
-        bytes_per_sec / MEBIBYTE
-    );
+    BenchResult {
+        backend,
+        total_secs: started.elapsed().as_secs_f64(),
+        total_bytes: (workload.blocksize * n_total_chunks) as f64,
+        latencies,
+        minor_page_faults: minor_page_faults() - minor_page_faults_before,
+    }
+}
+
+/// The number of minor page faults incurred by this process so far, read from `/proc/self/stat`.
+fn minor_page_faults() -> u64 {
+    let stat = std::fs::read_to_string("/proc/self/stat").expect("failed to read /proc/self/stat");
+    // The second field is the executable's name in parentheses (which may contain spaces). `minflt`
+    // is the 10th field, so it's the 8th field after the closing parenthesis.
+    let (_, fields) = stat.rsplit_once(") ").unwrap();
+    fields.split_whitespace().nth(7).unwrap().parse().unwrap()
 }
 
 fn clear_page_cache(directory: &Path) {
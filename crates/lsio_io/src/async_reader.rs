@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use futures::{channel::oneshot, future::join_all};
+
+use crate::{Chunk, Completion, IoError, Output, Reader};
+
+/// An async API for the IO backend `R`.
+///
+/// `AsyncReader` spawns one "dispatcher" thread, which receives every output from `R`'s
+/// completion channel, and uses each output's `user_data` to wake the future which is waiting for
+/// that byte range. So the futures returned by [`AsyncReader::get_ranges`] can be polled by any
+/// executor, and `R`'s worker threads don't need to run inside an async runtime. The dispatcher
+/// thread stops when `R`'s completion channel is closed (i.e. when `R` has been dropped).
+///
+/// Every read must be submitted via `AsyncReader::get_ranges`. The outputs of other operations
+/// are dropped.
+#[derive(Debug)]
+pub struct AsyncReader<R> {
+    inner: Mutex<R>,
+    pending: Arc<Mutex<HashMap<u64, PendingRange>>>,
+    next_user_data: AtomicU64,
+}
+
+#[derive(Debug)]
+struct PendingRange {
+    location: Arc<Path>,
+    range: Range<isize>,
+    /// The index of this range in the `ranges` passed to `get_ranges`.
+    index: u64,
+    sender: oneshot::Sender<anyhow::Result<Chunk>>,
+}
+
+impl<R> AsyncReader<R>
+where
+    R: Reader + Completion,
+{
+    pub fn new(inner: R) -> Self {
+        let completion = inner.completion().clone();
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let pending_for_dispatcher = Arc::clone(&pending);
+        thread::spawn(move || {
+            for output in completion.iter() {
+                dispatch(&pending_for_dispatcher, output);
+            }
+            // The IO backend has stopped. Dropping the senders wakes the waiting futures.
+            pending_for_dispatcher.lock().unwrap().clear();
+        });
+        Self {
+            inner: Mutex::new(inner),
+            pending,
+            next_user_data: AtomicU64::new(0),
+        }
+    }
+
+    /// Read `ranges` from `location`. See [`Reader::get_ranges`] for the meaning of `ranges`.
+    ///
+    /// Returns one result per range, in the same order as `ranges`. The `user_data` of each
+    /// [`Chunk`] is the index of its range in `ranges`.
+    pub async fn get_ranges(
+        &self,
+        location: &Path,
+        ranges: Vec<Range<isize>>,
+    ) -> Vec<anyhow::Result<Chunk>> {
+        let n_ranges = ranges.len() as u64;
+        let first_user_data = self.next_user_data.fetch_add(n_ranges, Relaxed);
+        let user_data: Vec<u64> = (first_user_data..first_user_data + n_ranges).collect();
+
+        // The dispatcher may receive outputs as soon as the ranges are submitted, so we must
+        // register the ranges first.
+        let shared_location: Arc<Path> = Arc::from(location);
+        let receivers: Vec<_> = {
+            let mut pending = self.pending.lock().unwrap();
+            user_data
+                .iter()
+                .zip(&ranges)
+                .zip(0..)
+                .map(|((&user_data, range), index)| {
+                    let (sender, receiver) = oneshot::channel();
+                    let pending_range = PendingRange {
+                        location: Arc::clone(&shared_location),
+                        range: range.clone(),
+                        index,
+                        sender,
+                    };
+                    pending.insert(user_data, pending_range);
+                    receiver
+                })
+                .collect()
+        };
+
+        let submitted = self
+            .inner
+            .lock()
+            .unwrap()
+            .get_ranges(location, ranges, user_data.clone());
+        if let Err(err) = submitted {
+            let mut pending = self.pending.lock().unwrap();
+            for user_data in &user_data {
+                pending.remove(user_data);
+            }
+            return (0..n_ranges)
+                .map(|_| Err(anyhow::format_err!("Failed to submit get_ranges: {err:#}")))
+                .collect();
+        }
+
+        join_all(receivers)
+            .await
+            .into_iter()
+            .map(|received| {
+                received.unwrap_or_else(|_| {
+                    Err(anyhow::format_err!(
+                        "The IO backend stopped before reading this range."
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Send `output` to the future which is waiting for it. Outputs which don't belong to a pending
+/// range are dropped.
+fn dispatch(pending: &Mutex<HashMap<u64, PendingRange>>, output: Result<Output, IoError>) {
+    let mut pending = pending.lock().unwrap();
+    match output {
+        Ok(Output::Chunk(chunk)) => {
+            if let Some(pending_range) = pending.remove(&chunk.user_data) {
+                let chunk = chunk.map_user_data(|_| pending_range.index);
+                // The receiver may have been dropped, if the future was cancelled.
+                let _ = pending_range.sender.send(Ok(chunk));
+            }
+        }
+        Ok(_) => (),
+        Err(err) => {
+            let failed_user_data: Vec<u64> = match err.user_data() {
+                Some(user_data) => vec![user_data],
+                None => pending
+                    .iter()
+                    .filter(|(_, p)| err.describes_range(&p.location, &p.range))
+                    .map(|(&user_data, _)| user_data)
+                    .collect(),
+            };
+            // `anyhow::Error` isn't `Clone`, so only the first failed range gets `err` itself.
+            let message = err.to_string();
+            let mut err = Some(anyhow::Error::from(err));
+            for user_data in failed_user_data {
+                if let Some(pending_range) = pending.remove(&user_data) {
+                    let err = err
+                        .take()
+                        .unwrap_or_else(|| anyhow::format_err!("{message}"));
+                    let _ = pending_range.sender.send(Err(err));
+                }
+            }
+        }
+    }
+}
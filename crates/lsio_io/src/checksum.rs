@@ -0,0 +1,194 @@
+/// The expected checksum of a byte range, which an IO backend can verify before sending the
+/// range's [`Chunk`](crate::Chunk). The variant chooses the algorithm. The checksum is computed
+/// over exactly the bytes of the range (i.e. over [`Chunk::buffer`](crate::Chunk::buffer)).
+///
+/// Verification can also be done by the user (e.g. on a separate threadpool) by calling
+/// [`Checksum::verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// CRC-32C (Castagnoli), as used by iSCSI, ext4 and Google Cloud Storage.
+    Crc32c(u32),
+    /// The 64-bit variant of xxHash (XXH64), with a seed of zero. Much faster than CRC-32C when
+    /// the CPU doesn't have CRC instructions.
+    XxHash64(u64),
+}
+
+impl Checksum {
+    /// Compute the CRC-32C of `bytes`.
+    pub fn crc32c(bytes: &[u8]) -> Self {
+        Self::Crc32c(crc32c(bytes))
+    }
+
+    /// Compute the XXH64 (with a seed of zero) of `bytes`.
+    pub fn xxhash64(bytes: &[u8]) -> Self {
+        Self::XxHash64(xxhash64(bytes))
+    }
+
+    /// Compute the checksum of `bytes`, using the same algorithm as `self`.
+    pub fn compute_like(&self, bytes: &[u8]) -> Self {
+        match self {
+            Self::Crc32c(_) => Self::crc32c(bytes),
+            Self::XxHash64(_) => Self::xxhash64(bytes),
+        }
+    }
+
+    /// Returns `Ok` if the checksum of `bytes` is `self`. Otherwise returns the actual checksum of
+    /// `bytes`.
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), Checksum> {
+        let actual = self.compute_like(bytes);
+        if actual == *self {
+            Ok(())
+        } else {
+            Err(actual)
+        }
+    }
+}
+
+/// The lookup table for a byte-at-a-time CRC-32C, using the reflected Castagnoli polynomial.
+const CRC32C_TABLE: [u32; 256] = {
+    const POLYNOMIAL: u32 = 0x82F6_3B78;
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (crc >> 8) ^ CRC32C_TABLE[((crc ^ byte as u32) & 0xFF) as usize]
+    })
+}
+
+const XXH_PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const XXH_PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const XXH_PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const XXH_PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const XXH_PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh64_round(0, val))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// XXH64 with a seed of zero. See https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+fn xxhash64(bytes: &[u8]) -> u64 {
+    let mut stripes = bytes.chunks_exact(32);
+    let mut hash = if bytes.len() >= 32 {
+        let mut acc = [
+            XXH_PRIME64_1.wrapping_add(XXH_PRIME64_2),
+            XXH_PRIME64_2,
+            0,
+            0u64.wrapping_sub(XXH_PRIME64_1),
+        ];
+        for stripe in &mut stripes {
+            for (lane, acc) in stripe.chunks_exact(8).zip(&mut acc) {
+                *acc = xxh64_round(*acc, read_u64(lane));
+            }
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.iter()
+            .fold(hash, |hash, &acc| xxh64_merge_round(hash, acc))
+    } else {
+        XXH_PRIME64_5
+    };
+    hash = hash.wrapping_add(bytes.len() as u64);
+
+    // Consume the remaining (fewer than 32) bytes.
+    let mut remainder = stripes.remainder();
+    while remainder.len() >= 8 {
+        hash ^= xxh64_round(0, read_u64(remainder));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_4);
+        remainder = &remainder[8..];
+    }
+    if remainder.len() >= 4 {
+        hash ^= (read_u32(remainder) as u64).wrapping_mul(XXH_PRIME64_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME64_2)
+            .wrapping_add(XXH_PRIME64_3);
+        remainder = &remainder[4..];
+    }
+    for &byte in remainder {
+        hash ^= (byte as u64).wrapping_mul(XXH_PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+    }
+
+    // Avalanche.
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_PRIME64_3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32c() {
+        // Test vectors from RFC 3720 (iSCSI), section B.4.
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8A91_36AA);
+        assert_eq!(crc32c(&[0xFF; 32]), 0x62A8_AB43);
+        let ascending: Vec<u8> = (0..32).collect();
+        assert_eq!(crc32c(&ascending), 0x46DD_794E);
+    }
+
+    #[test]
+    fn test_xxhash64() {
+        assert_eq!(xxhash64(b""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxhash64(b"abc"), 0x44BC_2CF5_AD77_0999);
+        assert_eq!(
+            xxhash64(b"Nobody inspects the spammish repetition"),
+            0xFBCE_A83C_8A37_8BF1
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let bytes = b"hello";
+        for checksum in [Checksum::crc32c(bytes), Checksum::xxhash64(bytes)] {
+            assert!(checksum.verify(bytes).is_ok());
+            assert_eq!(
+                checksum.verify(b"hellO"),
+                Err(checksum.compute_like(b"hellO"))
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+/// Decodes (e.g. decompresses) the bytes of a chunk. Used by
+/// [`DecodingReader`](crate::DecodingReader), which calls `decode` once per chunk, on the threads
+/// of a [`ComputePool`](lsio_threadpool::ComputePool). So `decode` may be called concurrently.
+///
+/// Any closure with the same signature as `decode` is a `Codec`.
+pub trait Codec: Send + Sync + 'static {
+    /// Decode `input`, and append the decoded bytes to `out` (which is empty when `decode` is
+    /// called).
+    fn decode(&self, input: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()>;
+}
+
+impl<F> Codec for F
+where
+    F: Fn(&[u8], &mut Vec<u8>) -> anyhow::Result<()> + Send + Sync + 'static,
+{
+    fn decode(&self, input: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        self(input, out)
+    }
+}
+
+/// Decompresses Zstandard frames.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    fn decode(&self, input: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        zstd::stream::copy_decode(input, out)?;
+        Ok(())
+    }
+}
+
+/// Decompresses gzip members. (If the chunk holds several concatenated members then they're all
+/// decompressed, like `gzip -d`.)
+#[cfg(feature = "gzip")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gzip;
+
+#[cfg(feature = "gzip")]
+impl Codec for Gzip {
+    fn decode(&self, input: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        use std::io::Read;
+        flate2::read::MultiGzDecoder::new(input).read_to_end(out)?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, any(feature = "zstd", feature = "gzip")))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let original: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+        let compressed = zstd::encode_all(original.as_slice(), 3).unwrap();
+        let mut out = Vec::new();
+        Zstd.decode(&compressed, &mut out).unwrap();
+        assert_eq!(out, original);
+        assert!(Zstd.decode(b"not zstd", &mut Vec::new()).is_err());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Write;
+        let original: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&original).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut out = Vec::new();
+        Gzip.decode(&compressed, &mut out).unwrap();
+        assert_eq!(out, original);
+        assert!(Gzip.decode(b"not gzip", &mut Vec::new()).is_err());
+    }
+}
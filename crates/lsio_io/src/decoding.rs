@@ -0,0 +1,201 @@
+use std::{
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Arc,
+    thread,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use lsio_aligned_bytes::AlignedBytesMut;
+use lsio_threadpool::ComputePool;
+
+use crate::{Advice, Chunk, Codec, Completion, IoError, Output, Reader};
+
+/// Decodes (e.g. decompresses) each chunk read by the IO backend `R`, using the [`Codec`] `C`.
+///
+/// As each [`Output::Chunk`] arrives from the IO backend, `DecodingReader` spawns a task onto a
+/// [`ComputePool`], which decodes the chunk, and sends the decoded chunk to `DecodingReader`'s own
+/// completion channel (see [`Completion`]). So the IO backend keeps reading whilst earlier chunks
+/// are being decoded. Each decoded chunk keeps the `user_data` and `range` of the chunk that was
+/// read (so `range` is the range of the _encoded_ bytes in the file). If a chunk can't be decoded
+/// then the user receives an [`IoError::Decode`] (which holds the chunk's `user_data`) instead.
+///
+/// All other outputs (including errors) are passed through unchanged. Chunks take a while to
+/// decode, so outputs may be received in a different order to the order in which the IO backend
+/// sent them. For example, the [`Output::FileComplete`] for a file may arrive before the file's
+/// last decoded chunk.
+///
+/// `DecodingReader` implements the required methods of [`Reader`] by forwarding them to the IO
+/// backend. Other operations (e.g. writes) can be submitted via [`DecodingReader::inner_mut`].
+///
+/// Outputs are moved from the IO backend's completion channel by a thread which `DecodingReader`
+/// starts, as soon as they arrive. So the IO backend's completion channel never fills up, which
+/// means that any backpressure applied by the IO backend (e.g. `lsio_uring`'s
+/// `output_high_water_mark`) no longer applies. The thread stops when the IO backend is dropped.
+#[derive(Debug)]
+pub struct DecodingReader<R, C> {
+    inner: R,
+    codec: Arc<C>,
+    output_rx: Receiver<Result<Output, IoError>>,
+}
+
+impl<R, C> DecodingReader<R, C>
+where
+    R: Completion,
+    C: Codec,
+{
+    /// Decode the chunks read by `inner` on the threads of `compute_pool`. `compute_pool` can be
+    /// shared with other users (e.g. other `DecodingReader`s), but should be separate from the IO
+    /// backend's threads. See [`ComputePool`].
+    pub fn new(inner: R, codec: C, compute_pool: Arc<ComputePool>) -> Self {
+        let codec = Arc::new(codec);
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        let inner_rx = inner.completion().clone();
+        let codec_for_thread = Arc::clone(&codec);
+        thread::Builder::new()
+            .name("lsio_decoding".to_string())
+            .spawn(move || forward_outputs(inner_rx, output_tx, codec_for_thread, compute_pool))
+            .expect("Failed to spawn the DecodingReader's thread");
+        Self {
+            inner,
+            codec,
+            output_rx,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+}
+
+impl<R, C> Completion for DecodingReader<R, C> {
+    fn completion(&self) -> &Receiver<Result<Output, IoError>> {
+        &self.output_rx
+    }
+}
+
+impl<R, C> Reader for DecodingReader<R, C>
+where
+    R: Reader,
+{
+    fn get_ranges(
+        &mut self,
+        location: &Path,
+        ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        self.inner.get_ranges(location, ranges, user_data)
+    }
+
+    fn get_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &Path,
+        ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .get_ranges_in_group(group_id, location, ranges, user_data)
+    }
+
+    /// The decoded chunks are written into new buffers. `buffers` only hold the encoded bytes.
+    fn get_ranges_into(
+        &mut self,
+        location: &Path,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<AlignedBytesMut>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        self.inner
+            .get_ranges_into(location, ranges, buffers, user_data)
+    }
+
+    fn exists(&mut self, location: &Path, user_data: u64) -> anyhow::Result<()> {
+        self.inner.exists(location, user_data)
+    }
+
+    fn metadata(&mut self, location: &Path, user_data: u64) -> anyhow::Result<()> {
+        self.inner.metadata(location, user_data)
+    }
+
+    fn advise(
+        &mut self,
+        location: &Path,
+        range: Range<isize>,
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> anyhow::Result<()> {
+        self.inner.advise(location, range, advice, user_data)
+    }
+}
+
+/// Runs on the `DecodingReader`'s thread until the IO backend is dropped (or until the
+/// `DecodingReader` is dropped, and the next output arrives).
+fn forward_outputs<C: Codec>(
+    inner_rx: Receiver<Result<Output, IoError>>,
+    output_tx: Sender<Result<Output, IoError>>,
+    codec: Arc<C>,
+    compute_pool: Arc<ComputePool>,
+) {
+    for output in inner_rx.iter() {
+        match output {
+            Ok(Output::Chunk(chunk)) => {
+                let output_tx = output_tx.clone();
+                let codec = Arc::clone(&codec);
+                // The decoded chunk is sent by the task, so we don't need the `Task` handle.
+                compute_pool.spawn(move || {
+                    // The user may have dropped the `DecodingReader`.
+                    let _ = output_tx.send(decode_chunk(codec.as_ref(), chunk));
+                });
+            }
+            output => {
+                if output_tx.send(output).is_err() {
+                    // The `DecodingReader` has been dropped.
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn decode_chunk<C: Codec>(codec: &C, chunk: Chunk) -> Result<Output, IoError> {
+    let Chunk {
+        buffer,
+        user_data,
+        range,
+    } = chunk;
+    let decode_error = |message: String| IoError::Decode {
+        user_data,
+        range: range.clone(),
+        message,
+    };
+    let mut decoded = Vec::new();
+    // Catch panics, so that a buggy codec produces an error (instead of a chunk which never
+    // arrives).
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        codec.decode(buffer.as_slice(), &mut decoded)
+    })) {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => return Err(decode_error(format!("{err:#}"))),
+        Err(_) => return Err(decode_error("The codec panicked.".to_string())),
+    }
+    // A chunk can't be empty, because `AlignedBytes` can't be empty.
+    let buffer = AlignedBytesMut::from_vec(decoded)
+        .map_err(|_| decode_error("The chunk decoded to zero bytes.".to_string()))?
+        .freeze()
+        .expect("Nothing else can view the memory of a new buffer");
+    Ok(Output::Chunk(Chunk {
+        buffer,
+        user_data,
+        range,
+    }))
+}
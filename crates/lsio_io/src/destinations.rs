@@ -0,0 +1,27 @@
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut};
+
+/// Freeze the `buffers` passed to [`Reader::get_ranges_into`](crate::Reader::get_ranges_into),
+/// so that IO backends can read into them. Each returned `AlignedBytes` views the whole of its
+/// underlying buffer.
+///
+/// Returns an error if any buffer shares its underlying memory with another `AlignedBytesMut`.
+pub fn freeze_destinations(buffers: Vec<AlignedBytesMut>) -> anyhow::Result<Vec<AlignedBytes>> {
+    buffers
+        .into_iter()
+        .enumerate()
+        .map(|(i, buffer)| match buffer.freeze() {
+            Ok(mut buffer) => {
+                // We're the only view of the underlying buffer, so we can use all of it.
+                buffer.reset_slice();
+                Ok(buffer)
+            }
+            // If we allowed this then we could race with the other views of this memory (e.g.
+            // a read which is still in flight).
+            Err(_) => Err(anyhow::format_err!(
+                "buffers[{i}] shares its underlying memory with another AlignedBytesMut \
+                    (which may still be in use, e.g. by a read which is still in flight). \
+                    Each buffer must be the only view of its underlying memory."
+            )),
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+use crate::{Completion, IoError, Lister, Output, Reader};
+
+/// The default maximum number of files that [`GetDirectory`] reads concurrently. Well below the
+/// default `ulimit -n` of 1,024 on most Linux distributions.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 256;
+
+/// Reads every file in a directory tree. Create a `GetDirectory` by calling
+/// [`Lister::get_directory`].
+///
+/// `GetDirectory` is an iterator over the outputs of the IO backend. It lists `root` (and,
+/// recursively, each subdirectory) using [`Lister::list`], and reads the entirety of each file it
+/// finds using [`Reader::get_ranges`]. The `user_data` of each file's [`Chunk`](crate::Chunk) is
+/// `user_data_fn(relative_path)`, where `relative_path` is the path of the file relative to
+/// `root`. The iterator yields exactly one `Chunk` or error per file (plus any
+/// [`Output::FileComplete`]s, if the IO backend has been configured to emit them, and one
+/// [`IoError::ReadDir`] per directory which can't be listed). The `Output::Listing`s are consumed
+/// by `GetDirectory`. The iterator ends when every file has been read.
+///
+/// # Throttling open file descriptors
+/// Each file is opened when its read is submitted, and closed once its read has completed. To
+/// avoid exceeding the process's limit on open file descriptors (`ulimit -n`) when reading
+/// thousands of small files (e.g. an unsharded Zarr array), `GetDirectory` only submits another
+/// file when one of the files in flight has produced its `Chunk` (or error). So at most
+/// `max_open_files` reads are in flight at once. (The IO backend closes each file shortly after
+/// sending its chunk, so the number of open file descriptors may briefly exceed
+/// `max_open_files` by a few descriptors.) Set the limit with
+/// [`GetDirectory::with_max_open_files`]. Defaults to [`DEFAULT_MAX_OPEN_FILES`].
+///
+/// No other operations should be in flight on the IO backend while iterating over a
+/// `GetDirectory`, because `GetDirectory` can't tell their outputs apart from its own.
+pub struct GetDirectory<'a, R, F> {
+    reader: &'a mut R,
+    root: PathBuf,
+    user_data_fn: F,
+    max_open_files: usize,
+    /// Files which have been found by a listing, but which haven't been submitted yet.
+    files_to_read: VecDeque<PathBuf>,
+    n_listings_in_flight: usize,
+    n_files_in_flight: usize,
+}
+
+impl<'a, R, F> GetDirectory<'a, R, F>
+where
+    R: Reader + Lister + Completion,
+    F: FnMut(&Path) -> u64,
+{
+    pub(crate) fn new(reader: &'a mut R, root: &Path, user_data_fn: F) -> anyhow::Result<Self> {
+        reader.list(root)?;
+        Ok(Self {
+            reader,
+            root: root.to_path_buf(),
+            user_data_fn,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            files_to_read: VecDeque::new(),
+            n_listings_in_flight: 1,
+            n_files_in_flight: 0,
+        })
+    }
+
+    /// The maximum number of files which will be read concurrently.
+    ///
+    /// # Panics
+    /// If `max_open_files` is zero.
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        assert!(max_open_files > 0, "max_open_files must be at least 1");
+        self.max_open_files = max_open_files;
+        self
+    }
+
+    /// Submit files from `files_to_read` until `max_open_files` files are in flight.
+    // `0..-1` means "the entire file", so it isn't empty.
+    #[allow(clippy::reversed_empty_ranges, clippy::single_range_in_vec_init)]
+    fn submit_files(&mut self) -> Result<(), IoError> {
+        while self.n_files_in_flight < self.max_open_files {
+            let Some(location) = self.files_to_read.pop_front() else {
+                break;
+            };
+            let relative_path = location.strip_prefix(&self.root).unwrap_or(&location);
+            let user_data = (self.user_data_fn)(relative_path);
+            self.reader
+                .get_ranges(&location, vec![0..-1], vec![user_data])
+                .map_err(|err| IoError::Internal {
+                    message: format!("Failed to submit a read of {location:?}: {err:#}"),
+                })?;
+            self.n_files_in_flight += 1;
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.n_listings_in_flight == 0
+            && self.n_files_in_flight == 0
+            && self.files_to_read.is_empty()
+    }
+}
+
+impl<R, F> Iterator for GetDirectory<'_, R, F>
+where
+    R: Reader + Lister + Completion,
+    F: FnMut(&Path) -> u64,
+{
+    type Item = Result<Output, IoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(err) = self.submit_files() {
+                return Some(Err(err));
+            }
+            if self.is_finished() {
+                return None;
+            }
+            // `recv` only fails if the IO backend has stopped.
+            let output = self.reader.completion().recv().ok()?;
+            match output {
+                Ok(Output::Listing(listing)) => {
+                    self.n_listings_in_flight = self.n_listings_in_flight.saturating_sub(1);
+                    for entry in listing {
+                        if entry.is_dir {
+                            if let Err(err) = self.reader.list(&entry.path) {
+                                return Some(Err(IoError::Internal {
+                                    message: format!(
+                                        "Failed to submit a listing of {:?}: {err:#}",
+                                        entry.path
+                                    ),
+                                }));
+                            }
+                            self.n_listings_in_flight += 1;
+                        } else {
+                            self.files_to_read.push_back(entry.path);
+                        }
+                    }
+                }
+                Err(err @ IoError::ReadDir { .. }) => {
+                    self.n_listings_in_flight = self.n_listings_in_flight.saturating_sub(1);
+                    return Some(Err(err));
+                }
+                output @ (Ok(Output::Chunk(_)) | Err(_)) => {
+                    self.n_files_in_flight = self.n_files_in_flight.saturating_sub(1);
+                    return Some(output);
+                }
+                output => return Some(output),
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use snafu::Snafu;
+
+/// The errors which IO backends send to the user via the [`Completion`](crate::Completion)
+/// channel.
+///
+/// Each variant has machine-readable fields (e.g. the path and byte range). The `details` fields
+/// hold backend-specific information which is only intended for humans.
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub))]
+pub enum IoError {
+    /// The file (or directory) at `path` does not exist. `user_data` identifies the byte range
+    /// which failed, if the error is specific to one byte range.
+    #[snafu(display("{} {details}", nix::errno::Errno::ENOENT))]
+    NotFound {
+        path: PathBuf,
+        user_data: Option<u64>,
+        details: String,
+    },
+
+    /// Reached the end of the file at `path` after reading only `got` of the `wanted` bytes of
+    /// `range`. (For example, because the file was truncated after it was opened.) `file_size` is
+    /// the size of the file (in bytes) which the IO backend used to resolve `range`, if known.
+    /// It's stale if the file changed size after its size was read.
+    #[snafu(display(
+        "Reached the end of the file with {} bytes of the requested range still unread. (Was the \
+            file truncated? The file was {} bytes when its size was read.) {details}",
+        wanted - got,
+        file_size.map_or_else(|| "an unknown number of".to_string(), |size| size.to_string())
+    ))]
+    ShortRead {
+        path: PathBuf,
+        range: Range<isize>,
+        got: usize,
+        wanted: usize,
+        file_size: Option<u64>,
+        details: String,
+    },
+
+    /// The operating system reported `errno` when running `opcode`. `user_data` identifies the
+    /// byte range which failed, if the error is specific to one byte range.
+    #[snafu(display("{errno} {details}"))]
+    Nix {
+        errno: nix::errno::Errno,
+        opcode: &'static str,
+        path: Option<PathBuf>,
+        range: Option<Range<isize>>,
+        user_data: Option<u64>,
+        details: String,
+    },
+
+    /// The operation on `path` (and `range`, if known) was cancelled because it didn't complete
+    /// within `timeout`.
+    #[snafu(display("Timed out after {timeout:?} {details}"))]
+    TimedOut {
+        path: PathBuf,
+        range: Option<Range<isize>>,
+        timeout: Duration,
+        details: String,
+    },
+
+    /// The user requested a byte range which can't be processed (e.g. because the range isn't the
+    /// same length as its buffer).
+    #[snafu(display("{message}"))]
+    InvalidRange {
+        path: PathBuf,
+        range: Range<isize>,
+        message: String,
+    },
+
+    /// The bytes read from `range` of `path` don't match the checksum which the user provided for
+    /// this range. `actual` is the checksum of the bytes which were read. `user_data` identifies
+    /// the byte range.
+    #[cfg(feature = "checksum")]
+    #[snafu(display(
+        "Checksum mismatch for range {range:?} of {path:?}: expected {expected:?}, got \
+            {actual:?}. {details}"
+    ))]
+    ChecksumMismatch {
+        path: PathBuf,
+        range: Range<isize>,
+        user_data: u64,
+        expected: crate::Checksum,
+        actual: crate::Checksum,
+        details: String,
+    },
+
+    /// The [`Codec`](crate::Codec) of a [`DecodingReader`](crate::DecodingReader) failed to decode
+    /// the chunk identified by `user_data`, which was read from `range` of its file (if known).
+    #[snafu(display(
+        "Failed to decode the chunk with user_data {user_data} (read from byte range {range:?}): \
+            {message}"
+    ))]
+    Decode {
+        user_data: u64,
+        range: Option<Range<usize>>,
+        message: String,
+    },
+
+    /// Failed to list the directory at `path`.
+    #[snafu(display("Failed to list {path:?}"))]
+    ReadDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// A bug in the IO backend.
+    #[snafu(display("{message}"))]
+    Internal { message: String },
+}
+
+impl IoError {
+    /// The `user_data` of the byte range which failed, if this error is specific to one byte
+    /// range.
+    pub fn user_data(&self) -> Option<u64> {
+        match self {
+            IoError::NotFound { user_data, .. } | IoError::Nix { user_data, .. } => *user_data,
+            #[cfg(feature = "checksum")]
+            IoError::ChecksumMismatch { user_data, .. } => Some(*user_data),
+            IoError::Decode { user_data, .. } => Some(*user_data),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this error (which doesn't carry a `user_data`) means that reading `range`
+    /// from `location` has failed. Errors which don't say which file failed return false.
+    pub fn describes_range(&self, location: &Path, range: &Range<isize>) -> bool {
+        let (path, failed_range) = match self {
+            IoError::NotFound { path, .. } => (path, None),
+            IoError::ShortRead { path, range, .. } | IoError::InvalidRange { path, range, .. } => {
+                (path, Some(range))
+            }
+            IoError::Nix {
+                path: Some(path),
+                range,
+                ..
+            }
+            | IoError::TimedOut { path, range, .. } => (path, range.as_ref()),
+            _ => return false,
+        };
+        path == location
+            && failed_range.is_none_or(|failed_range| range_contains(failed_range, range))
+    }
+}
+
+/// Returns true if `inner` is the same as `outer`, or if both ranges are absolute (non-negative)
+/// and `inner` is within `outer`. (A range that failed may have been merged with its neighbours.)
+fn range_contains(outer: &Range<isize>, inner: &Range<isize>) -> bool {
+    outer == inner
+        || (outer.start >= 0
+            && outer.end >= 0
+            && inner.start >= 0
+            && inner.end >= 0
+            && outer.start <= inner.start
+            && inner.end <= outer.end)
+}
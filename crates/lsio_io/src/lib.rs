@@ -1,19 +1,113 @@
 #![doc = include_str!("../README.md")]
 
-use lsio_aligned_bytes::AlignedBytes;
-use std::ops::Range;
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
-// TODO: Consider how to *group* instructions, such that LSIO guarantees that all operations in
-// group _n_ will be completed before any operations in group _n+1_ are started. See:
-// https://github.com/JackKelly/light-speed-io/issues/68
+mod async_reader;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod codec;
+mod decoding;
+mod destinations;
+mod directory;
+mod error;
+mod metadata;
+mod range;
+mod read_request;
+pub use async_reader::AsyncReader;
+#[cfg(feature = "checksum")]
+pub use checksum::Checksum;
+pub use codec::Codec;
+#[cfg(feature = "gzip")]
+pub use codec::Gzip;
+#[cfg(feature = "zstd")]
+pub use codec::Zstd;
+pub use decoding::DecodingReader;
+pub use destinations::freeze_destinations;
+pub use directory::{GetDirectory, DEFAULT_MAX_OPEN_FILES};
+pub use error::IoError;
+pub use metadata::MetadataReader;
+pub use range::{
+    check_one_user_data_per_range, fadvise_offset_and_len, resolve_range, try_resolve_range,
+    ByteRange,
+};
+pub use read_request::{check_batch, ReadOp, ReadRequest};
+
+// Re-exported so that users of the `Completion` helpers don't have to depend on
+// `crossbeam_channel`.
+pub use crossbeam_channel::{RecvTimeoutError, TryRecvError};
 
 /// All IO backends must expose their completion queue.
+///
+/// The provided methods are the recommended way to receive outputs, because they don't depend on
+/// the type of the channel. [`Completion::completion`] returns the raw channel, for power users.
 pub trait Completion {
-    fn completion(&self) -> &crossbeam_channel::Receiver<anyhow::Result<Output>>;
+    fn completion(&self) -> &crossbeam_channel::Receiver<Result<Output, IoError>>;
+
+    /// Receive an output if one is ready, without blocking.
+    fn try_recv(&self) -> Result<Result<Output, IoError>, TryRecvError> {
+        self.completion().try_recv()
+    }
+
+    /// Wait up to `timeout` for an output.
+    fn recv_timeout(&self, timeout: Duration) -> Result<Result<Output, IoError>, RecvTimeoutError> {
+        self.completion().recv_timeout(timeout)
+    }
+
+    /// Returns an iterator which blocks waiting for each output. The iterator only ends when the
+    /// IO backend has stopped (and the backend can't stop whilst the iterator borrows it). So use
+    /// [`Iterator::take`] to receive a known number of outputs.
+    fn outputs(&self) -> impl Iterator<Item = anyhow::Result<Output>> + '_
+    where
+        Self: Sized,
+    {
+        self.completion()
+            .iter()
+            .map(|output| output.map_err(anyhow::Error::from))
+    }
+
+    /// Receive up to `n` chunks and errors, waiting up to `timeout` (in total) for them, and
+    /// partition them into `(chunks, errors)`. This suits "read everything, then report every
+    /// failure" workflows: Set `n` to the number of ranges requested (each range produces exactly
+    /// one `Chunk` or error). Returns early (with fewer than `n` items) if `timeout` expires, or if
+    /// the IO backend has stopped. Use a `timeout` of zero to only drain the outputs which are
+    /// already available.
+    ///
+    /// Outputs which are neither chunks nor errors (e.g. [`Output::FileComplete`]) don't count
+    /// towards `n`, and are discarded.
+    fn drain_errors(&self, n: usize, timeout: Duration) -> (Vec<Chunk>, Vec<IoError>) {
+        let deadline = Instant::now() + timeout;
+        let mut chunks = Vec::new();
+        let mut errors = Vec::new();
+        while chunks.len() + errors.len() < n {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.recv_timeout(timeout) {
+                Ok(Ok(Output::Chunk(chunk))) => chunks.push(chunk),
+                Ok(Ok(_)) => (),
+                Ok(Err(err)) => errors.push(err),
+                Err(_) => break,
+            }
+        }
+        (chunks, errors)
+    }
 }
 
 /// Methods for IO backends that can read from IO.
 pub trait Reader {
+    /// Returns a [`ReadRequest`] builder, which can compose a single request for multiple byte
+    /// ranges from multiple files. This is the recommended way to read data. The other methods of
+    /// `Reader` are lower-level building blocks.
+    fn read(&mut self) -> ReadRequest<'_, Self>
+    where
+        Self: Sized,
+    {
+        ReadRequest::new(self)
+    }
+
     /// Submit a GetRanges operation.
     ///
     /// `ranges` specify the byte ranges to read. Negative numbers are relative to the filesize.
@@ -28,11 +122,19 @@ pub trait Reader {
     /// location at which this chunk appears in the merged array.
     ///
     /// # Errors:
-    /// If the user submits a `get_ranges` operation with an invalid filename then
-    /// the user will receive a single `std::io::Error(std::io::ErrorKind::NotFound)` with context
-    /// that describes the filename that failed. If a subset of the `ranges` results in an error
-    /// (e.g. reading beyond end of the file) then the user will receive a mixture of `Ok(Output)`
-    /// and `Err`, where the `Err` will include context such as the filename and byte range.
+    /// Returns an error (without submitting anything) if `ranges` is empty, if `ranges` and
+    /// `user_data` have different lengths, or (for backends which pass paths to the kernel as
+    /// C strings) if `location` contains a NUL byte.
+    ///
+    /// If the file can't be opened (e.g. because the filename is invalid) then the user will
+    /// receive one error per range (e.g. one [`IoError::NotFound`] per range), each of which holds
+    /// that range's `user_data`. So every range produces exactly one `Chunk` or error. If a subset
+    /// of the `ranges` results in an error (e.g. reading beyond end of the file) then the user
+    /// will receive a mixture of `Ok(Output)` and `Err(IoError)`, where the `IoError` will include
+    /// the filename and byte range. A range which doesn't resolve to a non-empty range of the file
+    /// (e.g. `-2000..-1` of a 1,000-byte file) produces an [`IoError::InvalidRange`]. If the file
+    /// shrinks after its size was read, then reads beyond the new end of the file produce an
+    /// [`IoError::ShortRead`], which holds the (stale) file size.
     fn get_ranges(
         &mut self,
         // We take ownership because this function returns immediately. If we used references then
@@ -42,23 +144,514 @@ pub trait Reader {
         ranges: Vec<Range<isize>>,
         user_data: Vec<u64>,
     ) -> anyhow::Result<()>;
+
+    /// Like [`Reader::get_ranges`], except that each range is a [`ByteRange`], which states its
+    /// intent explicitly (e.g. `ByteRange::Last(100)`) instead of using negative numbers.
+    fn get_byte_ranges(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<ByteRange>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        let ranges = ranges.into_iter().map(Range::from).collect();
+        self.get_ranges(location, ranges, user_data)
+    }
+
+    /// Submit a batch of read operations (each of which reads one or more byte ranges from one
+    /// file) together. This lets the IO backend see the whole batch at once (e.g. to submit it as
+    /// a single task), and is equivalent to calling [`Reader::get_ranges`] for each `ReadOp`.
+    ///
+    /// # Errors:
+    /// Returns an error (without submitting anything) if any `ReadOp` would be rejected by
+    /// [`Reader::get_ranges`] (e.g. because it has no ranges).
+    fn submit_batch(&mut self, ops: Vec<ReadOp>) -> anyhow::Result<()> {
+        check_batch(&ops)?;
+        for op in ops {
+            self.get_ranges(&op.location, op.ranges, op.user_data)?;
+        }
+        Ok(())
+    }
+
+    /// Read `range` of `location` into a single contiguous buffer. The user will receive exactly
+    /// one [`Chunk`] (or one error), identified by `user_data`, whose buffer covers the whole of
+    /// `range`. `range` has the same meaning as in [`Reader::get_ranges`].
+    ///
+    /// The IO backend may read `range` using several reads (e.g. because a single read can
+    /// transfer at most about 2 GiB on Linux, or because `range` was merged with neighbouring
+    /// ranges). Each of those reads targets its offset within the same allocation, so the user
+    /// never has to reassemble the pieces.
+    fn get_contiguous(
+        &mut self,
+        location: &std::path::Path,
+        range: Range<isize>,
+        user_data: u64,
+    ) -> anyhow::Result<()> {
+        self.get_ranges(location, vec![range], vec![user_data])
+    }
+
+    /// Read the entirety of each file in `locations`. The `user_data` of each [`Chunk`] is the
+    /// index of its file in `locations`.
+    fn get_whole_files(&mut self, locations: &[PathBuf]) -> anyhow::Result<()> {
+        for (i, location) in locations.iter().enumerate() {
+            self.get_byte_ranges(location, vec![ByteRange::Whole], vec![i as u64])?;
+        }
+        Ok(())
+    }
+
+    /// Read the same byte `range` from each file in `locations`. For example, read the index at
+    /// the end of each shard of a sharded Zarr array. `range` has the same meaning as in
+    /// [`Reader::get_ranges`] (so negative offsets are relative to the size of each file).
+    /// `user_data[i]` identifies the `Chunk` (or error) from `locations[i]`, so the user will
+    /// receive exactly `locations.len()` chunks and errors.
+    ///
+    /// This submits one operation per file, so the IO backend's limit on the number of open files
+    /// (if any) applies as usual.
+    ///
+    /// # Errors:
+    /// Returns an error (without submitting anything) if `locations` and `user_data` have
+    /// different lengths.
+    fn get_range_from_many(
+        &mut self,
+        locations: &[PathBuf],
+        range: Range<isize>,
+        user_data: &[u64],
+    ) -> anyhow::Result<()> {
+        if locations.len() != user_data.len() {
+            return Err(anyhow::format_err!(
+                "{} user_data instances were provided for {} locations. There must be one \
+                    user_data instance per location.",
+                user_data.len(),
+                locations.len()
+            ));
+        }
+        for (location, &user_data) in locations.iter().zip(user_data) {
+            self.get_ranges(location, vec![range.clone()], vec![user_data])?;
+        }
+        Ok(())
+    }
+
+    /// Submit a GetRanges operation which belongs to the group `group_id`.
+    ///
+    /// The IO backend guarantees that every operation in group _n_ will have completed (i.e. the
+    /// user will have received its `Output` or error) before any operation in group _n+1_ is
+    /// started. Operations within a group may run concurrently, and may complete in any order.
+    /// Operations which aren't in a group (e.g. those submitted by [`Reader::get_ranges`]) are
+    /// not affected by groups. See issue #68.
+    ///
+    /// Groups should be submitted in ascending order of `group_id`: A group only waits for groups
+    /// with lower IDs which have already been submitted.
+    ///
+    /// `location`, `ranges`, and `user_data` have the same meaning as in [`Reader::get_ranges`].
+    fn get_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()>;
+
+    /// Submit a GetRanges operation which reads into buffers provided by the caller, instead of
+    /// into buffers allocated by the IO backend. For example, `buffers` could be views into a
+    /// region of host memory which has been pinned for fast transfers to a GPU. The
+    /// [`Chunk::buffer`] returned for each range will be a view into the corresponding buffer.
+    ///
+    /// `ranges` and `user_data` have the same meaning as in [`Reader::get_ranges`]. One buffer per
+    /// range. Each buffer must be at least as long as its range. The IO backend may read more
+    /// bytes than requested (up to the length of the buffer) but the returned `Chunk` will only
+    /// view the requested range.
+    ///
+    /// IO backends which use `O_DIRECT` may also require that each range starts at an aligned
+    /// offset into the file, and that each buffer is aligned (in both its address and its
+    /// length).
+    ///
+    /// To scatter the ranges directly into one large array owned by the user (e.g. a numpy
+    /// array), wrap the array in an `ExternalMemory`, and create one view per range using the
+    /// `unsafe` function `AlignedBytesMut::from_external_memory` (both of which require the
+    /// `external-memory` feature of `lsio_aligned_bytes`). The caller is responsible for ensuring
+    /// that the views don't overlap (which is checked in debug builds), and that the array
+    /// outlives the views.
+    ///
+    /// # Errors:
+    /// Returns an error if any buffer shares its underlying memory with any other
+    /// `AlignedBytesMut` (e.g. if the buffer was created by [`AlignedBytesMut::split_to`] and the
+    /// other half is still alive). IO backends which use `O_DIRECT` may also return an error if
+    /// any buffer isn't aligned.
+    fn get_ranges_into(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<AlignedBytesMut>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()>;
+
+    /// Like [`Reader::get_ranges_into`], except that each buffer is a `Vec<u8>` (e.g. from the
+    /// caller's own memory pool), so the IO backend doesn't allocate any buffers. Each `Vec` is
+    /// wrapped by [`AlignedBytesMut::from_vec`] (so the `Vec`'s length, not its capacity, is the
+    /// length of its buffer), and the [`Chunk::buffer`] returned for each range is a view into
+    /// that `Vec`'s memory. The `Vec`'s memory is freed when the `Chunk` is dropped.
+    ///
+    /// A `Vec<u8>` is rarely aligned well enough for `O_DIRECT`. So IO backends which use
+    /// `O_DIRECT` will usually reject these buffers (in which case, disable `O_DIRECT`).
+    ///
+    /// # Errors:
+    /// Returns an error if any `Vec` is empty, or if the IO backend rejects a buffer (see
+    /// [`Reader::get_ranges_into`]).
+    fn get_ranges_into_vecs(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<Vec<u8>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        let buffers = buffers
+            .into_iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                AlignedBytesMut::from_vec(buffer)
+                    .map_err(|err| anyhow::format_err!("buffers[{i}]: {err}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        self.get_ranges_into(location, ranges, buffers, user_data)
+    }
+
+    /// Submit an Exists operation, which checks whether `location` exists (without opening it).
+    /// For example, a missing Zarr chunk means that the chunk is filled with the fill value.
+    ///
+    /// The user will receive a single [`Output::Exists`], identified by `user_data`, which also
+    /// holds the size of the file.
+    ///
+    /// # Errors:
+    /// A missing file isn't an error: The user will receive `Output::Exists` with `exists: false`.
+    /// Other failures (e.g. permission denied) are reported as an [`IoError`] which holds
+    /// `user_data`.
+    fn exists(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()>;
+
+    /// Submit a Metadata operation, which gets the size, modification time and mode of
+    /// `location` (without opening it).
+    ///
+    /// The user will receive a single [`Output::Metadata`], identified by `user_data`.
+    ///
+    /// # Errors:
+    /// Failures (including a missing file) are reported as an [`IoError`] which holds `user_data`.
+    fn metadata(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()>;
+
+    /// Submit an Advise operation, which tells the kernel how `range` of `location` will be
+    /// accessed, equivalent to `posix_fadvise`. For example, call `advise` with
+    /// [`Advice::WillNeed`] just before a big sequential scan, so the kernel starts reading the
+    /// data into the page cache.
+    ///
+    /// `range.start` must be non-negative. `range.end` must be non-negative, or `-1` (meaning the
+    /// end of the file).
+    ///
+    /// This is only a hint, so it's fire-and-forget: If `user_data` is `None` then the user won't
+    /// receive any output (not even if the advice fails). If `user_data` is `Some` then the user
+    /// will receive a single [`Output::Advised`] (or an [`IoError`] which holds `user_data`).
+    ///
+    /// The advice is about the page cache, so it has no effect on reads which bypass the page
+    /// cache (i.e. reads with `O_DIRECT`, which is the default for `lsio_uring`).
+    ///
+    /// # Errors:
+    /// Returns an error if `range` is invalid.
+    fn advise(
+        &mut self,
+        location: &std::path::Path,
+        range: Range<isize>,
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> anyhow::Result<()>;
+}
+
+/// Methods for IO backends that can write to IO.
+pub trait Writer {
+    /// Submit a PutRanges operation.
+    ///
+    /// Writes each of `buffers` into the corresponding byte range of `location`. `location` will
+    /// be created if it does not already exist. `ranges` follow the same conventions (including
+    /// negative numbers) as [`Reader::get_ranges`]. Each range must be the same length as its
+    /// buffer.
+    ///
+    /// `user_data` is used to identify each range. One `user_data` instance per range.
+    ///
+    /// The user will receive one [`Output::BytesWritten`] per range.
+    fn put_ranges(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()>;
+
+    /// Like [`Writer::put_ranges`], except that the operation belongs to the group `group_id`.
+    /// Reads and writes share the same groups, so every operation in group _n_ will have
+    /// completed before any operation in group _n+1_ is started. See
+    /// [`Reader::get_ranges_in_group`].
+    fn put_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()>;
+
+    /// Submit a Sync operation, which flushes the data of `location` (and, depending on `mode`,
+    /// its metadata) from the page cache to storage, so that the data survives a crash or a power
+    /// failure. The user will receive a single [`Output::Synced`] (or an [`IoError`] which holds
+    /// `user_data`) once the data is durable.
+    ///
+    /// # Ordering
+    /// Operations run concurrently, so a sync is *not* ordered after earlier calls to
+    /// [`Writer::put_ranges`] for the same file: A write which is still in flight when the sync
+    /// runs may not be durable. A sync only covers the writes whose [`Output::BytesWritten`] the
+    /// user had received before calling `sync`. To sync a batch of writes without waiting for
+    /// their outputs, submit the writes using [`Writer::put_ranges_in_group`], and then submit the
+    /// sync in a later group using [`Writer::sync_in_group`].
+    fn sync(
+        &mut self,
+        location: &std::path::Path,
+        mode: SyncMode,
+        user_data: u64,
+    ) -> anyhow::Result<()>;
+
+    /// Like [`Writer::sync`], except that the operation belongs to the group `group_id`. So the
+    /// sync starts only after every operation in the earlier groups (such as writes submitted by
+    /// [`Writer::put_ranges_in_group`]) has completed. See [`Reader::get_ranges_in_group`].
+    fn sync_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        mode: SyncMode,
+        user_data: u64,
+    ) -> anyhow::Result<()>;
+}
+
+/// Methods for IO backends that can list the contents of directories.
+pub trait Lister {
+    /// Submit a List operation.
+    ///
+    /// Lists the immediate contents of the directory `prefix` (i.e. this is not recursive). The
+    /// user will receive a single [`Output::Listing`], with one [`FileMetadata`] per entry, in no
+    /// particular order.
+    fn list(&mut self, prefix: &std::path::Path) -> anyhow::Result<()>;
+
+    /// Recursively list `root`, and read the entirety of every file in the directory tree. The
+    /// `user_data` of each file's [`Chunk`] is `user_data_fn(relative_path)`, where
+    /// `relative_path` is the path of the file relative to `root`.
+    ///
+    /// Returns a [`GetDirectory`] iterator, which yields one `Chunk` (or error) per file. The
+    /// number of files read concurrently is capped, to avoid exceeding the limit on open file
+    /// descriptors. See [`GetDirectory`] for details.
+    fn get_directory<F>(
+        &mut self,
+        root: &Path,
+        user_data_fn: F,
+    ) -> anyhow::Result<GetDirectory<'_, Self, F>>
+    where
+        Self: Reader + Completion + Sized,
+        F: FnMut(&Path) -> u64,
+    {
+        GetDirectory::new(self, root, user_data_fn)
+    }
+}
+
+/// Methods for IO backends that can copy byte ranges from one file into another file.
+pub trait Copier {
+    /// Submit a CopyRanges operation.
+    ///
+    /// Reads each of the `src_ranges` from `src`, and writes those bytes into the corresponding
+    /// `dst_ranges` of `dst`. `dst` will be created if it does not already exist. The bytes are
+    /// written straight from the IO backend's buffers, so they never pass through the completion
+    /// queue. `src_ranges` and `dst_ranges` follow the same conventions (including negative
+    /// numbers) as [`Reader::get_ranges`]. Each `dst_range` must be the same length as its
+    /// corresponding `src_range`.
+    ///
+    /// `user_data` is used to identify each range. One `user_data` instance per range.
+    ///
+    /// The user will receive one [`Output::BytesWritten`] per range. A range which doesn't resolve
+    /// to a non-empty range of its file produces an [`IoError::InvalidRange`] instead.
+    ///
+    /// # Errors:
+    /// Returns an error (without submitting anything) if `src_ranges` is empty, or if
+    /// `src_ranges`, `dst_ranges` and `user_data` have different lengths.
+    fn copy_ranges(
+        &mut self,
+        src: &std::path::Path,
+        src_ranges: Vec<Range<isize>>,
+        dst: &std::path::Path,
+        dst_ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()>;
 }
 
 /// `Chunk` is used throughout the LSIO stack. It is passed from the I/O layer to
 /// the compute layer, and to the application layer. (To be more precise: `Result<Chunk>` is usually
 /// what is passed around!).
+///
+/// IO backends identify chunks with a `u64` `user_data`. Use [`MetadataReader`] to attach
+/// arbitrary metadata (of type `M`) to each chunk instead.
+///
+/// The fields of `Chunk` are public, but more fields may be added in future. So prefer the
+/// accessors ([`Chunk::buffer`], [`Chunk::user_data`] and [`Chunk::range`]) and
+/// [`Chunk::into_parts`], which won't break when new fields are added.
 #[derive(Debug)]
-pub struct Chunk {
+pub struct Chunk<M = u64> {
     pub buffer: AlignedBytes,
     /// `user_data` can be used to uniquely identify each chunk, for example by providing an index
     /// into an array that provides more information about each chunk.
-    pub user_data: u64,
+    pub user_data: M,
+    /// The byte range that `buffer` was read from, as absolute offsets into the file (i.e. with
+    /// any negative offsets resolved). `None` if the IO backend doesn't know the byte range.
+    pub range: Option<Range<usize>>,
+}
+
+/// Metadata about a single file or directory. Returned by [`Reader::metadata`], and by
+/// [`Lister::list`] (one per entry in the directory).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub path: PathBuf,
+    /// The size of the file, in bytes.
+    pub size: u64,
+    pub is_dir: bool,
+    /// The time that the contents of the file were last modified, or `None` if the filesystem
+    /// doesn't record it.
+    pub mtime: Option<SystemTime>,
+    /// The file type and permission bits (`st_mode`). See [`FileMetadata::permissions`].
+    pub mode: u32,
+}
+
+impl FileMetadata {
+    /// The permission bits of [`FileMetadata::mode`] (including the setuid, setgid and sticky
+    /// bits). For example, `0o644`.
+    pub fn permissions(&self) -> u32 {
+        self.mode & 0o7777
+    }
+}
+
+/// How a byte range of a file will be accessed. Passed to [`Reader::advise`]. Each variant maps
+/// to one of the `POSIX_FADV_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// `POSIX_FADV_WILLNEED`: The range will be accessed soon, so the kernel starts reading it into
+    /// the page cache.
+    WillNeed,
+    /// `POSIX_FADV_SEQUENTIAL`: The range will be read sequentially, so the kernel doubles its
+    /// readahead window. Note that Linux only applies this to the file descriptor which was
+    /// advised, and IO backends open a new file descriptor for each operation. So prefer
+    /// `WillNeed` to prefetch data before a scan.
+    Sequential,
+    /// `POSIX_FADV_DONTNEED`: The range won't be accessed soon, so the kernel can evict it from the
+    /// page cache.
+    DontNeed,
+}
+
+/// What [`Writer::sync`] flushes to storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// `fsync`: Flush the file's data and all of its metadata (like [`std::fs::File::sync_all`]).
+    All,
+    /// `fdatasync`: Flush the file's data, and only the metadata which is needed to read the data
+    /// back (such as the file size, but not the modification time). This is usually faster than
+    /// `All` (like [`std::fs::File::sync_data`]).
+    Data,
+}
+
+impl From<Advice> for nix::fcntl::PosixFadviseAdvice {
+    fn from(advice: Advice) -> Self {
+        match advice {
+            Advice::WillNeed => Self::POSIX_FADV_WILLNEED,
+            Advice::Sequential => Self::POSIX_FADV_SEQUENTIAL,
+            Advice::DontNeed => Self::POSIX_FADV_DONTNEED,
+        }
+    }
+}
+
+impl<M> Chunk<M> {
+    /// The bytes that were read.
+    pub fn buffer(&self) -> &AlignedBytes {
+        &self.buffer
+    }
+
+    /// The `user_data` which identifies this chunk.
+    pub fn user_data(&self) -> &M {
+        &self.user_data
+    }
+
+    /// The byte range that the buffer was read from, as absolute offsets into the file. `None` if
+    /// the IO backend doesn't know the byte range.
+    pub fn range(&self) -> Option<Range<usize>> {
+        self.range.clone()
+    }
+
+    /// Consume the chunk, and return its buffer and its `user_data`.
+    ///
+    /// ```
+    /// # use lsio_aligned_bytes::AlignedBytesMut;
+    /// # use lsio_io::Chunk;
+    /// let buffer = AlignedBytesMut::zeroed(8, 8).freeze().unwrap();
+    /// let chunk = Chunk { buffer, user_data: 42, range: Some(0..8) };
+    /// let (buffer, user_data) = chunk.into_parts();
+    /// assert_eq!((buffer.len(), user_data), (8, 42));
+    /// ```
+    pub fn into_parts(self) -> (AlignedBytes, M) {
+        (self.buffer, self.user_data)
+    }
+
+    /// Replace the `user_data` of this chunk with `f(user_data)`.
+    pub fn map_user_data<N>(self, f: impl FnOnce(M) -> N) -> Chunk<N> {
+        Chunk {
+            buffer: self.buffer,
+            user_data: f(self.user_data),
+            range: self.range,
+        }
+    }
 }
 
-/// Holds the data that is output from each IO operation.
+/// Holds the data that is output from each IO operation. `M` is the type of each [`Chunk`]'s
+/// `user_data`.
 #[derive(Debug)]
-pub enum Output {
-    Chunk(Chunk),
-    // Other variants could be:
-    // `BytesWritten`, `Listing(Vec<FileMetadata>)`, etc.
+pub enum Output<M = u64> {
+    Chunk(Chunk<M>),
+    /// `nbytes` were written for the operation identified by `user_data`.
+    BytesWritten {
+        user_data: u64,
+        nbytes: usize,
+    },
+    /// The file passed to [`Writer::sync`] (with this `user_data`) has been flushed to storage.
+    Synced {
+        user_data: u64,
+    },
+    /// The contents of a directory.
+    Listing(Vec<FileMetadata>),
+    /// The result of [`Reader::exists`]. `size` is the size of the file in bytes, or `None` if
+    /// the file doesn't exist.
+    Exists {
+        user_data: u64,
+        exists: bool,
+        size: Option<u64>,
+    },
+    /// The result of [`Reader::metadata`].
+    Metadata {
+        user_data: u64,
+        metadata: FileMetadata,
+    },
+    /// The advice submitted by [`Reader::advise`] (with `Some(user_data)`) has been given to the
+    /// kernel.
+    Advised {
+        user_data: u64,
+    },
+    /// The stream identified by `user_data` has ended, after `nbytes` bytes were read. Emitted by
+    /// IO backends which can read streams (such as pipes and sockets) whose size isn't known in
+    /// advance.
+    StreamEnd {
+        user_data: u64,
+        nbytes: usize,
+    },
+    /// Every byte range requested from `location` (by a single call to `get_ranges` or one of its
+    /// friends) has been read (or has failed), so the caller can free any state it holds for this
+    /// file. `user_data_of_last` identifies the last byte range to finish. Only emitted if the IO
+    /// backend has been configured to emit it.
+    FileComplete {
+        location: PathBuf,
+        user_data_of_last: u64,
+    },
 }
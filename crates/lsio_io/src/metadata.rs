@@ -0,0 +1,171 @@
+use std::{collections::HashMap, ops::Range, path::Path, sync::Arc, time::Duration};
+
+use crate::{
+    check_one_user_data_per_range, Completion, IoError, Output, Reader, RecvTimeoutError,
+    TryRecvError,
+};
+
+/// Attaches arbitrary metadata (of type `M`) to each byte range read by the IO backend `R`.
+///
+/// IO backends identify each byte range with a single `u64` `user_data`, which is too small when
+/// each chunk needs richer routing information (e.g. an array index, a destination offset, and a
+/// codec ID). `MetadataReader` gives each range submitted via [`MetadataReader::get_ranges_with`]
+/// a unique `user_data`, and replaces the `user_data` of each [`Chunk`](crate::Chunk) with that
+/// range's metadata when the chunk is received (via [`MetadataReader::recv_timeout`] or
+/// [`MetadataReader::try_recv`]).
+///
+/// Every read must be submitted via `get_ranges_with`. Other operations (e.g. writes) can be
+/// submitted via [`MetadataReader::inner_mut`], and their outputs are passed through unchanged.
+///
+/// If an error identifies the range that failed (by its `user_data`, or by its file and possibly
+/// its byte range), then the metadata of the matching ranges is dropped (because those ranges will
+/// never produce a chunk).
+#[derive(Debug)]
+pub struct MetadataReader<R, M> {
+    inner: R,
+    pending: HashMap<u64, PendingRange<M>>,
+    next_user_data: u64,
+}
+
+#[derive(Debug)]
+struct PendingRange<M> {
+    location: Arc<Path>,
+    range: Range<isize>,
+    metadata: M,
+}
+
+impl<R, M> MetadataReader<R, M>
+where
+    R: Reader + Completion,
+    M: Send + 'static,
+{
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: HashMap::new(),
+            next_user_data: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Like [`Reader::get_ranges`], except that each range is identified by arbitrary metadata.
+    /// One `user_data` instance per range.
+    pub fn get_ranges_with(
+        &mut self,
+        location: &Path,
+        ranges: Vec<Range<isize>>,
+        user_data: Vec<M>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let shared_location: Arc<Path> = Arc::from(location);
+        let first_user_data = self.next_user_data;
+        let inner_user_data: Vec<u64> =
+            (first_user_data..first_user_data + ranges.len() as u64).collect();
+        self.inner
+            .get_ranges(location, ranges.clone(), inner_user_data.clone())?;
+        self.next_user_data += ranges.len() as u64;
+        for ((inner_user_data, range), metadata) in
+            inner_user_data.into_iter().zip(ranges).zip(user_data)
+        {
+            let pending_range = PendingRange {
+                location: Arc::clone(&shared_location),
+                range,
+                metadata,
+            };
+            self.pending.insert(inner_user_data, pending_range);
+        }
+        Ok(())
+    }
+
+    /// Receive an output if one is ready, without blocking.
+    pub fn try_recv(&mut self) -> Result<Result<Output<M>, IoError>, TryRecvError> {
+        let output = self.inner.try_recv()?;
+        Ok(self.attach_metadata(output))
+    }
+
+    /// Wait up to `timeout` for an output.
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Result<Output<M>, IoError>, RecvTimeoutError> {
+        let output = self.inner.recv_timeout(timeout)?;
+        Ok(self.attach_metadata(output))
+    }
+
+    /// The number of ranges which have been submitted, but which haven't produced a chunk (or an
+    /// error) yet.
+    pub fn n_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn attach_metadata(&mut self, output: Result<Output, IoError>) -> Result<Output<M>, IoError> {
+        match output {
+            Ok(Output::Chunk(chunk)) => match self.pending.remove(&chunk.user_data) {
+                Some(pending_range) => Ok(Output::Chunk(
+                    chunk.map_user_data(|_| pending_range.metadata),
+                )),
+                None => Err(IoError::Internal {
+                    message: format!(
+                        "Received a chunk with user_data {} which wasn't submitted via \
+                            MetadataReader::get_ranges_with.",
+                        chunk.user_data
+                    ),
+                }),
+            },
+            Ok(Output::BytesWritten { user_data, nbytes }) => {
+                Ok(Output::BytesWritten { user_data, nbytes })
+            }
+            Ok(Output::Listing(listing)) => Ok(Output::Listing(listing)),
+            Ok(Output::Exists {
+                user_data,
+                exists,
+                size,
+            }) => Ok(Output::Exists {
+                user_data,
+                exists,
+                size,
+            }),
+            Ok(Output::Metadata {
+                user_data,
+                metadata,
+            }) => Ok(Output::Metadata {
+                user_data,
+                metadata,
+            }),
+            Ok(Output::Synced { user_data }) => Ok(Output::Synced { user_data }),
+            Ok(Output::Advised { user_data }) => Ok(Output::Advised { user_data }),
+            Ok(Output::StreamEnd { user_data, nbytes }) => {
+                Ok(Output::StreamEnd { user_data, nbytes })
+            }
+            Ok(Output::FileComplete {
+                location,
+                user_data_of_last,
+            }) => Ok(Output::FileComplete {
+                location,
+                user_data_of_last,
+            }),
+            Err(err) => {
+                self.forget_failed_ranges(&err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Drop the metadata of the ranges which `err` says have failed.
+    fn forget_failed_ranges(&mut self, err: &IoError) {
+        if let Some(user_data) = err.user_data() {
+            self.pending.remove(&user_data);
+            return;
+        }
+        self.pending.retain(|_, pending_range| {
+            !err.describes_range(&pending_range.location, &pending_range.range)
+        });
+    }
+}
@@ -0,0 +1,281 @@
+use std::ops::{Range, RangeFrom, RangeFull};
+
+/// A byte range within a file, which states its intent explicitly instead of relying on the
+/// negative-number conventions of [`Reader::get_ranges`](crate::Reader::get_ranges) (where
+/// `0..-1` means "the entire file", and forgetting the `-1` reads nothing).
+///
+/// Convert a `ByteRange` into the `Range<isize>` taken by `get_ranges` using `From`/`Into`, or
+/// pass `ByteRange`s directly to [`Reader::get_byte_ranges`](crate::Reader::get_byte_ranges) or
+/// [`ReadRequest::range`](crate::ReadRequest::range). Any `Range<isize>` can be converted into a
+/// `ByteRange` (and back again) without loss, for backwards compatibility. But not every
+/// `ByteRange` has an equivalent `Range<isize>`: `Last(0)`, and offsets above `isize::MAX`, are
+/// converted into the empty range `0..0`, which `get_ranges` rejects as an
+/// [`IoError::InvalidRange`](crate::IoError::InvalidRange).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ByteRange {
+    /// The entire file. Equivalent to `0..-1`.
+    Whole,
+    /// From this offset to the end of the file (like `offset..`). Equivalent to `offset..-1`.
+    FromStart(usize),
+    /// The last `n` bytes of the file. Equivalent to `-n..-1`.
+    Last(usize),
+    /// Exactly these bytes, as absolute offsets from the start of the file.
+    Absolute(Range<usize>),
+    /// A range using the negative-number conventions of `get_ranges`, which can't be expressed by
+    /// the other variants (e.g. `-500..-100`).
+    Relative(Range<isize>),
+}
+
+impl ByteRange {
+    /// Resolve `self` into absolute byte offsets into a file of size `filesize` bytes. Returns
+    /// `None` if `self` doesn't resolve to a non-empty range (e.g. `Last(2000)` of a 1,000-byte
+    /// file, or `Last(0)`), or if an offset is above `isize::MAX`. Like [`try_resolve_range`],
+    /// ranges which extend beyond the end of the file are resolved (reading them is an error).
+    pub fn resolve(&self, filesize: usize) -> Option<Range<usize>> {
+        let range = match self {
+            Self::Whole => 0..filesize,
+            Self::FromStart(offset) => *offset..filesize,
+            Self::Last(n) => filesize.checked_sub(*n)?..filesize,
+            Self::Absolute(range) => range.clone(),
+            Self::Relative(range) => {
+                let range = try_resolve_range(range, isize::try_from(filesize).ok()?)?;
+                range.start as usize..range.end as usize
+            }
+        };
+        // The start of a non-empty range is below its end, so only the end needs checking.
+        (!range.is_empty() && isize::try_from(range.end).is_ok()).then_some(range)
+    }
+}
+
+impl From<Range<isize>> for ByteRange {
+    fn from(range: Range<isize>) -> Self {
+        match (range.start, range.end) {
+            (0, -1) => Self::Whole,
+            (start, -1) if start > 0 => Self::FromStart(start as usize),
+            (start, -1) if start < -1 => Self::Last(start.unsigned_abs()),
+            (start, end) if start >= 0 && end >= 0 => Self::Absolute(start as usize..end as usize),
+            _ => Self::Relative(range),
+        }
+    }
+}
+
+impl From<RangeFrom<usize>> for ByteRange {
+    fn from(range: RangeFrom<usize>) -> Self {
+        Self::FromStart(range.start)
+    }
+}
+
+impl From<RangeFull> for ByteRange {
+    fn from(_: RangeFull) -> Self {
+        Self::Whole
+    }
+}
+
+// `0..-1` means "the entire file", so it isn't empty.
+#[allow(clippy::reversed_empty_ranges)]
+impl From<ByteRange> for Range<isize> {
+    fn from(range: ByteRange) -> Self {
+        let to_isize = |offset: usize| isize::try_from(offset).ok();
+        let range = match range {
+            ByteRange::Whole => Some(0..-1),
+            ByteRange::FromStart(offset) => to_isize(offset).map(|start| start..-1),
+            // `-0..-1` would mean "the entire file".
+            ByteRange::Last(0) => None,
+            ByteRange::Last(n) => to_isize(n).map(|n| -n..-1),
+            ByteRange::Absolute(range) => to_isize(range.start)
+                .zip(to_isize(range.end))
+                .map(|(start, end)| start..end),
+            ByteRange::Relative(range) => Some(range),
+        };
+        // There's no equivalent `Range<isize>`, so use an empty range, which is invalid.
+        range.unwrap_or(0..0)
+    }
+}
+
+/// Resolve a (potentially negative) `range` into absolute byte offsets into a file of size
+/// `filesize` bytes. See [`Reader::get_ranges`](crate::Reader::get_ranges) for the meaning of
+/// negative numbers.
+pub fn resolve_range(range: &Range<isize>, filesize: isize) -> Range<isize> {
+    let start_offset = if range.start >= 0 {
+        range.start
+    } else {
+        // `range.start` is negative. We interpret a negative `range.start`
+        // as an offset from the end of the file.
+        filesize + range.start
+    };
+    assert!(start_offset >= 0);
+
+    let end_offset = if range.end >= 0 {
+        range.end
+    } else {
+        // `range.end` is negative. We interpret a negative `range.end`
+        // as an offset from the end of the file, where `range.end = -1` means the last byte.
+        filesize + range.end + 1
+    };
+    assert!(end_offset >= 0);
+    start_offset..end_offset
+}
+
+/// Like [`resolve_range`], but returns `None` (instead of panicking) if `range` doesn't resolve to
+/// a non-empty range of non-negative offsets. For example, if a negative offset reaches back
+/// beyond the start of the file (which can happen if the file has shrunk since its size was read).
+pub fn try_resolve_range(range: &Range<isize>, filesize: isize) -> Option<Range<isize>> {
+    let resolve = |offset: isize, end_of_file: isize| {
+        if offset >= 0 {
+            offset
+        } else {
+            end_of_file + offset
+        }
+    };
+    let resolved = resolve(range.start, filesize)..resolve(range.end, filesize + 1);
+    (resolved.start >= 0 && !resolved.is_empty()).then_some(resolved)
+}
+
+/// Returns an error unless there's exactly one `user_data` instance per range. Also returns an
+/// error if there are no ranges, because an operation with no ranges would open the file for
+/// nothing (and would produce no outputs, so the user couldn't tell when it had finished).
+pub fn check_one_user_data_per_range(n_ranges: usize, n_user_data: usize) -> anyhow::Result<()> {
+    if n_ranges == 0 {
+        return Err(anyhow::format_err!(
+            "No ranges were provided. At least one range must be requested."
+        ));
+    }
+    if n_ranges != n_user_data {
+        return Err(anyhow::format_err!(
+            "{n_user_data} user_data instances were provided for {n_ranges} ranges. There must be \
+                one user_data instance per range."
+        ));
+    }
+    Ok(())
+}
+
+/// Convert `range` into the `offset` and `len` arguments of `posix_fadvise`. See
+/// [`Reader::advise`](crate::Reader::advise). `range.start` must be non-negative. `range.end` must
+/// be non-negative, or `-1` (which means "the end of the file", and which `posix_fadvise` expresses
+/// as `len = 0`).
+pub fn fadvise_offset_and_len(range: &Range<isize>) -> anyhow::Result<(i64, i64)> {
+    let is_valid = match range.end {
+        -1 => range.start >= 0,
+        end => range.start >= 0 && end > range.start,
+    };
+    if !is_valid {
+        return Err(anyhow::format_err!(
+            "Can't advise the kernel about the range {range:?}. The start of the range must be \
+                non-negative, and the end must be after the start (or -1, meaning the end of the \
+                file)."
+        ));
+    }
+    let len = if range.end == -1 { 0 } else { range.len() };
+    Ok((range.start as i64, len as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Negative range ends are relative to the end of the file, so ranges like `0..-1` aren't empty.
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_resolve_range() {
+        const FILESIZE: isize = 1_000;
+        assert_eq!(resolve_range(&(0..-1), FILESIZE), 0..1_000);
+        assert_eq!(resolve_range(&(0..100), FILESIZE), 0..100);
+        assert_eq!(resolve_range(&(-100..-1), FILESIZE), 900..1_000);
+        assert_eq!(resolve_range(&(-500..-100), FILESIZE), 500..901);
+        assert_eq!(resolve_range(&(100..-1), FILESIZE), 100..1_000);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_byte_range_round_trips() {
+        let cases = [
+            (0..-1, ByteRange::Whole),
+            (100..-1, ByteRange::FromStart(100)),
+            (-100..-1, ByteRange::Last(100)),
+            (0..100, ByteRange::Absolute(0..100)),
+            (0..0, ByteRange::Absolute(0..0)),
+            (-500..-100, ByteRange::Relative(-500..-100)),
+        ];
+        for (range, byte_range) in cases {
+            assert_eq!(ByteRange::from(range.clone()), byte_range);
+            assert_eq!(Range::<isize>::from(byte_range), range);
+        }
+        assert_eq!(ByteRange::from(..), ByteRange::Whole);
+        assert_eq!(ByteRange::from(100..), ByteRange::FromStart(100));
+
+        // These `ByteRange`s have no equivalent `Range<isize>`, so they become an empty range:
+        let huge = isize::MAX as usize + 1;
+        for byte_range in [
+            ByteRange::Last(0),
+            ByteRange::Last(huge),
+            ByteRange::FromStart(huge),
+            ByteRange::Absolute(0..huge),
+            ByteRange::Absolute(huge..usize::MAX),
+        ] {
+            assert_eq!(Range::<isize>::from(byte_range), 0..0);
+        }
+    }
+
+    #[test]
+    fn test_byte_range_resolve() {
+        const FILESIZE: usize = 1_000;
+        assert_eq!(ByteRange::Whole.resolve(FILESIZE), Some(0..1_000));
+        assert_eq!(
+            ByteRange::FromStart(100).resolve(FILESIZE),
+            Some(100..1_000)
+        );
+        assert_eq!(ByteRange::Last(100).resolve(FILESIZE), Some(900..1_000));
+        assert_eq!(ByteRange::Absolute(10..20).resolve(FILESIZE), Some(10..20));
+        assert_eq!(ByteRange::Absolute(0..0).resolve(FILESIZE), None);
+        assert_eq!(ByteRange::Last(2_000).resolve(FILESIZE), None);
+        assert_eq!(ByteRange::Last(0).resolve(FILESIZE), None);
+        assert_eq!(
+            ByteRange::Relative(-500..-100).resolve(FILESIZE),
+            Some(500..901)
+        );
+        // Offsets above `isize::MAX`:
+        let huge = isize::MAX as usize + 1;
+        assert_eq!(ByteRange::Last(huge).resolve(FILESIZE), None);
+        assert_eq!(ByteRange::FromStart(huge).resolve(FILESIZE), None);
+        assert_eq!(ByteRange::Absolute(0..huge).resolve(FILESIZE), None);
+        assert_eq!(
+            ByteRange::Absolute(huge..usize::MAX).resolve(FILESIZE),
+            None
+        );
+        assert_eq!(ByteRange::Whole.resolve(huge), None);
+    }
+
+    #[test]
+    fn test_check_one_user_data_per_range() {
+        assert!(check_one_user_data_per_range(3, 3).is_ok());
+        let err = check_one_user_data_per_range(3, 2).unwrap_err();
+        assert!(err.to_string().contains("2 user_data instances"), "{err}");
+        let err = check_one_user_data_per_range(0, 0).unwrap_err();
+        assert!(err.to_string().contains("No ranges"), "{err}");
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_try_resolve_range() {
+        const FILESIZE: isize = 1_000;
+        assert_eq!(try_resolve_range(&(-100..-1), FILESIZE), Some(900..1_000));
+        // Ranges which extend beyond the end of the file are resolved. Reading them is an error.
+        assert_eq!(try_resolve_range(&(900..1_100), FILESIZE), Some(900..1_100));
+        // Reaches back beyond the start of the file:
+        assert_eq!(try_resolve_range(&(-2_000..-1), FILESIZE), None);
+        // Empty:
+        assert_eq!(try_resolve_range(&(100..100), FILESIZE), None);
+        assert_eq!(try_resolve_range(&(1_100..-1), FILESIZE), None);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_fadvise_offset_and_len() {
+        assert_eq!(fadvise_offset_and_len(&(0..-1)).unwrap(), (0, 0));
+        assert_eq!(fadvise_offset_and_len(&(100..-1)).unwrap(), (100, 0));
+        assert_eq!(fadvise_offset_and_len(&(100..300)).unwrap(), (100, 200));
+        assert!(fadvise_offset_and_len(&(-100..-1)).is_err());
+        assert!(fadvise_offset_and_len(&(0..-2)).is_err());
+        assert!(fadvise_offset_and_len(&(100..100)).is_err());
+    }
+}
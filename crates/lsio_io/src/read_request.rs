@@ -0,0 +1,131 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{check_one_user_data_per_range, ByteRange, Reader};
+
+/// A builder for composing a single read request, which can span multiple files and multiple
+/// byte ranges per file. Create a `ReadRequest` by calling [`Reader::read`]. For example:
+///
+/// ```ignore
+/// reader
+///     .read()
+///     .file("/path/to/a")
+///     .ranges(&[0..100, -100..-1])
+///     .file("/path/to/b")
+///     .range(0..-1)
+///     .submit()?;
+/// ```
+///
+/// Each range is anything which converts into a [`ByteRange`]: A `Range<isize>` (which follows
+/// the same conventions, including negative numbers, as [`Reader::get_ranges`]), a `ByteRange`
+/// (e.g. `ByteRange::Last(100)`), `..` (the entire file), or `offset..` (from `offset` to the
+/// end of the file).
+///
+/// Unless specified otherwise (using [`ReadRequest::range_with_user_data`]), the `user_data` of
+/// each range is the index of that range within the whole request. In the example above, the
+/// `user_data` of the three ranges would be 0, 1, and 2.
+///
+/// The whole request is submitted as a single batch (see [`Reader::submit_batch`]).
+#[derive(Debug)]
+pub struct ReadRequest<'a, R: Reader + ?Sized> {
+    reader: &'a mut R,
+    files: Vec<ReadOp>,
+    n_ranges: u64,
+}
+
+/// The byte ranges to read from a single file, as part of a batch submitted by
+/// [`Reader::submit_batch`]. `ranges` and `user_data` have the same meaning as in
+/// [`Reader::get_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOp {
+    pub location: PathBuf,
+    pub ranges: Vec<Range<isize>>,
+    pub user_data: Vec<u64>,
+}
+
+impl ReadOp {
+    pub fn new(location: impl AsRef<Path>, ranges: Vec<Range<isize>>, user_data: Vec<u64>) -> Self {
+        Self {
+            location: location.as_ref().to_path_buf(),
+            ranges,
+            user_data,
+        }
+    }
+}
+
+/// Returns an error if any `ReadOp` in `ops` would be rejected by [`Reader::get_ranges`]. Used by
+/// [`Reader::submit_batch`] to reject the whole batch before submitting any of it.
+pub fn check_batch(ops: &[ReadOp]) -> anyhow::Result<()> {
+    for (i, op) in ops.iter().enumerate() {
+        check_one_user_data_per_range(op.ranges.len(), op.user_data.len())
+            .with_context(|| format!("ops[{i}] (reading {:?}) is invalid", op.location))?;
+    }
+    Ok(())
+}
+
+impl<'a, R: Reader + ?Sized> ReadRequest<'a, R> {
+    pub(crate) fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader,
+            files: Vec::new(),
+            n_ranges: 0,
+        }
+    }
+
+    /// Start reading from `location`. Subsequent ranges will be read from `location`, until
+    /// `file` is called again.
+    pub fn file(mut self, location: impl AsRef<Path>) -> Self {
+        self.files
+            .push(ReadOp::new(location, Vec::new(), Vec::new()));
+        self
+    }
+
+    /// Read `range` from the current file.
+    ///
+    /// # Panics
+    /// If `file` has not been called yet.
+    pub fn range(self, range: impl Into<ByteRange>) -> Self {
+        let user_data = self.n_ranges;
+        self.range_with_user_data(range, user_data)
+    }
+
+    /// Read each of `ranges` from the current file.
+    ///
+    /// # Panics
+    /// If `file` has not been called yet.
+    pub fn ranges<T: Clone + Into<ByteRange>>(self, ranges: &[T]) -> Self {
+        ranges
+            .iter()
+            .fold(self, |request, range| request.range(range.to_owned()))
+    }
+
+    /// Read `range` from the current file, and identify the resulting `Chunk` with `user_data`.
+    ///
+    /// # Panics
+    /// If `file` has not been called yet.
+    pub fn range_with_user_data(mut self, range: impl Into<ByteRange>, user_data: u64) -> Self {
+        let file = self
+            .files
+            .last_mut()
+            .expect("`ReadRequest::file` must be called before adding any ranges");
+        let range: ByteRange = range.into();
+        file.ranges.push(range.into());
+        file.user_data.push(user_data);
+        self.n_ranges += 1;
+        self
+    }
+
+    /// Submit all the ranges to the IO backend, as a single batch.
+    pub fn submit(self) -> anyhow::Result<()> {
+        let ops = self
+            .files
+            .into_iter()
+            .filter(|file| !file.ranges.is_empty())
+            .collect();
+        self.reader.submit_batch(ops)
+    }
+}
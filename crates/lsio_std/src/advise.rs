@@ -0,0 +1,66 @@
+use std::{fs::File, io, ops::Range, os::fd::AsRawFd, path::PathBuf};
+
+use lsio_io::{Advice, IoError, Output};
+
+use crate::get_ranges::io_error;
+
+#[derive(Debug)]
+pub(crate) struct Advise {
+    location: PathBuf,
+    /// The range passed to `Reader::advise`. Only used to describe errors.
+    range: Range<isize>,
+    offset: i64,
+    len: i64,
+    advice: Advice,
+    user_data: Option<u64>,
+}
+
+impl Advise {
+    pub(crate) fn new(
+        location: PathBuf,
+        range: Range<isize>,
+        (offset, len): (i64, i64),
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> Self {
+        Self {
+            location,
+            range,
+            offset,
+            len,
+            advice,
+            user_data,
+        }
+    }
+
+    /// Open the file and call `posix_fadvise`. If `user_data` is `Some`, then send one
+    /// `Output::Advised` (or one `IoError`) to `output_tx`.
+    pub(crate) fn run(self, output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>) {
+        let result = File::open(&self.location)
+            .map_err(|err| (err, "openat"))
+            .and_then(|file| {
+                nix::fcntl::posix_fadvise(
+                    file.as_raw_fd(),
+                    self.offset,
+                    self.len,
+                    self.advice.into(),
+                )
+                .map_err(|errno| (io::Error::from(errno), "fadvise"))
+            });
+        let Some(user_data) = self.user_data else {
+            // Fire-and-forget.
+            return;
+        };
+        let output = match result {
+            Ok(()) => Ok(Output::Advised { user_data }),
+            Err((err, opcode)) => Err(io_error(
+                &err,
+                opcode,
+                &self.location,
+                Some(&self.range),
+                Some(user_data),
+            )),
+        };
+        let _ = output_tx.send(output);
+    }
+}
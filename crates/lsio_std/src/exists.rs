@@ -0,0 +1,45 @@
+use std::{io, path::PathBuf};
+
+use lsio_io::{IoError, Output};
+
+use crate::get_ranges::io_error;
+
+#[derive(Debug)]
+pub(crate) struct Exists {
+    location: PathBuf,
+    user_data: u64,
+}
+
+impl Exists {
+    pub(crate) fn new(location: PathBuf, user_data: u64) -> Self {
+        Self {
+            location,
+            user_data,
+        }
+    }
+
+    /// Check whether the file exists using a blocking `stat`, and send one `Output::Exists` (or
+    /// one `IoError`) to `output_tx`.
+    pub(crate) fn run(self, output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>) {
+        let output = match std::fs::metadata(&self.location) {
+            Ok(metadata) => Ok(Output::Exists {
+                user_data: self.user_data,
+                exists: true,
+                size: Some(metadata.len()),
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Output::Exists {
+                user_data: self.user_data,
+                exists: false,
+                size: None,
+            }),
+            Err(err) => Err(io_error(
+                &err,
+                "statx",
+                &self.location,
+                None,
+                Some(self.user_data),
+            )),
+        };
+        let _ = output_tx.send(output);
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    fs::File,
+    io,
+    iter::zip,
+    ops::Range,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut};
+use lsio_io::{try_resolve_range, Chunk, IoError, Output};
+
+/// The alignment of the buffers that we allocate. We don't use `O_DIRECT`, so we don't need to
+/// align our buffers. But we use the same alignment as `lsio_uring` so that the buffers that the
+/// user receives have the same alignment, whichever backend they use.
+const ALIGN: usize = 512;
+
+#[derive(Debug)]
+pub(crate) struct GetRanges {
+    location: PathBuf,
+    ranges: Vec<Range<isize>>,
+    user_data: Vec<u64>,
+
+    /// If `Some`, then read each range into its corresponding (caller-provided) destination buffer,
+    /// instead of allocating new buffers.
+    destinations: Option<Vec<AlignedBytes>>,
+
+    /// If `Some`, then this operation belongs to a group.
+    group_id: Option<u64>,
+}
+
+impl GetRanges {
+    pub(crate) fn new(
+        location: PathBuf,
+        ranges: Vec<Range<isize>>,
+        destinations: Option<Vec<AlignedBytes>>,
+        user_data: Vec<u64>,
+    ) -> Self {
+        // The public API has already checked these lengths.
+        debug_assert_eq!(ranges.len(), user_data.len());
+        if let Some(destinations) = &destinations {
+            debug_assert_eq!(ranges.len(), destinations.len());
+        }
+        Self {
+            location,
+            ranges,
+            user_data,
+            destinations,
+            group_id: None,
+        }
+    }
+
+    pub(crate) fn with_group_id(mut self, group_id: u64) -> Self {
+        self.group_id = Some(group_id);
+        self
+    }
+
+    pub(crate) fn group_id(&self) -> Option<u64> {
+        self.group_id
+    }
+
+    /// Open the file, and read each range using blocking `pread`s. Sends one `Output::Chunk` (or
+    /// one `IoError`) per range to `output_tx`, even if the file can't be opened.
+    pub(crate) fn run(self, output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>) {
+        let file = match File::open(&self.location) {
+            Ok(file) => file,
+            Err(err) => {
+                self.send_error_per_range(&err, "open", output_tx);
+                return;
+            }
+        };
+        let filesize: isize = match file.metadata() {
+            Ok(metadata) => metadata.len().try_into().unwrap(),
+            Err(err) => {
+                self.send_error_per_range(&err, "statx", output_tx);
+                return;
+            }
+        };
+
+        let mut destinations = self.destinations.map(Vec::into_iter);
+        for (range, &user_data) in zip(&self.ranges, &self.user_data) {
+            let Some(resolved_range) = try_resolve_range(range, filesize) else {
+                let _ = output_tx.send(Err(IoError::InvalidRange {
+                    path: self.location.clone(),
+                    range: range.to_owned(),
+                    message: format!(
+                        "The range {range:?} doesn't resolve to a non-empty range of the file, \
+                            which is {filesize} bytes. user_data={user_data}",
+                    ),
+                }));
+                // Keep each destination with its range.
+                if let Some(destinations) = destinations.as_mut() {
+                    destinations.next();
+                }
+                continue;
+            };
+            let len: usize = resolved_range.len();
+            let buffer = match destinations.as_mut().map(|d| d.next().unwrap()) {
+                None => match AlignedBytesMut::try_zeroed(len, ALIGN) {
+                    Ok(buffer) => buffer.freeze().unwrap(),
+                    Err(err) => {
+                        let _ = output_tx.send(Err(IoError::Nix {
+                            errno: nix::Error::ENOMEM,
+                            opcode: "pread",
+                            path: Some(self.location.clone()),
+                            range: Some(range.to_owned()),
+                            user_data: Some(user_data),
+                            details: err.to_string(),
+                        }));
+                        continue;
+                    }
+                },
+                Some(destination) if len > destination.len() => {
+                    let _ = output_tx.send(Err(IoError::InvalidRange {
+                        path: self.location.clone(),
+                        range: range.to_owned(),
+                        message: format!(
+                            "The range {range:?} (resolved to {resolved_range:?}) is {len} bytes \
+                                long, but the destination buffer is only {} bytes long. \
+                                user_data={user_data}",
+                            destination.len(),
+                        ),
+                    }));
+                    continue;
+                }
+                Some(destination) => destination,
+            };
+            let output = read_range(&file, &resolved_range, buffer)
+                .map(|buffer| {
+                    Output::Chunk(Chunk {
+                        buffer,
+                        user_data,
+                        range: Some(resolved_range.start as usize..resolved_range.end as usize),
+                    })
+                })
+                .map_err(|err| match err {
+                    ReadError::Io(err) => {
+                        io_error(&err, "pread", &self.location, Some(range), Some(user_data))
+                    }
+                    ReadError::EndOfFile { got } => IoError::ShortRead {
+                        path: self.location.clone(),
+                        range: range.to_owned(),
+                        got,
+                        wanted: len,
+                        file_size: Some(filesize as u64),
+                        details: format!("resolved_range: {resolved_range:?}"),
+                    },
+                });
+            let _ = output_tx.send(output);
+        }
+    }
+
+    /// Send one `IoError` per range, so the user receives one output per range even though the
+    /// whole file failed.
+    fn send_error_per_range(
+        &self,
+        err: &io::Error,
+        opcode: &'static str,
+        output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        for (range, &user_data) in zip(&self.ranges, &self.user_data) {
+            let err = io_error(err, opcode, &self.location, Some(range), Some(user_data));
+            let _ = output_tx.send(Err(err));
+        }
+    }
+}
+
+enum ReadError {
+    Io(io::Error),
+    /// Reached the end of the file after reading `got` bytes.
+    EndOfFile {
+        got: usize,
+    },
+}
+
+/// Read `resolved_range` of `file` into the start of `buffer`, and return `buffer` sliced to the
+/// bytes that were read. `buffer` must be at least as long as `resolved_range`.
+fn read_range(
+    file: &File,
+    resolved_range: &Range<isize>,
+    mut buffer: AlignedBytes,
+) -> Result<AlignedBytes, ReadError> {
+    let len = resolved_range.len();
+    assert!(len <= buffer.len());
+    // SAFETY: `buffer` is the only view of its underlying buffer (either because we've just
+    // allocated it, or because `freeze_destinations` checked that the user has given us the
+    // only view), and `buffer` is at least `len` bytes long.
+    let slice = unsafe { std::slice::from_raw_parts_mut(buffer.as_ptr() as *mut u8, len) };
+    let mut n_bytes_read = 0;
+    while n_bytes_read < len {
+        let file_offset = resolved_range.start as u64 + n_bytes_read as u64;
+        match file.read_at(&mut slice[n_bytes_read..], file_offset) {
+            Ok(0) => return Err(ReadError::EndOfFile { got: n_bytes_read }),
+            // Short reads are retried for the remaining bytes.
+            Ok(n) => n_bytes_read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(ReadError::Io(err)),
+        }
+    }
+    buffer.set_slice(0..len);
+    Ok(buffer)
+}
+
+/// Convert an `io::Error` into the same `IoError` that `lsio_uring` would send.
+pub(crate) fn io_error(
+    err: &io::Error,
+    opcode: &'static str,
+    path: &Path,
+    range: Option<&Range<isize>>,
+    user_data: Option<u64>,
+) -> IoError {
+    let details = err.to_string();
+    match err.kind() {
+        io::ErrorKind::NotFound => IoError::NotFound {
+            path: path.to_path_buf(),
+            user_data,
+            details,
+        },
+        _ => IoError::Nix {
+            errno: nix::errno::Errno::from_raw(err.raw_os_error().unwrap_or(0)),
+            opcode,
+            path: Some(path.to_path_buf()),
+            range: range.cloned(),
+            user_data,
+            details,
+        },
+    }
+}
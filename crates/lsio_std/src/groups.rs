@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::operation::Operation;
+
+/// Tracks groups of operations, so that every operation in group _n_ completes before any
+/// operation in group _n+1_ starts. See [`lsio_io::Reader::get_ranges_in_group`].
+#[derive(Debug, Default)]
+pub(crate) struct Groups {
+    state: Mutex<GroupsState>,
+}
+
+#[derive(Debug, Default)]
+struct GroupsState {
+    /// The number of unfinished operations in each group. The active group is the group with the
+    /// lowest ID.
+    n_unfinished_ops: BTreeMap<u64, usize>,
+
+    /// Operations which can't start until all the groups with lower IDs have finished.
+    held_back_ops: BTreeMap<u64, Vec<Operation>>,
+}
+
+impl GroupsState {
+    fn is_active(&self, group_id: u64) -> bool {
+        self.n_unfinished_ops
+            .first_key_value()
+            .is_none_or(|(&active_group_id, _)| group_id <= active_group_id)
+    }
+}
+
+impl Groups {
+    /// Register `operation` in `group_id`. Returns `operation` if `group_id` is the active group
+    /// (so `operation` can start now). Otherwise, holds back `operation` until all the groups
+    /// before `group_id` have finished.
+    pub(crate) fn join(&self, group_id: u64, operation: Operation) -> Option<Operation> {
+        let mut state = self.state.lock().unwrap();
+        *state.n_unfinished_ops.entry(group_id).or_default() += 1;
+        if state.is_active(group_id) {
+            Some(operation)
+        } else {
+            state
+                .held_back_ops
+                .entry(group_id)
+                .or_default()
+                .push(operation);
+            None
+        }
+    }
+
+    /// Record that an operation in `group_id` has finished. Returns the held-back operations
+    /// which can now start.
+    pub(crate) fn leave(&self, group_id: u64) -> Vec<Operation> {
+        let mut state = self.state.lock().unwrap();
+        let n_unfinished_ops = state.n_unfinished_ops.get_mut(&group_id).unwrap();
+        *n_unfinished_ops -= 1;
+        if *n_unfinished_ops == 0 {
+            state.n_unfinished_ops.remove(&group_id);
+        }
+        let mut ready_ops = Vec::new();
+        while let Some(&next_group_id) = state.held_back_ops.keys().next() {
+            if !state.is_active(next_group_id) {
+                break;
+            }
+            ready_ops.extend(state.held_back_ops.remove(&next_group_id).unwrap());
+        }
+        ready_ops
+    }
+}
@@ -0,0 +1,11 @@
+#![doc = include_str!("../README.md")]
+
+pub(crate) mod advise;
+pub(crate) mod exists;
+pub(crate) mod get_ranges;
+pub(crate) mod groups;
+pub(crate) mod metadata;
+pub(crate) mod operation;
+pub(crate) mod std_reader;
+
+pub use std_reader::StdReader;
@@ -0,0 +1,45 @@
+use std::{os::unix::fs::MetadataExt, path::PathBuf};
+
+use lsio_io::{FileMetadata, IoError, Output};
+
+use crate::get_ranges::io_error;
+
+#[derive(Debug)]
+pub(crate) struct Metadata {
+    location: PathBuf,
+    user_data: u64,
+}
+
+impl Metadata {
+    pub(crate) fn new(location: PathBuf, user_data: u64) -> Self {
+        Self {
+            location,
+            user_data,
+        }
+    }
+
+    /// Get the metadata of the file using a blocking `stat`, and send one `Output::Metadata` (or
+    /// one `IoError`) to `output_tx`.
+    pub(crate) fn run(self, output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>) {
+        let output = match std::fs::metadata(&self.location) {
+            Ok(metadata) => Ok(Output::Metadata {
+                user_data: self.user_data,
+                metadata: FileMetadata {
+                    path: self.location,
+                    size: metadata.len(),
+                    is_dir: metadata.is_dir(),
+                    mtime: metadata.modified().ok(),
+                    mode: metadata.mode(),
+                },
+            }),
+            Err(err) => Err(io_error(
+                &err,
+                "statx",
+                &self.location,
+                None,
+                Some(self.user_data),
+            )),
+        };
+        let _ = output_tx.send(output);
+    }
+}
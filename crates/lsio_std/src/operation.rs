@@ -0,0 +1,30 @@
+use lsio_io::{IoError, Output};
+
+use crate::{advise::Advise, exists::Exists, get_ranges::GetRanges, metadata::Metadata};
+
+/// The tasks processed by `StdReader`'s worker threads.
+#[derive(Debug)]
+pub(crate) enum Operation {
+    GetRanges(GetRanges),
+    Exists(Exists),
+    Metadata(Metadata),
+    Advise(Advise),
+}
+
+impl Operation {
+    pub(crate) fn group_id(&self) -> Option<u64> {
+        match self {
+            Operation::GetRanges(op) => op.group_id(),
+            Operation::Exists(_) | Operation::Metadata(_) | Operation::Advise(_) => None,
+        }
+    }
+
+    pub(crate) fn run(self, output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>) {
+        match self {
+            Operation::GetRanges(op) => op.run(output_tx),
+            Operation::Exists(op) => op.run(output_tx),
+            Operation::Metadata(op) => op.run(output_tx),
+            Operation::Advise(op) => op.run(output_tx),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use lsio_aligned_bytes::AlignedBytesMut;
+use lsio_io::{
+    check_one_user_data_per_range, fadvise_offset_and_len, freeze_destinations, Advice, Completion,
+    IoError, Output, Reader,
+};
+use lsio_threadpool::{ThreadPool, WorkerThread};
+
+use crate::{
+    advise::Advise, exists::Exists, get_ranges::GetRanges, groups::Groups, metadata::Metadata,
+    operation::Operation,
+};
+
+/// A portable IO backend, which reads using blocking `pread` calls on a threadpool.
+///
+/// `StdReader` sends exactly the same `Output`s (and `IoError`s) as `lsio_uring::IoUring`, so the
+/// two backends are interchangeable. Each worker thread processes one `GetRanges` operation at a
+/// time, so use more worker threads than you would for `IoUring` to keep the storage busy.
+pub struct StdReader {
+    threadpool: ThreadPool<Operation>,
+    output_rx: crossbeam_channel::Receiver<Result<Output, IoError>>,
+    groups: Arc<Groups>,
+}
+
+impl StdReader {
+    pub fn new(n_worker_threads: usize) -> Self {
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        let groups = Arc::new(Groups::default());
+        let groups_for_workers = Arc::clone(&groups);
+        Self {
+            threadpool: ThreadPool::new_named(
+                "lsio_std",
+                n_worker_threads,
+                move |worker_thread: WorkerThread<Operation>| {
+                    run_worker(&worker_thread, &output_tx, &groups_for_workers);
+                },
+            ),
+            output_rx,
+            groups,
+        }
+    }
+
+    /// Like [`Reader::get_ranges`], except that `location` can be anything which can be borrowed
+    /// as a [`Path`](std::path::Path), such as `"foo/bar"`, a `String`, or a `PathBuf`.
+    pub fn get_ranges(
+        &mut self,
+        location: impl AsRef<std::path::Path>,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        Reader::get_ranges(self, location.as_ref(), ranges, user_data)
+    }
+}
+
+/// The main loop for each worker thread.
+fn run_worker(
+    worker_thread: &WorkerThread<Operation>,
+    output_tx: &crossbeam_channel::Sender<Result<Output, IoError>>,
+    groups: &Groups,
+) {
+    while worker_thread.keep_running() {
+        match worker_thread.find_task() {
+            Some(operation) => {
+                let group_id = operation.group_id();
+                operation.run(output_tx);
+                if let Some(group_id) = group_id {
+                    // Finishing this operation may have finished its group, in which case the
+                    // operations in the next group can start.
+                    for operation in groups.leave(group_id) {
+                        worker_thread.push(operation);
+                    }
+                }
+            }
+            None => worker_thread.park(),
+        }
+    }
+}
+
+impl Completion for StdReader {
+    fn completion(&self) -> &crossbeam_channel::Receiver<Result<Output, IoError>> {
+        &self.output_rx
+    }
+}
+
+impl Reader for StdReader {
+    fn get_ranges(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let task = GetRanges::new(location.to_path_buf(), ranges, None, user_data);
+        self.threadpool.push(Operation::GetRanges(task));
+        Ok(())
+    }
+
+    fn get_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let task =
+            GetRanges::new(location.to_path_buf(), ranges, None, user_data).with_group_id(group_id);
+        if let Some(task) = self.groups.join(group_id, Operation::GetRanges(task)) {
+            self.threadpool.push(task);
+        }
+        Ok(())
+    }
+
+    fn get_ranges_into(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        buffers: Vec<AlignedBytesMut>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        if buffers.len() != ranges.len() {
+            return Err(anyhow::format_err!(
+                "{} buffers were provided for {} ranges. There must be one buffer per range.",
+                buffers.len(),
+                ranges.len()
+            ));
+        }
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let destinations = freeze_destinations(buffers)?;
+        let task = GetRanges::new(
+            location.to_path_buf(),
+            ranges,
+            Some(destinations),
+            user_data,
+        );
+        self.threadpool.push(Operation::GetRanges(task));
+        Ok(())
+    }
+
+    fn exists(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()> {
+        let task = Exists::new(location.to_path_buf(), user_data);
+        self.threadpool.push(Operation::Exists(task));
+        Ok(())
+    }
+
+    fn metadata(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()> {
+        let task = Metadata::new(location.to_path_buf(), user_data);
+        self.threadpool.push(Operation::Metadata(task));
+        Ok(())
+    }
+
+    fn advise(
+        &mut self,
+        location: &std::path::Path,
+        range: std::ops::Range<isize>,
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let offset_and_len = fadvise_offset_and_len(&range)?;
+        let task = Advise::new(
+            location.to_path_buf(),
+            range,
+            offset_and_len,
+            advice,
+            user_data,
+        );
+        self.threadpool.push(Operation::Advise(task));
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+// Our API takes a `Vec` of byte ranges, and we often want to request just one byte range.
+#![allow(clippy::single_range_in_vec_init)]
+// Negative range ends are relative to the end of the file, so ranges like `0..-1` aren't empty.
+#![allow(clippy::reversed_empty_ranges)]
+
+use lsio_aligned_bytes::AlignedBytesMut;
+use lsio_io::{Advice, Completion, DecodingReader, IoError, MetadataReader, Output, Reader};
+use lsio_std::StdReader;
+use lsio_threadpool::ComputePool;
+use std::{os::unix::fs::PermissionsExt, path::PathBuf, sync::Arc, time::Duration};
+
+const KIBIBYTE: usize = 1024;
+const MEBIBYTE: usize = KIBIBYTE * 1024;
+
+/// Write `contents` to a new file in the temporary directory, and return the filename.
+fn create_temp_file(prefix: &str, contents: &[u8]) -> std::io::Result<PathBuf> {
+    let filename =
+        std::env::temp_dir().join(format!("lsio_std_{prefix}_{}", rand::random::<u32>()));
+    std::fs::write(&filename, contents)?;
+    Ok(filename)
+}
+
+#[test]
+fn test_get_ranges() -> anyhow::Result<()> {
+    const FILE_SIZE: usize = MEBIBYTE;
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const N_CHUNKS: usize = FILE_SIZE / CHUNK_SIZE;
+
+    let file_contents: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_ranges", &file_contents)?;
+    let mut reader = StdReader::new(4);
+
+    let ranges = (0..N_CHUNKS)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    reader.get_ranges(&filename, ranges, (0..N_CHUNKS as u64).collect())?;
+
+    let mut received = vec![false; N_CHUNKS];
+    for _ in 0..N_CHUNKS {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = c.user_data as usize;
+                let expected = i * CHUNK_SIZE..(i + 1) * CHUNK_SIZE;
+                assert_eq!(c.range, Some(expected.clone()));
+                assert_eq!(c.buffer.as_slice(), &file_contents[expected]);
+                assert!(!received[i], "Received chunk {i} twice!");
+                received[i] = true;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_negative_ranges() -> anyhow::Result<()> {
+    const FILE_SIZE: usize = 10_007;
+    let file_contents: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("negative_ranges", &file_contents)?;
+    let mut reader = StdReader::new(1);
+
+    // Each range, and the bytes that it should resolve to.
+    let ranges = vec![0..-1, -100..-1, -500..-100, 0..100];
+    let expected = [
+        0..FILE_SIZE,
+        FILE_SIZE - 100..FILE_SIZE,
+        FILE_SIZE - 500..FILE_SIZE - 99,
+        0..100,
+    ];
+    reader.get_ranges(
+        &filename,
+        ranges.clone(),
+        (0..ranges.len() as u64).collect(),
+    )?;
+
+    for _ in 0..ranges.len() {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = c.user_data as usize;
+                assert_eq!(c.range, Some(expected[i].clone()));
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[expected[i].clone()],
+                    "range={:?}",
+                    ranges[i]
+                );
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_in_group() -> anyhow::Result<()> {
+    const N_RANGES_PER_GROUP: usize = 8;
+    const CHUNK_SIZE: usize = KIBIBYTE * 64;
+    const N_GROUPS: u64 = 3;
+
+    let filename = create_temp_file("groups", &vec![0; CHUNK_SIZE * N_RANGES_PER_GROUP])?;
+    let ranges: Vec<_> = (0..N_RANGES_PER_GROUP)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    let mut reader = StdReader::new(2);
+
+    // Submit each group as two `get_ranges_in_group` calls. Each chunk's `user_data` is its group.
+    let (first_half, second_half) = ranges.split_at(N_RANGES_PER_GROUP / 2);
+    for group_id in 0..N_GROUPS {
+        for half in [first_half, second_half] {
+            let user_data = vec![group_id; half.len()];
+            reader.get_ranges_in_group(group_id, &filename, half.to_vec(), user_data)?;
+        }
+    }
+
+    // Every chunk from group n must arrive before any chunk from group n+1.
+    let mut n_chunks_per_group = vec![0; N_GROUPS as usize];
+    for _ in 0..N_RANGES_PER_GROUP * N_GROUPS as usize {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let group_id = c.user_data as usize;
+                assert!(
+                    n_chunks_per_group[..group_id]
+                        .iter()
+                        .all(|&n| n == N_RANGES_PER_GROUP),
+                    "Received a chunk from group {group_id} before all earlier groups had \
+                        finished! {n_chunks_per_group:?}"
+                );
+                n_chunks_per_group[group_id] += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_into() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const ALIGN: usize = 512;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_ranges_into", &file_contents)?;
+    let mut reader = StdReader::new(1);
+
+    // The second range is shorter than its buffer. The third range is longer than its buffer.
+    let ranges = vec![
+        0..CHUNK_SIZE as isize,
+        CHUNK_SIZE as isize..(CHUNK_SIZE * 2 - 100) as isize,
+        0..(CHUNK_SIZE * 2) as isize,
+    ];
+    let mut buffers: Vec<AlignedBytesMut> = (0..3)
+        .map(|_| AlignedBytesMut::new(CHUNK_SIZE, ALIGN))
+        .collect();
+    let buffer_ptrs: Vec<*const u8> = buffers
+        .iter_mut()
+        .map(|buffer| buffer.as_mut_ptr() as *const u8)
+        .collect();
+    reader.get_ranges_into(&filename, ranges, buffers, vec![0, 1, 2])?;
+
+    let mut n_errors = 0;
+    for _ in 0..3 {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                // The chunk must be a view into the buffer that we provided:
+                assert_eq!(c.buffer.as_ptr(), buffer_ptrs[c.user_data as usize]);
+                let expected = match c.user_data {
+                    0 => &file_contents[..CHUNK_SIZE],
+                    1 => &file_contents[CHUNK_SIZE..CHUNK_SIZE * 2 - 100],
+                    _ => panic!("Unexpected chunk {c:?}"),
+                };
+                assert_eq!(c.buffer.as_slice(), expected);
+            }
+            Ok(Err(IoError::InvalidRange { .. })) => n_errors += 1,
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_errors, 1);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_beyond_end_of_file_is_an_error() -> anyhow::Result<()> {
+    let filename = create_temp_file("beyond_eof", &[42; KIBIBYTE])?;
+    let mut reader = StdReader::new(1);
+
+    reader.get_ranges(&filename, vec![0..(KIBIBYTE * 4) as isize], vec![0])?;
+    match reader.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(IoError::ShortRead {
+            path,
+            got,
+            wanted,
+            file_size,
+            ..
+        })) => {
+            assert_eq!(path, filename);
+            assert_eq!(got, KIBIBYTE);
+            assert_eq!(wanted, KIBIBYTE * 4);
+            assert_eq!(file_size, Some(KIBIBYTE as u64));
+        }
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    // A negative range which reaches back beyond the start of the file is an error, not a panic.
+    reader.get_ranges(&filename, vec![-(KIBIBYTE as isize) * 2..-1], vec![1])?;
+    match reader.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(IoError::InvalidRange { path, .. })) => assert_eq!(path, filename),
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_from_missing_file_is_not_found() -> anyhow::Result<()> {
+    let filename = std::env::temp_dir().join("lsio_std_this_file_does_not_exist");
+    let mut reader = StdReader::new(1);
+    reader.get_ranges(&filename, vec![0..1, 1..2], vec![7, 8])?;
+    // One error per range.
+    for expected_user_data in [7, 8] {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(IoError::NotFound {
+                path, user_data, ..
+            })) => {
+                assert_eq!(path, filename);
+                assert_eq!(user_data, Some(expected_user_data));
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_exists() -> anyhow::Result<()> {
+    let filename = create_temp_file("exists", &[0; 1234])?;
+    let missing = filename.with_extension("missing");
+    let mut reader = StdReader::new(1);
+    reader.exists(&filename, 1)?;
+    reader.exists(&missing, 2)?;
+
+    let mut outputs = Vec::new();
+    for _ in 0..2 {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Exists {
+                user_data,
+                exists,
+                size,
+            })) => outputs.push((user_data, exists, size)),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    outputs.sort();
+    assert_eq!(outputs, [(1, true, Some(1234)), (2, false, None)]);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_metadata() -> anyhow::Result<()> {
+    let filename = create_temp_file("metadata", &[0; 1234])?;
+    let mtime = std::time::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    std::fs::File::options()
+        .write(true)
+        .open(&filename)?
+        .set_modified(mtime)?;
+    std::fs::set_permissions(&filename, std::fs::Permissions::from_mode(0o640))?;
+    let dir = filename.parent().unwrap();
+    let missing = filename.with_extension("missing");
+    let mut reader = StdReader::new(1);
+    reader.metadata(&filename, 1)?;
+    reader.metadata(dir, 2)?;
+    reader.metadata(&missing, 3)?;
+
+    for _ in 0..3 {
+        match reader.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Metadata {
+                user_data: 1,
+                metadata,
+            })) => {
+                assert_eq!(metadata.path, filename);
+                assert_eq!(metadata.size, 1234);
+                assert!(!metadata.is_dir);
+                assert_eq!(metadata.mtime, Some(mtime));
+                assert_eq!(metadata.permissions(), 0o640);
+            }
+            Ok(Ok(Output::Metadata {
+                user_data: 2,
+                metadata,
+            })) => assert!(metadata.is_dir),
+            Ok(Err(IoError::NotFound {
+                path,
+                user_data: Some(3),
+                ..
+            })) => assert_eq!(path, missing),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_advise() -> anyhow::Result<()> {
+    let filename = create_temp_file("advise", &[0; 1234])?;
+    let missing = filename.with_extension("missing");
+    let mut reader = StdReader::new(1);
+    assert!(reader
+        .advise(&filename, -100..-1, Advice::WillNeed, None)
+        .is_err());
+
+    // Fire-and-forget, so there's no output (not even an error).
+    reader.advise(&missing, 0..-1, Advice::Sequential, None)?;
+    reader.advise(&filename, 0..-1, Advice::WillNeed, Some(1))?;
+    reader.advise(&missing, 0..100, Advice::DontNeed, Some(2))?;
+    let recv = || reader.completion().recv_timeout(Duration::from_millis(500));
+    assert!(matches!(recv(), Ok(Ok(Output::Advised { user_data: 1 }))));
+    assert!(matches!(
+        recv(),
+        Ok(Err(IoError::NotFound {
+            user_data: Some(2),
+            ..
+        }))
+    ));
+    assert!(reader.completion().is_empty());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_decoding_reader() -> anyhow::Result<()> {
+    // A toy codec, which reverses the bytes of each chunk. Chunks which start with `!` are
+    // corrupt, and chunks which start with `?` make the codec panic.
+    let reverse = |input: &[u8], out: &mut Vec<u8>| -> anyhow::Result<()> {
+        match input[0] {
+            b'!' => anyhow::bail!("Corrupt chunk"),
+            b'?' => panic!("Buggy codec"),
+            _ => out.extend(input.iter().rev()),
+        }
+        Ok(())
+    };
+    let filename = create_temp_file("decoding", b"olleh!!!!!dlrow?????")?;
+    let compute_pool = Arc::new(ComputePool::new(2));
+    let mut reader = DecodingReader::new(StdReader::new(2), reverse, compute_pool);
+
+    reader.get_ranges(
+        &filename,
+        vec![0..5, 5..10, 10..15, 15..20],
+        vec![0, 1, 2, 3],
+    )?;
+    reader.get_ranges(&filename.with_extension("missing"), vec![0..1], vec![4])?;
+
+    let mut decoded = vec![None; 3];
+    let mut user_data_of_errors = Vec::new();
+    for _ in 0..5 {
+        match reader.recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                assert_eq!(
+                    c.range,
+                    Some(c.user_data as usize * 5..c.user_data as usize * 5 + 5)
+                );
+                decoded[c.user_data as usize] = Some(c.buffer.to_vec());
+            }
+            Ok(Err(err @ (IoError::Decode { .. } | IoError::NotFound { .. }))) => {
+                user_data_of_errors.push(err.user_data().unwrap())
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(
+        decoded,
+        [Some(b"hello".to_vec()), None, Some(b"world".to_vec())]
+    );
+    user_data_of_errors.sort();
+    assert_eq!(user_data_of_errors, [1, 3, 4]);
+    assert!(reader.try_recv().is_err());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_metadata() -> anyhow::Result<()> {
+    #[derive(Debug, PartialEq)]
+    struct Metadata {
+        array_index: usize,
+        codec: &'static str,
+    }
+
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 4).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("metadata", &file_contents)?;
+    let mut reader = MetadataReader::new(StdReader::new(2));
+
+    const N_RANGES: usize = 4;
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+    let metadata = (0..N_RANGES)
+        .map(|array_index| Metadata {
+            array_index,
+            codec: "zstd",
+        })
+        .collect();
+    reader.get_ranges_with(&filename, ranges, metadata)?;
+    // Every range of a missing file fails, so their metadata is dropped.
+    reader.get_ranges_with(
+        &filename.with_extension("missing"),
+        vec![0..1, 1..2],
+        vec![
+            Metadata {
+                array_index: 10,
+                codec: "none",
+            },
+            Metadata {
+                array_index: 11,
+                codec: "none",
+            },
+        ],
+    )?;
+    assert_eq!(reader.n_pending(), N_RANGES + 2);
+
+    let mut n_chunks = 0;
+    let mut n_errors = 0;
+    while n_chunks + n_errors < N_RANGES + 2 {
+        match reader.recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data.array_index * KIBIBYTE;
+                assert_eq!(c.user_data.codec, "zstd");
+                assert_eq!(c.buffer.as_slice(), &file_contents[start..start + KIBIBYTE]);
+                n_chunks += 1;
+            }
+            Ok(Err(IoError::NotFound { .. })) => n_errors += 1,
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_chunks, N_RANGES);
+    assert_eq!(n_errors, 2);
+    assert_eq!(reader.n_pending(), 0);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
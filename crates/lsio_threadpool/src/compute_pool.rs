@@ -0,0 +1,160 @@
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+};
+
+use crate::{ThreadPool, WorkerThread};
+
+/// A one-off task, submitted by [`ComputePool::spawn`].
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A general-purpose threadpool which runs arbitrary closures, and returns their results.
+///
+/// `ComputePool` is intended for CPU-bound work which isn't tied to a long-running per-thread
+/// loop, such as decompressing chunks after they've been read. Internally, `ComputePool` is a
+/// [`ThreadPool`] whose tasks are boxed closures, so it has the same work-stealing and parking
+/// behaviour as any other `ThreadPool`.
+///
+/// A `ComputePool` is always separate from the `ThreadPool` used by an IO backend (such as
+/// `lsio_uring`). An IO backend's worker closure owns its thread for the lifetime of the pool
+/// (e.g. to keep a thread-local io_uring busy), and its tasks are IO operations, not closures. A
+/// long-running closure submitted to the IO pool would stall every IO operation in flight on
+/// that thread. So the usual pattern is to create one IO pool and one `ComputePool`, and to
+/// `spawn` the processing of each completed chunk onto the `ComputePool`:
+///
+/// ```
+/// use lsio_threadpool::ComputePool;
+///
+/// let pool = ComputePool::new(4);
+/// let tasks: Vec<_> = (0..8u64).map(|i| pool.spawn(move || i * i)).collect();
+/// let results: Vec<u64> = tasks.into_iter().map(|task| task.join().unwrap()).collect();
+/// assert_eq!(results, [0, 1, 4, 9, 16, 25, 36, 49]);
+/// ```
+pub struct ComputePool {
+    pool: ThreadPool<Job>,
+}
+
+impl ComputePool {
+    /// Starts a new `ComputePool` with `n_worker_threads` threads. The worker threads will shut
+    /// down when the `ComputePool` goes out of scope, after finishing the tasks they're running.
+    /// Tasks which haven't started yet are dropped, so their [`Task::join`] returns an error.
+    pub fn new(n_worker_threads: usize) -> Self {
+        let pool = ThreadPool::new_named(
+            "lsio_compute",
+            n_worker_threads,
+            |worker_thread: WorkerThread<Job>| {
+                while worker_thread.keep_running() {
+                    if let Some(job) = worker_thread.find_task_or_park() {
+                        job();
+                    }
+                }
+            },
+        );
+        Self { pool }
+    }
+
+    /// Run `f` on one of the worker threads. Returns a [`Task`], which can be used to wait for
+    /// the result of `f`.
+    ///
+    /// If `f` panics then the panic is caught (so the worker thread keeps running) and the panic
+    /// is returned by [`Task::join`].
+    pub fn spawn<F, R>(&self, f: F) -> Task<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.pool.push(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            // The receiver may have been dropped, if the user isn't interested in the result.
+            let _ = tx.send(result);
+        }));
+        Task { rx }
+    }
+}
+
+impl fmt::Debug for ComputePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComputePool").finish_non_exhaustive()
+    }
+}
+
+/// A handle to a closure submitted by [`ComputePool::spawn`].
+///
+/// Dropping a `Task` doesn't cancel the closure. It just means that the result will be dropped.
+#[derive(Debug)]
+pub struct Task<R> {
+    rx: mpsc::Receiver<thread::Result<R>>,
+}
+
+impl<R> Task<R> {
+    /// Block until the closure has finished, and return its result. Returns `Err` if the closure
+    /// panicked (like [`std::thread::JoinHandle::join`]), or if the `ComputePool` was dropped
+    /// before the closure started.
+    pub fn join(self) -> thread::Result<R> {
+        self.rx.recv().unwrap_or_else(|_| {
+            Err(Box::new(
+                "The ComputePool was dropped before this task ran.",
+            ))
+        })
+    }
+
+    /// Return the result of the closure if it has finished, or `None` if it's still running.
+    /// Never blocks.
+    pub fn try_join(&self) -> Option<thread::Result<R>> {
+        match self.rx.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(Box::new(
+                "The ComputePool was dropped before this task ran.",
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_spawn_runs_tasks_on_every_thread() {
+        const N_THREADS: usize = 4;
+        let pool = ComputePool::new(N_THREADS);
+        thread::sleep(Duration::from_millis(10));
+        let tasks: Vec<_> = (0..N_THREADS * 8)
+            .map(|i| {
+                pool.spawn(move || {
+                    thread::sleep(Duration::from_millis(1));
+                    (i, thread::current().id())
+                })
+            })
+            .collect();
+        let results: Vec<_> = tasks.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(results.iter().map(|(i, _)| *i).eq(0..N_THREADS * 8));
+        let thread_ids: HashSet<_> = results.iter().map(|(_, id)| *id).collect();
+        assert_eq!(thread_ids.len(), N_THREADS);
+    }
+
+    #[test]
+    fn test_panicking_task_does_not_kill_worker() {
+        let pool = ComputePool::new(1);
+        let task = pool.spawn(|| -> u8 { panic!("oops") });
+        let payload = task.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"oops"));
+        assert_eq!(pool.spawn(|| 42).join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_try_join() {
+        let pool = ComputePool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let task = pool.spawn(move || rx.recv().unwrap());
+        assert!(task.try_join().is_none());
+        tx.send(()).unwrap();
+        task.join().unwrap();
+    }
+}
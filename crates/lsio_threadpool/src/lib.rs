@@ -1,9 +1,13 @@
 #![doc = include_str!("../README.md")]
 
+mod compute_pool;
 mod park_manager;
 mod shared_state;
+mod stats;
 mod threadpool;
 mod worker;
 
+pub use compute_pool::{ComputePool, Task};
+pub use stats::{PoolStats, ThreadStats};
 pub use threadpool::ThreadPool;
 pub use worker::WorkerThread;
@@ -1,7 +1,7 @@
 use std::{
     collections::VecDeque,
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
+        atomic::{AtomicUsize, Ordering::SeqCst},
         mpsc::{self, RecvError},
         Arc,
     },
@@ -9,26 +9,30 @@ use std::{
 };
 
 pub(crate) enum ParkManagerCommand {
-    WakeAtMostNThreads(u32),
-    ThreadIsParked(thread::Thread),
+    WakeAtMostNThreads(usize),
+    /// Wake the worker thread with this index, if it's parked.
+    WakeThread(usize),
+    /// The worker thread with this index is parked.
+    ThreadIsParked(usize, thread::Thread),
     Stop,
 }
 
 pub(crate) struct ParkManager {
     rx: mpsc::Receiver<ParkManagerCommand>,
-    at_least_one_thread_is_parked: Arc<AtomicBool>,
-    parked_threads: VecDeque<thread::Thread>,
+    n_parked_threads: Arc<AtomicUsize>,
+    /// The index and handle of each parked worker thread.
+    parked_threads: VecDeque<(usize, thread::Thread)>,
 }
 
 impl ParkManager {
     pub(crate) fn start(
         rx: mpsc::Receiver<ParkManagerCommand>,
-        at_least_one_thread_is_parked: Arc<AtomicBool>,
+        n_parked_threads: Arc<AtomicUsize>,
         n_worker_threads: usize,
     ) -> thread::JoinHandle<()> {
         let mut park_manager = Self {
             rx,
-            at_least_one_thread_is_parked,
+            n_parked_threads,
             parked_threads: VecDeque::with_capacity(n_worker_threads),
         };
         thread::Builder::new()
@@ -42,8 +46,9 @@ impl ParkManager {
         loop {
             match self.rx.recv() {
                 Ok(cmd) => match cmd {
-                    ThreadIsParked(t) => self.thread_is_parked(t),
+                    ThreadIsParked(index, t) => self.thread_is_parked(index, t),
                     WakeAtMostNThreads(n) => self.wake_at_most_n_threads(n),
+                    WakeThread(index) => self.wake_thread(index),
                     Stop => break,
                 },
                 Err(RecvError) => break,
@@ -51,21 +56,30 @@ impl ParkManager {
         }
     }
 
-    fn thread_is_parked(&mut self, t: thread::Thread) {
-        self.at_least_one_thread_is_parked.store(true, Relaxed);
-        debug_assert!(!self.parked_threads.iter().any(|pt| pt.id() == t.id()));
-        self.parked_threads.push_back(t);
+    fn thread_is_parked(&mut self, index: usize, t: thread::Thread) {
+        if self.parked_threads.iter().any(|(i, _)| *i == index) {
+            // `WorkerThread::park` returned without parking last time (because it found a new
+            // task), so this thread is already registered. Don't count it twice.
+            self.n_parked_threads.fetch_sub(1, SeqCst);
+        } else {
+            self.parked_threads.push_back((index, t));
+        }
     }
 
-    fn wake_at_most_n_threads(&mut self, n: u32) {
-        for _ in 0..n {
-            match self.parked_threads.pop_front() {
-                Some(thread) => thread.unpark(),
-                None => break,
-            }
+    fn wake_at_most_n_threads(&mut self, n: usize) {
+        let n = n.min(self.parked_threads.len());
+        self.n_parked_threads.fetch_sub(n, SeqCst);
+        for (_, thread) in self.parked_threads.drain(..n) {
+            thread.unpark();
         }
-        if self.parked_threads.is_empty() {
-            self.at_least_one_thread_is_parked.store(false, Relaxed);
+    }
+
+    fn wake_thread(&mut self, index: usize) {
+        // If the thread isn't parked then it will find its task without being woken.
+        if let Some(position) = self.parked_threads.iter().position(|(i, _)| *i == index) {
+            let (_, thread) = self.parked_threads.remove(position).unwrap();
+            self.n_parked_threads.fetch_sub(1, SeqCst);
+            thread.unpark();
         }
     }
 }
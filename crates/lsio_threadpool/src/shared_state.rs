@@ -1,11 +1,11 @@
 use std::sync::{
-    atomic::{AtomicBool, Ordering::Relaxed},
+    atomic::{fence, AtomicBool, AtomicUsize, Ordering::SeqCst},
     mpsc, Arc,
 };
 
 use crossbeam_deque as deque;
 
-use crate::park_manager::ParkManagerCommand;
+use crate::{park_manager::ParkManagerCommand, stats::ThreadCounters};
 
 /// `ThreadPool` owns a `SharedState<T>`, and each `WorkerThread` owns a cloned `SharedState<T>`.
 #[derive(Debug)]
@@ -14,20 +14,62 @@ where
     T: Send,
 {
     pub(crate) injector: Arc<deque::Injector<T>>,
+    /// One "inbox" per worker thread. [`crate::ThreadPool::push`] deposits tasks into the inboxes
+    /// in rotation, so a burst of tasks is spread across the worker threads without every task
+    /// having to pass through `injector`. Crossbeam's local queues can only be pushed to by their
+    /// owning thread, so each inbox is an `Injector`, which its worker thread moves into its local
+    /// queue. Other worker threads may steal from any inbox.
+    pub(crate) inboxes: Arc<Vec<deque::Injector<T>>>,
     pub(crate) keep_running: Arc<AtomicBool>,
     pub(crate) chan_to_park_manager: mpsc::Sender<ParkManagerCommand>,
-    pub(crate) at_least_one_thread_is_parked: Arc<AtomicBool>,
+    /// The number of threads which have registered with the `ParkManager` as parked, and which
+    /// the `ParkManager` hasn't unparked yet. Incremented by [`crate::WorkerThread::park`], and
+    /// decremented by the `ParkManager`.
+    pub(crate) n_parked_threads: Arc<AtomicUsize>,
+    /// One set of counters per worker thread, indexed by the worker thread's index.
+    pub(crate) counters: Arc<Vec<ThreadCounters>>,
 }
 
 impl<T> SharedState<T>
 where
     T: Send,
 {
-    pub(crate) fn unpark_at_most_n_threads(&self, n: u32) {
-        if self.at_least_one_thread_is_parked.load(Relaxed) {
-            self.chan_to_park_manager
-                .send(ParkManagerCommand::WakeAtMostNThreads(n))
-                .unwrap();
+    /// Returns true if `injector` or the inbox of the worker thread with this `index` holds any
+    /// tasks, or if another thread's inbox holds tasks that can be stolen.
+    pub(crate) fn has_tasks_for_thread(&self, index: usize) -> bool {
+        !self.injector.is_empty()
+            || self
+                .inboxes
+                .iter()
+                .enumerate()
+                .any(|(i, inbox)| !inbox.is_empty() && (i == index || inbox.len() > 1))
+    }
+
+    /// Must be called _after_ pushing new tasks onto a queue.
+    pub(crate) fn unpark_at_most_n_threads(&self, n: usize) {
+        self.send_if_any_thread_is_parked(ParkManagerCommand::WakeAtMostNThreads(n));
+    }
+
+    /// Unpark one parked thread for each task waiting in `injector` (or every parked thread, if
+    /// there are more tasks than parked threads). Must be called _after_ pushing a new task onto
+    /// `injector`. Tasks only go to `injector` when every inbox is full, so a burst of tasks
+    /// shouldn't have to wait for parked threads to be woken one at a time.
+    pub(crate) fn unpark_for_injected_tasks(&self) {
+        self.unpark_at_most_n_threads(self.injector.len().max(1));
+    }
+
+    /// Unpark the worker thread with this `index` (if it's parked). Must be called _after_
+    /// pushing a new task onto that thread's inbox.
+    pub(crate) fn unpark_thread(&self, index: usize) {
+        self.send_if_any_thread_is_parked(ParkManagerCommand::WakeThread(index));
+    }
+
+    fn send_if_any_thread_is_parked(&self, cmd: ParkManagerCommand) {
+        // This fence pairs with the fence in `WorkerThread::park`: Either the parking thread will
+        // see our new task, or we will see that the thread is parked (or both).
+        fence(SeqCst);
+        if self.n_parked_threads.load(SeqCst) > 0 {
+            self.chan_to_park_manager.send(cmd).unwrap();
         }
     }
 }
@@ -39,9 +81,11 @@ where
     fn clone(&self) -> Self {
         Self {
             injector: Arc::clone(&self.injector),
+            inboxes: Arc::clone(&self.inboxes),
             keep_running: Arc::clone(&self.keep_running),
             chan_to_park_manager: self.chan_to_park_manager.clone(),
-            at_least_one_thread_is_parked: Arc::clone(&self.at_least_one_thread_is_parked),
+            n_parked_threads: Arc::clone(&self.n_parked_threads),
+            counters: Arc::clone(&self.counters),
         }
     }
 }
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// The counters of a single worker thread. Each worker thread only updates its own counters, with
+/// `Relaxed` ordering, so updating the counters costs almost nothing. Aligned to 128 bytes so that
+/// the counters of different worker threads don't share a cache line.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+pub(crate) struct ThreadCounters {
+    local_pops: AtomicU64,
+    inbox_pops: AtomicU64,
+    injector_pops: AtomicU64,
+    successful_steals: AtomicU64,
+    failed_steals: AtomicU64,
+}
+
+impl ThreadCounters {
+    pub(crate) fn add_local_pop(&self) {
+        self.local_pops.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn add_inbox_pop(&self) {
+        self.inbox_pops.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn add_injector_pop(&self) {
+        self.injector_pops.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn add_steal(&self, successful: bool) {
+        match successful {
+            true => self.successful_steals.fetch_add(1, Relaxed),
+            false => self.failed_steals.fetch_add(1, Relaxed),
+        };
+    }
+
+    pub(crate) fn snapshot(&self) -> ThreadStats {
+        ThreadStats {
+            local_pops: self.local_pops.load(Relaxed),
+            inbox_pops: self.inbox_pops.load(Relaxed),
+            injector_pops: self.injector_pops.load(Relaxed),
+            successful_steals: self.successful_steals.load(Relaxed),
+            failed_steals: self.failed_steals.load(Relaxed),
+        }
+    }
+}
+
+/// Where one worker thread has found its tasks. Returned by [`crate::WorkerThread::stats`], and
+/// (for every worker thread) by [`crate::ThreadPool::stats`].
+///
+/// The counters are updated with `Relaxed` ordering, so they're only approximately consistent
+/// with each other whilst the worker thread is running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// The number of tasks popped from this thread's local queue.
+    pub local_pops: u64,
+    /// The number of times that this thread took a batch of tasks from its own inbox.
+    pub inbox_pops: u64,
+    /// The number of times that this thread took a batch of tasks from the global injector queue.
+    pub injector_pops: u64,
+    /// The number of tasks that this thread stole from other threads (from their inboxes or their
+    /// local queues).
+    pub successful_steals: u64,
+    /// The number of times that this thread tried to steal from the other threads, but found
+    /// nothing to steal (or had to retry because of contention). An idle thread counts a failed
+    /// steal each time it looks for a task.
+    pub failed_steals: u64,
+}
+
+impl ThreadStats {
+    fn add(self, other: Self) -> Self {
+        Self {
+            local_pops: self.local_pops + other.local_pops,
+            inbox_pops: self.inbox_pops + other.inbox_pops,
+            injector_pops: self.injector_pops + other.injector_pops,
+            successful_steals: self.successful_steals + other.successful_steals,
+            failed_steals: self.failed_steals + other.failed_steals,
+        }
+    }
+}
+
+/// The [`ThreadStats`] of every worker thread in a [`crate::ThreadPool`]. Returned by
+/// [`crate::ThreadPool::stats`].
+///
+/// For example, if one thread's `injector_pops` is much larger than the other threads', then that
+/// thread is hogging the injector. Lots of `successful_steals` mean that the tasks weren't spread
+/// evenly when they were pushed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// One entry per worker thread, indexed by [`crate::WorkerThread::index`].
+    pub threads: Vec<ThreadStats>,
+}
+
+impl PoolStats {
+    /// The sum of the counters of every worker thread.
+    pub fn total(&self) -> ThreadStats {
+        self.threads
+            .iter()
+            .fold(ThreadStats::default(), |total, &stats| total.add(stats))
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
         mpsc::{self},
         Arc,
     },
@@ -12,9 +12,14 @@ use crossbeam_deque as deque;
 use crate::{
     park_manager::{ParkManager, ParkManagerCommand},
     shared_state::SharedState,
+    stats::{PoolStats, ThreadCounters},
     worker::WorkerThread,
 };
 
+/// [`ThreadPool::push`] skips any worker thread whose inbox already holds at least this many
+/// tasks.
+const MAX_TASKS_PER_INBOX: usize = 32;
+
 /// Manages a pool of worker threads. Each worker thread runs a clone of a user-supplied closure.
 #[derive(Debug)]
 pub struct ThreadPool<T>
@@ -24,6 +29,8 @@ where
     worker_thread_handles: Vec<JoinHandle<()>>,
     park_manager_thread_handle: Option<JoinHandle<()>>,
     shared: SharedState<T>,
+    /// The inbox that [`ThreadPool::push`] will try first.
+    next_inbox: AtomicUsize,
 }
 
 impl<T> ThreadPool<T>
@@ -36,6 +43,9 @@ where
     ///
     /// `new` also starts a separate thread which is responsible for tracking parked threads.
     ///
+    /// The worker threads are named `lsio_worker_0`, `lsio_worker_1`, etc. (which is how they
+    /// appear in `top`, `perf` and `gdb`). Use [`ThreadPool::new_named`] to choose the names.
+    ///
     /// Note that the `'static` lifetime constraint for `OP` basically just means that `op` can't
     /// capture any non-`'static` references. It's perfectly fine for `op` to capture owned types
     /// (such as `Vec`), as long as those owned types don't include any non-`'static` references.
@@ -76,21 +86,45 @@ where
     /// ```
     ///
     pub fn new<OP>(n_worker_threads: usize, op: OP) -> Self
+    where
+        OP: Fn(WorkerThread<T>) + Send + Clone + 'static,
+    {
+        Self::new_named("lsio_worker", n_worker_threads, op)
+    }
+
+    /// Like [`ThreadPool::new`], except that the worker threads are named `{name_prefix}_0`,
+    /// `{name_prefix}_1`, etc. Linux truncates thread names to 15 bytes, so keep `name_prefix`
+    /// short enough that the thread index isn't cut off.
+    ///
+    /// # Panics
+    /// If a worker thread can't be spawned (e.g. because the process has hit its limit on the
+    /// number of threads), or if `name_prefix` contains a NUL byte.
+    pub fn new_named<OP>(name_prefix: &str, n_worker_threads: usize, op: OP) -> Self
     where
         OP: Fn(WorkerThread<T>) + Send + Clone + 'static,
     {
         let (chan_to_park_manager, rx_for_park_manager) = mpsc::channel();
         let shared = SharedState {
             injector: Arc::new(deque::Injector::new()),
+            inboxes: Arc::new(
+                (0..n_worker_threads)
+                    .map(|_| deque::Injector::new())
+                    .collect(),
+            ),
             keep_running: Arc::new(AtomicBool::new(true)),
             chan_to_park_manager,
-            at_least_one_thread_is_parked: Arc::new(AtomicBool::new(false)),
+            n_parked_threads: Arc::new(AtomicUsize::new(0)),
+            counters: Arc::new(
+                (0..n_worker_threads)
+                    .map(|_| ThreadCounters::default())
+                    .collect(),
+            ),
         };
 
         // Spawn ParkManager thread:
         let park_manager_thread_handle = Some(ParkManager::start(
             rx_for_park_manager,
-            Arc::clone(&shared.at_least_one_thread_is_parked),
+            Arc::clone(&shared.n_parked_threads),
             n_worker_threads,
         ));
 
@@ -107,15 +141,20 @@ where
 
         // Spawn worker threads:
         let worker_thread_handles = (0..n_worker_threads)
-            .map(|_| {
+            .map(|index| {
                 let work_stealer = WorkerThread::new(
                     shared.clone(),
+                    index,
                     local_queues.pop().unwrap(),
                     Arc::clone(&stealers),
                 );
 
                 let op_clone = op.clone();
-                thread::spawn(move || (op_clone)(work_stealer))
+                let name = format!("{name_prefix}_{index}");
+                thread::Builder::new()
+                    .name(name.clone())
+                    .spawn(move || (op_clone)(work_stealer))
+                    .unwrap_or_else(|err| panic!("Failed to spawn worker thread {name}: {err}"))
             })
             .collect();
 
@@ -123,17 +162,51 @@ where
             worker_thread_handles,
             park_manager_thread_handle,
             shared,
+            next_inbox: AtomicUsize::new(0),
         }
     }
 
-    /// Push a task from outside the threadpool into the global
-    /// "[injector](crossbeam_deque::Injector)" queue.
+    /// Push a task from outside the threadpool.
     /// This is how users of `ThreadPool` submit tasks to the threadpool.
     ///
-    /// `push` will automatically unpark worker threads if necessary.
+    /// Tasks are deposited into each worker thread's inbox in rotation, so that a burst of tasks
+    /// is spread across all the worker threads. Inboxes which already hold lots of tasks are
+    /// skipped. If every inbox is full then the task is pushed onto the global
+    /// "[injector](crossbeam_deque::Injector)" queue. Either way, any worker thread may steal the
+    /// task.
+    ///
+    /// `push` will automatically unpark worker threads if necessary. (A task deposited into an
+    /// inbox unparks the inbox's own thread. A task pushed onto the injector unparks one thread
+    /// per task waiting in the injector.)
     pub fn push(&self, task: T) {
-        self.shared.injector.push(task);
-        self.shared.unpark_at_most_n_threads(1);
+        let inboxes = &self.shared.inboxes;
+        let first = self.next_inbox.fetch_add(1, Relaxed);
+        let index = (first..first + inboxes.len())
+            .map(|i| i % inboxes.len())
+            .find(|&i| inboxes[i].len() < MAX_TASKS_PER_INBOX);
+        match index {
+            Some(index) => {
+                inboxes[index].push(task);
+                self.shared.unpark_thread(index);
+            }
+            None => {
+                self.shared.injector.push(task);
+                self.shared.unpark_for_injected_tasks();
+            }
+        }
+    }
+
+    /// Where each worker thread has found its tasks. Useful for checking whether the work is
+    /// balanced across the worker threads. See [`PoolStats`].
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            threads: self
+                .shared
+                .counters
+                .iter()
+                .map(ThreadCounters::snapshot)
+                .collect(),
+        }
     }
 }
 
@@ -266,4 +339,266 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_push_spreads_a_burst_across_threads() {
+        const N_THREADS: usize = 4;
+        const N_TASKS: usize = N_THREADS * 8;
+
+        let (output_tx, output_rx) = mpsc::channel::<usize>();
+        let n_tasks_per_thread = Arc::new(Mutex::new(HashMap::new()));
+        let pool = ThreadPool::new(N_THREADS, {
+            let n_tasks_per_thread = Arc::clone(&n_tasks_per_thread);
+            move |worker_thread: WorkerThread<usize>| {
+                while worker_thread.keep_running() {
+                    match worker_thread.find_task() {
+                        Some(task) => {
+                            add_one_to_hash(&n_tasks_per_thread);
+                            thread::sleep(Duration::from_millis(1));
+                            output_tx.send(task).unwrap();
+                        }
+                        None => worker_thread.park(),
+                    };
+                }
+            }
+        });
+
+        // Let the worker threads park, and then push all the tasks at once:
+        thread::sleep(Duration::from_millis(10));
+        for i in 0..N_TASKS {
+            pool.push(i);
+        }
+        let mut outputs: Vec<usize> = output_rx.iter().take(N_TASKS).collect();
+        outputs.sort();
+        assert!(outputs.into_iter().eq(0..N_TASKS));
+        drop(pool);
+
+        // Each thread's inbox received an equal share of the burst. Other threads may have stolen
+        // some of those tasks, but every thread should have done a reasonable share of the work.
+        let n_tasks_per_thread =
+            Mutex::into_inner(Arc::into_inner(n_tasks_per_thread).unwrap()).unwrap();
+        assert_eq!(
+            n_tasks_per_thread.len(),
+            N_THREADS,
+            "{n_tasks_per_thread:?}"
+        );
+        const MIN_TASKS_PER_THREAD: usize = N_TASKS / N_THREADS / 2;
+        for (thread_id, n_tasks) in n_tasks_per_thread.iter() {
+            assert!(
+                *n_tasks >= MIN_TASKS_PER_THREAD,
+                "{thread_id:?} only did {n_tasks} tasks, which is < the threshold {MIN_TASKS_PER_THREAD} tasks!"
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_task_batch_never_returns_more_than_max() {
+        const N_TASKS: usize = 64;
+        const MAX: usize = 5;
+
+        let (output_tx, output_rx) = mpsc::channel::<Vec<usize>>();
+        let pool = ThreadPool::new(2, move |worker_thread: WorkerThread<usize>| {
+            assert!(worker_thread.find_task_batch(0).is_empty());
+            while worker_thread.keep_running() {
+                let tasks = worker_thread.find_task_batch(MAX);
+                if tasks.is_empty() {
+                    worker_thread.park();
+                } else {
+                    output_tx.send(tasks).unwrap();
+                }
+            }
+        });
+
+        // Let the worker threads park, and then push all the tasks at once:
+        thread::sleep(Duration::from_millis(10));
+        for i in 0..N_TASKS {
+            pool.push(i);
+        }
+        let mut outputs = Vec::new();
+        while outputs.len() < N_TASKS {
+            let batch = output_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+            assert!(!batch.is_empty() && batch.len() <= MAX, "{batch:?}");
+            outputs.extend(batch);
+        }
+        outputs.sort();
+        assert!(outputs.into_iter().eq(0..N_TASKS));
+    }
+
+    #[test]
+    fn test_burst_of_tasks_wakes_every_thread() {
+        const N_THREADS: usize = 4;
+        const N_TASKS: usize = 10_000;
+
+        let (output_tx, output_rx) = mpsc::channel::<usize>();
+        let n_tasks_per_thread = Arc::new(Mutex::new(HashMap::new()));
+        let pool = ThreadPool::new(N_THREADS, {
+            let n_tasks_per_thread = Arc::clone(&n_tasks_per_thread);
+            move |worker_thread: WorkerThread<usize>| {
+                while worker_thread.keep_running() {
+                    match worker_thread.find_task() {
+                        Some(task) => {
+                            add_one_to_hash(&n_tasks_per_thread);
+                            thread::sleep(Duration::from_micros(10));
+                            output_tx.send(task).unwrap();
+                        }
+                        None => worker_thread.park(),
+                    };
+                }
+            }
+        });
+
+        // Let the worker threads park, and then push all the tasks at once. Most of the tasks
+        // overflow the inboxes, and go onto the injector.
+        thread::sleep(Duration::from_millis(10));
+        for i in 0..N_TASKS {
+            pool.push(i);
+        }
+        let mut outputs: Vec<usize> = output_rx.iter().take(N_TASKS).collect();
+        outputs.sort();
+        assert!(outputs.into_iter().eq(0..N_TASKS));
+        drop(pool);
+
+        let n_tasks_per_thread =
+            Mutex::into_inner(Arc::into_inner(n_tasks_per_thread).unwrap()).unwrap();
+        assert_eq!(
+            n_tasks_per_thread.len(),
+            N_THREADS,
+            "{n_tasks_per_thread:?}"
+        );
+        const MIN_TASKS_PER_THREAD: usize = N_TASKS / N_THREADS / 4;
+        for (thread_id, n_tasks) in n_tasks_per_thread.iter() {
+            assert!(
+                *n_tasks >= MIN_TASKS_PER_THREAD,
+                "{thread_id:?} only did {n_tasks} tasks, which is < the threshold {MIN_TASKS_PER_THREAD} tasks!"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stats_count_where_tasks_were_found() {
+        const N_THREADS: usize = 4;
+        const N_LOCAL_TASKS: usize = 64;
+        const N_PUSHED_TASKS: usize = 1_000;
+        // The thread which receives this task pushes `N_LOCAL_TASKS` tasks onto its local queue,
+        // and then sleeps, so the other threads have to steal them.
+        const SPAWN: usize = usize::MAX;
+
+        let (output_tx, output_rx) = mpsc::channel::<usize>();
+        let pool = ThreadPool::new(N_THREADS, move |worker_thread: WorkerThread<usize>| {
+            while worker_thread.keep_running() {
+                match worker_thread.find_task_or_park() {
+                    Some(SPAWN) => {
+                        for i in 0..N_LOCAL_TASKS {
+                            worker_thread.push(i);
+                        }
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Some(task) => {
+                        thread::sleep(Duration::from_micros(10));
+                        output_tx.send(task).unwrap();
+                    }
+                    None => (),
+                }
+            }
+        });
+
+        thread::sleep(Duration::from_millis(10));
+        pool.push(SPAWN);
+        assert_eq!(output_rx.iter().take(N_LOCAL_TASKS).count(), N_LOCAL_TASKS);
+        // Push a burst of tasks, most of which overflow the inboxes and go onto the injector.
+        for i in 0..N_PUSHED_TASKS {
+            pool.push(i);
+        }
+        assert_eq!(
+            output_rx.iter().take(N_PUSHED_TASKS).count(),
+            N_PUSHED_TASKS
+        );
+
+        let stats = pool.stats();
+        assert_eq!(stats.threads.len(), N_THREADS);
+        let total = stats.total();
+        assert!(total.local_pops > 0, "{stats:?}");
+        assert!(total.inbox_pops > 0, "{stats:?}");
+        assert!(total.injector_pops > 0, "{stats:?}");
+        assert!(total.successful_steals > 0, "{stats:?}");
+        // Every thread looked for a task (and found nothing) before the first task was pushed.
+        for thread_stats in &stats.threads {
+            assert!(thread_stats.failed_steals > 0, "{stats:?}");
+        }
+    }
+
+    /// The CPU time (user + system) used so far by the calling thread, read from
+    /// `/proc/thread-self/stat`.
+    fn cpu_time_of_this_thread() -> Duration {
+        let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+        // The command name (the 2nd field) is in parentheses, and may contain spaces.
+        let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+        // utime and stime are the 14th and 15th fields, measured in clock ticks. The kernel
+        // always reports clock ticks to userspace at 100 Hz.
+        let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+        Duration::from_millis(ticks * 10)
+    }
+
+    #[test]
+    fn test_idle_threads_do_not_burn_cpu() {
+        const N_THREADS: usize = 4;
+        const IDLE_TIME: Duration = Duration::from_millis(500);
+
+        let (output_tx, output_rx) = mpsc::channel::<usize>();
+        let (cpu_time_tx, cpu_time_rx) = mpsc::channel::<Duration>();
+        let pool = ThreadPool::new(N_THREADS, move |worker_thread: WorkerThread<usize>| {
+            while worker_thread.keep_running() {
+                if let Some(task) = worker_thread.find_task_or_park() {
+                    output_tx.send(task).unwrap();
+                }
+            }
+            cpu_time_tx.send(cpu_time_of_this_thread()).unwrap();
+        });
+
+        // Give the threads some work, and then leave the pool idle.
+        for i in 0..N_THREADS * 4 {
+            pool.push(i);
+        }
+        assert_eq!(output_rx.iter().take(N_THREADS * 4).count(), N_THREADS * 4);
+        thread::sleep(IDLE_TIME);
+        drop(pool);
+
+        // A thread which busy-looped over `find_task` would use roughly `IDLE_TIME` of CPU.
+        let cpu_times: Vec<Duration> = cpu_time_rx.iter().collect();
+        assert_eq!(cpu_times.len(), N_THREADS);
+        for cpu_time in cpu_times {
+            assert!(cpu_time < IDLE_TIME / 5, "{cpu_time:?}");
+        }
+    }
+
+    #[test]
+    fn test_worker_threads_are_named() {
+        const N_THREADS: usize = 3;
+        let names = |new_pool: &dyn Fn(mpsc::Sender<(usize, String)>) -> ThreadPool<usize>| {
+            let (tx, rx) = mpsc::channel();
+            let pool = new_pool(tx);
+            let mut names: Vec<(usize, String)> = rx.iter().take(N_THREADS).collect();
+            drop(pool);
+            names.sort();
+            names
+        };
+        let op = |tx: mpsc::Sender<(usize, String)>| {
+            move |worker_thread: WorkerThread<usize>| {
+                let name = thread::current().name().unwrap().to_string();
+                tx.send((worker_thread.index(), name)).unwrap();
+            }
+        };
+
+        let default_names = names(&|tx| ThreadPool::new(N_THREADS, op(tx)));
+        let expected: Vec<(usize, String)> = (0..N_THREADS)
+            .map(|i| (i, format!("lsio_worker_{i}")))
+            .collect();
+        assert_eq!(default_names, expected);
+
+        let custom_names = names(&|tx| ThreadPool::new_named("my_pool", N_THREADS, op(tx)));
+        let expected: Vec<(usize, String)> = (0..N_THREADS)
+            .map(|i| (i, format!("my_pool_{i}")))
+            .collect();
+        assert_eq!(custom_names, expected);
+    }
 }
@@ -1,12 +1,22 @@
 use std::{
-    iter,
-    sync::{atomic::Ordering::Relaxed, Arc},
+    hint, iter,
+    sync::{
+        atomic::{
+            fence,
+            Ordering::{Relaxed, SeqCst},
+        },
+        Arc,
+    },
     thread,
 };
 
 use crossbeam_deque as deque;
 
-use crate::{park_manager::ParkManagerCommand, shared_state::SharedState};
+use crate::{
+    park_manager::ParkManagerCommand,
+    shared_state::SharedState,
+    stats::{ThreadCounters, ThreadStats},
+};
 
 /// Provides methods that allow user-defined closures to find new tasks to work on,
 /// submit new tasks, park this thread, and check if the closure should continue looping.
@@ -20,6 +30,9 @@ where
 {
     shared: SharedState<T>,
 
+    /// The index of this thread's inbox in `shared.inboxes`.
+    index: usize,
+
     /// Queues for implementing work-stealing:
     local_queue: deque::Worker<T>,
     stealers: Arc<Vec<deque::Stealer<T>>>,
@@ -31,36 +44,145 @@ where
 {
     pub(crate) fn new(
         shared: SharedState<T>,
+        index: usize,
         local_queue: deque::Worker<T>,
         stealers: Arc<Vec<deque::Stealer<T>>>,
     ) -> Self {
         Self {
             shared,
+            index,
             local_queue,
             stealers,
         }
     }
 
+    /// The index of this worker thread, in the range `0..n_worker_threads`. This can be used to
+    /// find per-thread state (e.g. statistics) which was set up before the `ThreadPool` started.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Where this worker thread has found its tasks so far. See [`ThreadStats`].
+    pub fn stats(&self) -> ThreadStats {
+        self.counters().snapshot()
+    }
+
+    fn counters(&self) -> &ThreadCounters {
+        &self.shared.counters[self.index]
+    }
+
     /// Get the next task to work on. This function never blocks.
     pub fn find_task(&self) -> Option<T> {
         // Adapted from https://docs.rs/crossbeam-deque/latest/crossbeam_deque/#examples
 
         // Pop a task from the local queue, if not empty.
-        self.local_queue.pop().or_else(|| {
-            // Otherwise, we need to look for a task elsewhere.
-            iter::repeat_with(|| {
-                // Try stealing a batch of tasks from the global queue.
-                self.shared
-                    .injector
-                    .steal_batch_and_pop(&self.local_queue)
-                    // Or try stealing a task from one of the other threads.
-                    .or_else(|| self.stealers.iter().map(|s| s.steal()).collect())
-            })
-            // Loop while no task was stolen and any steal operation needs to be retried.
-            .find(|s| !s.is_retry())
-            // Extract the stolen task, if there is one.
-            .and_then(|s| s.success())
+        if let Some(task) = self.local_queue.pop() {
+            self.counters().add_local_pop();
+            return Some(task);
+        }
+        // Otherwise, we need to look for a task elsewhere.
+        iter::repeat_with(|| {
+            // Try taking a batch of tasks from this thread's inbox.
+            let steal = self.shared.inboxes[self.index].steal_batch_and_pop(&self.local_queue);
+            if steal.is_success() {
+                self.counters().add_inbox_pop();
+                return steal;
+            }
+            // Or try stealing a batch of tasks from the global queue.
+            steal
+                .or_else(|| {
+                    let steal = self.shared.injector.steal_batch_and_pop(&self.local_queue);
+                    if steal.is_success() {
+                        self.counters().add_injector_pop();
+                    }
+                    steal
+                })
+                .or_else(|| self.steal_from_other_threads())
         })
+        // Loop while no task was stolen and any steal operation needs to be retried.
+        .find(|s| !s.is_retry())
+        // Extract the stolen task, if there is one.
+        .and_then(|s| s.success())
+    }
+
+    /// Get the next task to work on, or park this thread if there are no tasks.
+    ///
+    /// If [`WorkerThread::find_task`] returns `None` then this function retries a few times
+    /// (calling [`std::hint::spin_loop`] between attempts), because a task which is pushed within
+    /// the next microsecond or so is cheaper to pick up than to be unparked for. If there
+    /// is still no task then this function calls [`WorkerThread::park`], and then checks for a
+    /// task once more after waking. So, whilst the pool is idle, each worker thread sleeps
+    /// instead of burning CPU by looping over `find_task`.
+    ///
+    /// Returns `None` if there is still no task after waking (e.g. because the thread was woken
+    /// spuriously, or because the `ThreadPool` is shutting down). So the caller should check
+    /// [`WorkerThread::keep_running`] and then call `find_task_or_park` again:
+    ///
+    /// ```
+    /// # use lsio_threadpool::{ThreadPool, WorkerThread};
+    /// let pool = ThreadPool::new(2, |worker_thread: WorkerThread<u64>| {
+    ///     while worker_thread.keep_running() {
+    ///         if let Some(task) = worker_thread.find_task_or_park() {
+    ///             println!("{task}");
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    pub fn find_task_or_park(&self) -> Option<T> {
+        const N_SPINS: usize = 16;
+        for _ in 0..N_SPINS {
+            if let Some(task) = self.find_task() {
+                return Some(task);
+            }
+            hint::spin_loop();
+        }
+        self.park();
+        self.find_task()
+    }
+
+    /// Get up to `max` tasks to work on. This function never blocks, and returns an empty `Vec` if
+    /// there are no tasks (or if `max` is zero).
+    ///
+    /// Like [`WorkerThread::find_task`], this steals a batch of tasks into this thread's local
+    /// queue (if the local queue is empty). The tasks are then popped from the local queue, which
+    /// requires no synchronization with other threads. So taking several tasks at once is cheaper
+    /// than calling `find_task` several times. But the returned tasks can no longer be stolen by
+    /// other threads, so `max` should be no more than the number of tasks that the caller can
+    /// start straight away.
+    pub fn find_task_batch(&self, max: usize) -> Vec<T> {
+        if max == 0 {
+            return Vec::new();
+        }
+        let Some(first_task) = self.find_task() else {
+            return Vec::new();
+        };
+        let mut tasks = Vec::with_capacity(max.min(self.local_queue.len() + 1));
+        tasks.push(first_task);
+        tasks.extend(iter::from_fn(|| self.local_queue.pop()).take(max - 1));
+        tasks
+    }
+
+    /// Steal a task from the other threads' inboxes or, failing that, from one of the other
+    /// threads' local queues.
+    fn steal_from_other_threads(&self) -> deque::Steal<T> {
+        let steal = self
+            .steal_from_other_inboxes()
+            .or_else(|| self.stealers.iter().map(|s| s.steal()).collect());
+        self.counters().add_steal(steal.is_success());
+        steal
+    }
+
+    /// Steal a task from another thread's inbox. We leave the last task in each inbox for the
+    /// inbox's owner, which was unparked when that task was pushed. (If we stole the owner's only
+    /// task then the owner would wake up to find nothing to do.)
+    fn steal_from_other_inboxes(&self) -> deque::Steal<T> {
+        self.shared
+            .inboxes
+            .iter()
+            .enumerate()
+            .filter(|&(i, inbox)| i != self.index && inbox.len() > 1)
+            .map(|(_, inbox)| inbox.steal())
+            .collect()
     }
 
     /// Returns true if the task should keep running.
@@ -72,16 +194,34 @@ where
     ///
     /// Before parking, this function will register this thread with the `ParkManager`
     /// so that this thread can be automatically unparked when necessary.
+    ///
+    /// Returns without parking if a task was pushed onto the global queue (or onto this thread's
+    /// inbox) since the last call to `find_task`. Like [`std::thread::park`], `park` may also
+    /// return spuriously. So the caller should always check for new tasks after `park` returns.
     pub fn park(&self) {
         self.shared
             .chan_to_park_manager
-            .send(ParkManagerCommand::ThreadIsParked(thread::current()))
+            .send(ParkManagerCommand::ThreadIsParked(
+                self.index,
+                thread::current(),
+            ))
             .unwrap_or_else(|e| {
                 panic!(
                     "failed to send ThreadIsParked({:?}) message to ParkManager! {e:?}",
                     thread::current(),
                 )
             });
+        self.shared.n_parked_threads.fetch_add(1, SeqCst);
+
+        // A task might have been pushed after our last call to `find_task`, but before we
+        // incremented `n_parked_threads`, in which case nobody will unpark us. So check again.
+        // This fence pairs with the fence in `SharedState::send_if_any_thread_is_parked`.
+        fence(SeqCst);
+        if self.shared.has_tasks_for_thread(self.index) {
+            // We're still registered with the `ParkManager`, so we might be woken spuriously
+            // later on. That's fine.
+            return;
+        }
         thread::park();
     }
 
@@ -96,7 +236,7 @@ where
     fn maybe_unpark_other_threads(&self) {
         let n = self.local_queue.len();
         if n > 1 {
-            self.shared.unpark_at_most_n_threads(n as _);
+            self.shared.unpark_at_most_n_threads(n);
         }
     }
 }
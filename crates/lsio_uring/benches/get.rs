@@ -1,5 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use light_speed_io::object_store_adapter::ObjectStoreAdapter;
+use lsio_uring::{IoUring, ObjectStoreAdapter};
 use object_store::{path::Path as ObjectStorePath, ObjectStore};
 use std::{
     ops::Range,
@@ -10,13 +10,14 @@ use tokio::runtime::Runtime;
 
 const FILE_SIZE_BYTES: usize = 262_144;
 const DATA_PATH: &str = "/mnt/t700-2tb/fio/";
-const RANGE: Range<isize> = 0..(1024 * 16);
+const RANGE: Range<usize> = 0..(1024 * 16);
+const N_WORKER_THREADS: usize = 4;
 
 async fn uring_get(filenames: &Vec<ObjectStorePath>, n_iterations: u64) -> Duration {
     let mut total_time = Duration::ZERO;
     for _ in 0..n_iterations {
         // Setup (not timed):
-        let store = ObjectStoreAdapter::default();
+        let store = ObjectStoreAdapter::new(IoUring::new(N_WORKER_THREADS));
         clear_page_cache();
         let mut futures = Vec::with_capacity(filenames.len());
 
@@ -26,8 +27,9 @@ async fn uring_get(filenames: &Vec<ObjectStorePath>, n_iterations: u64) -> Durat
             futures.push(store.get(filename));
         }
         for f in futures {
-            let b = f.await.expect("At least one Result was an Error");
-            assert_eq!(b.as_slice().len(), FILE_SIZE_BYTES);
+            let result = f.await.expect("At least one Result was an Error");
+            let bytes = result.bytes().await.unwrap();
+            assert_eq!(bytes.len(), FILE_SIZE_BYTES);
         }
         total_time += start_of_iter.elapsed();
     }
@@ -38,7 +40,7 @@ async fn uring_get_range(filenames: &Vec<ObjectStorePath>, n_iterations: u64) ->
     let mut total_time = Duration::ZERO;
     for _ in 0..n_iterations {
         // Setup (not timed):
-        let store = ObjectStoreAdapter::default();
+        let store = ObjectStoreAdapter::new(IoUring::new(N_WORKER_THREADS));
         clear_page_cache();
         let mut futures = Vec::with_capacity(filenames.len());
 
@@ -48,8 +50,8 @@ async fn uring_get_range(filenames: &Vec<ObjectStorePath>, n_iterations: u64) ->
             futures.push(store.get_range(filename, RANGE));
         }
         for f in futures {
-            let b = f.await.expect("At least one Result was an Error");
-            assert_eq!(b.as_slice().len(), RANGE.len());
+            let bytes = f.await.expect("At least one Result was an Error");
+            assert_eq!(bytes.len(), RANGE.len());
         }
         total_time += start_of_iter.elapsed();
     }
@@ -57,10 +59,8 @@ async fn uring_get_range(filenames: &Vec<ObjectStorePath>, n_iterations: u64) ->
 }
 
 async fn local_file_system_get(filenames: &Vec<ObjectStorePath>, n_iterations: u64) -> Duration {
-    // Unfortunately, I can't find a better way to share code between `load_files_with_io_uring_local`
-    // and `load_files_with_local_file_system` because `ObjectStoreAdapter` doesn't yet `impl ObjectStore`.
-    // And `ObjectStoreAdapter::get` and `LocalFileSystem::get` return slightly different types.
-    // TODO: Reduce duplication if/when `ObjectStoreAdapter` implements `ObjectStore`.
+    // TODO: Reduce duplication with `uring_get`, now that `ObjectStoreAdapter` implements
+    // `ObjectStore`.
 
     let mut total_time = Duration::ZERO;
     for _ in 0..n_iterations {
@@ -95,15 +95,8 @@ async fn local_file_system_get_range(
     filenames: &Vec<ObjectStorePath>,
     n_iterations: u64,
 ) -> Duration {
-    // Unfortunately, I can't find a better way to share code between `load_files_with_io_uring_local`
-    // and `load_files_with_local_file_system` because `ObjectStoreAdapter` doesn't yet `impl ObjectStore`.
-    // And `ObjectStoreAdapter::get` and `LocalFileSystem::get` return slightly different types.
-    // TODO: Reduce duplication if/when `ObjectStoreAdapter` implements `ObjectStore`.
-
-    const RANGE_USIZE: Range<usize> = Range {
-        start: RANGE.start as usize,
-        end: RANGE.end as usize,
-    };
+    // TODO: Reduce duplication with `uring_get_range`, now that `ObjectStoreAdapter` implements
+    // `ObjectStore`.
 
     let mut total_time = Duration::ZERO;
     for _ in 0..n_iterations {
@@ -119,7 +112,7 @@ async fn local_file_system_get_range(
                 // We can't create the `store` outside of `spawn` and move it into `spawn`.
                 // So we have to create the `store` _inside_ this `async` block.
                 let store = object_store::local::LocalFileSystem::default();
-                store.get_range(&filename, RANGE_USIZE).await.unwrap()
+                store.get_range(&filename, RANGE).await.unwrap()
             }));
         }
 
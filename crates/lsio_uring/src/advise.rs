@@ -0,0 +1,156 @@
+use std::{ffi::CString, ops::Range, path::PathBuf, sync::Arc};
+
+use lsio_io::{Advice, IoError, Output};
+
+use crate::{
+    close::close_if_last_op_on_file,
+    open_file::{path_from_location, OpenFile, OpenFileBuilder},
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::{build_fadvise_sqe, build_openat_sqe},
+    user_data::UringUserData,
+};
+
+/// Open a file, `fadvise` a byte range of the file, and then close the file.
+#[derive(Debug)]
+pub(crate) struct Advise {
+    location: Arc<CString>,
+    /// `Some` once the file has been opened.
+    file: Option<Arc<OpenFile>>,
+    /// The range passed to `Reader::advise`. Only used to describe errors.
+    range: Range<isize>,
+    offset: i64,
+    len: i64,
+    advice: Advice,
+    /// If `None`, then don't send any output (not even errors).
+    user_data: Option<u64>,
+}
+
+impl Advise {
+    pub(crate) fn new(
+        location: Arc<CString>,
+        range: Range<isize>,
+        (offset, len): (i64, i64),
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> Self {
+        Self {
+            location,
+            file: None,
+            range,
+            offset,
+            len,
+            advice,
+            user_data,
+        }
+    }
+
+    fn send_error(
+        &self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        let Some(user_data) = self.user_data else {
+            return;
+        };
+        let details = format!(
+            "(reported by io_uring completion queue entry (CQE)). More details: \
+                idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {self:?}",
+        );
+        let path = self.path(idx_and_opcode).unwrap();
+        let err = match nix::Error::from_raw(-cqe_result) {
+            nix::Error::ENOENT => IoError::NotFound {
+                path,
+                user_data: Some(user_data),
+                details,
+            },
+            errno => IoError::Nix {
+                errno,
+                opcode: idx_and_opcode.opcode().name(),
+                path: Some(path),
+                range: Some(self.range.clone()),
+                user_data: Some(user_data),
+                details,
+            },
+        };
+        output_channel.send(Err(err)).unwrap();
+    }
+}
+
+impl UringOperation for Advise {
+    /// Opens the file. Or, if the file is already open (because this operation was re-queued when
+    /// the SQ was full), submits the `fadvise`.
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = match &self.file {
+            None => build_openat_sqe(index_of_op, &self.location, false, false, 0),
+            Some(file) => build_fadvise_sqe(index_of_op, file, self.offset, self.len, self.advice),
+        };
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(path_from_location(&self.location))
+    }
+
+    fn maybe_send_error(
+        &self,
+        _idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        // Errors are only sent if `user_data` is `Some`. So all errors are sent by
+        // `process_opcode_and_submit_next_step`.
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if cqe_result < 0 {
+            self.send_error(idx_and_opcode, cqe_result, output_channel);
+        }
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::OpenAt::CODE => {
+                if cqe_result < 0 {
+                    return NextStep::Done;
+                }
+                let mut builder = OpenFileBuilder::new(Arc::clone(&self.location));
+                builder.set_direct_io(false);
+                builder.skip_file_size();
+                builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
+                self.file = Some(Arc::new(builder.build()));
+                match self.submit_first_step(index_of_op, local_uring_submission_queue) {
+                    Ok(()) => NextStep::Pending,
+                    // The worker will call `submit_first_step` again later.
+                    Err(_) => NextStep::Requeue,
+                }
+            }
+            io_uring::opcode::Fadvise::CODE => {
+                if let (Some(user_data), 0..) = (self.user_data, cqe_result) {
+                    output_channel
+                        .send(Ok(Output::Advised { user_data }))
+                        .unwrap();
+                }
+                close_if_last_op_on_file(
+                    self.file.as_ref().unwrap(),
+                    None,
+                    index_of_op,
+                    local_uring_submission_queue,
+                    spawner,
+                    output_channel,
+                )
+            }
+            _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use lsio_io::{IoError, Output};
+
+use crate::{
+    operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
+    sqe::build_nop_sqe,
+    user_data::UringUserData,
+};
+
+/// A batch of operations which were submitted together by [`crate::IoUring`]'s
+/// `Reader::submit_batch`. The whole batch is pushed onto the threadpool as a single task, so the
+/// batch costs one push (and wakes at most one worker thread) however many operations it holds.
+///
+/// Like `List`, we submit a `nop` SQE (so that `Batch` follows the same lifecycle as every other
+/// operation). When the `nop` completes, we spawn each operation in the batch. Idle worker threads
+/// can then steal the spawned operations.
+#[derive(Debug)]
+pub(crate) struct Batch {
+    ops: Vec<Operation>,
+}
+
+impl Batch {
+    pub(crate) fn new(ops: Vec<Operation>) -> Self {
+        Self { ops }
+    }
+}
+
+impl UringOperation for Batch {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = build_nop_sqe(index_of_op);
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        _output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Nop::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        for op in self.ops.drain(..) {
+            spawner.push(op);
+        }
+        NextStep::Done
+    }
+}
@@ -1,13 +1,50 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use lsio_threadpool::WorkerThread;
+use lsio_io::{IoError, Output};
 
 use crate::{
     open_file::OpenFile,
     operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
     sqe::build_close_sqe,
+    trace::trace_event,
+    user_data::UringUserData,
 };
 
+/// Called by an operation which has finished reading `file`. If the calling operation is the last
+/// operation on `file`, then close `file` (and, if `file_complete` is `Some(user_data_of_last)`,
+/// emit `Output::FileComplete`). Returns the calling operation's `NextStep`.
+pub(crate) fn close_if_last_op_on_file(
+    file: &Arc<OpenFile>,
+    file_complete: Option<u64>,
+    index_of_op: usize,
+    local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    spawner: &Spawner,
+    output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+) -> NextStep {
+    if Arc::strong_count(file) > 1 {
+        return NextStep::Done;
+    }
+    // We're the last operation on this file, so it's time to close this file.
+    if let Some(user_data_of_last) = file_complete {
+        output_channel
+            .send(Ok(Output::FileComplete {
+                location: file.path(),
+                user_data_of_last,
+            }))
+            .unwrap();
+    }
+    let mut close_op = Close::new(Arc::clone(file));
+    match close_op.submit_first_step(index_of_op, local_uring_submission_queue) {
+        Ok(()) => NextStep::ReplaceWith(Operation::Close(close_op)),
+        Err(_) => {
+            // The SQ is full, so let the worker's main loop submit `close_op` later.
+            spawner.push(Operation::Close(close_op));
+            NextStep::Done
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Close {
     file: Arc<OpenFile>,
@@ -17,6 +54,10 @@ impl Close {
     pub(crate) fn new(file: Arc<OpenFile>) -> Self {
         Self { file }
     }
+
+    pub(crate) fn file(&self) -> &OpenFile {
+        &self.file
+    }
 }
 
 impl UringOperation for Close {
@@ -29,17 +70,26 @@ impl UringOperation for Close {
         unsafe { local_uring_submission_queue.push(&entry) }
     }
 
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.file.path())
+    }
+
     fn process_opcode_and_submit_next_step(
         &mut self,
-        idx_and_opcode: &crate::user_data::UringUserData,
-        _cqe_result: i32,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
         _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
-        _worker_thread: &WorkerThread<Operation>,
-        _output_channel: &mut crossbeam_channel::Sender<anyhow::Result<lsio_io::Output>>,
+        _spawner: &Spawner,
+        _output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
     ) -> NextStep {
         if idx_and_opcode.opcode().value() != io_uring::opcode::Close::CODE {
             panic!("Unrecognised opcode!");
         }
+        trace_event!(
+            "close done",
+            path = self.file.path(),
+            cqe_result = cqe_result,
+        );
         NextStep::Done
     }
 }
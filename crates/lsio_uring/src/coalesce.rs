@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    ops::Range,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use lsio_threadpool::ThreadPool;
+
+use crate::{get_ranges::GetRanges, operation::Operation};
+
+/// Accumulates the ranges of separate `get_ranges` calls for the same file into a single
+/// `GetRanges` operation, which is submitted once the coalescing window has expired (or once it
+/// has accumulated `max_bytes`). See [`crate::IoUringBuilder::coalesce_window`].
+///
+/// Each pending `GetRanges` counts as one unfinished operation from the moment it's created, so
+/// [`crate::IoUring::submit_barrier`] can't miss a `GetRanges` which is being flushed.
+#[derive(Debug)]
+pub(crate) struct Coalescer {
+    window: Duration,
+    max_bytes: usize,
+    state: Mutex<CoalescerState>,
+    /// Wakes the flusher thread when a new pending `GetRanges` is created, or when stopping.
+    condvar: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct CoalescerState {
+    pending: HashMap<Arc<CString>, PendingGetRanges>,
+    stop: bool,
+}
+
+#[derive(Debug)]
+struct PendingGetRanges {
+    get_ranges: GetRanges,
+    /// The number of bytes requested so far. Ranges relative to the end of the file aren't
+    /// counted, because the file size isn't known yet.
+    n_bytes: usize,
+    /// When the window for this file expires.
+    deadline: Instant,
+}
+
+impl Coalescer {
+    pub(crate) fn new(window: Duration, max_bytes: usize) -> Self {
+        Self {
+            window,
+            max_bytes,
+            state: Mutex::new(CoalescerState::default()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Add `ranges` to the pending `GetRanges` for `location`. If there isn't a pending
+    /// `GetRanges` for `location` then `new_get_ranges` is called to create one. Returns the
+    /// pending `GetRanges` if it has now accumulated at least `max_bytes`, in which case the
+    /// caller must submit it.
+    pub(crate) fn add(
+        &self,
+        location: Arc<CString>,
+        ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+        new_get_ranges: impl FnOnce(Arc<CString>) -> GetRanges,
+    ) -> Option<GetRanges> {
+        let n_bytes: usize = ranges
+            .iter()
+            .filter(|range| range.start >= 0 && range.end >= range.start)
+            .map(|range| range.len())
+            .sum();
+        let mut state = self.state.lock().unwrap();
+        let pending = state
+            .pending
+            .entry(Arc::clone(&location))
+            .or_insert_with(|| {
+                self.condvar.notify_one();
+                PendingGetRanges {
+                    get_ranges: new_get_ranges(location.clone()),
+                    n_bytes: 0,
+                    deadline: Instant::now() + self.window,
+                }
+            });
+        pending.get_ranges.extend(ranges, user_data);
+        pending.n_bytes += n_bytes;
+        if pending.n_bytes >= self.max_bytes {
+            state
+                .pending
+                .remove(&location)
+                .map(|pending| pending.get_ranges)
+        } else {
+            None
+        }
+    }
+
+    /// Remove and return every pending `GetRanges`, regardless of whether its window has expired.
+    pub(crate) fn take_all(&self) -> Vec<GetRanges> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .pending
+            .drain()
+            .map(|(_, pending)| pending.get_ranges)
+            .collect()
+    }
+
+    /// Submit each pending `GetRanges` to `threadpool` when its window expires. Returns when
+    /// [`Coalescer::stop`] is called. Run by the `IoUring`'s flusher thread.
+    pub(crate) fn run_flusher(&self, threadpool: &ThreadPool<Operation>) {
+        let mut state = self.state.lock().unwrap();
+        while !state.stop {
+            let now = Instant::now();
+            let expired: Vec<Arc<CString>> = state
+                .pending
+                .iter()
+                .filter(|(_, pending)| pending.deadline <= now)
+                .map(|(location, _)| Arc::clone(location))
+                .collect();
+            if !expired.is_empty() {
+                let expired: Vec<GetRanges> = expired
+                    .iter()
+                    .filter_map(|location| state.pending.remove(location))
+                    .map(|pending| pending.get_ranges)
+                    .collect();
+                // Don't block `add` whilst pushing onto the threadpool.
+                drop(state);
+                for get_ranges in expired {
+                    threadpool.push(Operation::GetRanges(get_ranges));
+                }
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            let next_deadline = state.pending.values().map(|pending| pending.deadline).min();
+            state = match next_deadline {
+                Some(deadline) => {
+                    self.condvar
+                        .wait_timeout(state, deadline.saturating_duration_since(now))
+                        .unwrap()
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Stop the flusher thread, and drop every pending `GetRanges` without submitting it.
+    pub(crate) fn stop(&self) {
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            state.stop = true;
+            std::mem::take(&mut state.pending)
+        };
+        self.condvar.notify_all();
+        drop(pending);
+    }
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use lsio_aligned_bytes::BufferPool;
+
+use crate::retry::RetryPolicy;
+
+/// Whether the kernel should poll the io_uring submission queue (SQ) using a kernel thread.
+///
+/// `SQPOLL` can reduce the number of syscalls, but it uses a CPU core whilst the kernel thread is
+/// polling, and it requires elevated privileges on some kernels. See the `IORING_SETUP_SQPOLL`
+/// section of the [`io_uring_setup` man page](https://man7.org/linux/man-pages/man2/io_uring_setup.2.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqPoll {
+    /// The worker thread submits SQEs using the `io_uring_enter` syscall.
+    Disabled,
+    /// A kernel thread polls the SQ. The kernel thread sleeps after `idle_ms` milliseconds
+    /// without any new SQEs.
+    Enabled { idle_ms: u32 },
+}
+
+impl Default for SqPoll {
+    fn default() -> Self {
+        Self::Enabled { idle_ms: 1000 }
+    }
+}
+
+/// What a worker thread does whilst it waits for its in-flight operations to complete (when it has
+/// no new operations to submit).
+///
+/// Whichever strategy is chosen, a worker thread which has no operations at all (neither in
+/// flight nor queued) parks itself, so idle workers never use the CPU. And a worker whose
+/// io_uring is full always blocks until a completion arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Block in the kernel until a completion arrives. This uses the least CPU, but each
+    /// completion costs a context switch (typically a few microseconds) before the worker can
+    /// act on it. The worker wakes at least once per millisecond to pick up new operations, so
+    /// a new operation may wait up to a millisecond before it's submitted. On kernels older than
+    /// Linux 5.11 (which can't block with a timeout), this falls back to `BusyPoll`.
+    Block,
+    /// Poll the completion queue without blocking. This reacts to completions (and to new
+    /// operations) as quickly as possible, but uses a whole CPU core per worker thread whilst
+    /// any operations are in flight. After every `max_spins` polls which find no completions, the
+    /// worker yields the CPU to other threads (such as the kernel's io_uring workers, which may
+    /// otherwise be starved when there are fewer CPU cores than busy threads).
+    BusyPoll { max_spins: u32 },
+    /// Poll the completion queue for up to 50 microseconds, then block as for
+    /// [`WaitStrategy::Block`]. Fast completions (e.g. reads from the page cache) are handled with
+    /// the latency of `BusyPoll`, whilst slow completions (e.g. reads from disk) use little CPU.
+    /// On kernels older than Linux 5.11, this falls back to `BusyPoll`.
+    Hybrid,
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        Self::BusyPoll {
+            max_spins: u32::MAX,
+        }
+    }
+}
+
+/// The configuration of the `IoUring` and its `UringWorker`s. Set by the user via
+/// [`crate::IoUringBuilder`].
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) sqpoll: SqPoll,
+    /// What workers do whilst waiting for completions. See
+    /// [`crate::IoUringBuilder::wait_strategy`].
+    pub(crate) wait_strategy: WaitStrategy,
+    /// If `Some`, merge byte ranges (within each `get_ranges` call) which are separated by fewer
+    /// than `max_gap` bytes.
+    pub(crate) max_gap: Option<usize>,
+    /// The maximum number of file sizes to cache. See [`crate::file_size_cache::FileSizeCache`].
+    pub(crate) file_size_cache_capacity: usize,
+    /// Workers stop starting new operations whilst the output channel holds at least this many
+    /// outputs. See [`crate::IoUringBuilder::output_high_water_mark`].
+    pub(crate) output_high_water_mark: usize,
+    /// How long [`crate::IoUring::shutdown`] waits for unfinished operations.
+    pub(crate) shutdown_timeout: Duration,
+    /// If `Some`, register a pool of buffers with each worker's io_uring. See
+    /// [`crate::IoUringBuilder::fixed_buffers`].
+    pub(crate) fixed_buffers: Option<FixedBuffersConfig>,
+    /// If `Some`, allocate the buffers for reads from this pool. See
+    /// [`crate::IoUringBuilder::buffer_pool`].
+    pub(crate) buffer_pool: Option<BufferPool>,
+    /// If `Some`, allocate the buffers for reads of at least this many bytes from huge pages. See
+    /// [`crate::IoUringBuilder::huge_pages`].
+    pub(crate) huge_pages_threshold: Option<usize>,
+    /// If true, `GetRanges` opens files as fixed files. See
+    /// [`crate::IoUringBuilder::fixed_files`].
+    pub(crate) fixed_files: bool,
+    /// If true, emit `Output::FileComplete` once every range of a file has been read. See
+    /// [`crate::IoUringBuilder::file_complete_outputs`].
+    pub(crate) file_complete_outputs: bool,
+    /// If true, `GetRanges` opens files with `O_DIRECT`. See
+    /// [`crate::IoUringBuilder::direct_io`].
+    pub(crate) direct_io: bool,
+    /// Extra flags which `GetRanges` opens files with. See [`crate::IoUringBuilder::open_flags`].
+    pub(crate) open_flags: libc::c_int,
+    /// If `Some`, the number of entries in each worker's completion queue (CQ). Otherwise, the
+    /// kernel's default (twice the size of the SQ). See [`crate::IoUringBuilder::setup_cqsize`].
+    pub(crate) cq_size: Option<u32>,
+    /// The maximum number of files which `GetRanges` operations hold open at once (across all
+    /// workers). See [`crate::IoUringBuilder::max_open_files`].
+    pub(crate) max_open_files: usize,
+    /// If `Some`, accumulate the ranges of separate `get_ranges` calls for the same file. See
+    /// [`crate::IoUringBuilder::coalesce_window`].
+    pub(crate) coalesce_window: Option<CoalesceWindowConfig>,
+    /// How to retry `read`s which fail with a transient error. See
+    /// [`crate::IoUringBuilder::retries`].
+    pub(crate) retry_policy: RetryPolicy,
+    /// If true, the bytes of each range which are beyond the end of the file are filled with
+    /// zeros. See [`crate::IoUringBuilder::zero_fill_past_eof`].
+    pub(crate) zero_fill_past_eof: bool,
+    /// If true, re-sequence the outputs of reads into submission order. See
+    /// [`crate::IoUringBuilder::ordered_completion`].
+    pub(crate) ordered_completion: bool,
+}
+
+/// How long, and up to how many bytes, to accumulate ranges for each file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CoalesceWindowConfig {
+    pub(crate) window: Duration,
+    pub(crate) max_bytes: usize,
+}
+
+/// The number and size of the registered buffers.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FixedBuffersConfig {
+    pub(crate) n_buffers: usize,
+    pub(crate) buffer_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sqpoll: SqPoll::default(),
+            wait_strategy: WaitStrategy::default(),
+            max_gap: None,
+            file_size_cache_capacity: 10_000,
+            output_high_water_mark: 1_024,
+            shutdown_timeout: Duration::from_secs(10),
+            fixed_buffers: None,
+            buffer_pool: None,
+            huge_pages_threshold: None,
+            fixed_files: false,
+            file_complete_outputs: false,
+            direct_io: true,
+            open_flags: 0,
+            cq_size: None,
+            max_open_files: default_max_open_files(),
+            coalesce_window: None,
+            retry_policy: RetryPolicy::default(),
+            zero_fill_past_eof: false,
+            ordered_completion: false,
+        }
+    }
+}
+
+/// Half of this process's soft limit on open file descriptors (`RLIMIT_NOFILE`), leaving the
+/// other half for the rest of the process (and for the files opened by other operations, such as
+/// `put_ranges`). Falls back to 512 if the limit can't be read.
+fn default_max_open_files() -> usize {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `rlimit` is a valid pointer to a `libc::rlimit`.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) } != 0 {
+        return 512;
+    }
+    usize::try_from(rlimit.rlim_cur / 2)
+        .unwrap_or(usize::MAX)
+        .max(1)
+}
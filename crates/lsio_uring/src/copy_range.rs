@@ -0,0 +1,215 @@
+use crate::{
+    close::Close,
+    open_file::OpenFile,
+    operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
+    sqe::{build_nop_sqe, build_sub_read_sqe, build_write_sqe, plan_read_range},
+    user_data::UringUserData,
+};
+use lsio_aligned_bytes::{AlignedBytes, AllocError};
+use lsio_io::{IoError, Output};
+use std::{ops::Range, path::PathBuf, sync::Arc};
+
+/// Reads `src_range` from `src` and then, on the same worker thread, writes those bytes into
+/// `dst_range` of `dst`.
+#[derive(Debug)]
+pub(crate) struct CopyRange {
+    src: Arc<OpenFile>,
+    src_range: Range<isize>,
+    dst: Arc<OpenFile>,
+    dst_range: Range<isize>,
+    user_data: u64,
+    buffer: Option<AlignedBytes>, // This is an `Option` so we can `take` it.
+    /// Set if the buffer couldn't be allocated, in which case a `nop` is submitted instead of the
+    /// `read`, and the error is reported when the `nop` completes.
+    alloc_error: Option<AllocError>,
+}
+
+impl CopyRange {
+    pub(crate) fn new(
+        src: Arc<OpenFile>,
+        src_range: Range<isize>,
+        dst: Arc<OpenFile>,
+        dst_range: Range<isize>,
+        user_data: u64,
+    ) -> Self {
+        Self {
+            src,
+            src_range,
+            dst,
+            dst_range,
+            user_data,
+            buffer: None,
+            alloc_error: None,
+        }
+    }
+
+    /// Submit the `write` SQE. Returns an error if the `dst_range` is invalid, or if the SQ is
+    /// full.
+    fn submit_write(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), IoError> {
+        let buffer = self.buffer.as_ref().unwrap();
+        let Some(dst_range) = self.dst.try_resolve_range(&self.dst_range) else {
+            return Err(IoError::InvalidRange {
+                path: self.dst.path(),
+                range: self.dst_range.clone(),
+                message: format!(
+                    "The destination range {:?} doesn't resolve to a non-empty range of the file, \
+                        which is {} bytes. self: {self:?}",
+                    self.dst_range,
+                    self.dst.size().map_or_else(
+                        || "an unknown number of".to_string(),
+                        |size| size.to_string()
+                    ),
+                ),
+            });
+        };
+        if dst_range.len() != buffer.len() {
+            return Err(IoError::InvalidRange {
+                path: self.dst.path(),
+                range: self.dst_range.clone(),
+                message: format!(
+                    "The destination range {:?} (resolved to {dst_range:?}) is {} bytes long, but \
+                        the source range {:?} is {} bytes long. self: {self:?}",
+                    self.dst_range,
+                    dst_range.len(),
+                    self.src_range,
+                    buffer.len(),
+                ),
+            });
+        }
+        let entry = build_write_sqe(
+            index_of_op,
+            &self.dst,
+            buffer,
+            dst_range.start.try_into().unwrap(),
+        );
+        unsafe { local_uring_submission_queue.push(&entry) }.map_err(|err| IoError::Internal {
+            message: format!("Failed to submit the write SQE: {err}. self: {self:?}"),
+        })
+    }
+
+    /// Close the source and/or destination files, if we're the last operation using them.
+    fn close_files_if_necessary(
+        &self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+    ) -> NextStep {
+        let mut next_step = NextStep::Done;
+        for file in [&self.src, &self.dst] {
+            if Arc::strong_count(file) == 1 {
+                let mut close_op = Close::new(Arc::clone(file));
+                if matches!(next_step, NextStep::Done)
+                    && close_op
+                        .submit_first_step(index_of_op, local_uring_submission_queue)
+                        .is_ok()
+                {
+                    next_step = NextStep::ReplaceWith(Operation::Close(close_op));
+                } else {
+                    // We can only replace `self` with one operation (and the SQ might be full).
+                    // So push any other `Close` operation onto the local queue.
+                    spawner.push(Operation::Close(close_op));
+                }
+            }
+        }
+        next_step
+    }
+}
+
+impl UringOperation for CopyRange {
+    /// This method assumes that both files have already been opened (by [`CopyRanges`]), and that
+    /// `src_range` is short enough to be read by a single `read` (which is checked by
+    /// [`CopyRanges`]).
+    ///
+    /// If the buffer can't be allocated, then we submit a `nop` instead of the `read`, and report
+    /// the error when the `nop` completes (because we can't send outputs from this method).
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let (sub_reads, buffer) =
+            match plan_read_range(&self.src, &self.src_range, None, None, None) {
+                Ok(planned) => planned,
+                Err(err) => {
+                    self.alloc_error = Some(err);
+                    let entry = build_nop_sqe(index_of_op);
+                    return unsafe { local_uring_submission_queue.push(&entry) };
+                }
+            };
+        let [sub_read] = sub_reads[..] else {
+            panic!("CopyRange can only read up to 2 GiB at once. self: {self:?}");
+        };
+        let entry = build_sub_read_sqe(index_of_op, 0, &self.src, &sub_read);
+        self.buffer = Some(buffer);
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::Read::CODE | io_uring::opcode::Nop::CODE => Some(self.src.path()),
+            _ => Some(self.dst.path()),
+        }
+    }
+
+    fn range(&self, idx_and_opcode: &UringUserData) -> Option<Range<isize>> {
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::Read::CODE | io_uring::opcode::Nop::CODE => {
+                Some(self.src_range.clone())
+            }
+            _ => Some(self.dst_range.clone()),
+        }
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::Read::CODE => {
+                if cqe_result >= 0 {
+                    match self.submit_write(index_of_op, local_uring_submission_queue) {
+                        // Wait for the `write` CQE.
+                        Ok(()) => return NextStep::Pending,
+                        Err(err) => output_channel.send(Err(err)).unwrap(),
+                    }
+                }
+            }
+            io_uring::opcode::Write::CODE => {
+                if cqe_result >= 0 {
+                    output_channel
+                        .send(Ok(Output::BytesWritten {
+                            user_data: self.user_data,
+                            nbytes: cqe_result as usize,
+                        }))
+                        .unwrap();
+                }
+            }
+            // The buffer couldn't be allocated, so nothing was read.
+            io_uring::opcode::Nop::CODE => {
+                let err = self.alloc_error.take().unwrap();
+                output_channel
+                    .send(Err(IoError::Nix {
+                        errno: nix::Error::ENOMEM,
+                        opcode: "read",
+                        path: Some(self.src.path()),
+                        range: Some(self.src_range.clone()),
+                        user_data: Some(self.user_data),
+                        details: format!("{err}. self: {self:?}"),
+                    }))
+                    .unwrap();
+            }
+            _ => panic!("Unrecognised opcode!"),
+        };
+        self.close_files_if_necessary(index_of_op, local_uring_submission_queue, spawner)
+    }
+}
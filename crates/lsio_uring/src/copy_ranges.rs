@@ -0,0 +1,232 @@
+use std::{ffi::CString, ops::Range, path::PathBuf, sync::Arc};
+
+use lsio_io::IoError;
+
+use crate::{
+    close::Close,
+    copy_range::CopyRange,
+    open_file::OpenFileBuilder,
+    operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
+    sqe::{build_openat_for_writing_sqe, build_openat_sqe, build_statx_sqe, MAX_READ_LEN},
+    user_data::UringUserData,
+};
+
+// We're expecting CQEs for `openat` and `statx`, for both the source and the destination.
+const N_CQES_EXPECTED: u8 = 4;
+
+/// The `sub_index` of SQEs for the source file.
+const SRC: u16 = 0;
+
+/// The `sub_index` of SQEs for the destination file.
+const DST: u16 = 1;
+
+#[derive(Debug)]
+pub(crate) struct CopyRanges {
+    src_builder: Option<OpenFileBuilder>,
+    dst_builder: Option<OpenFileBuilder>,
+    src_ranges: Vec<Range<isize>>,
+    dst_ranges: Vec<Range<isize>>,
+    user_data: Vec<u64>,
+    n_cqes_received: u8,
+}
+
+impl CopyRanges {
+    pub(crate) fn new(
+        src: CString,
+        src_ranges: Vec<Range<isize>>,
+        dst: CString,
+        dst_ranges: Vec<Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> Self {
+        assert_eq!(src_ranges.len(), dst_ranges.len());
+        assert_eq!(src_ranges.len(), user_data.len());
+        Self {
+            src_builder: Some(OpenFileBuilder::new(Arc::new(src))),
+            dst_builder: Some(OpenFileBuilder::new(Arc::new(dst))),
+            src_ranges,
+            dst_ranges,
+            user_data,
+            n_cqes_received: 0,
+        }
+    }
+
+    fn builder_mut(&mut self, sub_index: u16) -> &mut OpenFileBuilder {
+        match sub_index {
+            SRC => self.src_builder.as_mut().unwrap(),
+            DST => self.dst_builder.as_mut().unwrap(),
+            _ => panic!("Unrecognised sub_index {sub_index}!"),
+        }
+    }
+
+    /// Once both files are open, submit one `Operation::CopyRange` per byte range.
+    fn submit_copy_range_ops(
+        &mut self,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) {
+        let src = Arc::new(self.src_builder.take().unwrap().build());
+        let dst = Arc::new(self.dst_builder.take().unwrap().build());
+        for ((src_range, dst_range), user_data) in self
+            .src_ranges
+            .iter()
+            .zip(&self.dst_ranges)
+            .zip(&self.user_data)
+        {
+            let Some(resolved_src_range) = src.try_resolve_range(src_range) else {
+                output_channel
+                    .send(Err(IoError::InvalidRange {
+                        path: src.path(),
+                        range: src_range.to_owned(),
+                        message: format!(
+                            "The source range {src_range:?} doesn't resolve to a non-empty range \
+                                of the file, which is {} bytes. user_data={user_data}",
+                            src.size().map_or_else(
+                                || "an unknown number of".to_string(),
+                                |size| size.to_string()
+                            ),
+                        ),
+                    }))
+                    .unwrap();
+                continue;
+            };
+            // TODO: Split copies of more than 2 GiB into multiple reads and writes.
+            if resolved_src_range.len() > MAX_READ_LEN {
+                output_channel
+                    .send(Err(IoError::InvalidRange {
+                        path: src.path(),
+                        range: src_range.to_owned(),
+                        message: format!(
+                            "copy_ranges can copy at most {MAX_READ_LEN} bytes per range, but \
+                                the source range {src_range:?} is {} bytes long. \
+                                user_data={user_data}",
+                            resolved_src_range.len(),
+                        ),
+                    }))
+                    .unwrap();
+                continue;
+            }
+            let copy_range_op = CopyRange::new(
+                src.clone(),
+                src_range.to_owned(),
+                dst.clone(),
+                dst_range.to_owned(),
+                *user_data,
+            );
+            spawner.push(Operation::CopyRange(copy_range_op));
+        }
+
+        // If every range was rejected then nothing else will close the files.
+        for file in [src, dst] {
+            if Arc::strong_count(&file) == 1 {
+                spawner.push(Operation::Close(Close::new(file)));
+            }
+        }
+    }
+
+    /// If one file failed to open, then we still need to close the file that did open.
+    fn close_any_open_files(&mut self, spawner: &Spawner) {
+        for builder in [self.src_builder.take(), self.dst_builder.take()]
+            .into_iter()
+            .flatten()
+        {
+            if builder.is_ready() {
+                let close_op = Close::new(Arc::new(builder.build()));
+                spawner.push(Operation::Close(close_op));
+            }
+        }
+    }
+}
+
+impl UringOperation for CopyRanges {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let tag = |sub_index: u16, opcode: u8| -> u64 {
+            UringUserData::new_with_sub_index(index_of_op, sub_index, opcode).into()
+        };
+        use io_uring::opcode::{OpenAt, Statx};
+
+        let src_open_entry = build_openat_sqe(
+            index_of_op,
+            self.src_builder.as_ref().unwrap().location(),
+            false,
+            self.src_builder.as_ref().unwrap().is_direct_io(),
+            0,
+        )
+        .user_data(tag(SRC, OpenAt::CODE));
+        let src_statx_entry = build_statx_sqe(index_of_op, self.src_builder.as_mut().unwrap())
+            .user_data(tag(SRC, Statx::CODE));
+
+        // The destination file might not exist yet. So we link the `statx` to the `openat`, to
+        // make sure that `statx` only runs after `openat` has created the file.
+        let dst_open_entry = build_openat_for_writing_sqe(
+            index_of_op,
+            self.dst_builder.as_ref().unwrap().location(),
+            false, // The user's ranges are not necessarily aligned.
+        )
+        .user_data(tag(DST, OpenAt::CODE))
+        .flags(io_uring::squeue::Flags::IO_LINK);
+        let dst_statx_entry = build_statx_sqe(index_of_op, self.dst_builder.as_mut().unwrap())
+            .user_data(tag(DST, Statx::CODE));
+
+        unsafe {
+            local_uring_submission_queue.push_multiple(&[
+                src_open_entry,
+                src_statx_entry,
+                dst_open_entry,
+                dst_statx_entry,
+            ])?;
+        };
+        Ok(())
+    }
+
+    fn path(&self, idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        let builder = match idx_and_opcode.sub_index() {
+            SRC => &self.src_builder,
+            _ => &self.dst_builder,
+        };
+        builder.as_ref().map(OpenFileBuilder::path)
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) -> NextStep {
+        self.n_cqes_received += 1;
+        if cqe_result >= 0 {
+            let builder = self.builder_mut(idx_and_opcode.sub_index());
+            match idx_and_opcode.opcode().value() {
+                io_uring::opcode::OpenAt::CODE => {
+                    builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
+                }
+                io_uring::opcode::Statx::CODE => {
+                    unsafe { builder.assume_statx_is_initialised() };
+                }
+                _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
+            };
+        };
+
+        assert!(self.n_cqes_received <= N_CQES_EXPECTED);
+        if self.n_cqes_received == N_CQES_EXPECTED {
+            let src_is_ready = self.src_builder.as_ref().unwrap().is_ready();
+            let dst_is_ready = self.dst_builder.as_ref().unwrap().is_ready();
+            if src_is_ready && dst_is_ready {
+                self.submit_copy_range_ops(spawner, output_channel);
+            } else {
+                // At least one of the CQEs must have resulted in an error (which will already
+                // have been reported to the user by `maybe_send_error`).
+                self.close_any_open_files(spawner);
+            }
+            NextStep::Done
+        } else {
+            NextStep::Pending
+        }
+    }
+}
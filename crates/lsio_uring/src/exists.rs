@@ -0,0 +1,112 @@
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+use lsio_io::{IoError, Output};
+
+use crate::{
+    file_size_cache::FileSizeCache,
+    open_file::OpenFileBuilder,
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::build_statx_sqe,
+    user_data::UringUserData,
+};
+
+/// Check whether a file exists (and get its size) using a single `statx` SQE. The file isn't
+/// opened.
+#[derive(Debug)]
+pub(crate) struct Exists {
+    // We only use the `OpenFileBuilder` to hold the location and the `statx` buffer, so that we
+    // can re-use `build_statx_sqe`.
+    open_file_builder: OpenFileBuilder,
+    user_data: u64,
+
+    /// If `Some`, then insert the file size into this cache.
+    file_size_cache: Option<Arc<FileSizeCache>>,
+}
+
+impl Exists {
+    pub(crate) fn new(location: Arc<CString>, user_data: u64) -> Self {
+        Self {
+            open_file_builder: OpenFileBuilder::new(location),
+            user_data,
+            file_size_cache: None,
+        }
+    }
+
+    pub(crate) fn with_file_size_cache(mut self, file_size_cache: Arc<FileSizeCache>) -> Self {
+        self.file_size_cache = Some(file_size_cache);
+        self
+    }
+}
+
+impl UringOperation for Exists {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = build_statx_sqe(index_of_op, &mut self.open_file_builder);
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.open_file_builder.path())
+    }
+
+    fn maybe_send_error(
+        &self,
+        _idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        // `ENOENT` isn't an error for `Exists`. So all errors are sent by
+        // `process_opcode_and_submit_next_step`.
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        _spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Statx::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        let output = match cqe_result {
+            0.. => {
+                let builder = &mut self.open_file_builder;
+                unsafe { builder.assume_statx_is_initialised() };
+                let file_size = builder.file_size().unwrap();
+                if let Some(cache) = &self.file_size_cache {
+                    cache.insert(builder.location(), file_size);
+                }
+                Ok(Output::Exists {
+                    user_data: self.user_data,
+                    exists: true,
+                    size: Some(file_size.size),
+                })
+            }
+            _ if cqe_result == -libc::ENOENT => Ok(Output::Exists {
+                user_data: self.user_data,
+                exists: false,
+                size: None,
+            }),
+            _ => Err(IoError::Nix {
+                errno: nix::Error::from_raw(-cqe_result),
+                opcode: idx_and_opcode.opcode().name(),
+                path: Some(self.open_file_builder.path()),
+                range: None,
+                user_data: Some(self.user_data),
+                details: format!(
+                    "(reported by io_uring completion queue entry (CQE)). More details: \
+                        idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. \
+                        self: {self:?}",
+                ),
+            }),
+        };
+        output_channel.send(output).unwrap();
+        NextStep::Done
+    }
+}
@@ -0,0 +1,84 @@
+use std::ffi::CString;
+
+use dashmap::DashMap;
+
+/// The metadata that we get from `statx`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileSize {
+    /// The file size in bytes.
+    pub(crate) size: u64,
+    /// The alignment (in bytes) of the buffer's address which `O_DIRECT` requires
+    /// (`stx_dio_mem_align`). Zero if the filesystem doesn't report its alignment.
+    pub(crate) mem_alignment: u32,
+    /// The alignment (in bytes) of the file offset and length of each read which `O_DIRECT`
+    /// requires (`stx_dio_offset_align`). Zero if the filesystem doesn't report its alignment.
+    pub(crate) offset_alignment: u32,
+}
+
+/// Caches the size of each file that we've `statx`ed, so that subsequent reads of the same file
+/// don't have to `statx` the file again. Shared between the `IoUring` and all its worker threads.
+///
+/// The cache assumes that files don't change size. Writes submitted through the same `IoUring`
+/// remove the written file from the cache. If files are modified by other processes then the user
+/// should call [`crate::IoUring::clear_file_size_cache`].
+#[derive(Debug)]
+pub(crate) struct FileSizeCache {
+    map: DashMap<CString, FileSize>,
+    /// The maximum number of files to cache. Once the cache is full, new files are not cached.
+    capacity: usize,
+}
+
+impl FileSizeCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            map: DashMap::new(),
+            capacity,
+        }
+    }
+
+    pub(crate) fn get(&self, location: &CString) -> Option<FileSize> {
+        self.map.get(location).map(|entry| *entry)
+    }
+
+    pub(crate) fn insert(&self, location: &CString, file_size: FileSize) {
+        if self.map.len() < self.capacity || self.map.contains_key(location) {
+            self.map.insert(location.clone(), file_size);
+        }
+    }
+
+    pub(crate) fn remove(&self, location: &CString) {
+        self.map.remove(location);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.map.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_size_cache_capacity() {
+        let cache = FileSizeCache::new(1);
+        let file_size = |size| FileSize {
+            size,
+            mem_alignment: 512,
+            offset_alignment: 512,
+        };
+        let a = CString::new("a").unwrap();
+        let b = CString::new("b").unwrap();
+        cache.insert(&a, file_size(1));
+        cache.insert(&b, file_size(2)); // The cache is full, so `b` isn't cached.
+        assert_eq!(cache.get(&a), Some(file_size(1)));
+        assert_eq!(cache.get(&b), None);
+
+        // Existing entries can still be updated when the cache is full.
+        cache.insert(&a, file_size(3));
+        assert_eq!(cache.get(&a), Some(file_size(3)));
+
+        cache.remove(&a);
+        assert_eq!(cache.get(&a), None);
+    }
+}
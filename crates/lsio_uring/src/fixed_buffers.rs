@@ -0,0 +1,101 @@
+use std::sync::Mutex;
+
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut};
+
+/// The alignment of each fixed buffer. Page-aligned, so that each buffer pins as few pages as
+/// possible.
+pub(crate) const ALIGN: usize = 4096;
+
+/// A pool of buffers which are registered with every worker thread's io_uring (using
+/// `IORING_REGISTER_BUFFERS`). Reads into a registered buffer can use `ReadFixed`, which saves the
+/// kernel from pinning (and un-pinning) the buffer's pages for every read.
+///
+/// The same buffers are registered with every worker's io_uring, so a buffer's index is valid on
+/// every worker thread (which matters because operations can be stolen by other worker threads).
+///
+/// The pool keeps its own view of each buffer. A buffer is in use for as long as any other view
+/// of the buffer is alive (e.g. the `Chunk` that the user receives). So buffers are recycled as
+/// soon as the user drops their `Chunk`s.
+#[derive(Debug)]
+pub(crate) struct FixedBuffers {
+    buffers: Vec<AlignedBytes>,
+    /// The length of each buffer, in bytes. This is the requested `buffer_size` rounded up to a
+    /// multiple of the alignment.
+    buffer_size: usize,
+    /// The index of the buffer that `take` will check first. The `Mutex` also stops two threads
+    /// from taking the same buffer.
+    next_index: Mutex<usize>,
+}
+
+impl FixedBuffers {
+    pub(crate) fn new(n_buffers: usize, buffer_size: usize) -> Self {
+        assert!(n_buffers > 0 && n_buffers <= u16::MAX as usize);
+        let buffers: Vec<AlignedBytes> = (0..n_buffers)
+            .map(|_| {
+                let mut buffer = AlignedBytesMut::new(buffer_size, ALIGN).freeze().unwrap();
+                buffer.reset_slice();
+                buffer
+            })
+            .collect();
+        Self {
+            buffer_size: buffers[0].len(),
+            buffers,
+            next_index: Mutex::new(0),
+        }
+    }
+
+    /// The `iovec`s to pass to `register_buffers`. The index of each `iovec` is the `buf_index` of
+    /// the corresponding buffer.
+    pub(crate) fn iovecs(&self) -> Vec<libc::iovec> {
+        self.buffers
+            .iter()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_ptr() as *mut libc::c_void,
+                iov_len: buffer.len(),
+            })
+            .collect()
+    }
+
+    /// Take a buffer which isn't in use, if there is one and if it's at least `len` bytes long.
+    /// Returns the buffer's index, and a view of the whole buffer. The buffer is returned to the
+    /// pool when all the views of the buffer have been dropped.
+    pub(crate) fn take(&self, len: usize) -> Option<(u16, AlignedBytes)> {
+        if len > self.buffer_size {
+            return None;
+        }
+        let mut next_index = self.next_index.lock().unwrap();
+        let n_buffers = self.buffers.len();
+        let index = (*next_index..*next_index + n_buffers)
+            .map(|i| i % n_buffers)
+            .find(|&i| self.buffers[i].is_unique())?;
+        *next_index = (index + 1) % n_buffers;
+        Some((index as u16, self.buffers[index].clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take() {
+        let pool = FixedBuffers::new(2, 1000);
+        assert_eq!(pool.buffer_size, 4096);
+        assert!(pool.take(4097).is_none());
+
+        let (index_0, buffer_0) = pool.take(10).unwrap();
+        let (index_1, _buffer_1) = pool.take(4096).unwrap();
+        assert_ne!(index_0, index_1);
+        assert_eq!(buffer_0.len(), 4096);
+
+        // Every buffer is in use:
+        assert!(pool.take(10).is_none());
+
+        // Dropping every view of a buffer returns the buffer to the pool:
+        let view = buffer_0.slice(0..10).unwrap();
+        drop(buffer_0);
+        assert!(pool.take(10).is_none());
+        drop(view);
+        assert_eq!(pool.take(10).unwrap().0, index_0);
+    }
+}
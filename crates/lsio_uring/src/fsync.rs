@@ -0,0 +1,144 @@
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+use lsio_io::{IoError, Output, SyncMode};
+
+use crate::{
+    close::close_if_last_op_on_file,
+    groups::GroupMember,
+    open_file::{path_from_location, OpenFile, OpenFileBuilder},
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::{build_fsync_sqe, build_openat_sqe},
+    user_data::UringUserData,
+};
+
+/// Open a file, `fsync` (or `fdatasync`) the file, and then close the file.
+#[derive(Debug)]
+pub(crate) struct Fsync {
+    location: Arc<CString>,
+    /// `Some` once the file has been opened.
+    file: Option<Arc<OpenFile>>,
+    mode: SyncMode,
+    user_data: u64,
+    /// The group (if any) that this operation belongs to. The group won't finish until this
+    /// operation has been dropped.
+    group: Option<Arc<GroupMember>>,
+}
+
+impl Fsync {
+    pub(crate) fn new(location: Arc<CString>, mode: SyncMode, user_data: u64) -> Self {
+        Self {
+            location,
+            file: None,
+            mode,
+            user_data,
+            group: None,
+        }
+    }
+
+    pub(crate) fn with_group(mut self, group: Arc<GroupMember>) -> Self {
+        self.group = Some(group);
+        self
+    }
+}
+
+impl UringOperation for Fsync {
+    /// Opens the file. Or, if the file is already open (because this operation was re-queued when
+    /// the SQ was full), submits the `fsync`.
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        // Linux flushes the file's data regardless of whether the file descriptor is writable, so
+        // we open the file read-only (which doesn't create the file if it doesn't exist).
+        let entry = match &self.file {
+            None => build_openat_sqe(index_of_op, &self.location, false, false, 0),
+            Some(file) => build_fsync_sqe(index_of_op, file, self.mode),
+        };
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(path_from_location(&self.location))
+    }
+
+    fn maybe_send_error(
+        &self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        if cqe_result >= 0 {
+            return;
+        }
+        let details = format!(
+            "(reported by io_uring completion queue entry (CQE)). More details: \
+                idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {self:?}",
+        );
+        let path = path_from_location(&self.location);
+        let err = match nix::Error::from_raw(-cqe_result) {
+            nix::Error::ENOENT => IoError::NotFound {
+                path,
+                user_data: Some(self.user_data),
+                details,
+            },
+            errno => IoError::Nix {
+                errno,
+                opcode: idx_and_opcode.opcode().name(),
+                path: Some(path),
+                range: None,
+                user_data: Some(self.user_data),
+                details,
+            },
+        };
+        output_channel.send(Err(err)).unwrap();
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::OpenAt::CODE => {
+                if cqe_result < 0 {
+                    // The error has already been sent by `maybe_send_error`.
+                    return NextStep::Done;
+                }
+                let mut builder = OpenFileBuilder::new(Arc::clone(&self.location));
+                builder.set_direct_io(false);
+                builder.skip_file_size();
+                builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
+                self.file = Some(Arc::new(builder.build()));
+                match self.submit_first_step(index_of_op, local_uring_submission_queue) {
+                    Ok(()) => NextStep::Pending,
+                    // The worker will call `submit_first_step` again later.
+                    Err(_) => NextStep::Requeue,
+                }
+            }
+            io_uring::opcode::Fsync::CODE => {
+                if cqe_result >= 0 {
+                    output_channel
+                        .send(Ok(Output::Synced {
+                            user_data: self.user_data,
+                        }))
+                        .unwrap();
+                }
+                close_if_last_op_on_file(
+                    self.file.as_ref().unwrap(),
+                    None,
+                    index_of_op,
+                    local_uring_submission_queue,
+                    spawner,
+                    output_channel,
+                )
+            }
+            _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
+        }
+    }
+}
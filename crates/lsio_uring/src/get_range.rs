@@ -1,53 +1,516 @@
 use crate::{
-    close::Close,
+    close::close_if_last_op_on_file,
+    fixed_buffers::FixedBuffers,
+    groups::GroupMember,
+    merge_ranges::{member_chunk, MergedMember, MergedRange},
     open_file::OpenFile,
-    operation::{NextStep, Operation, UringOperation},
-    sqe::build_read_range_sqe,
+    operation::{cqe_error, send_cqe_error, NextStep, UringOperation},
+    retry::RetryPolicy,
+    spawner::Spawner,
+    sqe::{
+        build_link_timeout_sqe, build_nop_sqe, build_sub_read_sqe, build_timeout_sqe,
+        plan_read_range, plan_read_range_into, SubRead,
+    },
+    trace::trace_event,
     user_data::UringUserData,
 };
-use lsio_aligned_bytes::AlignedBytes;
-use lsio_io::{Chunk, Output};
-use lsio_threadpool::WorkerThread;
-use std::{ops::Range, sync::Arc};
+use io_uring::{squeue, types};
+use lsio_aligned_bytes::{AlignedBytes, AllocError, BufferPool};
+#[cfg(feature = "checksum")]
+use lsio_io::Checksum;
+use lsio_io::{Chunk, IoError, Output};
+use std::{collections::VecDeque, ops::Range, path::PathBuf, sync::Arc, time::Duration};
+
+/// The maximum number of `read` SQEs that a single `GetRange` will have in flight at once. Each
+/// `read` may be followed by a `LinkTimeout` SQE, so twice this number must not be more than the
+/// headroom that the `UringWorker` leaves in the SQ for each operation.
+const MAX_SUB_READS_IN_FLIGHT: usize = 2;
 
 #[derive(Debug)]
 pub(crate) struct GetRange {
     file: Arc<OpenFile>, // TODO: Replace Arc with Atomic counter?
     range: Range<isize>,
     user_data: u64,
+    /// Before submission, `buffer` holds the caller-provided destination buffer (if any). After
+    /// submission, `buffer` holds the buffer that the kernel is reading into.
     buffer: Option<AlignedBytes>, // This is an `Option` so we can `take` it.
+
+    /// `read` will transfer at most 2 GiB, so larger ranges are split into multiple `SubRead`s.
+    /// Each `SubRead` is updated as bytes are read, so it always describes the bytes which remain
+    /// to be read. `None` until the buffer has been allocated (in `submit_first_step`).
+    sub_reads: Option<Vec<SubRead>>,
+    /// The indices of the `sub_reads` which need to be pushed onto the SQ (including `SubRead`s
+    /// which need to be retried after a short read).
+    unsubmitted_sub_reads: VecDeque<usize>,
+    /// The number of `sub_reads` which have been pushed onto the SQ, but whose CQEs we haven't
+    /// received yet.
+    n_sub_reads_in_flight: usize,
+    /// The number of `LinkTimeout`s which have been pushed onto the SQ, but whose CQEs we haven't
+    /// received yet. (The CQE of a `LinkTimeout` may arrive after the CQE of its `read`).
+    n_link_timeouts_in_flight: usize,
+    /// Set if any `SubRead` fails. (Unless this is a merged range, the error has already been
+    /// reported by `maybe_send_error`).
+    failed: bool,
+    /// The opcode and result of the first failed `read` CQE (if any). Used to send one error per
+    /// incomplete member of a merged range.
+    failed_cqe: Option<(&'static str, i32)>,
+    /// Set if the buffer couldn't be allocated. The error is reported when the `nop` (which
+    /// `submit_first_step` submits instead of the reads) completes.
+    alloc_error: Option<AllocError>,
+    /// If `Some`, then `range` is a merged range, and the `Chunk` will be split into one `Chunk`
+    /// per member (and `user_data` is ignored).
+    members: Option<Vec<MergedMember>>,
+    /// The group (if any) that this operation belongs to. The group won't finish until this
+    /// operation has been dropped.
+    group: Option<Arc<GroupMember>>,
+    /// If `Some`, then read into one of these registered buffers (if one is free).
+    fixed_buffers: Option<Arc<FixedBuffers>>,
+    /// If `Some`, then allocate the buffer from this pool (unless we read into a fixed buffer).
+    buffer_pool: Option<BufferPool>,
+    /// If `Some`, then allocate buffers of at least this many bytes from huge pages.
+    huge_pages_threshold: Option<usize>,
+    /// If true, and this is the last operation on `file`, then emit `Output::FileComplete`.
+    file_complete_output: bool,
+    /// If `Some`, then each `read` is cancelled if it hasn't completed within this timeout.
+    timeout: Option<Duration>,
+    /// `timeout`, in the form that the kernel reads when the `LinkTimeout` SQEs are submitted.
+    /// Boxed so that its address doesn't change if this operation is moved.
+    timespec: Option<Box<types::Timespec>>,
+    /// How to retry `read`s which fail with a transient error.
+    retry_policy: RetryPolicy,
+    /// The number of times that this operation has retried a failed `read`.
+    n_retries: u32,
+    /// If true, then the bytes of `range` which are beyond the end of the file are filled with
+    /// zeros (instead of reporting a `ShortRead` error).
+    zero_fill_past_eof: bool,
+    /// The `Timeout`s which are waiting to retry a `SubRead`: Each entry holds the index of the
+    /// `SubRead` and the timeout (boxed, so that its address doesn't change whilst the kernel may
+    /// read it).
+    backoff_timers: Vec<(usize, Box<types::Timespec>)>,
+    /// If `Some`, then send an error instead of the `Chunk` if the bytes read don't match this
+    /// checksum. Only used for ranges which haven't been merged.
+    #[cfg(feature = "checksum")]
+    checksum: Option<Checksum>,
 }
 
 impl GetRange {
     pub(crate) fn new(file: Arc<OpenFile>, range: Range<isize>, user_data: u64) -> Self {
-        // TODO: Split reads of more than 2 GiB into multiple smaller reads! See issue #99.
-        if range.len() > 2_147_479_552 {
-            panic!(
-                "`read` will transfer at most 2 GiB but {} bytes were requested. \
-                     See https://github.com/JackKelly/light-speed-io/issues/99",
-                range.len()
-            );
-        }
         Self {
             file,
             range,
             user_data,
             buffer: None,
+            sub_reads: None,
+            unsubmitted_sub_reads: VecDeque::new(),
+            n_sub_reads_in_flight: 0,
+            n_link_timeouts_in_flight: 0,
+            failed: false,
+            failed_cqe: None,
+            alloc_error: None,
+            members: None,
+            group: None,
+            fixed_buffers: None,
+            buffer_pool: None,
+            huge_pages_threshold: None,
+            file_complete_output: false,
+            timeout: None,
+            timespec: None,
+            retry_policy: RetryPolicy::default(),
+            n_retries: 0,
+            zero_fill_past_eof: false,
+            backoff_timers: Vec::new(),
+            #[cfg(feature = "checksum")]
+            checksum: None,
+        }
+    }
+
+    /// Read a `MergedRange`, and output one `Chunk` per member of the `MergedRange`.
+    pub(crate) fn new_merged(file: Arc<OpenFile>, merged_range: MergedRange) -> Self {
+        Self {
+            members: Some(merged_range.members),
+            ..Self::new(file, merged_range.range, 0)
+        }
+    }
+
+    /// Read `range` into `destination`, instead of into a newly allocated buffer.
+    pub(crate) fn new_into(
+        file: Arc<OpenFile>,
+        range: Range<isize>,
+        destination: AlignedBytes,
+        user_data: u64,
+    ) -> Self {
+        Self {
+            buffer: Some(destination),
+            ..Self::new(file, range, user_data)
+        }
+    }
+
+    pub(crate) fn with_group(mut self, group: Option<Arc<GroupMember>>) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub(crate) fn with_fixed_buffers(mut self, fixed_buffers: Option<Arc<FixedBuffers>>) -> Self {
+        self.fixed_buffers = fixed_buffers;
+        self
+    }
+
+    pub(crate) fn with_buffer_pool(mut self, buffer_pool: Option<BufferPool>) -> Self {
+        self.buffer_pool = buffer_pool;
+        self
+    }
+
+    pub(crate) fn with_huge_pages_threshold(mut self, huge_pages_threshold: Option<usize>) -> Self {
+        self.huge_pages_threshold = huge_pages_threshold;
+        self
+    }
+
+    pub(crate) fn with_file_complete_output(mut self, file_complete_output: bool) -> Self {
+        self.file_complete_output = file_complete_output;
+        self
+    }
+
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self.timespec = timeout.map(|timeout| {
+            Box::new(
+                types::Timespec::new()
+                    .sec(timeout.as_secs())
+                    .nsec(timeout.subsec_nanos()),
+            )
+        });
+        self
+    }
+
+    pub(crate) fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub(crate) fn with_zero_fill_past_eof(mut self, zero_fill_past_eof: bool) -> Self {
+        self.zero_fill_past_eof = zero_fill_past_eof;
+        self
+    }
+
+    #[cfg(feature = "checksum")]
+    pub(crate) fn with_checksum(mut self, checksum: Option<Checksum>) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Returns `chunk` as an `Output`. Or, if `chunk` doesn't match `self.checksum`, returns an
+    /// error. The checksum is computed on this (the worker) thread.
+    fn verify_checksum(&self, chunk: Chunk) -> Result<Output, IoError> {
+        #[cfg(feature = "checksum")]
+        if let Some(expected) = self.checksum {
+            if let Err(actual) = expected.verify(chunk.buffer.as_slice()) {
+                return Err(IoError::ChecksumMismatch {
+                    path: self.file.path(),
+                    range: self.range.clone(),
+                    user_data: chunk.user_data,
+                    expected,
+                    actual,
+                    details: format!("Read {} bytes.", chunk.buffer.len()),
+                });
+            }
+        }
+        Ok(Output::Chunk(chunk))
+    }
+
+    pub(crate) fn file(&self) -> &OpenFile {
+        &self.file
+    }
+
+    /// Push `SubRead`s onto the SQ until `MAX_SUB_READS_IN_FLIGHT` are in flight, or until all
+    /// the `SubRead`s have been submitted.
+    ///
+    /// If the SQ is full then the remaining `SubRead`s will be submitted when the next CQE for
+    /// this operation arrives. Only returns an error if the SQ is full _and_ there are no
+    /// `SubRead`s or `LinkTimeout`s in flight (so no more CQEs will arrive for this operation).
+    fn submit_sub_reads(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let sub_reads = self.sub_reads.as_ref().unwrap();
+        while self.n_sub_reads_in_flight < MAX_SUB_READS_IN_FLIGHT {
+            let Some(&sub_index) = self.unsubmitted_sub_reads.front() else {
+                break;
+            };
+            let sub_index_u16 = sub_index.try_into().unwrap();
+            let entry = build_sub_read_sqe(
+                index_of_op,
+                sub_index_u16,
+                &self.file,
+                &sub_reads[sub_index],
+            );
+            let result = match &self.timespec {
+                None => unsafe { local_uring_submission_queue.push(&entry) },
+                Some(timespec) => {
+                    // The `read` and its `LinkTimeout` must be pushed together.
+                    let entries = [
+                        entry.flags(squeue::Flags::IO_LINK),
+                        build_link_timeout_sqe(index_of_op, sub_index_u16, timespec),
+                    ];
+                    unsafe { local_uring_submission_queue.push_multiple(&entries) }
+                }
+            };
+            if let Err(err) = result {
+                return match self.n_sub_reads_in_flight
+                    + self.n_link_timeouts_in_flight
+                    + self.backoff_timers.len()
+                {
+                    0 => Err(err),
+                    _ => Ok(()),
+                };
+            }
+            self.unsubmitted_sub_reads.pop_front();
+            self.n_sub_reads_in_flight += 1;
+            if self.timespec.is_some() {
+                self.n_link_timeouts_in_flight += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn all_sub_reads_are_done(&self) -> bool {
+        self.n_sub_reads_in_flight == 0
+            && self.n_link_timeouts_in_flight == 0
+            && self.backoff_timers.is_empty()
+            && (self.failed || self.unsubmitted_sub_reads.is_empty())
+    }
+
+    /// Returns true if the failed `read` whose CQE has result `cqe_result` will be retried.
+    fn will_retry_read(&self, cqe_result: i32) -> bool {
+        !self.failed && self.retry_policy.should_retry(cqe_result, self.n_retries)
+    }
+
+    /// Wait for the backoff (using a `Timeout` SQE), and then retry the `SubRead` identified by
+    /// `sub_index`. If the SQ is full then the `SubRead` is retried without waiting.
+    fn retry_sub_read_after_backoff(
+        &mut self,
+        index_of_op: usize,
+        sub_index: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) {
+        let backoff = self.retry_policy.backoff(self.n_retries);
+        self.n_retries += 1;
+        let timespec = Box::new(
+            types::Timespec::new()
+                .sec(backoff.as_secs())
+                .nsec(backoff.subsec_nanos()),
+        );
+        let entry = build_timeout_sqe(index_of_op, sub_index.try_into().unwrap(), &timespec);
+        match unsafe { local_uring_submission_queue.push(&entry) } {
+            Ok(()) => self.backoff_timers.push((sub_index, timespec)),
+            Err(_) => self.unsubmitted_sub_reads.push_front(sub_index),
+        }
+    }
+
+    /// Process a successful CQE for the `SubRead` identified by `sub_index`. If the kernel read
+    /// fewer bytes than we need then queue the `SubRead` to be retried for the remaining bytes.
+    /// See issue #100. Or, if `zero_fill_past_eof` is set and the read reached the end of the
+    /// file, then fill the remaining bytes with zeros.
+    fn process_sub_read_result(
+        &mut self,
+        sub_index: usize,
+        n_bytes_read: u32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        let sub_read = &mut self.sub_reads.as_mut().unwrap()[sub_index];
+        sub_read.advance(n_bytes_read);
+        let reached_eof = n_bytes_read == 0
+            || self
+                .file
+                .size()
+                .is_some_and(|size| sub_read.file_offset >= size);
+        if sub_read.required_len == 0 {
+            // This `SubRead` is complete. (We may have read fewer than `len` bytes because the
+            // buffer is padded beyond the end of the range, or beyond the end of the file.)
+        } else if self.zero_fill_past_eof && reached_eof {
+            // Safety: No reads into this `SubRead`'s bytes are in flight, and `self.buffer`
+            // keeps the buffer alive.
+            unsafe { sub_read.fill_with_zeros() };
+        } else if n_bytes_read == 0 {
+            // A zero-length read means that we've hit the end of the file.
+            // The file may have been truncated since its size was read.
+            self.failed = true;
+            self.file.forget_cached_size();
+            if self.members.is_some() {
+                // `send_member_outputs` reports each member which wasn't read.
+                return;
+            }
+            let wanted = self.buffer.as_ref().unwrap().len();
+            // The unread bytes are at the end of the range. (The `SubRead`s may also require
+            // bytes before the start of the range, to align the reads for `O_DIRECT`.)
+            let n_bytes_unread: usize = self
+                .sub_reads
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|sub_read| sub_read.required_len as usize)
+                .sum();
+            output_channel
+                .send(Err(IoError::ShortRead {
+                    path: self.file.path(),
+                    range: self.range.clone(),
+                    got: wanted.saturating_sub(n_bytes_unread),
+                    wanted,
+                    file_size: self.file.size(),
+                    details: format!("self: {self:?}"),
+                }))
+                .unwrap();
+        } else {
+            // Short read! Retry this `SubRead` for the remaining bytes, ahead of any other
+            // `SubRead`s.
+            self.unsubmitted_sub_reads.push_front(sub_index);
+        }
+    }
+
+    /// The number of bytes of the absolute file `range` which haven't been read.
+    fn n_bytes_unread(&self, range: &Range<usize>) -> usize {
+        self.sub_reads
+            .iter()
+            .flatten()
+            .map(|sub_read| {
+                let unread_start = sub_read.file_offset as usize;
+                let unread_end = unread_start + sub_read.required_len as usize;
+                unread_end
+                    .min(range.end)
+                    .saturating_sub(unread_start.max(range.start))
+            })
+            .sum()
+    }
+
+    /// Send one `Chunk` for each member of a merged range whose bytes have all been read, and one
+    /// error for each member which is incomplete (because a `read` failed, or because the file
+    /// ended early). So the user receives exactly one output per member, like the user would if
+    /// the ranges hadn't been merged.
+    fn send_member_outputs(
+        &mut self,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        let merged_start = self.file.resolve_range(&self.range).start as usize;
+        let buffer = self.buffer.take();
+        for member in self.members.as_ref().unwrap() {
+            let range = merged_start + member.range.start..merged_start + member.range.end;
+            let n_bytes_unread = self.n_bytes_unread(&range);
+            let output = match (&buffer, &self.alloc_error, self.failed_cqe) {
+                (Some(buffer), _, _) if n_bytes_unread == 0 => {
+                    Ok(Output::Chunk(member_chunk(buffer, merged_start, member)))
+                }
+                (_, Some(err), _) => Err(IoError::Nix {
+                    errno: nix::Error::ENOMEM,
+                    opcode: "read",
+                    path: Some(self.file.path()),
+                    range: Some(range.start as isize..range.end as isize),
+                    user_data: Some(member.user_data),
+                    details: err.to_string(),
+                }),
+                // We don't include `self` in `details`, because `self` holds every member.
+                (_, _, Some((opcode, cqe_result))) => Err(cqe_error(
+                    opcode,
+                    cqe_result,
+                    Some(self.file.path()),
+                    Some(range.start as isize..range.end as isize),
+                    Some(member.user_data),
+                    self.timeout,
+                    format!(
+                        "(reported by io_uring completion queue entry (CQE)). More details: \
+                            opcode: {opcode}. cqe_result: {cqe_result}.",
+                    ),
+                )),
+                _ => Err(IoError::ShortRead {
+                    path: self.file.path(),
+                    range: range.start as isize..range.end as isize,
+                    got: range.len() - n_bytes_unread,
+                    wanted: range.len(),
+                    file_size: self.file.size(),
+                    details: format!("user_data: {}", member.user_data),
+                }),
+            };
+            output_channel.send(output).unwrap();
         }
     }
 }
 
 impl UringOperation for GetRange {
     /// This method assume that the file has already been opened (by the [`GetRanges`] operation).
+    ///
+    /// If this `GetRange` has been re-queued (because the SQ was full) then this method submits
+    /// the remaining `SubRead`s.
+    ///
+    /// If the buffer can't be allocated, then we submit a `nop` instead of the reads, and report
+    /// the error when the `nop` completes (because we can't send outputs from this method).
     fn submit_first_step(
         &mut self,
         index_of_op: usize,
         local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
     ) -> Result<(), io_uring::squeue::PushError> {
-        let (entry, buffer) = build_read_range_sqe(index_of_op, &self.file, &self.range);
-        self.buffer = Some(buffer);
-        unsafe { local_uring_submission_queue.push(&entry) } // TODO: Does `entry` have to stay
-                                                             // alive for longer?
+        if self.sub_reads.is_none() {
+            let planned = match self.buffer.take() {
+                Some(destination) => Ok(plan_read_range_into(&self.file, &self.range, destination)),
+                None => plan_read_range(
+                    &self.file,
+                    &self.range,
+                    self.fixed_buffers.as_deref(),
+                    self.buffer_pool.as_ref(),
+                    self.huge_pages_threshold,
+                ),
+            };
+            match planned {
+                Ok((sub_reads, buffer)) => {
+                    self.buffer = Some(buffer);
+                    self.unsubmitted_sub_reads = (0..sub_reads.len()).collect();
+                    self.sub_reads = Some(sub_reads);
+                }
+                Err(err) => {
+                    self.alloc_error = Some(err);
+                    self.sub_reads = Some(Vec::new());
+                }
+            }
+        }
+        if self.alloc_error.is_some() {
+            let entry = build_nop_sqe(index_of_op);
+            return unsafe { local_uring_submission_queue.push(&entry) };
+        }
+        self.submit_sub_reads(index_of_op, local_uring_submission_queue)
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.file.path())
+    }
+
+    fn range(&self, _idx_and_opcode: &UringUserData) -> Option<Range<isize>> {
+        Some(self.range.clone())
+    }
+
+    fn timeout(&self, _idx_and_opcode: &UringUserData) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn user_data(&self, _idx_and_opcode: &UringUserData) -> Option<u64> {
+        self.members.is_none().then_some(self.user_data)
+    }
+
+    fn maybe_send_error(
+        &self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        // The errors of a merged range are sent by `send_member_outputs`, once every CQE has
+        // arrived, so that the members which were read still produce their `Chunk`s.
+        if self.members.is_none() {
+            send_cqe_error(self, idx_and_opcode, cqe_result, output_channel);
+        }
+    }
+
+    fn will_retry(&self, idx_and_opcode: &UringUserData, cqe_result: i32) -> bool {
+        matches!(
+            idx_and_opcode.opcode().value(),
+            io_uring::opcode::Read::CODE | io_uring::opcode::ReadFixed::CODE
+        ) && self.will_retry_read(cqe_result)
     }
 
     fn process_opcode_and_submit_next_step(
@@ -55,38 +518,107 @@ impl UringOperation for GetRange {
         idx_and_opcode: &UringUserData,
         cqe_result: i32,
         local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
-        _worker_thread: &WorkerThread<Operation>,
-        output_channel: &mut crossbeam_channel::Sender<anyhow::Result<Output>>,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
     ) -> NextStep {
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        let sub_index = idx_and_opcode.sub_index() as usize;
         // Check that the opcode of the CQE is what we expected:
-        if idx_and_opcode.opcode().value() != io_uring::opcode::Read::CODE {
-            panic!("Unrecognised opcode!");
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::Read::CODE | io_uring::opcode::ReadFixed::CODE => {
+                trace_event!(
+                    "read done",
+                    path = self.file.path(),
+                    range = self.range,
+                    sub_index = sub_index,
+                    cqe_result = cqe_result,
+                );
+                self.n_sub_reads_in_flight -= 1;
+                if cqe_result < 0 && self.will_retry_read(cqe_result) {
+                    self.retry_sub_read_after_backoff(
+                        index_of_op,
+                        sub_index,
+                        local_uring_submission_queue,
+                    );
+                } else if cqe_result < 0 {
+                    self.failed = true;
+                    let opcode = idx_and_opcode.opcode().name();
+                    self.failed_cqe.get_or_insert((opcode, cqe_result));
+                } else if !self.failed {
+                    self.process_sub_read_result(sub_index, cqe_result as u32, output_channel);
+                }
+            }
+            // If the timeout fired, then the `read`'s CQE reports the failure.
+            io_uring::opcode::LinkTimeout::CODE => self.n_link_timeouts_in_flight -= 1,
+            // The buffer couldn't be allocated, so nothing was read.
+            io_uring::opcode::Nop::CODE => {
+                // `send_member_outputs` reports the error for each member of a merged range.
+                if self.members.is_none() {
+                    let err = self.alloc_error.take().unwrap();
+                    output_channel
+                        .send(Err(IoError::Nix {
+                            errno: nix::Error::ENOMEM,
+                            opcode: "read",
+                            path: Some(self.file.path()),
+                            range: Some(self.range.clone()),
+                            user_data: Some(self.user_data),
+                            details: format!("{err}. self: {self:?}"),
+                        }))
+                        .unwrap();
+                }
+                self.failed = true;
+            }
+            // The backoff has elapsed, so retry the `SubRead` (unless another `SubRead` failed).
+            io_uring::opcode::Timeout::CODE => {
+                self.backoff_timers.retain(|(i, _)| *i != sub_index);
+                if !self.failed {
+                    self.unsubmitted_sub_reads.push_front(sub_index);
+                }
+            }
+            _ => panic!("Unrecognised opcode!"),
+        }
+
+        if !self.failed
+            && self
+                .submit_sub_reads(index_of_op, local_uring_submission_queue)
+                .is_err()
+        {
+            // The SQ is full, and we have no SQEs in flight (so no more CQEs will arrive for us).
+            // So let the worker's main loop call `submit_first_step` later.
+            return NextStep::Requeue;
         }
-        if cqe_result >= 0 {
-            // TODO: Check we've read the correct number of bytes:
-            //       Check `cqe_result_value == self.buffer.len()`.
-            // TODO: Retry if we read less data than requested! See issue #100.
 
+        if !self.all_sub_reads_are_done() {
+            // We're waiting for more CQEs. We must keep `buffer` alive until all the reads have
+            // completed, even if one of them failed.
+            return NextStep::Pending;
+        }
+
+        if self.members.is_some() {
+            self.send_member_outputs(output_channel);
+        } else if !self.failed {
+            let buffer = self.buffer.take().unwrap();
+            let range = self.file.resolve_range(&self.range);
+            let range = range.start as usize..range.end as usize;
             output_channel
-                .send(Ok(Output::Chunk(Chunk {
-                    buffer: self.buffer.take().unwrap(),
+                .send(self.verify_checksum(Chunk {
+                    buffer,
                     user_data: self.user_data,
-                })))
+                    range: Some(range),
+                }))
                 .unwrap();
         };
-        // Check if it's time to close the file:
-        if Arc::strong_count(&self.file) == 1 {
-            // We're the last operation on this file, so it's time to close this file.
-            let mut close_op = Close::new(Arc::clone(&self.file));
-            close_op
-                .submit_first_step(
-                    idx_and_opcode.index_of_op() as _,
-                    local_uring_submission_queue,
-                )
-                .unwrap();
-            NextStep::ReplaceWith(Operation::Close(close_op))
-        } else {
-            NextStep::Done
-        }
+        let file_complete = self.file_complete_output.then(|| match &self.members {
+            None => self.user_data,
+            Some(members) => members.last().unwrap().user_data,
+        });
+        close_if_last_op_on_file(
+            &self.file,
+            file_complete,
+            index_of_op,
+            local_uring_submission_queue,
+            spawner,
+            output_channel,
+        )
     }
 }
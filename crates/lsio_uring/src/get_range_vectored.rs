@@ -0,0 +1,331 @@
+use std::{fmt, ops::Range, path::PathBuf, sync::Arc};
+
+use lsio_aligned_bytes::AlignedBytes;
+use lsio_io::{Chunk, IoError, Output};
+
+use crate::{
+    close::close_if_last_op_on_file,
+    groups::GroupMember,
+    open_file::OpenFile,
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::build_readv_sqe,
+    user_data::UringUserData,
+};
+
+/// The maximum number of buffers that a single `readv` can read into (`IOV_MAX` on Linux).
+pub(crate) const MAX_IOVECS: usize = 1024;
+
+/// One of the user's byte ranges, within a [`GetRangeVectored`].
+#[derive(Debug)]
+pub(crate) struct VectoredMember {
+    /// The user's byte range (which may be relative to the end of the file). Used to describe
+    /// errors.
+    pub(crate) range: Range<isize>,
+    /// The absolute file offset of the first byte of this member.
+    pub(crate) resolved_start: usize,
+    pub(crate) len: usize,
+    /// The caller-provided buffer. At least `len` bytes long.
+    pub(crate) destination: AlignedBytes,
+    pub(crate) user_data: u64,
+}
+
+impl VectoredMember {
+    pub(crate) fn resolved_range(&self) -> Range<isize> {
+        let start = self.resolved_start as isize;
+        start..start + self.len as isize
+    }
+}
+
+/// Read byte ranges which are exactly adjacent in the file using a single `readv` SQE, which
+/// scatters the bytes into each range's caller-provided buffer. This is cheaper than submitting
+/// one `read` per range.
+#[derive(Debug)]
+pub(crate) struct GetRangeVectored {
+    file: Arc<OpenFile>,
+    /// In ascending order of file offset. Each member starts where the previous member ends.
+    members: Vec<VectoredMember>,
+    /// The `iovec`s for the `readv` which is in flight. Rebuilt before each submission, to
+    /// describe the bytes which remain to be read.
+    iovecs: IoVecs,
+    /// The number of bytes (from the start of the first member) which have been read so far.
+    n_bytes_read: usize,
+    /// The group (if any) that this operation belongs to. The group won't finish until this
+    /// operation has been dropped.
+    group: Option<Arc<GroupMember>>,
+    /// If true, and this is the last operation on `file`, then emit `Output::FileComplete`.
+    file_complete_output: bool,
+}
+
+impl GetRangeVectored {
+    /// `members` must be in ascending order of file offset, and each member must start where the
+    /// previous member ends.
+    pub(crate) fn new(file: Arc<OpenFile>, members: Vec<VectoredMember>) -> Self {
+        assert!(!members.is_empty() && members.len() <= MAX_IOVECS);
+        for (prev, next) in members.iter().zip(&members[1..]) {
+            assert_eq!(prev.resolved_start + prev.len, next.resolved_start);
+        }
+        for member in &members {
+            assert!(member.len <= member.destination.len());
+        }
+        Self {
+            file,
+            members,
+            iovecs: IoVecs(Vec::new()),
+            n_bytes_read: 0,
+            group: None,
+            file_complete_output: false,
+        }
+    }
+
+    pub(crate) fn with_group(mut self, group: Option<Arc<GroupMember>>) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub(crate) fn with_file_complete_output(mut self, file_complete_output: bool) -> Self {
+        self.file_complete_output = file_complete_output;
+        self
+    }
+
+    fn start(&self) -> usize {
+        self.members[0].resolved_start
+    }
+
+    fn total_len(&self) -> usize {
+        self.members.iter().map(|member| member.len).sum()
+    }
+
+    /// Returns true if every byte of `member` has been read.
+    fn is_complete(&self, member: &VectoredMember) -> bool {
+        member.resolved_start + member.len <= self.start() + self.n_bytes_read
+    }
+
+    /// Describe the bytes which remain to be read: Skip the members which have been read, and
+    /// skip the bytes which have been read of the first incomplete member.
+    fn build_iovecs(&self) -> IoVecs {
+        let read_up_to = self.start() + self.n_bytes_read;
+        IoVecs(
+            self.members
+                .iter()
+                .filter(|member| !self.is_complete(member))
+                .map(|member| {
+                    let n_bytes_already_read = read_up_to.saturating_sub(member.resolved_start);
+                    // SAFETY: `destination` is the only view of its underlying buffer (because
+                    // `freeze_destinations` checked that the user has given us the only view), and
+                    // `destination` is at least `len` bytes long.
+                    libc::iovec {
+                        iov_base: unsafe {
+                            member.destination.as_ptr().add(n_bytes_already_read) as *mut _
+                        },
+                        iov_len: member.len - n_bytes_already_read,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Send one `Chunk` per complete member, and one `ShortRead` per incomplete member (unless
+    /// `maybe_send_error` has already reported the failure of the incomplete members).
+    fn send_outputs(
+        &mut self,
+        report_short_reads: bool,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        let read_up_to = self.start() + self.n_bytes_read;
+        for member in std::mem::take(&mut self.members) {
+            let end = member.resolved_start + member.len;
+            if end <= read_up_to {
+                let mut buffer = member.destination;
+                buffer.set_slice(0..member.len);
+                let chunk = Chunk {
+                    buffer,
+                    user_data: member.user_data,
+                    range: Some(member.resolved_start..end),
+                };
+                output_channel.send(Ok(Output::Chunk(chunk))).unwrap();
+            } else if report_short_reads {
+                output_channel
+                    .send(Err(IoError::ShortRead {
+                        path: self.file.path(),
+                        range: member.range,
+                        got: read_up_to.saturating_sub(member.resolved_start),
+                        wanted: member.len,
+                        file_size: self.file.size(),
+                        details: format!("user_data: {}", member.user_data),
+                    }))
+                    .unwrap();
+            }
+        }
+    }
+}
+
+impl UringOperation for GetRangeVectored {
+    /// This method assumes that the file has already been opened (by the [`GetRanges`]
+    /// operation).
+    ///
+    /// If this operation has been re-queued (because the SQ was full after a short read) then
+    /// this method submits a `readv` for the remaining bytes.
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        // We're not in flight, so the kernel isn't using the old `iovecs`.
+        self.iovecs = self.build_iovecs();
+        let file_offset = (self.start() + self.n_bytes_read) as u64;
+        let entry = build_readv_sqe(index_of_op, &self.file, &self.iovecs.0, file_offset);
+        // SAFETY: `iovecs` and `members` (which own the destination buffers) live in this
+        // operation, which the worker keeps alive until the CQE has been received. The `iovecs`
+        // are heap-allocated, so they don't move even if this operation moves.
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.file.path())
+    }
+
+    fn maybe_send_error(
+        &self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        if cqe_result >= 0 {
+            return;
+        }
+        // Send one error per member which hasn't been read, so the user receives one output per
+        // range. We don't include `self` in `details`, because `self` holds every member.
+        let details = format!(
+            "(reported by io_uring completion queue entry (CQE)). More details: \
+                idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}.",
+        );
+        for member in self.members.iter().filter(|m| !self.is_complete(m)) {
+            output_channel
+                .send(Err(IoError::Nix {
+                    errno: nix::Error::from_raw(-cqe_result),
+                    opcode: idx_and_opcode.opcode().name(),
+                    path: Some(self.file.path()),
+                    range: Some(member.range.clone()),
+                    user_data: Some(member.user_data),
+                    details: details.clone(),
+                }))
+                .unwrap();
+        }
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Readv::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        let mut report_short_reads = false;
+        match cqe_result {
+            // The error has already been reported by `maybe_send_error`.
+            _ if cqe_result < 0 => (),
+            // A zero-length read means that we've hit the end of the file. The file may have been
+            // truncated since its size was read.
+            0 => {
+                report_short_reads = true;
+                self.file.forget_cached_size();
+            }
+            n_bytes => {
+                self.n_bytes_read += n_bytes as usize;
+                if self.n_bytes_read < self.total_len() {
+                    // Short read! Read the remaining bytes. See issue #100.
+                    return match self.submit_first_step(index_of_op, local_uring_submission_queue) {
+                        Ok(()) => NextStep::Pending,
+                        // We have no SQEs in flight, so let the worker's main loop call
+                        // `submit_first_step` later.
+                        Err(_) => NextStep::Requeue,
+                    };
+                }
+            }
+        }
+
+        let file_complete = self
+            .file_complete_output
+            .then(|| self.members.last().unwrap().user_data);
+        self.send_outputs(report_short_reads, output_channel);
+        close_if_last_op_on_file(
+            &self.file,
+            file_complete,
+            index_of_op,
+            local_uring_submission_queue,
+            spawner,
+            output_channel,
+        )
+    }
+}
+
+/// The `iovec`s passed to the kernel by `readv`.
+struct IoVecs(Vec<libc::iovec>);
+
+// SAFETY: Each `iovec` points into a destination buffer owned by the same `GetRangeVectored`, and
+// `AlignedBytes` is `Send`.
+unsafe impl Send for IoVecs {}
+
+impl fmt::Debug for IoVecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|iovec| (iovec.iov_base, iovec.iov_len)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use io_uring::types;
+    use lsio_aligned_bytes::AlignedBytesMut;
+
+    use super::*;
+    use crate::{file_size_cache::FileSize, open_file::OpenFileBuilder};
+
+    #[test]
+    fn test_build_iovecs_after_short_read() {
+        let mut builder = OpenFileBuilder::new(Arc::new(CString::new("f").unwrap()));
+        builder.set_file_descriptor(types::Fd(-1));
+        builder.set_file_size(FileSize {
+            size: 10_000,
+            mem_alignment: 0,
+            offset_alignment: 0,
+        });
+        let member = |resolved_start: usize, len| VectoredMember {
+            range: resolved_start as isize..(resolved_start + len) as isize,
+            resolved_start,
+            len,
+            destination: AlignedBytesMut::new(len, 512).freeze().unwrap(),
+            user_data: resolved_start as u64,
+        };
+        let members = vec![member(1000, 100), member(1100, 200), member(1300, 300)];
+        let ptrs: Vec<usize> = members
+            .iter()
+            .map(|m| m.destination.as_ptr() as usize)
+            .collect();
+        let mut op = GetRangeVectored::new(Arc::new(builder.build()), members);
+
+        let iovecs = op.build_iovecs();
+        assert_eq!(iovecs.0.len(), 3);
+        assert_eq!(iovecs.0[0].iov_base as usize, ptrs[0]);
+        assert_eq!(iovecs.0[2].iov_len, 300);
+
+        // After reading 150 bytes, the first member is complete, and 50 bytes of the second
+        // member have been read.
+        op.n_bytes_read = 150;
+        let iovecs = op.build_iovecs();
+        assert_eq!(iovecs.0.len(), 2);
+        assert_eq!(iovecs.0[0].iov_base as usize, ptrs[1] + 50);
+        assert_eq!(iovecs.0[0].iov_len, 150);
+        assert_eq!(iovecs.0[1].iov_base as usize, ptrs[2]);
+        assert_eq!(iovecs.0[1].iov_len, 300);
+    }
+}
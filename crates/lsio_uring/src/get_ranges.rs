@@ -1,47 +1,540 @@
-use std::{ffi::CString, iter::zip, ops::Range, sync::Arc};
+use std::{ffi::CString, iter::zip, ops::Range, path::PathBuf, sync::Arc, time::Duration};
 
-use lsio_threadpool::WorkerThread;
+use lsio_aligned_bytes::{AlignedBytes, BufferPool};
+#[cfg(feature = "checksum")]
+use lsio_io::Checksum;
+use lsio_io::IoError;
 
 use crate::{
+    close::Close,
+    file_size_cache::FileSizeCache,
+    fixed_buffers::FixedBuffers,
     get_range::GetRange,
-    open_file::OpenFileBuilder,
+    get_range_vectored::{GetRangeVectored, VectoredMember, MAX_IOVECS},
+    groups::GroupMember,
+    merge_ranges::{find_adjacent_runs, merge_ranges, MergedMember, MergedRange},
+    open_file::{OpenFile, OpenFileBuilder},
+    open_file_limit::OpenFilePermit,
     operation::{NextStep, Operation, UringOperation},
-    sqe::{build_openat_sqe, build_statx_sqe},
+    retry::RetryPolicy,
+    spawner::Spawner,
+    sqe::{build_openat_sqe, build_statx_sqe, can_read_vectored_into, MAX_READ_LEN},
+    trace::trace_event,
+    user_data::UringUserData,
 };
 
-const N_CQES_EXPECTED: u8 = 2; // We're expecting CQEs for `openat` and `statx`.
-
 #[derive(Debug)]
 pub(crate) struct GetRanges {
     open_file_builder: Option<OpenFileBuilder>,
     ranges: Vec<Range<isize>>,
     user_data: Vec<u64>,
 
+    /// If `Some`, then read each range into its corresponding (caller-provided) destination buffer,
+    /// instead of allocating new buffers.
+    destinations: Option<Vec<AlignedBytes>>,
+
+    /// If `Some`, merge ranges which are separated by fewer than `max_gap` bytes. If
+    /// `destinations` is `Some`, then only ranges which are exactly adjacent are merged (into a
+    /// single vectored read).
+    max_gap: Option<usize>,
+
+    /// If `Some`, then this operation (and the `GetRange` operations it spawns) belong to a group.
+    group: Option<Arc<GroupMember>>,
+
+    /// If `Some`, then only `statx` the file if its size isn't already in the cache.
+    file_size_cache: Option<Arc<FileSizeCache>>,
+
+    /// If `Some`, then the `GetRange` operations read into these registered buffers (if they're
+    /// free). Ignored if `destinations` is `Some`.
+    fixed_buffers: Option<Arc<FixedBuffers>>,
+
+    /// If `Some`, then the `GetRange` operations allocate their buffers from this pool. Ignored if
+    /// `destinations` is `Some`.
+    buffer_pool: Option<BufferPool>,
+
+    /// If `Some`, then the `GetRange` operations allocate buffers of at least this many bytes from
+    /// huge pages. Ignored if `destinations` is `Some`.
+    huge_pages_threshold: Option<usize>,
+
+    /// If true, then try to open the file as a fixed file. See
+    /// [`crate::IoUringBuilder::fixed_files`].
+    fixed_file: bool,
+
+    /// If true, then the last `GetRange` operation emits `Output::FileComplete`.
+    file_complete_output: bool,
+
+    /// If `Some`, then each `read` SQE of the `GetRange` operations is cancelled if it hasn't
+    /// completed within this timeout.
+    timeout: Option<Duration>,
+
+    /// How the `GetRange` operations retry `read`s which fail with a transient error.
+    retry_policy: RetryPolicy,
+
+    /// If true, then the `GetRange` operations fill the bytes beyond the end of the file with
+    /// zeros. The file is always `statx`ed, so the `GetRange` operations know where it ends.
+    zero_fill_past_eof: bool,
+
+    /// If `Some`, then the `GetRange` operation for each range verifies the corresponding checksum.
+    /// Ranges with checksums must not be merged (so `max_gap` must be `None`).
+    #[cfg(feature = "checksum")]
+    checksums: Option<Vec<Checksum>>,
+
+    /// The opcode and result of the first CQE which failed (if any). If the file can't be opened
+    /// then we send one error per range (instead of one error per failed CQE), so that the user
+    /// receives one output per range.
+    failed_cqe: Option<(&'static str, i32)>,
+
+    /// Set if there was no free slot for a fixed file. The file will be re-opened as a normal file
+    /// once every other CQE for this operation has arrived.
+    retry_openat: bool,
+
     // If both CQEs succeed then we'll capture their outputs in `open_file_builder`. But, in case
     // one or more CQEs reports a failure, we need an additional mechanism to track how many CQEs
     // we've received.
     n_cqes_received: u8,
+    /// We expect CQEs for `openat` and `statx`, or just for `openat` if the file size was cached.
+    n_cqes_expected: u8,
 }
 
 impl GetRanges {
-    pub(crate) fn new(location: CString, ranges: Vec<Range<isize>>, user_data: Vec<u64>) -> Self {
-        assert_eq!(ranges.len(), user_data.len());
+    pub(crate) fn new(
+        location: Arc<CString>,
+        ranges: Vec<Range<isize>>,
+        destinations: Option<Vec<AlignedBytes>>,
+        user_data: Vec<u64>,
+    ) -> Self {
+        // The public API has already checked these lengths.
+        debug_assert_eq!(ranges.len(), user_data.len());
+        if let Some(destinations) = &destinations {
+            debug_assert_eq!(ranges.len(), destinations.len());
+        }
         Self {
             open_file_builder: Some(OpenFileBuilder::new(location)),
             ranges,
             user_data,
+            destinations,
+            max_gap: None,
+            group: None,
+            file_size_cache: None,
+            fixed_buffers: None,
+            buffer_pool: None,
+            huge_pages_threshold: None,
+            fixed_file: false,
+            file_complete_output: false,
+            timeout: None,
+            retry_policy: RetryPolicy::default(),
+            zero_fill_past_eof: false,
+            #[cfg(feature = "checksum")]
+            checksums: None,
+            failed_cqe: None,
+            retry_openat: false,
             n_cqes_received: 0,
+            n_cqes_expected: 2,
+        }
+    }
+
+    #[cfg(feature = "checksum")]
+    pub(crate) fn with_checksums(mut self, checksums: Vec<Checksum>) -> Self {
+        debug_assert_eq!(self.ranges.len(), checksums.len());
+        self.checksums = Some(checksums);
+        self
+    }
+
+    /// Append more ranges (from a later `get_ranges` call for the same file). Only used by the
+    /// [`crate::coalesce::Coalescer`], before this operation has been submitted.
+    pub(crate) fn extend(&mut self, ranges: Vec<Range<isize>>, user_data: Vec<u64>) {
+        debug_assert_eq!(ranges.len(), user_data.len());
+        debug_assert!(self.destinations.is_none());
+        self.ranges.extend(ranges);
+        self.user_data.extend(user_data);
+    }
+
+    pub(crate) fn with_max_gap(mut self, max_gap: Option<usize>) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    pub(crate) fn with_file_size_cache(mut self, file_size_cache: Arc<FileSizeCache>) -> Self {
+        self.open_file_builder
+            .as_mut()
+            .unwrap()
+            .set_file_size_cache(Arc::clone(&file_size_cache));
+        self.file_size_cache = Some(file_size_cache);
+        self
+    }
+
+    pub(crate) fn with_fixed_buffers(mut self, fixed_buffers: Option<Arc<FixedBuffers>>) -> Self {
+        self.fixed_buffers = fixed_buffers;
+        self
+    }
+
+    pub(crate) fn with_buffer_pool(mut self, buffer_pool: Option<BufferPool>) -> Self {
+        self.buffer_pool = buffer_pool;
+        self
+    }
+
+    pub(crate) fn with_huge_pages_threshold(mut self, huge_pages_threshold: Option<usize>) -> Self {
+        self.huge_pages_threshold = huge_pages_threshold;
+        self
+    }
+
+    pub(crate) fn with_fixed_file(mut self, fixed_file: bool) -> Self {
+        self.fixed_file = fixed_file;
+        self
+    }
+
+    pub(crate) fn with_file_complete_output(mut self, file_complete_output: bool) -> Self {
+        self.file_complete_output = file_complete_output;
+        self
+    }
+
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub(crate) fn with_zero_fill_past_eof(mut self, zero_fill_past_eof: bool) -> Self {
+        self.zero_fill_past_eof = zero_fill_past_eof;
+        self
+    }
+
+    /// If `direct_io` is false, then open the file without `O_DIRECT`.
+    pub(crate) fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.open_file_builder
+            .as_mut()
+            .unwrap()
+            .set_direct_io(direct_io);
+        self
+    }
+
+    /// Open the file with `open_flags` (as well as `O_RDONLY`, and `O_DIRECT` if enabled).
+    pub(crate) fn with_open_flags(mut self, open_flags: libc::c_int) -> Self {
+        self.open_file_builder
+            .as_mut()
+            .unwrap()
+            .set_open_flags(open_flags);
+        self
+    }
+
+    pub(crate) fn with_group(mut self, group: Arc<GroupMember>) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Returns true if this operation may open its file (or has already opened its file). See
+    /// [`crate::open_file_limit::OpenFileLimit`].
+    pub(crate) fn has_open_file_permit(&self) -> bool {
+        self.open_file_builder
+            .as_ref()
+            .is_none_or(OpenFileBuilder::has_open_file_permit)
+    }
+
+    pub(crate) fn set_open_file_permit(&mut self, permit: OpenFilePermit) {
+        if let Some(builder) = &mut self.open_file_builder {
+            builder.set_open_file_permit(permit);
+        }
+    }
+
+    /// Send one error per range, describing the CQE which failed.
+    fn send_error_per_range(
+        &self,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) {
+        let Some((opcode, cqe_result)) = self.failed_cqe else {
+            return;
+        };
+        let errno = nix::Error::from_raw(-cqe_result);
+        let path = self.open_file_builder.as_ref().unwrap().path();
+        // We don't include `self` in `details` (unlike `maybe_send_error`), because `self` holds
+        // every range, and we send one error per range.
+        let details = format!(
+            "(reported by io_uring completion queue entry (CQE)). More details: opcode: \
+                {opcode}. cqe_result: {cqe_result}.",
+        );
+        for (range, &user_data) in zip(&self.ranges, &self.user_data) {
+            let err = match errno {
+                nix::Error::ENOENT => IoError::NotFound {
+                    path: path.clone(),
+                    user_data: Some(user_data),
+                    details: details.clone(),
+                },
+                errno => IoError::Nix {
+                    errno,
+                    opcode,
+                    path: Some(path.clone()),
+                    range: Some(range.to_owned()),
+                    user_data: Some(user_data),
+                    details: details.clone(),
+                },
+            };
+            output_channel.send(Err(err)).unwrap();
         }
     }
 
     // io_uring can't process multiple range requests in a single op. So, once we've opened the
     // file and gotten its metadata, we need to submit one `Operation::GetRange` per byte range.
-    fn submit_get_range_ops(&mut self, worker_thread: &WorkerThread<Operation>) {
+    //
+    // If there's only one `GetRange` (e.g. when the user reads a single range) then we submit its
+    // first step straight away, and it replaces this operation. This saves a round-trip through
+    // the worker's queue (where the `GetRange` could be stolen by another worker, or wait behind
+    // other operations), which matters for the latency of small, single reads.
+    fn submit_get_range_ops(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) -> NextStep {
         let file = Arc::new(self.open_file_builder.take().unwrap().build());
+        self.remove_unresolvable_ranges(&file, output_channel);
+        let mut ops = self.build_get_range_ops(&file, output_channel);
+        if Arc::strong_count(&file) == 1 {
+            // No operation is reading the file (e.g. because every range was invalid), so no
+            // operation will close the file.
+            spawner.push(Operation::Close(Close::new(file)));
+            return NextStep::Done;
+        }
+        if let [_] = ops.as_slice() {
+            let mut op = ops.pop().unwrap();
+            return match op.submit_first_step(index_of_op, local_uring_submission_queue) {
+                Ok(()) => NextStep::ReplaceWith(op),
+                Err(_) => {
+                    // The SQ is full, so let the worker's main loop submit `op` later.
+                    spawner.push(op);
+                    NextStep::Done
+                }
+            };
+        }
+        for op in ops {
+            spawner.push(op);
+        }
+        NextStep::Done
+    }
+
+    /// Send an `InvalidRange` error for (and then forget) each range which doesn't resolve to a
+    /// non-empty range of the file. For example, a negative range which reaches back beyond the
+    /// start of the file. The file size may be stale (if it came from the `FileSizeCache` and the
+    /// file has since shrunk), so we also forget the cached file size.
+    fn remove_unresolvable_ranges(
+        &mut self,
+        file: &OpenFile,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) {
+        let is_resolvable: Vec<bool> = self
+            .ranges
+            .iter()
+            .map(|range| file.try_resolve_range(range).is_some())
+            .collect();
+        if is_resolvable.iter().all(|&is_resolvable| is_resolvable) {
+            return;
+        }
+        file.forget_cached_size();
+        for ((range, user_data), _) in zip(zip(&self.ranges, &self.user_data), &is_resolvable)
+            .filter(|(_, &is_resolvable)| !is_resolvable)
+        {
+            output_channel
+                .send(Err(IoError::InvalidRange {
+                    path: file.path(),
+                    range: range.to_owned(),
+                    message: match file.size() {
+                        Some(filesize) => format!(
+                            "The range {range:?} doesn't resolve to a non-empty range of the \
+                                file, which was {filesize} bytes when its size was read. \
+                                user_data={user_data}",
+                        ),
+                        None => format!("The range {range:?} is empty. user_data={user_data}"),
+                    },
+                }))
+                .unwrap();
+        }
+        let mut keep = is_resolvable.iter();
+        self.ranges.retain(|_| *keep.next().unwrap());
+        let mut keep = is_resolvable.iter();
+        self.user_data.retain(|_| *keep.next().unwrap());
+        if let Some(destinations) = &mut self.destinations {
+            let mut keep = is_resolvable.iter();
+            destinations.retain(|_| *keep.next().unwrap());
+        }
+        #[cfg(feature = "checksum")]
+        if let Some(checksums) = &mut self.checksums {
+            let mut keep = is_resolvable.iter();
+            checksums.retain(|_| *keep.next().unwrap());
+        }
+    }
+
+    /// Build the operations which read the ranges of `file`.
+    fn build_get_range_ops(
+        &mut self,
+        file: &Arc<OpenFile>,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) -> Vec<Operation> {
+        if let (Some(max_gap), None) = (self.max_gap, &self.destinations) {
+            // Negative ranges can only be resolved now that we know the file size.
+            let resolved_ranges: Vec<_> = self
+                .ranges
+                .iter()
+                .map(|range| file.resolve_range(range))
+                .collect();
+            // A range which runs past the end of the file will fail, so don't merge it with its
+            // neighbours. (If the file wasn't `statx`ed then we don't know its size. But a merged
+            // `GetRange` which fails still sends a `Chunk` for each member which was read.)
+            let is_past_eof = |range: &Range<isize>| {
+                !self.zero_fill_past_eof && file.size().is_some_and(|size| range.end as u64 > size)
+            };
+            let (mergeable, past_eof): (Vec<usize>, Vec<usize>) =
+                (0..resolved_ranges.len()).partition(|&i| !is_past_eof(&resolved_ranges[i]));
+            let mut merged_ranges = merge_ranges(
+                &mergeable
+                    .iter()
+                    .map(|&i| resolved_ranges[i].clone())
+                    .collect::<Vec<_>>(),
+                &mergeable
+                    .iter()
+                    .map(|&i| self.user_data[i])
+                    .collect::<Vec<_>>(),
+                max_gap,
+            );
+            merged_ranges.extend(past_eof.into_iter().map(|i| {
+                let range = resolved_ranges[i].clone();
+                MergedRange {
+                    members: vec![MergedMember {
+                        range: 0..range.len(),
+                        user_data: self.user_data[i],
+                    }],
+                    range,
+                }
+            }));
+            return merged_ranges
+                .into_iter()
+                .map(|merged_range| {
+                    let get_range_op = GetRange::new_merged(file.clone(), merged_range)
+                        .with_group(self.group.clone())
+                        .with_fixed_buffers(self.fixed_buffers.clone())
+                        .with_buffer_pool(self.buffer_pool.clone())
+                        .with_huge_pages_threshold(self.huge_pages_threshold)
+                        .with_file_complete_output(self.file_complete_output)
+                        .with_timeout(self.timeout)
+                        .with_retry_policy(self.retry_policy)
+                        .with_zero_fill_past_eof(self.zero_fill_past_eof);
+                    Operation::GetRange(get_range_op)
+                })
+                .collect();
+        }
+        if let Some(destinations) = self.destinations.take() {
+            return self.build_get_range_into_ops(file, destinations, output_channel);
+        }
+        #[cfg(feature = "checksum")]
+        let mut checksums = self.checksums.iter().flatten();
+        let mut ops = Vec::with_capacity(self.ranges.len());
         for (range, user_data) in zip(&self.ranges, &self.user_data) {
-            let get_range_op = GetRange::new(file.clone(), range.to_owned(), *user_data);
-            worker_thread.push(Operation::GetRange(get_range_op));
+            let get_range_op = GetRange::new(file.clone(), range.to_owned(), *user_data)
+                .with_fixed_buffers(self.fixed_buffers.clone())
+                .with_buffer_pool(self.buffer_pool.clone())
+                .with_huge_pages_threshold(self.huge_pages_threshold)
+                .with_group(self.group.clone())
+                .with_file_complete_output(self.file_complete_output)
+                .with_timeout(self.timeout)
+                .with_retry_policy(self.retry_policy)
+                .with_zero_fill_past_eof(self.zero_fill_past_eof);
+            #[cfg(feature = "checksum")]
+            let get_range_op = get_range_op.with_checksum(checksums.next().copied());
+            ops.push(Operation::GetRange(get_range_op));
         }
+        ops
+    }
+
+    /// Build the operations which read each range into its caller-provided `destination`. If
+    /// merging is enabled (and there's no timeout), then ranges which are exactly adjacent in the
+    /// file are read by a single `readv` (which scatters the bytes into the destinations).
+    /// Otherwise, each range is read by its own `GetRange`.
+    fn build_get_range_into_ops(
+        &self,
+        file: &Arc<OpenFile>,
+        destinations: Vec<AlignedBytes>,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) -> Vec<Operation> {
+        let mut members = Vec::with_capacity(destinations.len());
+        for ((range, &user_data), destination) in
+            zip(zip(&self.ranges, &self.user_data), destinations)
+        {
+            let resolved_range = file.resolve_range(range);
+            if resolved_range.len() > destination.len() {
+                output_channel
+                    .send(Err(IoError::InvalidRange {
+                        path: file.path(),
+                        range: range.to_owned(),
+                        message: format!(
+                            "The range {range:?} (resolved to {resolved_range:?}) is {} bytes \
+                                long, but the destination buffer is only {} bytes long. \
+                                user_data={user_data}",
+                            resolved_range.len(),
+                            destination.len(),
+                        ),
+                    }))
+                    .unwrap();
+                continue;
+            }
+            members.push(Some(VectoredMember {
+                range: range.to_owned(),
+                resolved_start: resolved_range.start as usize,
+                len: resolved_range.len(),
+                destination,
+                user_data,
+            }));
+        }
+
+        // `GetRangeVectored` doesn't support timeouts, or filling the bytes past EOF with zeros.
+        let runs: Vec<Vec<usize>> =
+            if self.max_gap.is_some() && self.timeout.is_none() && !self.zero_fill_past_eof {
+                let (vectorable, not_vectorable): (Vec<usize>, Vec<usize>) = (0..members.len())
+                    .partition(|&i| {
+                        let member = members[i].as_ref().unwrap();
+                        can_read_vectored_into(file, &member.resolved_range(), &member.destination)
+                    });
+                let vectorable_ranges: Vec<_> = vectorable
+                    .iter()
+                    .map(|&i| members[i].as_ref().unwrap().resolved_range())
+                    .collect();
+                find_adjacent_runs(&vectorable_ranges, MAX_IOVECS, MAX_READ_LEN)
+                    .into_iter()
+                    .map(|run| run.into_iter().map(|j| vectorable[j]).collect())
+                    .chain(not_vectorable.into_iter().map(|i| vec![i]))
+                    .collect()
+            } else {
+                (0..members.len()).map(|i| vec![i]).collect()
+            };
+
+        let mut ops = Vec::with_capacity(runs.len());
+        for run in runs {
+            let mut run_members: Vec<_> = run.iter().map(|&i| members[i].take().unwrap()).collect();
+            let op = if run_members.len() == 1 {
+                let member = run_members.pop().unwrap();
+                let get_range_op = GetRange::new_into(
+                    file.clone(),
+                    member.range,
+                    member.destination,
+                    member.user_data,
+                )
+                .with_group(self.group.clone())
+                .with_file_complete_output(self.file_complete_output)
+                .with_timeout(self.timeout)
+                .with_retry_policy(self.retry_policy)
+                .with_zero_fill_past_eof(self.zero_fill_past_eof);
+                Operation::GetRange(get_range_op)
+            } else {
+                let get_range_op = GetRangeVectored::new(file.clone(), run_members)
+                    .with_group(self.group.clone())
+                    .with_file_complete_output(self.file_complete_output);
+                Operation::GetRangeVectored(get_range_op)
+            };
+            ops.push(op);
+        }
+        ops
     }
 }
 
@@ -51,56 +544,148 @@ impl UringOperation for GetRanges {
         index_of_op: usize,
         local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
     ) -> Result<(), io_uring::squeue::PushError> {
+        let builder = self.open_file_builder.as_mut().unwrap();
+        trace_event!(
+            "submit",
+            path = builder.path(),
+            n_ranges = self.ranges.len(),
+            retry_openat = self.retry_openat,
+        );
         let open_entry = build_openat_sqe(
             index_of_op,
-            self.open_file_builder.as_ref().unwrap().location(),
+            builder.location(),
+            self.fixed_file,
+            builder.is_direct_io(),
+            builder.open_flags(),
         );
-        let statx_entry =
-            build_statx_sqe(index_of_op, &mut self.open_file_builder.as_mut().unwrap());
-        unsafe {
-            local_uring_submission_queue.push(&open_entry)?;
-            local_uring_submission_queue.push(&statx_entry)?;
-        };
-        Ok(())
+        let cached_file_size = self
+            .file_size_cache
+            .as_ref()
+            .and_then(|cache| cache.get(builder.location()));
+        if let Some(file_size) = cached_file_size {
+            builder.set_file_size(file_size);
+        }
+        if builder.file_size().is_none()
+            && !self.zero_fill_past_eof
+            && self
+                .ranges
+                .iter()
+                .all(|range| range.start >= 0 && range.end >= 0)
+        {
+            // Every range is relative to the start of the file, so we can submit the reads as
+            // soon as the file is open, without waiting for `statx`.
+            builder.skip_file_size();
+        }
+        if !builder.needs_statx() {
+            // We already know the file size (from the cache, or because we're re-trying `openat`),
+            // or we don't need it. So we don't need to `statx` the file.
+            self.n_cqes_expected = self.n_cqes_received + 1;
+            return unsafe { local_uring_submission_queue.push(&open_entry) };
+        }
+        let statx_entry = build_statx_sqe(index_of_op, builder);
+        // `push_multiple` is atomic: Either both SQEs are pushed, or neither are pushed.
+        unsafe { local_uring_submission_queue.push_multiple(&[open_entry, statx_entry]) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        self.open_file_builder.as_ref().map(OpenFileBuilder::path)
+    }
+
+    fn will_retry(&self, idx_and_opcode: &UringUserData, cqe_result: i32) -> bool {
+        // `ENFILE` means that this worker's io_uring has no free slots for fixed files.
+        self.fixed_file
+            && idx_and_opcode.opcode().value() == io_uring::opcode::OpenAt::CODE
+            && cqe_result == -libc::ENFILE
+    }
+
+    fn maybe_send_error(
+        &self,
+        _idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, IoError>>,
+    ) {
+        // Errors are sent by `send_error_per_range`, once every CQE has arrived.
     }
 
     fn process_opcode_and_submit_next_step(
         &mut self,
-        idx_and_opcode: &crate::user_data::UringUserData,
+        idx_and_opcode: &UringUserData,
         cqe_result: i32,
-        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
-        worker_thread: &WorkerThread<Operation>,
-        _output_channel: &mut crossbeam_channel::Sender<anyhow::Result<lsio_io::Output>>,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
     ) -> NextStep {
-        self.n_cqes_received += 1;
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::OpenAt::CODE => trace_event!(
+                "openat done",
+                path = self.path(idx_and_opcode),
+                cqe_result = cqe_result,
+            ),
+            io_uring::opcode::Statx::CODE => trace_event!(
+                "statx done",
+                path = self.path(idx_and_opcode),
+                cqe_result = cqe_result,
+            ),
+            _ => (),
+        }
+        if self.will_retry(idx_and_opcode, cqe_result) {
+            // Don't count this CQE, because we'll re-open the file as a normal file.
+            self.fixed_file = false;
+            self.retry_openat = true;
+        } else {
+            self.n_cqes_received += 1;
+            if cqe_result < 0 && self.failed_cqe.is_none() {
+                self.failed_cqe = Some((idx_and_opcode.opcode().name(), cqe_result));
+            }
+        }
         if cqe_result >= 0 {
+            let builder = self.open_file_builder.as_mut().unwrap();
             match idx_and_opcode.opcode().value() {
+                io_uring::opcode::OpenAt::CODE if self.fixed_file => {
+                    builder.set_fixed_file(io_uring::types::Fixed(cqe_result as u32));
+                }
                 io_uring::opcode::OpenAt::CODE => {
-                    self.open_file_builder
-                        .as_mut()
-                        .unwrap()
-                        .set_file_descriptor(io_uring::types::Fd(cqe_result));
+                    builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
                 }
                 io_uring::opcode::Statx::CODE => {
-                    unsafe {
-                        self.open_file_builder
-                            .as_mut()
-                            .unwrap()
-                            .assume_statx_is_initialised();
-                    };
+                    unsafe { builder.assume_statx_is_initialised() };
+                    if let Some(cache) = &self.file_size_cache {
+                        cache.insert(builder.location(), builder.file_size().unwrap());
+                    }
                 }
                 _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
             };
         };
 
-        assert!(self.n_cqes_received <= N_CQES_EXPECTED);
-        if self.n_cqes_received == N_CQES_EXPECTED {
+        assert!(self.n_cqes_received <= self.n_cqes_expected);
+        if self.retry_openat && self.n_cqes_received + 1 == self.n_cqes_expected {
+            // The only CQE that we're still waiting for is the `openat` which must be retried.
+            self.retry_openat = false;
+            if self.open_file_builder.as_ref().unwrap().needs_statx() {
+                // `statx` failed, so there's no point opening the file.
+                self.send_error_per_range(output_channel);
+                return NextStep::Done;
+            }
+            let index_of_op = idx_and_opcode.index_of_op() as usize;
+            return match self.submit_first_step(index_of_op, local_uring_submission_queue) {
+                Ok(()) => NextStep::Pending,
+                // The worker will call `submit_first_step` again later.
+                Err(_) => NextStep::Requeue,
+            };
+        }
+        if self.n_cqes_received == self.n_cqes_expected {
             if self.open_file_builder.as_mut().unwrap().is_ready() {
-                self.submit_get_range_ops(worker_thread);
-                NextStep::Done
+                let index_of_op = idx_and_opcode.index_of_op() as usize;
+                self.submit_get_range_ops(
+                    index_of_op,
+                    local_uring_submission_queue,
+                    spawner,
+                    output_channel,
+                )
             } else {
                 // We've seen all the CQEs we were expecting, but `open_file_builder` isn't ready. So
                 // at least one of the CQEs must have resulted in an error. Nevertheless, we're "done".
+                self.send_error_per_range(output_channel);
                 NextStep::Done
             }
         } else {
@@ -109,3 +694,72 @@ impl UringOperation for GetRanges {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_size_cache::FileSize;
+
+    /// Returns the number of SQEs pushed by `GetRanges::submit_first_step`.
+    fn n_sqes_submitted(get_ranges: &mut GetRanges) -> usize {
+        // We never submit these SQEs to the kernel, so it doesn't matter that `get_ranges` will be
+        // dropped before the `statx` could complete.
+        let mut uring = io_uring::IoUring::new(8).unwrap();
+        get_ranges
+            .submit_first_step(0, &mut uring.submission())
+            .unwrap();
+        let n_sqes = uring.submission().len();
+        n_sqes
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_file_size_cache_skips_statx() {
+        let cache = Arc::new(FileSizeCache::new(10));
+        let location = CString::new("/tmp/lsio_uring_file_size_cache").unwrap();
+        let new_get_ranges = || {
+            // A negative range, so that the file size is needed.
+            GetRanges::new(Arc::new(location.clone()), vec![-1024..-1], None, vec![0])
+                .with_file_size_cache(Arc::clone(&cache))
+        };
+
+        // The first `GetRanges` has to `statx` the file.
+        assert_eq!(n_sqes_submitted(&mut new_get_ranges()), 2);
+
+        // Simulate the `statx` completing.
+        let file_size = FileSize {
+            size: 1024,
+            mem_alignment: 512,
+            offset_alignment: 512,
+        };
+        cache.insert(&location, file_size);
+
+        // The second `GetRanges` uses the cached file size, so only needs to `openat` the file.
+        let mut get_ranges = new_get_ranges();
+        assert_eq!(n_sqes_submitted(&mut get_ranges), 1);
+        assert_eq!(get_ranges.n_cqes_expected, 1);
+        let builder = get_ranges.open_file_builder.as_ref().unwrap();
+        assert_eq!(builder.file_size(), Some(file_size));
+
+        // Once the cache is cleared, we have to `statx` the file again.
+        cache.clear();
+        assert_eq!(n_sqes_submitted(&mut new_get_ranges()), 2);
+    }
+
+    #[test]
+    fn test_non_negative_ranges_skip_statx() {
+        let location = Arc::new(CString::new("/tmp/lsio_uring_skip_statx").unwrap());
+
+        let mut get_ranges =
+            GetRanges::new(location.clone(), vec![0..10, 20..30], None, vec![0, 1]);
+        assert_eq!(n_sqes_submitted(&mut get_ranges), 1);
+        assert_eq!(get_ranges.n_cqes_expected, 1);
+        let builder = get_ranges.open_file_builder.as_ref().unwrap();
+        assert!(!builder.needs_statx());
+
+        // A single negative range means that we need the file size.
+        let mut get_ranges = GetRanges::new(location, vec![0..10, -10..-1], None, vec![0, 1]);
+        assert_eq!(n_sqes_submitted(&mut get_ranges), 2);
+        assert!(get_ranges.open_file_builder.as_ref().unwrap().needs_statx());
+    }
+}
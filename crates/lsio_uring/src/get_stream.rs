@@ -0,0 +1,244 @@
+use std::{
+    ffi::CString,
+    os::fd::{IntoRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut};
+use lsio_io::{Chunk, IoError, Output};
+
+use crate::{
+    close::close_if_last_op_on_file,
+    open_file::{location_from_path, path_from_location, OpenFile, OpenFileBuilder},
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::{build_openat_sqe, build_stream_read_sqe},
+    user_data::UringUserData,
+};
+
+/// Streams are never read with `O_DIRECT`, so their buffers don't need to be aligned to the
+/// filesystem's block size.
+const BUFFER_ALIGN: usize = 64;
+
+/// What [`crate::IoUring::get_stream`] reads from.
+#[derive(Debug)]
+pub enum StreamSource {
+    /// Open this path (e.g. a FIFO). Opening a FIFO waits until the FIFO has a writer.
+    Path(PathBuf),
+    /// Read from this file descriptor (e.g. a pipe or a socket).
+    Fd(OwnedFd),
+}
+
+impl From<PathBuf> for StreamSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&Path> for StreamSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl From<OwnedFd> for StreamSource {
+    fn from(fd: OwnedFd) -> Self {
+        Self::Fd(fd)
+    }
+}
+
+/// Read a stream (whose size isn't known in advance) in chunks of up to `chunk_size` bytes, until
+/// a read returns zero bytes (end of file). Each chunk is sent as soon as it's read. Only one read
+/// is in flight at a time, so the chunks are sent in order. Then close the file.
+#[derive(Debug)]
+pub(crate) struct GetStream {
+    location: Arc<CString>,
+    /// `Some` once the file has been opened.
+    file: Option<Arc<OpenFile>>,
+    chunk_size: u32,
+    user_data: u64,
+    /// The buffer of the read which is in flight (or which is about to be submitted).
+    buffer: Option<AlignedBytes>,
+    /// The number of bytes read so far, which is also the offset of the next chunk.
+    nbytes: usize,
+}
+
+impl GetStream {
+    /// # Errors
+    /// Returns an error if `source` is a path which contains a NUL byte.
+    pub(crate) fn new(
+        source: StreamSource,
+        chunk_size: u32,
+        user_data: u64,
+    ) -> anyhow::Result<Self> {
+        let (location, file) = match source {
+            StreamSource::Path(path) => (Arc::new(location_from_path(&path)?), None),
+            StreamSource::Fd(fd) => {
+                // The file descriptor is closed by the `Close` operation at the end of the stream.
+                let fd = fd.into_raw_fd();
+                let location = Arc::new(CString::new(format!("/proc/self/fd/{fd}")).unwrap());
+                let mut builder = OpenFileBuilder::new(Arc::clone(&location));
+                builder.set_direct_io(false);
+                builder.skip_file_size();
+                builder.set_file_descriptor(io_uring::types::Fd(fd));
+                (location, Some(Arc::new(builder.build())))
+            }
+        };
+        Ok(Self {
+            location,
+            file,
+            chunk_size,
+            user_data,
+            buffer: None,
+            nbytes: 0,
+        })
+    }
+
+    /// The `user_data` of the SQE which this operation has in flight. Used to cancel the stream.
+    pub(crate) fn user_data_of_sqe_in_flight(&self, index_of_op: usize) -> u64 {
+        let opcode = match self.file {
+            None => io_uring::opcode::OpenAt::CODE,
+            Some(_) => io_uring::opcode::Read::CODE,
+        };
+        UringUserData::new(index_of_op, opcode).into()
+    }
+
+    fn send_error(
+        &self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        let details = format!(
+            "(reported by io_uring completion queue entry (CQE)). More details: \
+                idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {self:?}",
+        );
+        let path = path_from_location(&self.location);
+        let err = match nix::Error::from_raw(-cqe_result) {
+            nix::Error::ENOENT => IoError::NotFound {
+                path,
+                user_data: Some(self.user_data),
+                details,
+            },
+            errno => IoError::Nix {
+                errno,
+                opcode: idx_and_opcode.opcode().name(),
+                path: Some(path),
+                range: None,
+                user_data: Some(self.user_data),
+                details,
+            },
+        };
+        output_channel.send(Err(err)).unwrap();
+    }
+}
+
+impl UringOperation for GetStream {
+    /// Opens the file. Or, if the file is already open, submits the next read.
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = match &self.file {
+            None => build_openat_sqe(index_of_op, &self.location, false, false, 0),
+            Some(file) => {
+                let chunk_size = self.chunk_size as usize;
+                // If the SQ is full, then we keep the buffer for the next attempt.
+                let buffer = self.buffer.get_or_insert_with(|| {
+                    AlignedBytesMut::with_capacity(chunk_size, BUFFER_ALIGN)
+                        .freeze()
+                        .unwrap()
+                });
+                build_stream_read_sqe(
+                    index_of_op,
+                    file,
+                    buffer.as_ptr() as *mut u8,
+                    self.chunk_size,
+                )
+            }
+        };
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(path_from_location(&self.location))
+    }
+
+    fn maybe_send_error(
+        &self,
+        _idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        // Errors hold the stream's `user_data`. So all errors are sent by
+        // `process_opcode_and_submit_next_step`.
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if cqe_result < 0 {
+            self.send_error(idx_and_opcode, cqe_result, output_channel);
+        }
+        let index_of_op = idx_and_opcode.index_of_op() as usize;
+        match idx_and_opcode.opcode().value() {
+            io_uring::opcode::OpenAt::CODE => {
+                if cqe_result < 0 {
+                    return NextStep::Done;
+                }
+                let mut builder = OpenFileBuilder::new(Arc::clone(&self.location));
+                builder.set_direct_io(false);
+                builder.skip_file_size();
+                builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
+                self.file = Some(Arc::new(builder.build()));
+            }
+            io_uring::opcode::Read::CODE => {
+                // The kernel has finished with the buffer, so we can send it (or drop it, at the
+                // end of the stream or if there's an error).
+                let mut buffer = self.buffer.take().unwrap();
+                if cqe_result == 0 {
+                    output_channel
+                        .send(Ok(Output::StreamEnd {
+                            user_data: self.user_data,
+                            nbytes: self.nbytes,
+                        }))
+                        .unwrap();
+                }
+                if cqe_result <= 0 {
+                    return close_if_last_op_on_file(
+                        self.file.as_ref().unwrap(),
+                        None,
+                        index_of_op,
+                        local_uring_submission_queue,
+                        spawner,
+                        output_channel,
+                    );
+                }
+                let len = cqe_result as usize;
+                buffer.set_slice(0..len);
+                output_channel
+                    .send(Ok(Output::Chunk(Chunk {
+                        buffer,
+                        user_data: self.user_data,
+                        range: Some(self.nbytes..self.nbytes + len),
+                    })))
+                    .unwrap();
+                self.nbytes += len;
+            }
+            _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
+        }
+        // Submit the first (or next) read.
+        match self.submit_first_step(index_of_op, local_uring_submission_queue) {
+            Ok(()) => NextStep::Pending,
+            // The worker will call `submit_first_step` again later.
+            Err(_) => NextStep::Requeue,
+        }
+    }
+}
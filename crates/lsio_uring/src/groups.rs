@@ -0,0 +1,132 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use lsio_threadpool::WorkerThread;
+
+use crate::operation::Operation;
+
+/// Tracks groups of operations, so that every operation in group _n_ completes before any
+/// operation in group _n+1_ starts. See [`lsio_io::Reader::get_ranges_in_group`] and issue #68.
+///
+/// Each grouped operation holds an `Arc<GroupMember>` (which is cloned into any operations that it
+/// spawns). A group is finished when all of its `GroupMember`s have been dropped.
+#[derive(Debug, Default)]
+pub(crate) struct Groups {
+    state: Mutex<GroupsState>,
+
+    /// Set when the active group changes and there are held-back operations which can now start.
+    /// This allows `release_ready_ops` to return quickly without locking `state`.
+    ops_are_ready: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct GroupsState {
+    /// The number of unfinished operations in each group. The active group is the group with the
+    /// lowest ID.
+    n_unfinished_ops: BTreeMap<u64, usize>,
+
+    /// Operations which can't start until all the groups with lower IDs have finished.
+    held_back_ops: BTreeMap<u64, Vec<Operation>>,
+}
+
+impl GroupsState {
+    fn is_active(&self, group_id: u64) -> bool {
+        self.n_unfinished_ops
+            .first_key_value()
+            .is_none_or(|(&active_group_id, _)| group_id <= active_group_id)
+    }
+}
+
+impl Groups {
+    /// Register a new operation in `group_id`. The operation must hold on to the returned
+    /// `GroupMember` until the operation has finished.
+    pub(crate) fn join(self: &Arc<Self>, group_id: u64) -> Arc<GroupMember> {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .n_unfinished_ops
+            .entry(group_id)
+            .or_default() += 1;
+        Arc::new(GroupMember {
+            group_id,
+            groups: Arc::clone(self),
+        })
+    }
+
+    /// Returns `operation` if `group_id` is the active group (so `operation` can start now).
+    /// Otherwise, holds back `operation` until all the groups before `group_id` have finished.
+    pub(crate) fn start_or_hold_back(
+        &self,
+        group_id: u64,
+        operation: Operation,
+    ) -> Option<Operation> {
+        let mut state = self.state.lock().unwrap();
+        if state.is_active(group_id) {
+            Some(operation)
+        } else {
+            state
+                .held_back_ops
+                .entry(group_id)
+                .or_default()
+                .push(operation);
+            None
+        }
+    }
+
+    /// Push any held-back operations which can now start onto `worker_thread`'s queue.
+    pub(crate) fn release_ready_ops(&self, worker_thread: &WorkerThread<Operation>) {
+        if !self.ops_are_ready.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        while let Some(&group_id) = state.held_back_ops.keys().next() {
+            if !state.is_active(group_id) {
+                break;
+            }
+            for operation in state.held_back_ops.remove(&group_id).unwrap() {
+                worker_thread.push(operation);
+            }
+        }
+    }
+
+    /// Drop all the held-back operations (e.g. because the `IoUring` is being dropped).
+    pub(crate) fn drop_held_back_ops(&self) {
+        // We must take the operations out of `state` before dropping them, because dropping an
+        // operation locks `state` (when its `GroupMember` is dropped).
+        let held_back_ops = std::mem::take(&mut self.state.lock().unwrap().held_back_ops);
+        drop(held_back_ops);
+    }
+
+    fn leave(&self, group_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        let n_unfinished_ops = state.n_unfinished_ops.get_mut(&group_id).unwrap();
+        *n_unfinished_ops -= 1;
+        if *n_unfinished_ops == 0 {
+            state.n_unfinished_ops.remove(&group_id);
+            if let Some(&next_group_id) = state.held_back_ops.keys().next() {
+                if state.is_active(next_group_id) {
+                    self.ops_are_ready.store(true, Ordering::Release);
+                }
+            }
+        }
+    }
+}
+
+/// Membership of a group. The group finishes when all its `GroupMember`s have been dropped.
+#[derive(Debug)]
+pub(crate) struct GroupMember {
+    group_id: u64,
+    groups: Arc<Groups>,
+}
+
+impl Drop for GroupMember {
+    fn drop(&mut self) {
+        self.groups.leave(self.group_id);
+    }
+}
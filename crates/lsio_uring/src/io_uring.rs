@@ -1,34 +1,900 @@
-use std::{ffi::CString, os::unix::ffi::OsStrExt};
+use std::{
+    any::Any,
+    ffi::CString,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering::Acquire, Ordering::Relaxed},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
+use crate::advise::Advise;
+use crate::batch::Batch;
+use crate::coalesce::Coalescer;
+use crate::config::{CoalesceWindowConfig, Config, FixedBuffersConfig, SqPoll, WaitStrategy};
+use crate::copy_ranges::CopyRanges;
+use crate::exists::Exists;
+use crate::file_size_cache::FileSizeCache;
+use crate::fixed_buffers::FixedBuffers;
+use crate::fsync::Fsync;
 use crate::get_ranges::GetRanges;
+use crate::get_stream::{GetStream, StreamSource};
+use crate::groups::Groups;
+use crate::list::List;
+use crate::metadata::Metadata;
+use crate::open_file::{location_from_path, path_from_location};
+use crate::open_file_limit::OpenFileLimit;
 use crate::operation::Operation;
-use crate::worker::UringWorker;
-use lsio_io::{Completion, Output, Reader};
+use crate::probe::{probe, Capabilities, ProbeError};
+use crate::put_ranges::PutRanges;
+use crate::retry::RetryPolicy;
+use crate::sequencer::Sequencer;
+use crate::sqe::{is_aligned_for_direct_io, is_buffer_aligned_for_direct_io};
+use crate::stats::WorkerStats;
+use crate::worker::{build_ring, UringWorker, SQ_RING_SIZE};
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut, BufferPool};
+use lsio_io::{
+    check_batch, check_one_user_data_per_range, fadvise_offset_and_len, freeze_destinations,
+    Advice, Completion, Copier, IoError, Lister, Output, ReadOp, Reader, SyncMode, Writer,
+};
 use lsio_threadpool::{ThreadPool, WorkerThread};
 
+/// An IO backend which runs one io_uring per worker thread. Create an `IoUring` with
+/// [`IoUring::new`] or [`IoUring::builder`].
+///
+/// If a worker thread panics, then the operations queued on that worker thread will never finish.
+/// So the panic is sent to the completion channel as an [`IoError::Internal`] (which holds the
+/// panic's message), and every subsequent call which submits an operation returns an error.
 pub struct IoUring {
-    threadpool: ThreadPool<Operation>,
-    output_rx: crossbeam_channel::Receiver<anyhow::Result<Output>>,
+    /// Shared with the coalescer's flusher thread (if any).
+    threadpool: Arc<ThreadPool<Operation>>,
+    output_rx: crossbeam_channel::Receiver<Result<Output, IoError>>,
+    groups: Arc<Groups>,
+    open_file_limit: Arc<OpenFileLimit>,
+    max_gap: Option<usize>,
+    file_size_cache: Arc<FileSizeCache>,
+    fixed_buffers: Option<Arc<FixedBuffers>>,
+    buffer_pool: Option<BufferPool>,
+    huge_pages_threshold: Option<usize>,
+    fixed_files: bool,
+    file_complete_outputs: bool,
+    direct_io: bool,
+    open_flags: libc::c_int,
+    retry_policy: RetryPolicy,
+    zero_fill_past_eof: bool,
+    worker_stats: Arc<Vec<Arc<WorkerStats>>>,
+    /// The number of operations which have been submitted but haven't finished (including the
+    /// operations spawned by other operations, and held-back grouped operations).
+    n_unfinished_ops: Arc<AtomicUsize>,
+    /// Set by the first worker thread to panic.
+    worker_panic: Arc<OnceLock<String>>,
+    shutdown_timeout: Duration,
+    /// `Some` if `get_ranges` calls are coalesced. See [`IoUringBuilder::coalesce_window`].
+    coalescer: Option<Arc<Coalescer>>,
+    /// The thread which submits coalesced operations when their window expires.
+    flusher_thread: Option<thread::JoinHandle<()>>,
+    /// `Some` if the outputs of reads are re-sequenced into submission order. See
+    /// [`IoUringBuilder::ordered_completion`].
+    sequencer: Option<Arc<Sequencer>>,
 }
 
+/// How often [`IoUring::shutdown`] and [`IoUring::submit_barrier`] check whether all operations
+/// have finished.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
 impl IoUring {
+    /// Create an `IoUring` with the default configuration.
+    ///
+    /// If the kernel doesn't support io_uring then the worker threads fail to start, and every
+    /// method which submits an operation returns an error. Use [`IoUring::try_new`] to find out
+    /// straight away.
     pub fn new(n_worker_threads: usize) -> Self {
-        let (output_tx, output_rx) = crossbeam_channel::bounded(1_024);
-        Self {
-            threadpool: ThreadPool::new(
-                n_worker_threads,
-                move |worker_thread: WorkerThread<Operation>| {
-                    let mut uring_worker = UringWorker::new(worker_thread, output_tx.clone());
+        Self::builder(n_worker_threads).build()
+    }
+
+    /// Like [`IoUring::new`], except that it returns an error (instead of an `IoUring` which
+    /// can't do anything) if the kernel doesn't support io_uring, or doesn't support the opcodes
+    /// which `IoUring` requires. This lets applications fall back to another [`Reader`] (such as
+    /// `lsio_std::StdReader`). See [`IoUringBuilder::try_build`].
+    pub fn try_new(n_worker_threads: usize) -> anyhow::Result<Self> {
+        Self::builder(n_worker_threads).try_build()
+    }
+
+    /// Ask the kernel whether it supports io_uring, and which of the opcodes and features that
+    /// `IoUring` uses are available. This creates (and then drops) a small io_uring, so it's
+    /// cheap, but it's not free.
+    ///
+    /// # Errors
+    /// Returns an error if the kernel can't create an io_uring at all (e.g. because io_uring is
+    /// disabled), or can't report which opcodes it supports (which requires Linux 5.6).
+    pub fn probe() -> Result<Capabilities, ProbeError> {
+        probe()
+    }
+
+    /// Returns an [`IoUringBuilder`], which can be used to configure the `IoUring`.
+    pub fn builder(n_worker_threads: usize) -> IoUringBuilder {
+        IoUringBuilder {
+            n_worker_threads,
+            config: Config::default(),
+        }
+    }
+
+    /// Returns the statistics for each worker thread, in the order of the worker threads' indices.
+    pub fn worker_stats(&self) -> &[Arc<WorkerStats>] {
+        &self.worker_stats
+    }
+
+    /// Forget the cached file sizes. Call this if files may have been modified by another process
+    /// since they were last read by this `IoUring`.
+    pub fn clear_file_size_cache(&self) {
+        self.file_size_cache.clear();
+    }
+
+    /// Wait for every operation that has been submitted to finish, and then stop and join the
+    /// worker threads. Unlike dropping the `IoUring` (which stops the worker threads as soon as
+    /// possible), `shutdown` guarantees that every operation has sent all of its outputs.
+    ///
+    /// `shutdown` consumes the `IoUring`, which drops the completion channel's receiver. So, to
+    /// receive the outputs of the operations which finish during `shutdown`, clone the receiver
+    /// (returned by [`Completion::completion`]) before calling `shutdown`.
+    ///
+    /// # Errors
+    /// Returns an error if any operations are still unfinished after the shutdown timeout (see
+    /// [`IoUringBuilder::shutdown_timeout`]), or if a worker thread has panicked. The worker
+    /// threads are stopped regardless.
+    pub fn shutdown(self) -> anyhow::Result<()> {
+        self.flush_coalesced_ops();
+        self.wait_for_unfinished_ops("the IoUring to shut down")
+    }
+
+    /// Block until every operation submitted before this call has finished, i.e. until each
+    /// operation has sent all of its outputs to the completion channel (and has closed its files).
+    /// The outputs are left in the completion channel. This saves counting the expected outputs
+    /// by hand (e.g. in tests, or before checkpointing).
+    ///
+    /// The barrier covers the operations spawned by earlier operations, and operations which are
+    /// held back (in a group, or waiting for a file to be closed). `submit_barrier` takes
+    /// `&mut self`, so no new operations can be submitted while it waits. So, unlike waiting for
+    /// each worker's [`WorkerStats::ops_in_flight`] to reach zero, the barrier can't be starved by
+    /// a steady stream of new operations, and it can't return early while an operation is still
+    /// in the threadpool's queue (waiting to be picked up by a worker thread).
+    ///
+    /// # Errors
+    /// Returns an error if any operations are still unfinished after the shutdown timeout (see
+    /// [`IoUringBuilder::shutdown_timeout`]), or if a worker thread has panicked.
+    pub fn submit_barrier(&mut self) -> anyhow::Result<()> {
+        self.flush_coalesced_ops();
+        self.wait_for_unfinished_ops("the barrier")
+    }
+
+    /// Submit the coalesced operations (if any) without waiting for their windows to expire.
+    fn flush_coalesced_ops(&self) {
+        if let Some(coalescer) = &self.coalescer {
+            for get_ranges in coalescer.take_all() {
+                // Pending operations were counted as unfinished when they were created.
+                self.threadpool.push(Operation::GetRanges(get_ranges));
+            }
+        }
+    }
+
+    /// Poll until `n_unfinished_ops` is zero, or until the shutdown timeout expires.
+    fn wait_for_unfinished_ops(&self, waiting_for: &str) -> anyhow::Result<()> {
+        let deadline = Instant::now() + self.shutdown_timeout;
+        loop {
+            // `Acquire` pairs with the `Release` decrement by each worker when an operation
+            // finishes.
+            let n_unfinished_ops = self.n_unfinished_ops.load(Acquire);
+            if n_unfinished_ops == 0 {
+                // Every output has been sent, but may not have been re-sequenced yet.
+                if let Some(sequencer) = &self.sequencer {
+                    sequencer.flush();
+                }
+                return Ok(());
+            }
+            self.check_worker_threads()?;
+            if Instant::now() >= deadline {
+                return Err(anyhow::format_err!(
+                    "{n_unfinished_ops} operations were still unfinished after waiting {:?} for \
+                        {waiting_for}.",
+                    self.shutdown_timeout
+                ));
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+    }
+
+    /// Read a stream whose size isn't known in advance (such as a FIFO, a pipe, or a socket) until
+    /// the end of the stream. `source` is a path (which is opened) or a file descriptor (which
+    /// `get_stream` takes ownership of). The stream is closed at the end of the stream.
+    ///
+    /// Each read asks for `chunk_size` bytes, and each read which returns data is sent as a
+    /// [`Chunk`](lsio_io::Chunk) as soon as it completes. Chunks can be shorter than `chunk_size`
+    /// (e.g. if the writer of a pipe wrote fewer bytes). Every chunk has the same `user_data`.
+    /// The chunks are sent in order, and each chunk's `range` is its position in the stream. A
+    /// read which returns zero bytes marks the end of the stream, and produces a final
+    /// [`Output::StreamEnd`]. If a read fails then the stream is closed, and the error (which
+    /// holds `user_data`) is the final output for this stream.
+    ///
+    /// Streams are always read without `O_DIRECT` (regardless of
+    /// [`IoUringBuilder::direct_io`]), because pipes and sockets don't support `O_DIRECT`. The
+    /// file size cache, fixed files, fixed buffers and the buffer pool aren't used. Streams which
+    /// are still waiting for data when the `IoUring` is dropped are cancelled.
+    pub fn get_stream(
+        &mut self,
+        source: impl Into<StreamSource>,
+        chunk_size: usize,
+        user_data: u64,
+    ) -> anyhow::Result<()> {
+        let chunk_size = u32::try_from(chunk_size)
+            .ok()
+            .filter(|&chunk_size| chunk_size > 0)
+            .ok_or_else(|| {
+                anyhow::format_err!(
+                    "chunk_size must be between 1 and {} bytes. Received {chunk_size}.",
+                    u32::MAX
+                )
+            })?;
+        let task = Operation::GetStream(GetStream::new(source.into(), chunk_size, user_data)?);
+        self.submit(task)
+    }
+
+    /// Like [`Reader::get_ranges`], except that each read is cancelled if it hasn't completed
+    /// within `timeout` of being submitted to the kernel (e.g. because a networked filesystem has
+    /// stopped responding). A range whose read is cancelled produces an [`IoError::TimedOut`]
+    /// instead of a `Chunk`. The other ranges (including other ranges of the same file) continue
+    /// unaffected. The timeout doesn't apply to opening the file.
+    ///
+    /// Ranges larger than 2 GiB are read using multiple reads, each with its own timeout.
+    pub fn get_ranges_with_timeout(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let cstring = location_to_cstring(location)?;
+        self.register_ranges(location, &ranges, &user_data);
+        let task = Operation::GetRanges(
+            self.new_get_ranges(cstring, ranges, None, user_data)
+                .with_max_gap(self.max_gap)
+                .with_timeout(Some(timeout)),
+        );
+        self.submit(task)
+    }
+
+    /// Like [`Reader::get_ranges`], except that the bytes read from each range are checked against
+    /// the corresponding checksum in `checksums` before the range's `Chunk` is sent. If the
+    /// checksum doesn't match (e.g. because of bit-rot, or corruption in transit from a networked
+    /// filesystem) then the range produces an [`IoError::ChecksumMismatch`] instead of a `Chunk`.
+    ///
+    /// The checksums are computed on the worker threads, which delays the other operations on
+    /// that thread. To verify very large ranges without stalling IO, read the ranges with
+    /// [`Reader::get_ranges`] and call [`Checksum::verify`](lsio_io::Checksum::verify) on a
+    /// separate threadpool (such as an `lsio_threadpool::ComputePool`) instead. Ranges with
+    /// checksums are never merged (see [`IoUringBuilder::max_gap`]), because each checksum
+    /// covers exactly one range.
+    ///
+    /// # Errors
+    /// Returns an error (without submitting anything) unless there's exactly one `user_data` and
+    /// one checksum per range.
+    #[cfg(feature = "checksum")]
+    pub fn get_ranges_with_checksums(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+        checksums: Vec<lsio_io::Checksum>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        if checksums.len() != ranges.len() {
+            return Err(anyhow::format_err!(
+                "{} checksums were provided for {} ranges. There must be one checksum per range.",
+                checksums.len(),
+                ranges.len()
+            ));
+        }
+        let cstring = location_to_cstring(location)?;
+        self.register_ranges(location, &ranges, &user_data);
+        let task = Operation::GetRanges(
+            self.new_get_ranges(cstring, ranges, None, user_data)
+                .with_checksums(checksums),
+        );
+        self.submit(task)
+    }
+
+    /// Returns the receiver of the re-sequenced outputs, if [`IoUringBuilder::ordered_completion`]
+    /// is enabled. This is the same receiver as [`Completion::completion`], so the methods of
+    /// [`Completion`] also receive the outputs in order.
+    pub fn ordered_completion(
+        &self,
+    ) -> Option<&crossbeam_channel::Receiver<Result<Output, IoError>>> {
+        self.sequencer.as_ref().map(|_| &self.output_rx)
+    }
+
+    /// Tell the sequencer (if any) about `ranges`, which are about to be submitted.
+    fn register_ranges(
+        &self,
+        location: &std::path::Path,
+        ranges: &[std::ops::Range<isize>],
+        user_data: &[u64],
+    ) {
+        if let Some(sequencer) = &self.sequencer {
+            sequencer.register(location, ranges, user_data);
+        }
+    }
+
+    /// Count `task` as unfinished, and push it onto the threadpool.
+    fn submit(&self, task: Operation) -> anyhow::Result<()> {
+        self.check_worker_threads()?;
+        self.n_unfinished_ops.fetch_add(1, Relaxed);
+        self.threadpool.push(task);
+        Ok(())
+    }
+
+    /// Like [`Self::submit`], but holds `task` back until the previous group has finished.
+    fn submit_in_group(&mut self, group_id: u64, task: Operation) -> anyhow::Result<()> {
+        self.check_worker_threads()?;
+        // Held-back operations are unfinished too.
+        self.n_unfinished_ops.fetch_add(1, Relaxed);
+        if let Some(task) = self.groups.start_or_hold_back(group_id, task) {
+            self.threadpool.push(task);
+        }
+        Ok(())
+    }
+
+    /// Returns an error if a worker thread has panicked. The operations which were queued on (or
+    /// in flight in) that worker thread will never finish, so it isn't safe to keep using this
+    /// `IoUring`.
+    fn check_worker_threads(&self) -> anyhow::Result<()> {
+        match self.worker_panic.get() {
+            Some(message) => Err(anyhow::format_err!(
+                "{message}. This IoUring can't process any more operations."
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`Reader::get_ranges`], except that `location` can be anything which can be borrowed
+    /// as a [`Path`](std::path::Path), such as `"foo/bar"`, a `String`, or a `PathBuf`.
+    pub fn get_ranges(
+        &mut self,
+        location: impl AsRef<std::path::Path>,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        Reader::get_ranges(self, location.as_ref(), ranges, user_data)
+    }
+
+    /// Like [`Reader::get_ranges`], except that `location` has already been converted to a
+    /// `CString`. This allows the caller to convert each path once, and then share the `CString`
+    /// between many calls (e.g. when reading millions of chunks from a handful of files).
+    pub fn get_ranges_prepared(
+        &mut self,
+        location: Arc<CString>,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        if self.sequencer.is_some() {
+            self.register_ranges(&path_from_location(&location), &ranges, &user_data);
+        }
+        if let Some(coalescer) = &self.coalescer {
+            self.check_worker_threads()?;
+            let get_ranges = coalescer.add(location, ranges, user_data, |location| {
+                // The pending operation is unfinished until it has been submitted and has
+                // finished.
+                self.n_unfinished_ops.fetch_add(1, Relaxed);
+                // Coalesced ranges which are adjacent (or overlapping) are always merged.
+                self.new_get_ranges(location, Vec::new(), None, Vec::new())
+                    .with_max_gap(Some(self.max_gap.unwrap_or(1)))
+            });
+            if let Some(get_ranges) = get_ranges {
+                self.threadpool.push(Operation::GetRanges(get_ranges));
+            }
+            return Ok(());
+        }
+        let task = Operation::GetRanges(
+            self.new_get_ranges(location, ranges, None, user_data)
+                .with_max_gap(self.max_gap),
+        );
+        self.submit(task)
+    }
+
+    /// A `GetRanges` operation which uses this `IoUring`'s configuration (except for `max_gap`).
+    /// The buffer configuration (e.g. `fixed_buffers`) is ignored if `destinations` is `Some`.
+    fn new_get_ranges(
+        &self,
+        location: Arc<CString>,
+        ranges: Vec<std::ops::Range<isize>>,
+        destinations: Option<Vec<AlignedBytes>>,
+        user_data: Vec<u64>,
+    ) -> GetRanges {
+        GetRanges::new(location, ranges, destinations, user_data)
+            .with_file_size_cache(Arc::clone(&self.file_size_cache))
+            .with_fixed_buffers(self.fixed_buffers.clone())
+            .with_buffer_pool(self.buffer_pool.clone())
+            .with_huge_pages_threshold(self.huge_pages_threshold)
+            .with_fixed_file(self.fixed_files)
+            .with_file_complete_output(self.file_complete_outputs)
+            .with_direct_io(self.direct_io)
+            .with_open_flags(self.open_flags)
+            .with_retry_policy(self.retry_policy)
+            .with_zero_fill_past_eof(self.zero_fill_past_eof)
+    }
+}
+
+/// The message of a panic, if the panic's payload is a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message,
+        _ => "(the panic's payload isn't a string)",
+    }
+}
+
+/// Convert `location` to the `CString` that we pass to io_uring.
+fn location_to_cstring(location: &std::path::Path) -> anyhow::Result<Arc<CString>> {
+    location_from_path(location).map(Arc::new)
+}
+
+/// Configures and builds an [`IoUring`]. Create an `IoUringBuilder` using [`IoUring::builder`].
+#[derive(Debug, Clone)]
+pub struct IoUringBuilder {
+    n_worker_threads: usize,
+    config: Config,
+}
+
+impl IoUringBuilder {
+    /// Whether each worker thread's io_uring uses a kernel thread to poll its submission queue.
+    /// Defaults to `SqPoll::Enabled { idle_ms: 1000 }`.
+    pub fn sqpoll(mut self, sqpoll: SqPoll) -> Self {
+        self.config.sqpoll = sqpoll;
+        self
+    }
+
+    /// What each worker thread does whilst it waits for its in-flight operations to complete.
+    /// See [`WaitStrategy`] for the trade-off between CPU usage and latency of each strategy.
+    /// Defaults to `WaitStrategy::BusyPoll { max_spins: u32::MAX }` (which never yields).
+    pub fn wait_strategy(mut self, wait_strategy: WaitStrategy) -> Self {
+        self.config.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// Merge byte ranges (within each call to `get_ranges` or `get_ranges_in_group`) which
+    /// overlap, or which are separated by fewer than `max_gap` bytes, into a single read. Each
+    /// of the user's byte ranges is still returned as its own `Chunk`, but the `Chunk`s from a
+    /// merged read share the same underlying buffer. This reduces the number of IO operations
+    /// when reading lots of small, nearby byte ranges. Defaults to not merging byte ranges.
+    ///
+    /// `get_ranges_into` can't read the bytes between ranges (because there's nowhere to put
+    /// them), so it only merges byte ranges which are exactly adjacent in the file. Each group of
+    /// adjacent byte ranges is read by a single vectored read (`readv`), which scatters the bytes
+    /// into the caller's buffers.
+    pub fn max_gap(mut self, max_gap: usize) -> Self {
+        self.config.max_gap = Some(max_gap);
+        self
+    }
+
+    /// The maximum number of files whose sizes are cached (so that subsequent reads of the same
+    /// file don't have to `statx` the file). Set to `0` to disable the cache. Defaults to 10,000.
+    ///
+    /// Files are only `statx`ed if at least one of the ranges requested from the file is relative
+    /// to the end of the file (i.e. negative). If every range is non-negative then the reads are
+    /// submitted as soon as the file is open.
+    pub fn file_size_cache_capacity(mut self, capacity: usize) -> Self {
+        self.config.file_size_cache_capacity = capacity;
+        self
+    }
+
+    /// Apply backpressure when the user isn't consuming outputs fast enough: Whilst the completion
+    /// channel holds at least `high_water_mark` outputs, the worker threads stop starting new
+    /// operations (but continue to process the operations which are already in flight).
+    ///
+    /// The completion channel itself is unbounded, so that worker threads never block whilst
+    /// processing completion queue entries (which would stall all the other operations on that
+    /// thread). As such, the completion channel may hold more than `high_water_mark` outputs,
+    /// because operations which are already in flight will always send their outputs. Defaults
+    /// to 1,024.
+    pub fn output_high_water_mark(mut self, high_water_mark: usize) -> Self {
+        self.config.output_high_water_mark = high_water_mark;
+        self
+    }
+
+    /// Retry each `read` which fails with a transient error (`EAGAIN`, `EINTR`, or `EIO`) up to
+    /// `retries` times per range, before reporting the error. Networked filesystems often return
+    /// these errors when the server is briefly unavailable, and the same read then succeeds a
+    /// moment later. Other errors (such as `ENOENT`) are never retried, and neither is opening
+    /// the file. Vectored reads (see [`IoUring::get_ranges_into`]) aren't retried either.
+    ///
+    /// Each retry waits for a backoff (see [`IoUringBuilder::backoff`]) without blocking the
+    /// worker thread. Defaults to 0 (no retries).
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.config.retry_policy.retries = retries;
+        self
+    }
+
+    /// How long to wait before the first retry of a failed `read` (see
+    /// [`IoUringBuilder::retries`]). Each subsequent retry of the same range waits twice as long
+    /// as the previous retry. Defaults to 10 milliseconds.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.config.retry_policy.backoff = backoff;
+        self
+    }
+
+    /// If `enabled`, then the bytes of each range which are beyond the end of the file are filled
+    /// with zeros, instead of reporting a `ShortRead` error. So every `Chunk` is as long as the
+    /// range requested. This is useful for fixed-layout formats, where the last chunk of a file
+    /// may be shorter than the other chunks, but it's convenient to request `0..CHUNK_SIZE` for
+    /// every chunk.
+    ///
+    /// The file is always `statx`ed (to find where it ends), even if every range is relative to
+    /// the start of the file. Reads into caller-provided buffers aren't merged into vectored
+    /// reads. Defaults to false.
+    pub fn zero_fill_past_eof(mut self, enabled: bool) -> Self {
+        self.config.zero_fill_past_eof = enabled;
+        self
+    }
+
+    /// How long [`IoUring::shutdown`] and [`IoUring::submit_barrier`] wait for unfinished
+    /// operations before giving up. Defaults to 10 seconds.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Register `n_buffers` buffers, each of `buffer_size` bytes, with each worker thread's
+    /// io_uring. Reads which fit into a registered buffer (and which aren't reading into a
+    /// caller-provided buffer) read into a free registered buffer using `ReadFixed`, which saves
+    /// the kernel from pinning the buffer's pages for every read. This helps when reading lots of
+    /// chunks of a similar size.
+    ///
+    /// Each `Chunk` that was read into a registered buffer keeps that buffer in use until the
+    /// `Chunk` is dropped. When all the registered buffers are in use, reads fall back to
+    /// allocating new buffers. `n_buffers` must be between 1 and 65,535. Defaults to not
+    /// registering any buffers.
+    pub fn fixed_buffers(mut self, n_buffers: usize, buffer_size: usize) -> Self {
+        self.config.fixed_buffers = Some(FixedBuffersConfig {
+            n_buffers,
+            buffer_size,
+        });
+        self
+    }
+
+    /// Allocate the buffers for reads from `buffer_pool`. Each buffer is returned to the pool when
+    /// the user drops every `Chunk` which views the buffer, so the next read can reuse the
+    /// allocation instead of allocating (and page-faulting) a fresh buffer. This helps when reading
+    /// millions of chunks. Reads into caller-provided buffers, and reads into fixed buffers (see
+    /// [`IoUringBuilder::fixed_buffers`]), don't use the pool. Defaults to not using a pool.
+    pub fn buffer_pool(mut self, buffer_pool: BufferPool) -> Self {
+        self.config.buffer_pool = Some(buffer_pool);
+        self
+    }
+
+    /// Allocate the buffers for reads of at least `threshold` bytes from huge pages (using
+    /// [`AlignedBytesMut::new_huge`]), which reduces the number of page faults when reading
+    /// multi-gigabyte chunks. If no huge pages are reserved (see `/proc/sys/vm/nr_hugepages`)
+    /// then these buffers fall back to transparent huge pages. Reads into fixed buffers (see
+    /// [`IoUringBuilder::fixed_buffers`]) and into caller-provided buffers don't use huge pages,
+    /// and reads which use huge pages don't use the buffer pool. Defaults to not using huge pages.
+    pub fn huge_pages(mut self, threshold: usize) -> Self {
+        self.config.huge_pages_threshold = Some(threshold);
+        self
+    }
+
+    /// Open the files read by `get_ranges` (and friends) as "fixed files": Each worker thread
+    /// registers a table of file slots with its io_uring, and opens files directly into free
+    /// slots. This saves the kernel from looking up the file descriptor for every read, which
+    /// helps when reading lots of small chunks from thousands of files. When all of a worker's
+    /// slots are in use, files are opened with normal file descriptors.
+    ///
+    /// A fixed file is only valid in the io_uring which opened it, so all the reads of a fixed
+    /// file run on the worker thread which opened the file (instead of being shared between the
+    /// worker threads). So this may be slower when reading lots of chunks from a few files.
+    /// Defaults to `false`.
+    pub fn fixed_files(mut self, enabled: bool) -> Self {
+        self.config.fixed_files = enabled;
+        self
+    }
+
+    /// Emit an [`Output::FileComplete`] after the last byte range requested by each call to
+    /// `get_ranges` (and friends) has been read (or has failed), so the caller knows when it can
+    /// free any state it holds for that file. No `FileComplete` is emitted if the file can't be
+    /// opened. Defaults to `false`.
+    pub fn file_complete_outputs(mut self, enabled: bool) -> Self {
+        self.config.file_complete_outputs = enabled;
+        self
+    }
+
+    /// Open the files read by `get_ranges` (and friends) with `O_DIRECT`, which bypasses the page
+    /// cache. Reads are then rounded out to the file offset alignment that the filesystem reports
+    /// to `statx` (or to 512-byte boundaries if the filesystem doesn't report its alignment), each
+    /// buffer is aligned to the filesystem's memory alignment, and the buffers passed to
+    /// `get_ranges_into` must be aligned (`get_ranges_into` returns an error if a buffer's address
+    /// or length isn't a multiple of 512 bytes). If the file isn't `statx`ed (because every range
+    /// is non-negative) then reads are rounded out to 4 KiB boundaries, which satisfies almost
+    /// every block device. Without `O_DIRECT`, each read reads exactly the requested range, and
+    /// repeated reads of the same data are served from the page cache. Defaults to `true`.
+    pub fn direct_io(mut self, enabled: bool) -> Self {
+        self.config.direct_io = enabled;
+        self
+    }
+
+    /// Extra flags (OR-ed with `O_RDONLY`) with which `get_ranges` (and friends) open files. For
+    /// example, `libc::O_NOATIME` stops reads from updating each file's access time (which saves
+    /// a metadata write per file, but which fails with `EPERM` unless this process owns the file
+    /// or has `CAP_FOWNER`), and `libc::O_CLOEXEC` stops child processes from inheriting the file
+    /// descriptors. Defaults to no extra flags.
+    ///
+    /// Flags which don't change how the file is read (such as `O_NOATIME`, `O_CLOEXEC`,
+    /// `O_NOFOLLOW` and `O_NONBLOCK`) are compatible with `O_DIRECT` (see
+    /// [`IoUringBuilder::direct_io`]), so reads are still aligned as usual. Use `direct_io`, not
+    /// `open_flags`, to control `O_DIRECT`, because the alignment of reads depends on it.
+    ///
+    /// Panics if `flags` contains `O_DIRECT`, `O_PATH` (which can't be read from), or any flag
+    /// which implies writing (`O_WRONLY`, `O_RDWR`, `O_CREAT`, `O_TRUNC`, `O_APPEND` or
+    /// `O_EXCL`).
+    pub fn open_flags(mut self, flags: libc::c_int) -> Self {
+        const FORBIDDEN_FLAGS: libc::c_int = libc::O_WRONLY
+            | libc::O_RDWR
+            | libc::O_CREAT
+            | libc::O_TRUNC
+            | libc::O_APPEND
+            | libc::O_EXCL
+            | libc::O_DIRECT
+            | libc::O_PATH;
+        assert_eq!(
+            flags & FORBIDDEN_FLAGS,
+            0,
+            "open_flags {flags:#o} contains flags ({:#o}) which can't be used to open files for \
+                reading.",
+            flags & FORBIDDEN_FLAGS
+        );
+        self.config.open_flags = flags;
+        self
+    }
+
+    /// The number of entries in each worker thread's io_uring completion queue (CQ). The kernel
+    /// may round `entries` up to the next power of two. Defaults to twice the size of the
+    /// submission queue (SQ), which is 128 entries. A larger CQ helps when lots of SQEs complete
+    /// at once (e.g. when each operation submits several linked SQEs).
+    ///
+    /// Panics if `entries` is smaller than the default.
+    pub fn setup_cqsize(mut self, entries: u32) -> Self {
+        assert!(
+            entries as usize >= SQ_RING_SIZE * 2,
+            "The CQ must have at least {} entries, but {entries} entries were requested.",
+            SQ_RING_SIZE * 2
+        );
+        self.config.cq_size = Some(entries);
+        self
+    }
+
+    /// The maximum number of files which `get_ranges` (and friends) hold open at once, across all
+    /// the worker threads. This prevents reads of thousands of files from failing with `EMFILE`
+    /// ("too many open files"). When the limit is reached, operations which need to open another
+    /// file are held back (in the order they were submitted) until a file has been closed. Each
+    /// file is closed as soon as all of its byte ranges have been read. Files opened by
+    /// `put_ranges` and `copy_ranges` don't count towards the limit.
+    ///
+    /// Defaults to half of this process's soft limit on open file descriptors (`ulimit -n`).
+    ///
+    /// Panics if `max_open_files` is zero.
+    pub fn max_open_files(mut self, max_open_files: usize) -> Self {
+        assert!(max_open_files > 0, "max_open_files must be at least 1");
+        self.config.max_open_files = max_open_files;
+        self
+    }
+
+    /// Coalesce the byte ranges requested by separate calls to `get_ranges` (or
+    /// `get_ranges_prepared`) for the same file: The first call for a file starts a window of
+    /// `window`. The ranges of every call for that file during the window are accumulated, and
+    /// are submitted as a single operation when the window expires, or as soon as the accumulated
+    /// ranges total at least `max_bytes` (ranges relative to the end of the file don't count
+    /// towards `max_bytes`). Ranges which are adjacent or overlapping (or which are separated by
+    /// fewer than [`IoUringBuilder::max_gap`] bytes) are then merged into a single read. This cuts
+    /// the number of IO operations when a consumer requests adjacent ranges one call at a time.
+    /// Other methods (such as `get_ranges_in_group` and `get_ranges_into`) aren't coalesced.
+    ///
+    /// The tradeoff is latency: Reads aren't started until the window expires (or until
+    /// `max_bytes` is reached), so the first range requested for each file is delayed by up to
+    /// `window`, even if no other ranges are requested for that file. Keep `window` small (e.g. a
+    /// few hundred microseconds) relative to the latency of the storage.
+    /// [`IoUring::submit_barrier`] and [`IoUring::shutdown`] submit the accumulated ranges
+    /// immediately. Ranges which are still accumulating when the `IoUring` is dropped are never
+    /// read. If [`IoUringBuilder::file_complete_outputs`] is enabled then one `FileComplete` is
+    /// emitted per coalesced operation, rather than per call. Defaults to not coalescing.
+    pub fn coalesce_window(mut self, window: Duration, max_bytes: usize) -> Self {
+        self.config.coalesce_window = Some(CoalesceWindowConfig { window, max_bytes });
+        self
+    }
+
+    /// Deliver the outputs of reads in the order in which their ranges were submitted, instead of
+    /// in the order in which the reads complete. This suits streaming consumers (e.g. writing a
+    /// reassembled file sequentially). Receive the ordered outputs from
+    /// [`IoUring::ordered_completion`] (which is the same channel as [`Completion::completion`]).
+    /// Defaults to `false`.
+    ///
+    /// Each range's `Chunk` (or error) is held back until the outputs of every range submitted
+    /// before it have been delivered. So one slow read holds back the outputs of every later
+    /// range, and the held-back chunks stay in memory (the memory cost is up to the total size of
+    /// the ranges in flight). The outputs are re-sequenced by an extra thread.
+    ///
+    /// Only the ranges requested through [`Reader`] methods (and the other `get_ranges_*` methods)
+    /// are re-sequenced, and they're matched to their outputs by `user_data`, so each range in
+    /// flight should have a unique `user_data`. Other outputs (such as `Output::FileComplete`, or
+    /// the outputs of `get_stream`) are delivered as soon as they arrive. If a single error
+    /// describes several ranges (e.g. when adjacent ranges were merged into one read, see
+    /// [`IoUringBuilder::max_gap`]) then the error is delivered in place of the first of those
+    /// ranges, and the other ranges produce no output.
+    pub fn ordered_completion(mut self, enabled: bool) -> Self {
+        self.config.ordered_completion = enabled;
+        self
+    }
+
+    /// Like [`IoUringBuilder::build`], except that it first checks that the kernel supports the
+    /// opcodes which `IoUring` requires (see [`IoUring::probe`]), and that it can create an
+    /// io_uring with this configuration (e.g. that `SQPOLL` is permitted). Returns an error if
+    /// not, instead of building an `IoUring` whose worker threads fail to start.
+    pub fn try_build(self) -> anyhow::Result<IoUring> {
+        let capabilities = probe()?;
+        if !capabilities.is_supported() {
+            return Err(anyhow::format_err!(
+                "The kernel's io_uring doesn't support {}, which lsio_uring requires. Linux 5.6 \
+                    or later is required.",
+                capabilities.missing().join(", ")
+            ));
+        }
+        build_ring(&self.config).map_err(|err| {
+            anyhow::format_err!("Failed to create an io_uring with this configuration: {err}")
+        })?;
+        Ok(self.build())
+    }
+
+    /// Spawn the worker threads, and return the `IoUring`.
+    pub fn build(self) -> IoUring {
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+        let config = self.config;
+        // If the outputs are re-sequenced, then the worker threads send their outputs to the
+        // sequencer thread, which forwards them to `output_rx` in submission order.
+        let sequencer = config
+            .ordered_completion
+            .then(|| Arc::new(Sequencer::new()));
+        let output_tx = match &sequencer {
+            None => output_tx,
+            Some(sequencer) => {
+                let (raw_tx, raw_rx) = crossbeam_channel::unbounded();
+                let sequencer = Arc::clone(sequencer);
+                let high_water_mark = config.output_high_water_mark;
+                // The sequencer thread stops once the worker threads have stopped.
+                thread::Builder::new()
+                    .name("lsio_uring_sequencer".to_string())
+                    .spawn(move || sequencer.run(raw_rx, output_tx, high_water_mark))
+                    .expect("Failed to spawn the sequencer thread");
+                raw_tx
+            }
+        };
+        let max_gap = config.max_gap;
+        let file_size_cache = Arc::new(FileSizeCache::new(config.file_size_cache_capacity));
+        let groups = Arc::new(Groups::default());
+        let groups_for_workers = Arc::clone(&groups);
+        let open_file_limit = Arc::new(OpenFileLimit::new(config.max_open_files));
+        let open_file_limit_for_workers = Arc::clone(&open_file_limit);
+        let worker_stats: Arc<Vec<Arc<WorkerStats>>> = Arc::new(
+            (0..self.n_worker_threads)
+                .map(|_| Arc::new(WorkerStats::default()))
+                .collect(),
+        );
+        let worker_stats_for_workers = Arc::clone(&worker_stats);
+        let n_unfinished_ops = Arc::new(AtomicUsize::new(0));
+        let n_unfinished_ops_for_workers = Arc::clone(&n_unfinished_ops);
+        let worker_panic = Arc::new(OnceLock::new());
+        let worker_panic_for_workers = Arc::clone(&worker_panic);
+        let shutdown_timeout = config.shutdown_timeout;
+        let fixed_buffers = config
+            .fixed_buffers
+            .map(|c| Arc::new(FixedBuffers::new(c.n_buffers, c.buffer_size)));
+        let fixed_buffers_for_workers = fixed_buffers.clone();
+        let buffer_pool = config.buffer_pool.clone();
+        let huge_pages_threshold = config.huge_pages_threshold;
+        let fixed_files = config.fixed_files;
+        let file_complete_outputs = config.file_complete_outputs;
+        let direct_io = config.direct_io;
+        let open_flags = config.open_flags;
+        let retry_policy = config.retry_policy;
+        let zero_fill_past_eof = config.zero_fill_past_eof;
+        let coalescer = config
+            .coalesce_window
+            .map(|c| Arc::new(Coalescer::new(c.window, c.max_bytes)));
+        let threadpool = Arc::new(ThreadPool::new_named(
+            "lsio_uring",
+            self.n_worker_threads,
+            move |worker_thread: WorkerThread<Operation>| {
+                let worker_index = worker_thread.index();
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let stats = Arc::clone(&worker_stats_for_workers[worker_index]);
+                    let mut uring_worker = UringWorker::new(
+                        worker_thread,
+                        output_tx.clone(),
+                        Arc::clone(&groups_for_workers),
+                        Arc::clone(&open_file_limit_for_workers),
+                        stats,
+                        Arc::clone(&n_unfinished_ops_for_workers),
+                        fixed_buffers_for_workers.clone(),
+                        &config,
+                    );
                     uring_worker.run();
-                },
-            ),
+                }));
+                if let Err(payload) = result {
+                    let message = format!(
+                        "Worker thread {worker_index} panicked: {}",
+                        panic_message(payload.as_ref())
+                    );
+                    let _ = worker_panic_for_workers.set(message.clone());
+                    // Tell the user why their operations will never finish. (This fails if the
+                    // receiver has been dropped, which may be why the worker panicked.)
+                    let _ = output_tx.send(Err(IoError::Internal { message }));
+                }
+            },
+        ));
+        let flusher_thread = coalescer.as_ref().map(|coalescer| {
+            let coalescer = Arc::clone(coalescer);
+            let threadpool = Arc::clone(&threadpool);
+            thread::Builder::new()
+                .name("lsio_coalescer".to_string())
+                .spawn(move || coalescer.run_flusher(&threadpool))
+                .expect("Failed to spawn the coalescer's flusher thread")
+        });
+        IoUring {
+            threadpool,
             output_rx,
+            groups,
+            open_file_limit,
+            max_gap,
+            file_size_cache,
+            fixed_buffers,
+            buffer_pool,
+            huge_pages_threshold,
+            fixed_files,
+            file_complete_outputs,
+            direct_io,
+            open_flags,
+            retry_policy,
+            zero_fill_past_eof,
+            worker_stats,
+            n_unfinished_ops,
+            worker_panic,
+            shutdown_timeout,
+            coalescer,
+            flusher_thread,
+            sequencer,
+        }
+    }
+}
+
+impl Drop for IoUring {
+    fn drop(&mut self) {
+        // Held-back operations may hold references to `groups`, so we must drop them explicitly.
+        self.groups.drop_held_back_ops();
+        self.open_file_limit.drop_held_back_ops();
+        // The flusher thread holds a reference to the threadpool, so we must join the flusher
+        // thread before the threadpool can be dropped (which joins the worker threads).
+        if let Some(coalescer) = &self.coalescer {
+            coalescer.stop();
+        }
+        if let Some(flusher_thread) = self.flusher_thread.take() {
+            let _ = flusher_thread.join();
         }
     }
 }
 
 impl Completion for IoUring {
-    fn completion(&self) -> &crossbeam_channel::Receiver<anyhow::Result<Output>> {
+    fn completion(&self) -> &crossbeam_channel::Receiver<Result<Output, IoError>> {
         &self.output_rx
     }
 }
@@ -40,10 +906,234 @@ impl Reader for IoUring {
         ranges: Vec<std::ops::Range<isize>>,
         user_data: Vec<u64>,
     ) -> anyhow::Result<()> {
-        let location = CString::new(location.as_os_str().as_bytes())
-            .expect("Failed to convert path '{path}' to CString");
-        let task = Operation::GetRanges(GetRanges::new(location, ranges, user_data));
-        self.threadpool.push(task);
-        Ok(())
+        self.get_ranges_prepared(location_to_cstring(location)?, ranges, user_data)
+    }
+
+    /// Submits the whole batch to the threadpool as a single task, which spawns one `GetRanges`
+    /// operation per `ReadOp` when a worker thread picks it up. The batch isn't coalesced with
+    /// other calls (see [`IoUringBuilder::coalesce_window`]), because it's already a batch.
+    fn submit_batch(&mut self, ops: Vec<ReadOp>) -> anyhow::Result<()> {
+        check_batch(&ops)?;
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let locations = ops
+            .iter()
+            .map(|op| location_to_cstring(&op.location))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let ops = ops
+            .into_iter()
+            .zip(locations)
+            .map(|(op, location)| {
+                self.register_ranges(&op.location, &op.ranges, &op.user_data);
+                Operation::GetRanges(
+                    self.new_get_ranges(location, op.ranges, None, op.user_data)
+                        .with_max_gap(self.max_gap),
+                )
+            })
+            .collect();
+        self.submit(Operation::Batch(Batch::new(ops)))
+    }
+
+    fn get_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let cstring = location_to_cstring(location)?;
+        self.register_ranges(location, &ranges, &user_data);
+        let group = self.groups.join(group_id);
+        let task = Operation::GetRanges(
+            self.new_get_ranges(cstring, ranges, None, user_data)
+                .with_max_gap(self.max_gap)
+                .with_group(group),
+        );
+        self.submit_in_group(group_id, task)
+    }
+
+    fn get_ranges_into(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        buffers: Vec<AlignedBytesMut>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        if buffers.len() != ranges.len() {
+            return Err(anyhow::format_err!(
+                "{} buffers were provided for {} ranges. There must be one buffer per range.",
+                buffers.len(),
+                ranges.len()
+            ));
+        }
+        check_one_user_data_per_range(ranges.len(), user_data.len())?;
+        let cstring = location_to_cstring(location)?;
+        let destinations = freeze_destinations(buffers)?;
+        if self.direct_io {
+            if let Some(i) = destinations
+                .iter()
+                .position(|buffer| !is_buffer_aligned_for_direct_io(buffer))
+            {
+                return Err(anyhow::format_err!(
+                    "buffers[{i}] isn't aligned for O_DIRECT. Its address and length must be \
+                        multiples of 512 bytes. Either align the buffer, or disable O_DIRECT \
+                        using IoUringBuilder::direct_io(false)."
+                ));
+            }
+        }
+        self.register_ranges(location, &ranges, &user_data);
+        let task = Operation::GetRanges(
+            self.new_get_ranges(cstring, ranges, Some(destinations), user_data)
+                .with_max_gap(self.max_gap),
+        );
+        self.submit(task)
+    }
+
+    fn exists(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()> {
+        let task = Operation::Exists(
+            Exists::new(location_to_cstring(location)?, user_data)
+                .with_file_size_cache(Arc::clone(&self.file_size_cache)),
+        );
+        self.submit(task)
+    }
+
+    fn metadata(&mut self, location: &std::path::Path, user_data: u64) -> anyhow::Result<()> {
+        let task = Operation::Metadata(Metadata::new(location_to_cstring(location)?, user_data));
+        self.submit(task)
+    }
+
+    fn advise(
+        &mut self,
+        location: &std::path::Path,
+        range: std::ops::Range<isize>,
+        advice: Advice,
+        user_data: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let offset_and_len = fadvise_offset_and_len(&range)?;
+        let task = Operation::Advise(Advise::new(
+            location_to_cstring(location)?,
+            range,
+            offset_and_len,
+            advice,
+            user_data,
+        ));
+        self.submit(task)
+    }
+}
+
+impl Writer for IoUring {
+    fn put_ranges(
+        &mut self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        let task = self.new_put_ranges(location, ranges, buffers, user_data)?;
+        self.submit(Operation::PutRanges(task))
+    }
+
+    fn put_ranges_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        let task = self.new_put_ranges(location, ranges, buffers, user_data)?;
+        let group = self.groups.join(group_id);
+        self.submit_in_group(group_id, Operation::PutRanges(task.with_group(group)))
+    }
+
+    fn sync(
+        &mut self,
+        location: &std::path::Path,
+        mode: SyncMode,
+        user_data: u64,
+    ) -> anyhow::Result<()> {
+        let task = Fsync::new(location_to_cstring(location)?, mode, user_data);
+        self.submit(Operation::Fsync(task))
+    }
+
+    fn sync_in_group(
+        &mut self,
+        group_id: u64,
+        location: &std::path::Path,
+        mode: SyncMode,
+        user_data: u64,
+    ) -> anyhow::Result<()> {
+        let location = location_to_cstring(location)?;
+        let group = self.groups.join(group_id);
+        let task = Fsync::new(location, mode, user_data).with_group(group);
+        self.submit_in_group(group_id, Operation::Fsync(task))
+    }
+}
+
+impl IoUring {
+    /// A `PutRanges` operation, after checking the arguments passed to [`Writer::put_ranges`].
+    fn new_put_ranges(
+        &self,
+        location: &std::path::Path,
+        ranges: Vec<std::ops::Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<PutRanges> {
+        if buffers.len() != ranges.len() || user_data.len() != ranges.len() {
+            return Err(anyhow::format_err!(
+                "There must be one buffer and one user_data per range. Received {} ranges, {} \
+                    buffers, and {} user_data.",
+                ranges.len(),
+                buffers.len(),
+                user_data.len()
+            ));
+        }
+        // Like reads, writes can only use `O_DIRECT` if they're aligned. If any write isn't
+        // aligned then we write the whole file through the page cache.
+        let direct = ranges
+            .iter()
+            .zip(&buffers)
+            .all(|(range, buffer)| is_aligned_for_direct_io(range, buffer));
+        let location = location_from_path(location)?;
+        // Writing might change the size of the file.
+        self.file_size_cache.remove(&location);
+        Ok(PutRanges::new(location, ranges, buffers, user_data, direct))
+    }
+}
+
+impl Lister for IoUring {
+    fn list(&mut self, prefix: &std::path::Path) -> anyhow::Result<()> {
+        let task = Operation::List(List::new(prefix.to_path_buf()));
+        self.submit(task)
+    }
+}
+
+impl Copier for IoUring {
+    fn copy_ranges(
+        &mut self,
+        src: &std::path::Path,
+        src_ranges: Vec<std::ops::Range<isize>>,
+        dst: &std::path::Path,
+        dst_ranges: Vec<std::ops::Range<isize>>,
+        user_data: Vec<u64>,
+    ) -> anyhow::Result<()> {
+        check_one_user_data_per_range(src_ranges.len(), user_data.len())?;
+        if dst_ranges.len() != src_ranges.len() {
+            return Err(anyhow::format_err!(
+                "There must be one dst_range per src_range. Received {} src_ranges and {} \
+                    dst_ranges.",
+                src_ranges.len(),
+                dst_ranges.len()
+            ));
+        }
+        let src = location_from_path(src)?;
+        let dst = location_from_path(dst)?;
+        // Writing might change the size of the file.
+        self.file_size_cache.remove(&dst);
+        let task =
+            Operation::CopyRanges(CopyRanges::new(src, src_ranges, dst, dst_ranges, user_data));
+        self.submit(task)
     }
 }
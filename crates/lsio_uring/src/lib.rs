@@ -1,15 +1,48 @@
 #![doc = include_str!("../README.md")]
 
+pub(crate) mod advise;
+pub(crate) mod batch;
 pub(crate) mod close;
+pub(crate) mod coalesce;
+pub(crate) mod config;
+pub(crate) mod copy_range;
+pub(crate) mod copy_ranges;
+pub(crate) mod exists;
+pub(crate) mod file_size_cache;
+pub(crate) mod fixed_buffers;
+pub(crate) mod fsync;
 pub(crate) mod get_range;
+pub(crate) mod get_range_vectored;
 pub(crate) mod get_ranges;
+pub(crate) mod get_stream;
+pub(crate) mod groups;
 pub(crate) mod io_uring;
+pub(crate) mod list;
+pub(crate) mod merge_ranges;
+pub(crate) mod metadata;
+#[cfg(feature = "object_store")]
+pub(crate) mod object_store_adapter;
 pub(crate) mod opcode;
 pub(crate) mod open_file;
+pub(crate) mod open_file_limit;
 pub(crate) mod operation;
+pub(crate) mod probe;
+pub(crate) mod put_range;
+pub(crate) mod put_ranges;
+pub(crate) mod retry;
+pub(crate) mod sequencer;
+pub(crate) mod spawner;
 pub(crate) mod sqe;
+pub(crate) mod stats;
+pub(crate) mod trace;
 pub(crate) mod tracker;
 pub(crate) mod user_data;
 pub(crate) mod worker;
 
-pub use io_uring::IoUring;
+pub use config::{SqPoll, WaitStrategy};
+pub use get_stream::StreamSource;
+pub use io_uring::{IoUring, IoUringBuilder};
+#[cfg(feature = "object_store")]
+pub use object_store_adapter::ObjectStoreAdapter;
+pub use probe::{Capabilities, ProbeError};
+pub use stats::WorkerStats;
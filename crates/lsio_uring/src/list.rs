@@ -0,0 +1,80 @@
+use std::{os::unix::fs::MetadataExt, path::PathBuf};
+
+use lsio_io::{FileMetadata, IoError, Output};
+
+use crate::{
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::build_nop_sqe,
+    user_data::UringUserData,
+};
+
+/// List the contents of a directory.
+///
+/// io_uring doesn't (yet) support `getdents64`. So we submit a `nop` SQE (so that `List` follows
+/// the same lifecycle as every other operation) and, when the `nop` completes, we list the
+/// directory using blocking syscalls on this worker thread.
+#[derive(Debug)]
+pub(crate) struct List {
+    prefix: PathBuf,
+}
+
+impl List {
+    pub(crate) fn new(prefix: PathBuf) -> Self {
+        Self { prefix }
+    }
+
+    fn read_dir(&self) -> Result<Vec<FileMetadata>, IoError> {
+        let context = |source| IoError::ReadDir {
+            path: self.prefix.clone(),
+            source,
+        };
+        let mut listing = Vec::new();
+        for entry in std::fs::read_dir(&self.prefix).map_err(context)? {
+            let entry = entry.map_err(context)?;
+            let metadata = entry.metadata().map_err(context)?;
+            listing.push(FileMetadata {
+                path: entry.path(),
+                size: metadata.len(),
+                is_dir: metadata.is_dir(),
+                mtime: metadata.modified().ok(),
+                mode: metadata.mode(),
+            });
+        }
+        Ok(listing)
+    }
+}
+
+impl UringOperation for List {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = build_nop_sqe(index_of_op);
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.prefix.clone())
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        _spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Nop::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        if cqe_result >= 0 {
+            output_channel
+                .send(self.read_dir().map(Output::Listing))
+                .unwrap();
+        }
+        NextStep::Done
+    }
+}
@@ -0,0 +1,220 @@
+use std::ops::Range;
+
+use lsio_aligned_bytes::AlignedBytes;
+use lsio_io::Chunk;
+
+/// One of the user's byte ranges, within a [`MergedRange`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MergedMember {
+    /// The user's byte range, relative to the start of the `MergedRange`.
+    pub(crate) range: Range<usize>,
+    pub(crate) user_data: u64,
+}
+
+/// A single byte range which covers one or more of the user's byte ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MergedRange {
+    /// The resolved byte range to read from the file.
+    pub(crate) range: Range<isize>,
+    pub(crate) members: Vec<MergedMember>,
+}
+
+/// Merge byte ranges which overlap, or which are separated by fewer than `max_gap` bytes. This
+/// reduces the number of IO operations when reading lots of small, nearby byte ranges (e.g. the
+/// chunks in a Zarr shard). Reading the bytes in the gaps is usually much cheaper than issuing
+/// an extra IO operation.
+///
+/// `ranges` must already be resolved (i.e. must not contain negative numbers). The merged ranges
+/// are returned in ascending order of their start offset.
+pub(crate) fn merge_ranges(
+    ranges: &[Range<isize>],
+    user_data: &[u64],
+    max_gap: usize,
+) -> Vec<MergedRange> {
+    assert_eq!(ranges.len(), user_data.len());
+    let mut order: Vec<usize> = (0..ranges.len()).collect();
+    order.sort_by_key(|&i| ranges[i].start);
+
+    let mut merged: Vec<MergedRange> = Vec::new();
+    for i in order {
+        let range = &ranges[i];
+        assert!(range.start >= 0 && range.end >= range.start);
+        match merged.last_mut() {
+            Some(last) if range.start - last.range.end < max_gap as isize => {
+                last.range.end = last.range.end.max(range.end);
+            }
+            _ => merged.push(MergedRange {
+                range: range.clone(),
+                members: Vec::new(),
+            }),
+        }
+        let last = merged.last_mut().unwrap();
+        let offset = last.range.start;
+        last.members.push(MergedMember {
+            range: (range.start - offset) as usize..(range.end - offset) as usize,
+            user_data: user_data[i],
+        });
+    }
+    merged
+}
+
+/// Returns the [`Chunk`] of `member`, from `buffer` (which holds the bytes of a whole
+/// [`MergedRange`], starting at the absolute file offset `merged_start`). The `Chunk` is a view
+/// into the same underlying buffer as `buffer`, so the `Chunk`s of all the members share one
+/// buffer.
+pub(crate) fn member_chunk(
+    buffer: &AlignedBytes,
+    merged_start: usize,
+    member: &MergedMember,
+) -> Chunk {
+    Chunk {
+        buffer: buffer.slice(member.range.clone()).unwrap(),
+        user_data: member.user_data,
+        range: Some(merged_start + member.range.start..merged_start + member.range.end),
+    }
+}
+
+/// Find runs of byte ranges which are exactly adjacent in the file (i.e. each range starts where
+/// the previous range ends), so that each run can be read by a single vectored read. Each run
+/// holds at most `max_ranges` ranges, which sum to at most `max_len` bytes. Ranges which aren't
+/// adjacent to any other range are returned as runs of length 1.
+///
+/// `ranges` must already be resolved. Returns the indices into `ranges` of each run, in ascending
+/// order of file offset.
+pub(crate) fn find_adjacent_runs(
+    ranges: &[Range<isize>],
+    max_ranges: usize,
+    max_len: usize,
+) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..ranges.len()).collect();
+    order.sort_by_key(|&i| ranges[i].start);
+
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut run_len = 0;
+    for i in order {
+        let range = &ranges[i];
+        assert!(range.start >= 0 && range.end >= range.start);
+        match runs.last_mut() {
+            Some(run)
+                if ranges[*run.last().unwrap()].end == range.start
+                    && run.len() < max_ranges
+                    && run_len + range.len() <= max_len =>
+            {
+                run.push(i);
+                run_len += range.len();
+            }
+            _ => {
+                runs.push(vec![i]);
+                run_len = range.len();
+            }
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use lsio_aligned_bytes::AlignedBytesMut;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_ranges() {
+        let ranges = [200..300, 0..100, 50..150, 400..500, 105..110];
+        let user_data = [0, 1, 2, 3, 4];
+        let merged = merge_ranges(&ranges, &user_data, 60);
+
+        // 0..100, 50..150 (overlapping) and 105..110 (contained), and 200..300 (a gap of 50
+        // bytes) are merged. 400..500 is 100 bytes away.
+        let member = |range: Range<usize>, user_data| MergedMember { range, user_data };
+        assert_eq!(
+            merged,
+            vec![
+                MergedRange {
+                    range: 0..300,
+                    members: vec![
+                        member(0..100, 1),
+                        member(50..150, 2),
+                        member(105..110, 4),
+                        member(200..300, 0),
+                    ],
+                },
+                MergedRange {
+                    range: 400..500,
+                    members: vec![member(0..100, 3)],
+                },
+            ]
+        );
+
+        // With `max_gap = 0`, only overlapping ranges are merged.
+        let merged = merge_ranges(&[0..100, 100..200, 150..250], &[0, 1, 2], 0);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1].range, 100..250);
+    }
+
+    #[test]
+    fn test_find_adjacent_runs() {
+        let ranges = [
+            100..200,
+            0..100,
+            300..400,
+            200..250,
+            250..300,
+            500..600,
+            450..500,
+        ];
+        assert_eq!(
+            find_adjacent_runs(&ranges, usize::MAX, usize::MAX),
+            vec![vec![1, 0, 3, 4, 2], vec![6, 5]]
+        );
+
+        // Runs are split when they reach `max_ranges` or `max_len`.
+        assert_eq!(
+            find_adjacent_runs(&ranges, 2, usize::MAX),
+            vec![vec![1, 0], vec![3, 4], vec![2], vec![6, 5]]
+        );
+        assert_eq!(
+            find_adjacent_runs(&ranges, usize::MAX, 250),
+            vec![vec![1, 0, 3], vec![4, 2], vec![6, 5]]
+        );
+
+        // Overlapping ranges aren't adjacent.
+        assert_eq!(
+            find_adjacent_runs(&[0..100, 50..150], usize::MAX, usize::MAX),
+            vec![vec![0], vec![1]]
+        );
+    }
+
+    #[test]
+    fn test_member_chunk() {
+        // Simulate reading the file `0, 1, 2, ..., 255` into a buffer which starts before the
+        // merged range (as it would if the merged range wasn't aligned).
+        const MERGED_START: usize = 10;
+        let mut buffer = AlignedBytesMut::new(256, 8);
+        for i in 0..256 {
+            unsafe { *buffer.as_mut_ptr().add(i) = i as u8 };
+        }
+        let mut buffer = buffer.freeze().unwrap();
+        buffer.set_slice(MERGED_START..40);
+
+        let ranges = [10..20, 15..30, 32..40, 100..200];
+        let user_data = [0, 1, 2, 3];
+        let merged = merge_ranges(&ranges, &user_data, 8);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].range.start, MERGED_START as isize);
+
+        // Only check the first merged range, because `buffer` holds the first merged range.
+        assert_eq!(merged[0].members.len(), 3);
+        for member in &merged[0].members {
+            let chunk = member_chunk(&buffer, MERGED_START, member);
+            let range = &ranges[chunk.user_data as usize];
+            assert_eq!(
+                chunk.range,
+                Some(range.start as usize..range.end as usize),
+                "{chunk:?}"
+            );
+            let expected: Vec<u8> = (range.start as u8..range.end as u8).collect();
+            assert_eq!(chunk.buffer.as_slice(), expected, "{chunk:?}");
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+use lsio_io::{IoError, Output};
+
+use crate::{
+    open_file::OpenFileBuilder,
+    operation::{NextStep, UringOperation},
+    spawner::Spawner,
+    sqe::build_statx_sqe,
+    user_data::UringUserData,
+};
+
+/// Get the metadata of a file (or directory) using a single `statx` SQE. The file isn't opened.
+#[derive(Debug)]
+pub(crate) struct Metadata {
+    // Like `Exists`, we only use the `OpenFileBuilder` to hold the location and the `statx`
+    // buffer.
+    open_file_builder: OpenFileBuilder,
+    user_data: u64,
+}
+
+impl Metadata {
+    pub(crate) fn new(location: Arc<CString>, user_data: u64) -> Self {
+        Self {
+            open_file_builder: OpenFileBuilder::new(location),
+            user_data,
+        }
+    }
+}
+
+impl UringOperation for Metadata {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = build_statx_sqe(index_of_op, &mut self.open_file_builder);
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.open_file_builder.path())
+    }
+
+    fn maybe_send_error(
+        &self,
+        _idx_and_opcode: &UringUserData,
+        _cqe_result: i32,
+        _output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) {
+        // Errors are sent by `process_opcode_and_submit_next_step`, so that they hold
+        // `user_data`.
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        _spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Statx::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        let path = self.open_file_builder.path();
+        let details = || {
+            format!(
+                "(reported by io_uring completion queue entry (CQE)). More details: \
+                    idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {self:?}",
+            )
+        };
+        let output = match cqe_result {
+            0.. => Ok(Output::Metadata {
+                user_data: self.user_data,
+                metadata: unsafe { self.open_file_builder.file_metadata() },
+            }),
+            _ if cqe_result == -libc::ENOENT => Err(IoError::NotFound {
+                path,
+                user_data: Some(self.user_data),
+                details: details(),
+            }),
+            _ => Err(IoError::Nix {
+                errno: nix::Error::from_raw(-cqe_result),
+                opcode: idx_and_opcode.opcode().name(),
+                path: Some(path),
+                range: None,
+                user_data: Some(self.user_data),
+                details: details(),
+            }),
+        };
+        output_channel.send(output).unwrap();
+        NextStep::Done
+    }
+}
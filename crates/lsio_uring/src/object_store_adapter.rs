@@ -0,0 +1,243 @@
+use std::{fmt, io, ops::Range, path::PathBuf};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use lsio_io::{AsyncReader, IoError};
+use object_store::{
+    path::Path, Error, GetOptions, GetRange, GetResult, GetResultPayload, ListResult,
+    MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+    Result,
+};
+
+use crate::IoUring;
+
+/// The name of this store, used in [`object_store::Error::Generic`].
+const STORE: &str = "lsio_uring";
+
+/// Implements [`ObjectStore`] using [`IoUring`], so that LSIO can be used wherever an
+/// `ObjectStore` is expected (e.g. by `parquet`'s async reader, or by DataFusion).
+///
+/// Each [`Path`] is interpreted as an absolute path on the local filesystem (like
+/// `object_store::local::LocalFileSystem::new()`).
+///
+/// Only reads are implemented. [`ObjectStore::get_ranges`] submits all its byte ranges in a single
+/// operation, so the ranges are read concurrently (and nearby ranges may be merged, if the
+/// `IoUring` has been configured with a `max_gap`). [`ObjectStore::get_opts`] gets the size and
+/// modification time of the file using a blocking `stat`, and doesn't support conditional or
+/// versioned requests. All other methods return [`object_store::Error::NotImplemented`].
+pub struct ObjectStoreAdapter {
+    reader: AsyncReader<IoUring>,
+}
+
+impl ObjectStoreAdapter {
+    pub fn new(uring: IoUring) -> Self {
+        Self {
+            reader: AsyncReader::new(uring),
+        }
+    }
+
+    /// Read `ranges` from `location`, and return one `Bytes` per range (without copying the
+    /// bytes). Returns the error of the first range which failed.
+    async fn read_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        // `AlignedBytes` can't be empty, so don't ask the IO backend to read empty ranges.
+        let non_empty_ranges: Vec<Range<isize>> = ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| range.start as isize..range.end as isize)
+            .collect();
+        let mut chunks = self
+            .reader
+            .get_ranges(&to_filesystem_path(location), non_empty_ranges)
+            .await
+            .into_iter();
+        ranges
+            .iter()
+            .map(|range| {
+                if range.is_empty() {
+                    return Ok(Bytes::new());
+                }
+                let chunk = chunks
+                    .next()
+                    .unwrap()
+                    .map_err(|err| to_object_store_error(err, location))?;
+                Ok(Bytes::from_owner(chunk.buffer))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for ObjectStoreAdapter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectStoreAdapter").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ObjectStoreAdapter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectStoreAdapter({STORE})")
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ObjectStoreAdapter {
+    async fn put_opts(
+        &self,
+        _location: &Path,
+        _payload: PutPayload,
+        _opts: PutOptions,
+    ) -> Result<PutResult> {
+        Err(Error::NotImplemented)
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        _location: &Path,
+        _opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        Err(Error::NotImplemented)
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        if options.if_match.is_some()
+            || options.if_none_match.is_some()
+            || options.if_modified_since.is_some()
+            || options.if_unmodified_since.is_some()
+            || options.version.is_some()
+        {
+            return Err(Error::NotImplemented);
+        }
+        let meta = object_meta(location)?;
+        let range = match options.range {
+            Some(range) => {
+                resolve_get_range(range, meta.size).map_err(|message| Error::Generic {
+                    store: STORE,
+                    source: message.into(),
+                })?
+            }
+            None => 0..meta.size,
+        };
+        let bytes = if options.head {
+            Bytes::new()
+        } else {
+            self.read_ranges(location, std::slice::from_ref(&range))
+                .await?
+                .remove(0)
+        };
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(stream::once(async { Ok(bytes) }).boxed()),
+            meta,
+            range,
+            attributes: Default::default(),
+        })
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        Ok(self.read_ranges(location, &[range]).await?.remove(0))
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.read_ranges(location, ranges).await
+    }
+
+    async fn delete(&self, _location: &Path) -> Result<()> {
+        Err(Error::NotImplemented)
+    }
+
+    fn list(&self, _prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        stream::once(async { Err(Error::NotImplemented) }).boxed()
+    }
+
+    async fn list_with_delimiter(&self, _prefix: Option<&Path>) -> Result<ListResult> {
+        Err(Error::NotImplemented)
+    }
+
+    async fn copy(&self, _from: &Path, _to: &Path) -> Result<()> {
+        Err(Error::NotImplemented)
+    }
+
+    async fn copy_if_not_exists(&self, _from: &Path, _to: &Path) -> Result<()> {
+        Err(Error::NotImplemented)
+    }
+}
+
+fn to_filesystem_path(location: &Path) -> PathBuf {
+    PathBuf::from("/").join(location.as_ref())
+}
+
+/// Map an error from [`AsyncReader`] to an `object_store::Error`.
+fn to_object_store_error(err: anyhow::Error, location: &Path) -> Error {
+    match err.downcast::<IoError>() {
+        Ok(err @ IoError::NotFound { .. }) => Error::NotFound {
+            path: location.to_string(),
+            source: Box::new(err),
+        },
+        Ok(err) => Error::Generic {
+            store: STORE,
+            source: Box::new(err),
+        },
+        Err(err) => Error::Generic {
+            store: STORE,
+            source: err.into(),
+        },
+    }
+}
+
+/// Get the size and modification time of the file at `location`.
+fn object_meta(location: &Path) -> Result<ObjectMeta> {
+    let path = to_filesystem_path(location);
+    let metadata = match std::fs::metadata(&path) {
+        Ok(metadata) if !metadata.is_dir() => metadata,
+        Ok(_) => {
+            return Err(Error::NotFound {
+                path: location.to_string(),
+                source: format!("{path:?} is a directory").into(),
+            })
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::NotFound {
+                path: location.to_string(),
+                source: err.into(),
+            })
+        }
+        Err(err) => {
+            return Err(Error::Generic {
+                store: STORE,
+                source: err.into(),
+            })
+        }
+    };
+    let last_modified = metadata.modified().map_err(|err| Error::Generic {
+        store: STORE,
+        source: err.into(),
+    })?;
+    Ok(ObjectMeta {
+        location: location.clone(),
+        last_modified: DateTime::<Utc>::from(last_modified),
+        size: metadata.len() as usize,
+        e_tag: None,
+        version: None,
+    })
+}
+
+/// Resolve `range` into absolute byte offsets into an object of `size` bytes, following the
+/// semantics documented on [`GetRange`].
+fn resolve_get_range(range: GetRange, size: usize) -> std::result::Result<Range<usize>, String> {
+    match range {
+        GetRange::Bounded(range) if range.is_empty() => Err(format!("Range {range:?} is empty")),
+        GetRange::Bounded(range) if range.start >= size => Err(format!(
+            "Wanted range starting at {}, but object was only {size} bytes long",
+            range.start
+        )),
+        GetRange::Bounded(range) => Ok(range.start..range.end.min(size)),
+        GetRange::Offset(offset) if offset >= size => Err(format!(
+            "Wanted range starting at {offset}, but object was only {size} bytes long"
+        )),
+        GetRange::Offset(offset) => Ok(offset..size),
+        GetRange::Suffix(n) => Ok(size.saturating_sub(n)..size),
+    }
+}
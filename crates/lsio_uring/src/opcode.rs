@@ -14,8 +14,18 @@ impl OpCode {
     pub(crate) fn name(&self) -> &'static str {
         match self.0 {
             opcode::OpenAt::CODE => "openat",
+            opcode::Statx::CODE => "statx",
             opcode::Read::CODE => "read",
+            opcode::ReadFixed::CODE => "read_fixed",
+            opcode::Readv::CODE => "readv",
+            opcode::Write::CODE => "write",
             opcode::Close::CODE => "close",
+            opcode::Fadvise::CODE => "fadvise",
+            opcode::Fsync::CODE => "fsync",
+            opcode::Nop::CODE => "nop",
+            opcode::LinkTimeout::CODE => "link_timeout",
+            opcode::Timeout::CODE => "timeout",
+            opcode::AsyncCancel::CODE => "async_cancel",
             _ => "Un-recognised opcode",
         }
     }
@@ -1,55 +1,224 @@
-use std::ffi::CString;
+use std::{
+    ffi::CString,
+    ops::Range,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use lsio_io::{resolve_range, try_resolve_range, FileMetadata};
+
+use crate::{
+    file_size_cache::{FileSize, FileSizeCache},
+    open_file_limit::OpenFilePermit,
+};
+
+/// Convert `path` into the `CString` that we give to io_uring.
+///
+/// # Errors
+/// Returns an error if `path` contains a NUL byte (which can't be passed to the kernel).
+pub(crate) fn location_from_path(path: &Path) -> anyhow::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        anyhow::format_err!("The path {path:?} contains a NUL byte, so it can't be opened.")
+    })
+}
+
+/// Convert the `CString` that we give to io_uring back into a path, for reporting errors.
+pub(crate) fn path_from_location(location: &CString) -> PathBuf {
+    PathBuf::from(std::ffi::OsStr::from_bytes(location.as_bytes()))
+}
+
+/// Convert a timestamp reported by `statx` (which may be before the Unix epoch) into a
+/// `SystemTime`.
+fn system_time(timestamp: &libc::statx_timestamp) -> SystemTime {
+    let nanos = Duration::from_nanos(timestamp.tv_nsec.into());
+    if timestamp.tv_sec >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp.tv_sec as u64) + nanos
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(timestamp.tv_sec.unsigned_abs()) + nanos
+    }
+}
+
+/// A file descriptor, or the index of a file which has been registered with an io_uring.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FileDescriptor {
+    Fd(io_uring::types::Fd),
+    /// A "fixed file", which was opened directly into the table of registered files of the
+    /// worker thread's io_uring. Fixed files save the kernel from looking up the file descriptor
+    /// for every SQE. A fixed file is only valid in the io_uring which opened it, so operations on
+    /// fixed files must not move to other worker threads. See [`crate::spawner::Spawner`].
+    Fixed(io_uring::types::Fixed),
+}
+
+impl FileDescriptor {
+    pub(crate) fn is_fixed(&self) -> bool {
+        matches!(self, Self::Fixed(_))
+    }
+
+    /// The `fd` and `flags` for an SQE which acts on this file. For a fixed file, the `fd` field of
+    /// the SQE holds the index of the registered file, and the `FIXED_FILE` flag is set. (This is
+    /// what the `io_uring` crate does for `types::Fixed`. We do it ourselves so that a single
+    /// opcode builder can handle both kinds of file.)
+    pub(crate) fn fd_and_flags(&self) -> (io_uring::types::Fd, io_uring::squeue::Flags) {
+        match *self {
+            Self::Fd(fd) => (fd, io_uring::squeue::Flags::empty()),
+            Self::Fixed(fixed) => (
+                io_uring::types::Fd(fixed.0.try_into().unwrap()),
+                io_uring::squeue::Flags::FIXED_FILE,
+            ),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct OpenFile {
-    location: CString,
-    file_descriptor: io_uring::types::Fd,
-    /// The file size in bytes.
-    /// Note that we always have to `statx` the file to get the `alignment`, so we'll always get
-    /// the file size, too.
-    size: u64,
-    alignment: u32,
+    location: Arc<CString>,
+    file_descriptor: FileDescriptor,
+    /// The file size in bytes, and the alignments required by `O_DIRECT` (as reported by `statx`).
+    /// `None` if we didn't `statx` the file (because every byte range requested by the user is
+    /// relative to the start of the file, so we don't need to know the file size).
+    file_size: Option<FileSize>,
+    /// True if the file was opened with `O_DIRECT`.
+    direct_io: bool,
+    /// The cache (if any) which may hold `size`.
+    file_size_cache: Option<Arc<FileSizeCache>>,
+    /// Released when the `OpenFile` is dropped (after the file has been closed). See
+    /// [`crate::open_file_limit::OpenFileLimit`].
+    _open_file_permit: Option<OpenFilePermit>,
 }
 
 impl OpenFile {
-    pub(crate) fn file_descriptor(&self) -> &io_uring::types::Fd {
+    pub(crate) fn path(&self) -> PathBuf {
+        path_from_location(&self.location)
+    }
+
+    pub(crate) fn file_descriptor(&self) -> &FileDescriptor {
         &self.file_descriptor
     }
 
-    pub(crate) fn size(&self) -> u64 {
-        self.size
+    /// The file size in bytes, or `None` if we didn't `statx` the file.
+    pub(crate) fn size(&self) -> Option<u64> {
+        self.file_size.map(|file_size| file_size.size)
+    }
+
+    /// The alignment of the buffer's address required by `O_DIRECT`. Zero if the filesystem
+    /// doesn't report its alignment. `None` if we didn't `statx` the file.
+    pub(crate) fn mem_alignment(&self) -> Option<u32> {
+        self.file_size.map(|file_size| file_size.mem_alignment)
+    }
+
+    /// The alignment of the file offset and length of each read required by `O_DIRECT`. Zero if
+    /// the filesystem doesn't report its alignment. `None` if we didn't `statx` the file.
+    pub(crate) fn offset_alignment(&self) -> Option<u32> {
+        self.file_size.map(|file_size| file_size.offset_alignment)
+    }
+
+    /// Resolve `range` into absolute offsets into this file. See [`resolve_range`].
+    ///
+    /// # Panics
+    /// If `range` is relative to the end of the file, and we don't know the file size.
+    pub(crate) fn resolve_range(&self, range: &Range<isize>) -> Range<isize> {
+        match self.size() {
+            Some(size) => resolve_range(range, size.try_into().unwrap()),
+            None => {
+                assert!(
+                    range.start >= 0 && range.end >= 0,
+                    "Can't resolve {range:?} without knowing the file size"
+                );
+                range.clone()
+            }
+        }
+    }
+
+    /// Like [`OpenFile::resolve_range`], but returns `None` if `range` doesn't resolve to a
+    /// non-empty range of non-negative offsets. See [`try_resolve_range`].
+    pub(crate) fn try_resolve_range(&self, range: &Range<isize>) -> Option<Range<isize>> {
+        match self.size() {
+            Some(size) => try_resolve_range(range, size.try_into().unwrap()),
+            // We only skip `statx` if every range is non-negative.
+            None => {
+                (range.start >= 0 && range.end >= 0 && !range.is_empty()).then(|| range.clone())
+            }
+        }
+    }
+
+    pub(crate) fn is_direct_io(&self) -> bool {
+        self.direct_io
     }
 
-    pub(crate) fn alignment(&self) -> u32 {
-        self.alignment
+    /// Call this if a read shows that the file has changed size since `size` was read, so that the
+    /// next operation on this file gets the file's new size from `statx`.
+    pub(crate) fn forget_cached_size(&self) {
+        if let Some(cache) = &self.file_size_cache {
+            cache.remove(&self.location);
+        }
     }
 }
 
 /// Used to build an [`OpenFile`].
 #[derive(Debug)]
 pub(crate) struct OpenFileBuilder {
-    location: CString,
-    file_descriptor: Option<io_uring::types::Fd>,
+    location: Arc<CString>,
+    file_descriptor: Option<FileDescriptor>,
     statx: libc::statx,
-    assume_statx_is_initialised: bool,
+    /// Set when `statx` completes, or from the `FileSizeCache`.
+    file_size: Option<FileSize>,
+    /// True if the file will be opened with `O_DIRECT`. Defaults to true.
+    direct_io: bool,
+    /// Extra flags to open the file with. See [`crate::IoUringBuilder::open_flags`].
+    open_flags: libc::c_int,
+    /// If false, then the `OpenFile` can be built without a file size. Defaults to true.
+    needs_file_size: bool,
+    file_size_cache: Option<Arc<FileSizeCache>>,
+    open_file_permit: Option<OpenFilePermit>,
 }
 
 impl OpenFileBuilder {
-    pub(crate) fn new(location: CString) -> Self {
+    pub(crate) fn new(location: Arc<CString>) -> Self {
         Self {
             location,
             file_descriptor: None,
             statx: unsafe { std::mem::zeroed() },
-            assume_statx_is_initialised: false,
+            file_size: None,
+            direct_io: true,
+            open_flags: 0,
+            needs_file_size: true,
+            file_size_cache: None,
+            open_file_permit: None,
         }
     }
 
-    pub(crate) const fn location(&self) -> &CString {
+    pub(crate) fn location(&self) -> &CString {
         &self.location
     }
 
+    pub(crate) fn path(&self) -> PathBuf {
+        path_from_location(&self.location)
+    }
+
     pub(crate) fn set_file_descriptor(&mut self, file_descriptor: io_uring::types::Fd) {
-        self.file_descriptor = Some(file_descriptor);
+        self.file_descriptor = Some(FileDescriptor::Fd(file_descriptor));
+    }
+
+    pub(crate) fn set_fixed_file(&mut self, fixed_file: io_uring::types::Fixed) {
+        self.file_descriptor = Some(FileDescriptor::Fixed(fixed_file));
+    }
+
+    pub(crate) fn set_direct_io(&mut self, direct_io: bool) {
+        self.direct_io = direct_io;
+    }
+
+    pub(crate) fn is_direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    pub(crate) fn set_open_flags(&mut self, open_flags: libc::c_int) {
+        self.open_flags = open_flags;
+    }
+
+    pub(crate) fn open_flags(&self) -> libc::c_int {
+        self.open_flags
     }
 
     pub(crate) fn get_statx_ptr(&mut self) -> *mut libc::statx {
@@ -57,11 +226,67 @@ impl OpenFileBuilder {
     }
 
     pub(crate) unsafe fn assume_statx_is_initialised(&mut self) {
-        self.assume_statx_is_initialised = true;
+        // `O_DIRECT` requires the buffer's address to be aligned to `stx_dio_mem_align`, and the
+        // file offset and length to be aligned to `stx_dio_offset_align`.
+        self.file_size = Some(FileSize {
+            size: self.statx.stx_size,
+            mem_alignment: self.statx.stx_dio_mem_align,
+            offset_alignment: self.statx.stx_dio_offset_align,
+        });
+    }
+
+    /// The metadata reported by `statx`.
+    ///
+    /// # Safety
+    /// The `statx` SQE must have completed successfully.
+    pub(crate) unsafe fn file_metadata(&self) -> FileMetadata {
+        let statx = &self.statx;
+        let mode = u32::from(statx.stx_mode);
+        FileMetadata {
+            path: self.path(),
+            size: statx.stx_size,
+            is_dir: mode & libc::S_IFMT == libc::S_IFDIR,
+            mtime: (statx.stx_mask & libc::STATX_MTIME != 0).then(|| system_time(&statx.stx_mtime)),
+            mode,
+        }
+    }
+
+    /// Use a cached `file_size`, instead of getting the file size from `statx`.
+    pub(crate) fn set_file_size(&mut self, file_size: FileSize) {
+        self.file_size = Some(file_size);
+    }
+
+    /// The cache which the file size may be stored in. See [`OpenFile::forget_cached_size`].
+    pub(crate) fn set_file_size_cache(&mut self, file_size_cache: Arc<FileSizeCache>) {
+        self.file_size_cache = Some(file_size_cache);
+    }
+
+    /// The permit to hold this file open. Moved into the `OpenFile` by `build`.
+    pub(crate) fn set_open_file_permit(&mut self, permit: OpenFilePermit) {
+        self.open_file_permit = Some(permit);
+    }
+
+    pub(crate) fn has_open_file_permit(&self) -> bool {
+        self.open_file_permit.is_some()
+    }
+
+    pub(crate) fn file_size(&self) -> Option<FileSize> {
+        self.file_size
+    }
+
+    /// Build the `OpenFile` without a file size (i.e. without waiting for `statx`). Only valid if
+    /// the file size isn't needed to resolve any of the byte ranges.
+    pub(crate) fn skip_file_size(&mut self) {
+        self.needs_file_size = false;
+    }
+
+    /// Returns true if we need the file size, and haven't got it yet.
+    pub(crate) fn needs_statx(&self) -> bool {
+        self.needs_file_size && self.file_size.is_none()
     }
 
     pub(crate) fn is_ready(&self) -> bool {
-        self.file_descriptor.is_some() && self.assume_statx_is_initialised
+        self.file_descriptor.is_some() && !self.needs_statx()
     }
 
     /// Safety: [`Self::is_ready`] must return `true` before calling `build`!
@@ -71,9 +296,10 @@ impl OpenFileBuilder {
         OpenFile {
             location: self.location,
             file_descriptor: self.file_descriptor.unwrap(),
-            size: self.statx.stx_size,
-            alignment: self.statx.stx_dio_mem_align,
-            // TODO: Maybe also use `statx.stx_dio_offset_align`.
+            file_size: self.file_size,
+            direct_io: self.direct_io,
+            file_size_cache: self.file_size_cache,
+            _open_file_permit: self.open_file_permit,
         }
     }
 }
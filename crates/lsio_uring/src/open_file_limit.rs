@@ -0,0 +1,145 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+};
+
+use lsio_threadpool::WorkerThread;
+
+use crate::operation::Operation;
+
+/// Limits the number of files (across all worker threads) which `GetRanges` operations hold open
+/// at once, so that reading thousands of files doesn't fail with `EMFILE` ("too many open
+/// files"). See [`crate::IoUringBuilder::max_open_files`].
+///
+/// Before a `GetRanges` operation submits its `openat`, it must acquire an [`OpenFilePermit`].
+/// The permit travels with the file (in its `OpenFileBuilder`, and then in its `OpenFile`), and is
+/// released when the file is closed (or if the file couldn't be opened). If no permit is
+/// available then the operation is held back until a file is closed.
+#[derive(Debug)]
+pub(crate) struct OpenFileLimit {
+    max_open_files: usize,
+
+    /// The number of permits which are currently held.
+    n_open_files: AtomicUsize,
+
+    /// The number of operations in `held_back_ops` (or which are about to be pushed onto
+    /// `held_back_ops`). This allows `release_ready_ops` to return quickly without locking
+    /// `held_back_ops`.
+    n_held_back_ops: AtomicUsize,
+
+    /// `GetRanges` operations which are waiting for a permit, in the order they were held back.
+    held_back_ops: Mutex<VecDeque<Operation>>,
+}
+
+impl OpenFileLimit {
+    pub(crate) fn new(max_open_files: usize) -> Self {
+        assert!(max_open_files > 0);
+        Self {
+            max_open_files,
+            n_open_files: AtomicUsize::new(0),
+            n_held_back_ops: AtomicUsize::new(0),
+            held_back_ops: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn try_acquire(self: &Arc<Self>) -> Option<OpenFilePermit> {
+        self.n_open_files
+            .fetch_update(SeqCst, SeqCst, |n| {
+                (n < self.max_open_files).then_some(n + 1)
+            })
+            .ok()?;
+        Some(OpenFilePermit {
+            limit: Arc::clone(self),
+        })
+    }
+
+    /// Returns `operation` if it can start now: Either because it doesn't need to open a file, or
+    /// because it has acquired a permit. Otherwise, holds back `operation` until a file is closed.
+    pub(crate) fn acquire_or_hold_back(
+        self: &Arc<Self>,
+        mut operation: Operation,
+    ) -> Option<Operation> {
+        let Operation::GetRanges(get_ranges) = &mut operation else {
+            return Some(operation);
+        };
+        if get_ranges.has_open_file_permit() {
+            return Some(operation);
+        }
+        let mut held_back_ops = self.held_back_ops.lock().unwrap();
+        // We announce that we're about to hold back an operation _before_ trying to acquire a
+        // permit. So either we'll see the permit released by a thread which is closing a file, or
+        // that thread will see our held-back operation in `release_ready_ops` (or both).
+        self.n_held_back_ops.fetch_add(1, SeqCst);
+        match self.try_acquire() {
+            Some(permit) => {
+                self.n_held_back_ops.fetch_sub(1, SeqCst);
+                get_ranges.set_open_file_permit(permit);
+                Some(operation)
+            }
+            None => {
+                held_back_ops.push_back(operation);
+                None
+            }
+        }
+    }
+
+    /// Give a permit to each held-back operation (in the order they were held back) for as long as
+    /// permits are available, and push those operations onto `worker_thread`'s queue.
+    pub(crate) fn release_ready_ops(self: &Arc<Self>, worker_thread: &WorkerThread<Operation>) {
+        if self.n_held_back_ops.load(SeqCst) == 0 {
+            return;
+        }
+        let mut held_back_ops = self.held_back_ops.lock().unwrap();
+        while !held_back_ops.is_empty() {
+            let Some(permit) = self.try_acquire() else {
+                break;
+            };
+            let mut operation = held_back_ops.pop_front().unwrap();
+            self.n_held_back_ops.fetch_sub(1, SeqCst);
+            if let Operation::GetRanges(get_ranges) = &mut operation {
+                get_ranges.set_open_file_permit(permit);
+            }
+            worker_thread.push(operation);
+        }
+    }
+
+    /// Drop all the held-back operations (e.g. because the `IoUring` is being dropped).
+    pub(crate) fn drop_held_back_ops(&self) {
+        // Take the operations out of `held_back_ops` before dropping them, so that we don't hold
+        // the lock whilst running their destructors.
+        let held_back_ops = std::mem::take(&mut *self.held_back_ops.lock().unwrap());
+        self.n_held_back_ops.fetch_sub(held_back_ops.len(), SeqCst);
+        drop(held_back_ops);
+    }
+}
+
+/// Permission to hold one file open. The permit is released when it's dropped.
+#[derive(Debug)]
+pub(crate) struct OpenFilePermit {
+    limit: Arc<OpenFileLimit>,
+}
+
+impl Drop for OpenFilePermit {
+    fn drop(&mut self) {
+        self.limit.n_open_files.fetch_sub(1, SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits_are_limited_and_released_on_drop() {
+        let limit = Arc::new(OpenFileLimit::new(2));
+        let a = limit.try_acquire().unwrap();
+        let _b = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+        drop(a);
+        assert!(limit.try_acquire().is_some());
+        assert_eq!(limit.n_open_files.load(SeqCst), 1);
+    }
+}
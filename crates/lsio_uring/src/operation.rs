@@ -1,16 +1,45 @@
-use lsio_threadpool::WorkerThread;
+use std::{ops::Range, path::PathBuf, time::Duration};
 
-use crate::{close::Close, get_range::GetRange, get_ranges::GetRanges, user_data::UringUserData};
+use lsio_io::IoError;
+
+use crate::{
+    advise::Advise, batch::Batch, close::Close, copy_range::CopyRange, copy_ranges::CopyRanges,
+    exists::Exists, fsync::Fsync, get_range::GetRange, get_range_vectored::GetRangeVectored,
+    get_ranges::GetRanges, get_stream::GetStream, list::List, metadata::Metadata,
+    put_range::PutRange, put_ranges::PutRanges, spawner::Spawner, user_data::UringUserData,
+};
 
 /// We keep a `Tracker<Operation>` in each thread to track progress of each operation:
 #[derive(Debug)]
 pub(crate) enum Operation {
     GetRanges(GetRanges),
     GetRange(GetRange),
+    GetRangeVectored(GetRangeVectored),
+    GetStream(GetStream),
+    CopyRanges(CopyRanges),
+    CopyRange(CopyRange),
+    PutRanges(PutRanges),
+    PutRange(PutRange),
+    List(List),
+    Exists(Exists),
+    Metadata(Metadata),
+    Advise(Advise),
+    Fsync(Fsync),
     Close(Close),
+    Batch(Batch),
 }
 
 impl Operation {
+    /// Returns true if this operation acts on a fixed file. Fixed files are only valid in the
+    /// io_uring which opened them, so this operation must stay on the current worker thread.
+    pub(crate) fn uses_fixed_file(&self) -> bool {
+        match self {
+            Operation::GetRange(s) => s.file().file_descriptor().is_fixed(),
+            Operation::Close(s) => s.file().file_descriptor().is_fixed(),
+            _ => false,
+        }
+    }
+
     fn apply_func_to_all_inner_structs<F, R>(&mut self, mut f: F) -> R
     where
         F: FnMut(&mut dyn UringOperation) -> R,
@@ -19,7 +48,19 @@ impl Operation {
         match self {
             GetRanges(s) => f(s),
             GetRange(s) => f(s),
+            GetRangeVectored(s) => f(s),
+            GetStream(s) => f(s),
+            CopyRanges(s) => f(s),
+            CopyRange(s) => f(s),
+            PutRanges(s) => f(s),
+            PutRange(s) => f(s),
+            List(s) => f(s),
+            Exists(s) => f(s),
+            Metadata(s) => f(s),
+            Advise(s) => f(s),
+            Fsync(s) => f(s),
             Close(s) => f(s),
+            Batch(s) => f(s),
         }
     }
 }
@@ -40,8 +81,8 @@ impl UringOperation for Operation {
         idx_and_opcode: &UringUserData,
         cqe_result: i32,
         local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
-        worker_thread: &WorkerThread<Operation>,
-        output_channel: &mut crossbeam_channel::Sender<anyhow::Result<lsio_io::Output>>,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, IoError>>,
     ) -> NextStep {
         self.apply_func_to_all_inner_structs(|s| {
             UringOperation::maybe_send_error(s, idx_and_opcode, cqe_result, output_channel);
@@ -50,7 +91,7 @@ impl UringOperation for Operation {
                 idx_and_opcode,
                 cqe_result,
                 local_uring_submission_queue,
-                worker_thread,
+                spawner,
                 output_channel,
             )
         })
@@ -75,28 +116,114 @@ pub(crate) trait UringOperation: std::fmt::Debug {
         idx_and_opcode: &UringUserData,
         cqe_result: i32,
         local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
-        worker_thread: &WorkerThread<Operation>,
-        output_channel: &mut crossbeam_channel::Sender<anyhow::Result<lsio_io::Output>>,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, IoError>>,
     ) -> NextStep;
 
+    /// The path of the file that the SQE identified by `idx_and_opcode` acts on (if any). Used to
+    /// describe errors.
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        None
+    }
+
+    /// The byte range that the SQE identified by `idx_and_opcode` acts on (if any). Used to
+    /// describe errors.
+    fn range(&self, _idx_and_opcode: &UringUserData) -> Option<Range<isize>> {
+        None
+    }
+
+    /// The timeout (if any) of the `LinkTimeout` linked to the SQE identified by `idx_and_opcode`.
+    /// If set, then `ECANCELED` means that the SQE timed out.
+    fn timeout(&self, _idx_and_opcode: &UringUserData) -> Option<Duration> {
+        None
+    }
+
+    /// The `user_data` of the user's byte range that the SQE identified by `idx_and_opcode` acts
+    /// on, if the SQE acts on exactly one of the user's byte ranges. Used to describe errors.
+    fn user_data(&self, _idx_and_opcode: &UringUserData) -> Option<u64> {
+        None
+    }
+
+    /// Returns true if the operation will retry the SQE which produced this failed CQE, in which
+    /// case the error isn't reported to the user.
+    fn will_retry(&self, _idx_and_opcode: &UringUserData, _cqe_result: i32) -> bool {
+        false
+    }
+
     fn maybe_send_error(
         &self,
         idx_and_opcode: &UringUserData,
         cqe_result: i32,
-        output_channel: &mut crossbeam_channel::Sender<anyhow::Result<lsio_io::Output>>,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, IoError>>,
     ) {
-        if cqe_result < 0 {
-            // TODO: We probably want a custom Error struct (or enum?) which has machine-readable
-            // fields for filename, byte_range(s), user_data, error code, opcode. But this
-            // `anyhow::Error` will do for now.
-            let nix_err = nix::Error::from_raw(-cqe_result);
-            let context = format!(
-                "{nix_err} (reported by io_uring completion queue entry (CQE)). More details: \
-                    idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {self:?}",
-            );
-            let err = Err(anyhow::Error::new(nix_err).context(context));
-            output_channel.send(err).unwrap();
-        }
+        send_cqe_error(self, idx_and_opcode, cqe_result, output_channel);
+    }
+}
+
+/// The default implementation of [`UringOperation::maybe_send_error`]: If the CQE reports a
+/// failure (which `op` won't retry), then send one error, described by `op`.
+pub(crate) fn send_cqe_error<O: UringOperation + ?Sized>(
+    op: &O,
+    idx_and_opcode: &UringUserData,
+    cqe_result: i32,
+    output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, IoError>>,
+) {
+    // The CQE of a `LinkTimeout` only tells us whether the timeout fired. If it did fire, then
+    // the CQE of the linked SQE reports the failure. The CQE of a `Timeout` (which waits before a
+    // retry) reports `ETIME` when the timer expires, which isn't a failure.
+    let is_timer = matches!(
+        idx_and_opcode.opcode().value(),
+        io_uring::opcode::LinkTimeout::CODE | io_uring::opcode::Timeout::CODE
+    );
+    if cqe_result < 0 && !is_timer && !op.will_retry(idx_and_opcode, cqe_result) {
+        let err = cqe_error(
+            idx_and_opcode.opcode().name(),
+            cqe_result,
+            op.path(idx_and_opcode),
+            op.range(idx_and_opcode),
+            op.user_data(idx_and_opcode),
+            op.timeout(idx_and_opcode),
+            format!(
+                "(reported by io_uring completion queue entry (CQE)). More details: \
+                    idx_and_opcode: {idx_and_opcode:?}. cqe_result: {cqe_result}. self: {op:?}",
+            ),
+        );
+        output_channel.send(Err(err)).unwrap();
+    }
+}
+
+/// Convert the failed `cqe_result` of an `opcode` SQE into the error which is sent to the user.
+/// `range` and `user_data` identify the user's byte range which failed (if known). If `timeout` is
+/// `Some`, then `ECANCELED` means that the SQE timed out.
+pub(crate) fn cqe_error(
+    opcode: &'static str,
+    cqe_result: i32,
+    path: Option<PathBuf>,
+    range: Option<Range<isize>>,
+    user_data: Option<u64>,
+    timeout: Option<Duration>,
+    details: String,
+) -> IoError {
+    match (nix::Error::from_raw(-cqe_result), path, timeout) {
+        (nix::Error::ENOENT, Some(path), _) => IoError::NotFound {
+            path,
+            user_data,
+            details,
+        },
+        (nix::Error::ECANCELED, Some(path), Some(timeout)) => IoError::TimedOut {
+            path,
+            range,
+            timeout,
+            details,
+        },
+        (errno, path, _) => IoError::Nix {
+            errno,
+            opcode,
+            path,
+            range,
+            user_data,
+            details,
+        },
     }
 }
 
@@ -104,4 +231,8 @@ pub(crate) enum NextStep {
     Pending,
     Done,
     ReplaceWith(Operation),
+    /// The operation couldn't push its next step onto the SQ, and it has no SQEs in flight. So
+    /// stop tracking the operation, and push it back onto the worker's local queue. The worker will
+    /// call `submit_first_step` again later.
+    Requeue,
 }
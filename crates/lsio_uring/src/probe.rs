@@ -0,0 +1,105 @@
+use io_uring::{opcode, Probe};
+use snafu::{ResultExt, Snafu};
+
+/// The errors returned by [`crate::IoUring::probe`].
+#[derive(Debug, Snafu)]
+pub enum ProbeError {
+    /// The kernel refused to create an io_uring. For example, because the kernel is older than
+    /// Linux 5.1, or because io_uring has been disabled (by the `kernel.io_uring_disabled` sysctl,
+    /// or by a seccomp filter, as in some container runtimes).
+    #[snafu(display(
+        "Failed to create an io_uring. (Is the kernel older than Linux 5.1, or is io_uring \
+            disabled?) {source}"
+    ))]
+    Setup { source: std::io::Error },
+
+    /// The kernel created an io_uring, but can't report which opcodes it supports. The kernel is
+    /// probably older than Linux 5.6.
+    #[snafu(display(
+        "Failed to probe the opcodes supported by io_uring. (Is the kernel older than Linux \
+            5.6?) {source}"
+    ))]
+    Probe { source: std::io::Error },
+}
+
+/// What the running kernel's io_uring supports. Returned by [`crate::IoUring::probe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `IORING_OP_OPENAT` (Linux 5.6). Required.
+    pub openat: bool,
+    /// `IORING_OP_STATX` (Linux 5.6). Required.
+    pub statx: bool,
+    /// `IORING_OP_READ` (Linux 5.6). Required.
+    pub read: bool,
+    /// `IORING_OP_CLOSE` (Linux 5.6). Required.
+    pub close: bool,
+    /// `IORING_FEAT_NODROP` (Linux 5.5), which stops the kernel from dropping completions when
+    /// the completion queue is full. Required.
+    pub nodrop: bool,
+    /// `IORING_FEAT_EXT_ARG` (Linux 5.11), which lets workers block with a timeout. Without it,
+    /// [`crate::WaitStrategy::Block`] and [`crate::WaitStrategy::Hybrid`] fall back to
+    /// `BusyPoll`.
+    pub ext_arg: bool,
+}
+
+impl Capabilities {
+    /// The names of the required opcodes and features which the kernel doesn't support.
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            (self.openat, "IORING_OP_OPENAT"),
+            (self.statx, "IORING_OP_STATX"),
+            (self.read, "IORING_OP_READ"),
+            (self.close, "IORING_OP_CLOSE"),
+            (self.nodrop, "IORING_FEAT_NODROP"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| (!supported).then_some(name))
+        .collect()
+    }
+
+    /// Returns true if the kernel supports every opcode and feature which `IoUring` requires.
+    pub fn is_supported(&self) -> bool {
+        self.missing().is_empty()
+    }
+}
+
+/// Create a small io_uring, and ask the kernel which opcodes it supports.
+pub(crate) fn probe() -> Result<Capabilities, ProbeError> {
+    let ring = io_uring::IoUring::new(2).context(SetupSnafu)?;
+    let mut probe = Probe::new();
+    ring.submitter()
+        .register_probe(&mut probe)
+        .context(ProbeSnafu)?;
+    Ok(Capabilities {
+        openat: probe.is_supported(opcode::OpenAt::CODE),
+        statx: probe.is_supported(opcode::Statx::CODE),
+        read: probe.is_supported(opcode::Read::CODE),
+        close: probe.is_supported(opcode::Close::CODE),
+        nodrop: ring.params().is_feature_nodrop(),
+        ext_arg: ring.params().is_feature_ext_arg(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing() {
+        let all = Capabilities {
+            openat: true,
+            statx: true,
+            read: true,
+            close: true,
+            nodrop: true,
+            ext_arg: false,
+        };
+        assert!(all.is_supported());
+        let no_statx = Capabilities {
+            statx: false,
+            ..all
+        };
+        assert_eq!(no_statx.missing(), ["IORING_OP_STATX"]);
+        assert!(!no_statx.is_supported());
+    }
+}
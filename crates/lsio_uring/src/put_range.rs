@@ -0,0 +1,115 @@
+use crate::{
+    close::Close,
+    groups::GroupMember,
+    open_file::OpenFile,
+    operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
+    sqe::build_write_sqe,
+    user_data::UringUserData,
+};
+use lsio_aligned_bytes::AlignedBytes;
+use lsio_io::{IoError, Output};
+use std::{ops::Range, path::PathBuf, sync::Arc};
+
+#[derive(Debug)]
+pub(crate) struct PutRange {
+    file: Arc<OpenFile>,
+    /// The (resolved) byte range to write to. Must be the same length as `buffer`.
+    range: Range<isize>,
+    buffer: AlignedBytes,
+    user_data: u64,
+    /// The group (if any) that this operation belongs to. The group won't finish until this
+    /// operation has been dropped.
+    group: Option<Arc<GroupMember>>,
+}
+
+impl PutRange {
+    pub(crate) fn new(
+        file: Arc<OpenFile>,
+        range: Range<isize>,
+        buffer: AlignedBytes,
+        user_data: u64,
+    ) -> Self {
+        // `PutRanges` sends an `InvalidRange` error (instead of creating a `PutRange`) for any
+        // range which breaks these rules.
+        debug_assert!(range.start >= 0);
+        debug_assert_eq!(range.len(), buffer.len());
+        Self {
+            file,
+            range,
+            buffer,
+            user_data,
+            group: None,
+        }
+    }
+
+    pub(crate) fn with_group(mut self, group: Option<Arc<GroupMember>>) -> Self {
+        self.group = group;
+        self
+    }
+}
+
+impl UringOperation for PutRange {
+    /// This method assumes that the file has already been opened (by the [`PutRanges`] operation).
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        let entry = build_write_sqe(
+            index_of_op,
+            &self.file,
+            &self.buffer,
+            self.range.start as u64,
+        );
+        unsafe { local_uring_submission_queue.push(&entry) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        Some(self.file.path())
+    }
+
+    fn range(&self, _idx_and_opcode: &UringUserData) -> Option<Range<isize>> {
+        Some(self.range.clone())
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<Output, IoError>>,
+    ) -> NextStep {
+        // Check that the opcode of the CQE is what we expected:
+        if idx_and_opcode.opcode().value() != io_uring::opcode::Write::CODE {
+            panic!("Unrecognised opcode!");
+        }
+        if cqe_result >= 0 {
+            output_channel
+                .send(Ok(Output::BytesWritten {
+                    user_data: self.user_data,
+                    nbytes: cqe_result as usize,
+                }))
+                .unwrap();
+        };
+        // Check if it's time to close the file:
+        if Arc::strong_count(&self.file) == 1 {
+            // We're the last operation on this file, so it's time to close this file.
+            let mut close_op = Close::new(Arc::clone(&self.file));
+            match close_op.submit_first_step(
+                idx_and_opcode.index_of_op() as _,
+                local_uring_submission_queue,
+            ) {
+                Ok(()) => NextStep::ReplaceWith(Operation::Close(close_op)),
+                Err(_) => {
+                    // The SQ is full, so let the worker's main loop submit `close_op` later.
+                    spawner.push(Operation::Close(close_op));
+                    NextStep::Done
+                }
+            }
+        } else {
+            NextStep::Done
+        }
+    }
+}
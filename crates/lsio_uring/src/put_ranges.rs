@@ -0,0 +1,177 @@
+use std::{ffi::CString, ops::Range, path::PathBuf, sync::Arc};
+
+use lsio_aligned_bytes::AlignedBytes;
+use lsio_io::IoError;
+
+use crate::{
+    close::Close,
+    groups::GroupMember,
+    open_file::OpenFileBuilder,
+    operation::{NextStep, Operation, UringOperation},
+    put_range::PutRange,
+    spawner::Spawner,
+    sqe::{build_openat_for_writing_sqe, build_statx_sqe},
+    user_data::UringUserData,
+};
+
+const N_CQES_EXPECTED: u8 = 2; // We're expecting CQEs for `openat` and `statx`.
+
+#[derive(Debug)]
+pub(crate) struct PutRanges {
+    open_file_builder: Option<OpenFileBuilder>,
+    ranges: Vec<Range<isize>>,
+    buffers: Vec<AlignedBytes>,
+    user_data: Vec<u64>,
+
+    /// Open the file with `O_DIRECT`. Only `true` if all the writes are aligned.
+    direct: bool,
+
+    // In case one or more CQEs reports a failure, we need to track how many CQEs we've received.
+    n_cqes_received: u8,
+
+    /// If `Some`, then this operation (and the `PutRange` operations it spawns) belong to a group.
+    group: Option<Arc<GroupMember>>,
+}
+
+impl PutRanges {
+    pub(crate) fn new(
+        location: CString,
+        ranges: Vec<Range<isize>>,
+        buffers: Vec<AlignedBytes>,
+        user_data: Vec<u64>,
+        direct: bool,
+    ) -> Self {
+        assert_eq!(ranges.len(), buffers.len());
+        assert_eq!(ranges.len(), user_data.len());
+        let mut open_file_builder = OpenFileBuilder::new(Arc::new(location));
+        open_file_builder.set_direct_io(direct);
+        Self {
+            open_file_builder: Some(open_file_builder),
+            ranges,
+            buffers,
+            user_data,
+            direct,
+            n_cqes_received: 0,
+            group: None,
+        }
+    }
+
+    pub(crate) fn with_group(mut self, group: Arc<GroupMember>) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    // io_uring can't write multiple ranges in a single op. So, once we've opened the file and
+    // gotten its metadata, we need to submit one `Operation::PutRange` per byte range.
+    fn submit_put_range_ops(
+        &mut self,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) {
+        let file = Arc::new(self.open_file_builder.take().unwrap().build());
+        let buffers = std::mem::take(&mut self.buffers);
+        for ((range, buffer), user_data) in self.ranges.iter().zip(buffers).zip(&self.user_data) {
+            // Negative ranges can only be resolved now that we know the file size.
+            let Some(resolved_range) = file.try_resolve_range(range) else {
+                output_channel
+                    .send(Err(IoError::InvalidRange {
+                        path: file.path(),
+                        range: range.to_owned(),
+                        message: format!(
+                            "The range {range:?} doesn't resolve to a non-empty range of the \
+                                file, which is {} bytes. user_data={user_data}",
+                            file.size().map_or_else(
+                                || "an unknown number of".to_string(),
+                                |size| size.to_string()
+                            ),
+                        ),
+                    }))
+                    .unwrap();
+                continue;
+            };
+            if resolved_range.len() != buffer.len() {
+                output_channel
+                    .send(Err(IoError::InvalidRange {
+                        path: file.path(),
+                        range: range.to_owned(),
+                        message: format!(
+                            "The range {range:?} (resolved to {resolved_range:?}) is {} bytes \
+                                long, but the buffer is {} bytes long. user_data={user_data}",
+                            resolved_range.len(),
+                            buffer.len(),
+                        ),
+                    }))
+                    .unwrap();
+                continue;
+            }
+            let put_range_op = PutRange::new(file.clone(), resolved_range, buffer, *user_data)
+                .with_group(self.group.clone());
+            spawner.push(Operation::PutRange(put_range_op));
+        }
+
+        // If every range failed validation then nothing else will close the file.
+        if Arc::strong_count(&file) == 1 {
+            spawner.push(Operation::Close(Close::new(file)));
+        }
+    }
+}
+
+impl UringOperation for PutRanges {
+    fn submit_first_step(
+        &mut self,
+        index_of_op: usize,
+        local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+    ) -> Result<(), io_uring::squeue::PushError> {
+        // The file might not exist yet. So we link the `statx` to the `openat`, to make sure that
+        // `statx` only runs after `openat` has created the file.
+        let open_entry = build_openat_for_writing_sqe(
+            index_of_op,
+            self.open_file_builder.as_ref().unwrap().location(),
+            self.direct,
+        )
+        .flags(io_uring::squeue::Flags::IO_LINK);
+        let statx_entry = build_statx_sqe(index_of_op, self.open_file_builder.as_mut().unwrap());
+        // `push_multiple` is atomic: Either both SQEs are pushed, or neither are pushed.
+        unsafe { local_uring_submission_queue.push_multiple(&[open_entry, statx_entry]) }
+    }
+
+    fn path(&self, _idx_and_opcode: &UringUserData) -> Option<PathBuf> {
+        self.open_file_builder.as_ref().map(OpenFileBuilder::path)
+    }
+
+    fn process_opcode_and_submit_next_step(
+        &mut self,
+        idx_and_opcode: &UringUserData,
+        cqe_result: i32,
+        _local_uring_submission_queue: &mut io_uring::squeue::SubmissionQueue,
+        spawner: &Spawner,
+        output_channel: &mut crossbeam_channel::Sender<Result<lsio_io::Output, lsio_io::IoError>>,
+    ) -> NextStep {
+        self.n_cqes_received += 1;
+        if cqe_result >= 0 {
+            let builder = self.open_file_builder.as_mut().unwrap();
+            match idx_and_opcode.opcode().value() {
+                io_uring::opcode::OpenAt::CODE => {
+                    builder.set_file_descriptor(io_uring::types::Fd(cqe_result));
+                }
+                io_uring::opcode::Statx::CODE => {
+                    unsafe { builder.assume_statx_is_initialised() };
+                }
+                _ => panic!("Unrecognised opcode! {idx_and_opcode:?}"),
+            };
+        };
+
+        assert!(self.n_cqes_received <= N_CQES_EXPECTED);
+        if self.n_cqes_received == N_CQES_EXPECTED {
+            if self.open_file_builder.as_ref().unwrap().is_ready() {
+                self.submit_put_range_ops(spawner, output_channel);
+            }
+            // Otherwise, at least one of the CQEs must have resulted in an error (which will
+            // already have been reported to the user by `maybe_send_error`).
+            NextStep::Done
+        } else {
+            // We're expecting one more CQE.
+            NextStep::Pending
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+/// How `GetRange` operations retry `read`s which fail with a transient error. See
+/// [`crate::IoUringBuilder::retries`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// The maximum number of times that each `GetRange` operation retries its failed `read`s.
+    pub(crate) retries: u32,
+    /// How long to wait before the first retry. Each subsequent retry waits twice as long as the
+    /// previous retry.
+    pub(crate) backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns true if a `read` which failed with `cqe_result`, and which has already been retried
+    /// `n_retries` times, should be retried.
+    pub(crate) fn should_retry(&self, cqe_result: i32, n_retries: u32) -> bool {
+        n_retries < self.retries && is_transient(cqe_result)
+    }
+
+    /// How long to wait before retry number `n_retries + 1`.
+    pub(crate) fn backoff(&self, n_retries: u32) -> Duration {
+        self.backoff.saturating_mul(1 << n_retries.min(16))
+    }
+}
+
+/// Returns true if `cqe_result` is an errno which may succeed if the `read` is retried (e.g. the
+/// errors returned by networked filesystems when the server is briefly unavailable). Errors such
+/// as `ENOENT` or `EINVAL` will never succeed on retry.
+fn is_transient(cqe_result: i32) -> bool {
+    matches!(-cqe_result, libc::EAGAIN | libc::EINTR | libc::EIO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        };
+        for errno in [libc::EAGAIN, libc::EINTR, libc::EIO] {
+            assert!(policy.should_retry(-errno, 0));
+            assert!(policy.should_retry(-errno, 1));
+            assert!(!policy.should_retry(-errno, 2));
+        }
+        for errno in [libc::ENOENT, libc::EINVAL, libc::EBADF, libc::ECANCELED] {
+            assert!(!policy.should_retry(-errno, 0));
+        }
+        assert!(!RetryPolicy::default().should_retry(-libc::EIO, 0));
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(10),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(1), Duration::from_millis(20));
+        assert_eq!(policy.backoff(2), Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use lsio_io::{IoError, Output};
+
+/// How long the sequencer thread sleeps whilst the ordered channel is above its high-water mark.
+const BACKPRESSURE_SLEEP: Duration = Duration::from_micros(100);
+
+/// Re-sequences the outputs of reads into the order in which the ranges were submitted. See
+/// [`crate::IoUringBuilder::ordered_completion`].
+///
+/// Each range is registered (by the thread which submits it) before it's submitted. The sequencer
+/// thread receives every output from the worker threads, and holds back each `Chunk` (or error)
+/// until the outputs of all the ranges which were submitted before it have been sent.
+#[derive(Debug)]
+pub(crate) struct Sequencer {
+    pending: Mutex<Pending>,
+    /// Each message asks the sequencer thread to process every output which the worker threads
+    /// have already sent, and then to send `()` on the enclosed channel.
+    flush_tx: Sender<Sender<()>>,
+    flush_rx: Receiver<Sender<()>>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    /// One slot per registered range, in submission order.
+    slots: VecDeque<Slot>,
+    /// The sequence number of `slots[0]`.
+    first_seq: u64,
+    /// The sequence numbers of the slots which are still waiting for their output, keyed by
+    /// `user_data`.
+    waiting: HashMap<u64, VecDeque<u64>>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    location: Arc<Path>,
+    range: Range<isize>,
+    user_data: u64,
+    state: SlotState,
+}
+
+#[derive(Debug)]
+enum SlotState {
+    Waiting,
+    Ready(Result<Output, IoError>),
+    /// The range failed, and the error was reported by an earlier slot (because one error
+    /// described several ranges, e.g. when ranges were merged into a single read).
+    Skipped,
+}
+
+impl Sequencer {
+    pub(crate) fn new() -> Self {
+        let (flush_tx, flush_rx) = crossbeam_channel::unbounded();
+        Self {
+            pending: Mutex::new(Pending::default()),
+            flush_tx,
+            flush_rx,
+        }
+    }
+
+    /// Register `ranges` of `location` (in order). Must be called before the ranges are submitted.
+    pub(crate) fn register(&self, location: &Path, ranges: &[Range<isize>], user_data: &[u64]) {
+        let location: Arc<Path> = Arc::from(location);
+        let mut pending = self.pending.lock().unwrap();
+        for (range, &user_data) in ranges.iter().zip(user_data) {
+            let seq = pending.first_seq + pending.slots.len() as u64;
+            pending.waiting.entry(user_data).or_default().push_back(seq);
+            pending.slots.push_back(Slot {
+                location: Arc::clone(&location),
+                range: range.clone(),
+                user_data,
+                state: SlotState::Waiting,
+            });
+        }
+    }
+
+    /// Block until the sequencer thread has processed every output which the worker threads have
+    /// already sent. Returns immediately if the sequencer thread has stopped.
+    pub(crate) fn flush(&self) {
+        let (ack_tx, ack_rx) = crossbeam_channel::bounded(1);
+        if self.flush_tx.send(ack_tx).is_ok() {
+            let _ = ack_rx.recv();
+        }
+    }
+
+    /// Re-sequence the outputs from `raw_rx` into `ordered_tx`. Whilst `ordered_tx` holds at least
+    /// `high_water_mark` outputs, we stop receiving from `raw_rx`, so the worker threads see the
+    /// backpressure. Returns when every worker thread has dropped its sender.
+    pub(crate) fn run(
+        &self,
+        raw_rx: Receiver<Result<Output, IoError>>,
+        ordered_tx: Sender<Result<Output, IoError>>,
+        high_water_mark: usize,
+    ) {
+        loop {
+            if ordered_tx.len() >= high_water_mark {
+                if let Ok(ack_tx) = self.flush_rx.try_recv() {
+                    self.flush_all(&raw_rx, &ordered_tx, ack_tx);
+                }
+                thread::sleep(BACKPRESSURE_SLEEP);
+                continue;
+            }
+            crossbeam_channel::select! {
+                recv(raw_rx) -> output => match output {
+                    Ok(output) => self.process(output, &ordered_tx),
+                    Err(_) => return,
+                },
+                recv(self.flush_rx) -> ack_tx => {
+                    self.flush_all(&raw_rx, &ordered_tx, ack_tx.unwrap());
+                }
+            }
+        }
+    }
+
+    fn flush_all(
+        &self,
+        raw_rx: &Receiver<Result<Output, IoError>>,
+        ordered_tx: &Sender<Result<Output, IoError>>,
+        ack_tx: Sender<()>,
+    ) {
+        for output in raw_rx.try_iter() {
+            self.process(output, ordered_tx);
+        }
+        let _ = ack_tx.send(());
+    }
+
+    /// Store `output` in its slot (if any) and send every output which is now in sequence.
+    /// Outputs which don't belong to a registered range are sent straight away.
+    fn process(
+        &self,
+        output: Result<Output, IoError>,
+        ordered_tx: &Sender<Result<Output, IoError>>,
+    ) {
+        let mut pending = self.pending.lock().unwrap();
+        let unsequenced = match output {
+            Ok(Output::Chunk(chunk)) => match pending.take_waiting(chunk.user_data) {
+                Some(seq) => pending.fill(seq, Ok(Output::Chunk(chunk))),
+                None => Some(Ok(Output::Chunk(chunk))),
+            },
+            Ok(output) => Some(Ok(output)),
+            Err(err) => pending.fill_error(err),
+        };
+        // The receiver can't be dropped whilst the `IoUring` (which owns it) is alive.
+        if let Some(output) = unsequenced {
+            let _ = ordered_tx.send(output);
+        }
+        while let Some(slot) = pending.slots.front() {
+            if matches!(slot.state, SlotState::Waiting) {
+                break;
+            }
+            let slot = pending.slots.pop_front().unwrap();
+            pending.first_seq += 1;
+            if let SlotState::Ready(output) = slot.state {
+                let _ = ordered_tx.send(output);
+            }
+        }
+    }
+}
+
+impl Pending {
+    /// Returns the sequence number of the oldest slot which is waiting for `user_data`.
+    fn take_waiting(&mut self, user_data: u64) -> Option<u64> {
+        let seqs = self.waiting.get_mut(&user_data)?;
+        let seq = seqs.pop_front();
+        if seqs.is_empty() {
+            self.waiting.remove(&user_data);
+        }
+        seq
+    }
+
+    fn slot_mut(&mut self, seq: u64) -> &mut Slot {
+        &mut self.slots[(seq - self.first_seq) as usize]
+    }
+
+    /// Returns `None` (because the output has been stored in its slot).
+    fn fill(
+        &mut self,
+        seq: u64,
+        output: Result<Output, IoError>,
+    ) -> Option<Result<Output, IoError>> {
+        self.slot_mut(seq).state = SlotState::Ready(output);
+        None
+    }
+
+    /// Store `err` in the slot of the oldest range which it describes, and skip the other ranges
+    /// which it describes. Returns `err` if it doesn't describe any waiting range.
+    fn fill_error(&mut self, err: IoError) -> Option<Result<Output, IoError>> {
+        if let Some(user_data) = err.user_data() {
+            return match self.take_waiting(user_data) {
+                Some(seq) => self.fill(seq, Err(err)),
+                None => Some(Err(err)),
+            };
+        }
+        let described: Vec<(u64, u64)> = self
+            .slots
+            .iter()
+            .zip(self.first_seq..)
+            .filter(|(slot, _)| {
+                matches!(slot.state, SlotState::Waiting)
+                    && err.describes_range(&slot.location, &slot.range)
+            })
+            .map(|(slot, seq)| (seq, slot.user_data))
+            .collect();
+        let Some(&(first_seq, _)) = described.first() else {
+            return Some(Err(err));
+        };
+        for &(seq, user_data) in &described {
+            if let Some(seqs) = self.waiting.get_mut(&user_data) {
+                seqs.retain(|&s| s != seq);
+                if seqs.is_empty() {
+                    self.waiting.remove(&user_data);
+                }
+            }
+            self.slot_mut(seq).state = SlotState::Skipped;
+        }
+        self.fill(first_seq, Err(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsio_aligned_bytes::AlignedBytesMut;
+    use lsio_io::Chunk;
+    use std::path::PathBuf;
+
+    fn chunk(user_data: u64) -> Result<Output, IoError> {
+        Ok(Output::Chunk(Chunk {
+            buffer: AlignedBytesMut::new(8, 8).freeze().unwrap(),
+            user_data,
+            range: None,
+        }))
+    }
+
+    fn user_data_of(output: Result<Output, IoError>) -> Option<u64> {
+        match output {
+            Ok(Output::Chunk(chunk)) => Some(chunk.user_data),
+            Ok(_) => None,
+            Err(err) => err.user_data(),
+        }
+    }
+
+    #[test]
+    fn test_outputs_are_sent_in_submission_order() {
+        let sequencer = Sequencer::new();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let path = PathBuf::from("/a");
+        sequencer.register(&path, &[0..1, 1..2, 2..3, 3..4], &[10, 11, 12, 13]);
+
+        sequencer.process(chunk(12), &tx);
+        sequencer.process(chunk(11), &tx);
+        assert!(rx.is_empty());
+        sequencer.process(chunk(10), &tx);
+        let received: Vec<_> = rx.try_iter().map(user_data_of).collect();
+        assert_eq!(received, [Some(10), Some(11), Some(12)]);
+
+        // Unregistered outputs aren't held back.
+        sequencer.process(chunk(99), &tx);
+        assert_eq!(
+            rx.try_iter().map(user_data_of).collect::<Vec<_>>(),
+            [Some(99)]
+        );
+
+        sequencer.process(chunk(13), &tx);
+        assert_eq!(
+            rx.try_iter().map(user_data_of).collect::<Vec<_>>(),
+            [Some(13)]
+        );
+        assert!(sequencer.pending.lock().unwrap().slots.is_empty());
+    }
+
+    #[test]
+    fn test_an_error_can_describe_several_ranges() {
+        let sequencer = Sequencer::new();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let path = PathBuf::from("/a");
+        sequencer.register(&path, &[0..10, 10..20, 20..30], &[0, 1, 2]);
+
+        // A read of the merged range `0..20` failed.
+        sequencer.process(chunk(2), &tx);
+        let err = IoError::ShortRead {
+            path: path.clone(),
+            range: 0..20,
+            got: 0,
+            wanted: 20,
+            file_size: None,
+            details: String::new(),
+        };
+        sequencer.process(Err(err), &tx);
+        let received: Vec<_> = rx.try_iter().collect();
+        assert_eq!(received.len(), 2);
+        assert!(matches!(received[0], Err(IoError::ShortRead { .. })));
+        assert!(matches!(&received[1], Ok(Output::Chunk(c)) if c.user_data == 2));
+    }
+}
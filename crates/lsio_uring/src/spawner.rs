@@ -0,0 +1,54 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+use lsio_threadpool::WorkerThread;
+
+use crate::operation::Operation;
+
+/// Pushes operations which are spawned by other operations (e.g. the `GetRange` operations
+/// spawned by `GetRanges`) onto the worker thread's queue, and counts each spawned operation as
+/// unfinished. The count is used by [`crate::IoUring::shutdown`] to wait for all operations to
+/// finish.
+///
+/// Operations on fixed files can only run on the worker thread which opened the file. So these
+/// operations are pushed onto the worker's queue of pinned operations, which other worker threads
+/// can't steal from.
+pub(crate) struct Spawner<'a> {
+    worker_thread: &'a WorkerThread<Operation>,
+    n_unfinished_ops: &'a AtomicUsize,
+    pinned_ops: &'a RefCell<VecDeque<Operation>>,
+}
+
+impl<'a> Spawner<'a> {
+    pub(crate) fn new(
+        worker_thread: &'a WorkerThread<Operation>,
+        n_unfinished_ops: &'a AtomicUsize,
+        pinned_ops: &'a RefCell<VecDeque<Operation>>,
+    ) -> Self {
+        Self {
+            worker_thread,
+            n_unfinished_ops,
+            pinned_ops,
+        }
+    }
+
+    pub(crate) fn push(&self, operation: Operation) {
+        // `Relaxed` is sufficient because the spawning operation is still unfinished, so the count
+        // can't reach zero until after the spawning operation's (`Release`) decrement.
+        self.n_unfinished_ops.fetch_add(1, Relaxed);
+        self.requeue(operation);
+    }
+
+    /// Push an operation which has already been counted as unfinished (e.g. an operation which
+    /// couldn't be submitted, and must be tried again later).
+    pub(crate) fn requeue(&self, operation: Operation) {
+        if operation.uses_fixed_file() {
+            self.pinned_ops.borrow_mut().push_back(operation);
+        } else {
+            self.worker_thread.push(operation);
+        }
+    }
+}
@@ -2,19 +2,47 @@ use io_uring::squeue;
 use io_uring::types;
 use lsio_aligned_bytes::AlignedBytes;
 use lsio_aligned_bytes::AlignedBytesMut;
+use lsio_aligned_bytes::AllocError;
+use lsio_aligned_bytes::BufferPool;
+use lsio_io::Advice;
+use lsio_io::SyncMode;
 use std::ffi::CString;
 use std::ops::Range;
 
+use crate::fixed_buffers::{self, FixedBuffers};
+use crate::open_file::FileDescriptor;
 use crate::open_file::OpenFile;
 use crate::open_file::OpenFileBuilder;
 use crate::user_data::UringUserData;
 
-const ALIGN: isize = 512; // TODO: Get ALIGN at runtime from statx.
+/// The alignment that we assume `O_DIRECT` requires when we don't know the file's alignment (when
+/// writing, or when the filesystem doesn't report its alignment to `statx`).
+const ALIGN: isize = 512;
+
+/// The alignment of reads with `O_DIRECT` when we didn't `statx` the file (so we don't know what
+/// alignment the filesystem requires). This is the page size on most systems, which satisfies the
+/// alignment requirements of almost every block device.
+const UNKNOWN_FILE_ALIGN: isize = 4096;
 
 /// # Documentation about the openat operation in io_uring:
 /// - https://man7.org/linux/man-pages/man2/openat.2.html
 /// - https://man7.org/linux/man-pages/man3/io_uring_prep_openat.3.html
-pub(crate) fn build_openat_sqe(index_of_op: usize, location: &CString) -> squeue::Entry {
+///
+/// If `fixed_file` is true then the file is opened into a free slot of the io_uring's table of
+/// registered files, and the CQE's result is the index of the slot (instead of a file
+/// descriptor). The CQE's result is `-ENFILE` if there are no free slots.
+///
+/// If `direct` is true then the file is opened with `O_DIRECT`, so every read must be aligned (see
+/// [`plan_read_range`]). `open_flags` are OR-ed with `O_RDONLY` (see
+/// [`crate::IoUringBuilder::open_flags`]).
+pub(crate) fn build_openat_sqe(
+    index_of_op: usize,
+    location: &CString,
+    fixed_file: bool,
+    direct: bool,
+    open_flags: libc::c_int,
+) -> squeue::Entry {
+    let flags = libc::O_RDONLY | open_flags | if direct { libc::O_DIRECT } else { 0 };
     // Prepare the "openat" submission queue entry (SQE):
     io_uring::opcode::OpenAt::new(
         // `dirfd` is ignored if the pathname is absolute.
@@ -22,11 +50,29 @@ pub(crate) fn build_openat_sqe(index_of_op: usize, location: &CString) -> squeue
         types::Fd(-1),
         location.as_ptr(),
     )
-    .flags(libc::O_RDONLY | libc::O_DIRECT)
+    .flags(flags)
+    .file_index(fixed_file.then(types::DestinationSlot::auto_target))
     .build()
     .user_data(UringUserData::new(index_of_op, io_uring::opcode::OpenAt::CODE).into())
 }
 
+/// Open `location` for writing, creating the file if necessary.
+///
+/// Only set `direct` to `true` if every write to this file will be aligned (see
+/// [`is_aligned_for_direct_io`]). Otherwise, writes will fail with `EINVAL`.
+pub(crate) fn build_openat_for_writing_sqe(
+    index_of_op: usize,
+    location: &CString,
+    direct: bool,
+) -> squeue::Entry {
+    let flags = libc::O_WRONLY | libc::O_CREAT | if direct { libc::O_DIRECT } else { 0 };
+    io_uring::opcode::OpenAt::new(types::Fd(-1), location.as_ptr())
+        .flags(flags)
+        .mode(0o644) // The permissions of the file, if the file is created.
+        .build()
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::OpenAt::CODE).into())
+}
+
 /// Build a `statx` submission queue entry (SQE).
 ///
 /// # Safety
@@ -51,83 +97,588 @@ pub(crate) fn build_statx_sqe(
     )
     // See here for a description of the flags for statx:
     // https://man7.org/linux/man-pages/man2/statx.2.html
-    .mask(libc::STATX_SIZE | libc::STATX_DIOALIGN)
+    // `STATX_MTIME` and `STATX_MODE` are only used by `Metadata`. Requesting them costs nothing,
+    // because they're part of the basic stats which every filesystem fills in anyway.
+    .mask(libc::STATX_SIZE | libc::STATX_DIOALIGN | libc::STATX_MTIME | libc::STATX_MODE)
     .build()
     .user_data(UringUserData::new(index_of_op, io_uring::opcode::Statx::CODE).into())
 }
 
-pub(crate) fn build_read_range_sqe(
-    index_of_op: usize,
+/// Returns `true` if `buffer` can be written to `range` using `O_DIRECT`. That is, if the range
+/// is not relative to the end of the file, and the range's offset, the range's length, and the
+/// buffer's address are all aligned.
+pub(crate) fn is_aligned_for_direct_io(range: &Range<isize>, buffer: &AlignedBytes) -> bool {
+    range.start >= 0
+        && range.end >= 0
+        && range.start % ALIGN == 0
+        && (range.len() as isize) % ALIGN == 0
+        && (buffer.as_ptr() as isize) % ALIGN == 0
+}
+
+/// Returns `true` if `buffer` might be read into using `O_DIRECT`. That is, if the buffer's address
+/// and length are aligned to the smallest alignment that any filesystem requires. (The file's
+/// actual alignment isn't known until the file has been `statx`ed, and may be larger.)
+pub(crate) fn is_buffer_aligned_for_direct_io(buffer: &AlignedBytes) -> bool {
+    (buffer.as_ptr() as isize) % ALIGN == 0 && (buffer.len() as isize) % ALIGN == 0
+}
+
+/// `read` will transfer at most this many bytes. See the NOTES section of
+/// https://man7.org/linux/man-pages/man2/read.2.html
+pub(crate) const MAX_READ_LEN: usize = 2_147_479_552;
+
+/// The arguments for a single `read` syscall: Read `len` bytes from `file_offset` into the memory
+/// starting at `addr`. Reads longer than [`MAX_READ_LEN`] are split into multiple `SubRead`s.
+///
+/// We store the address as a `u64` (like the SQE does) so that operations can be sent between
+/// threads.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SubRead {
+    pub(crate) addr: u64,
+    pub(crate) len: u32,
+    pub(crate) file_offset: u64,
+    /// The number of bytes that must be read to satisfy the user's range. This can be less than
+    /// `len` because the buffer is padded to a multiple of the alignment, and the kernel will
+    /// return fewer than `len` bytes when it hits the end of the file.
+    pub(crate) required_len: u32,
+    /// If `Some`, then `addr` is inside the registered buffer with this index, so the read can
+    /// use `ReadFixed`.
+    pub(crate) buf_index: Option<u16>,
+}
+
+impl SubRead {
+    /// Update `self` to describe the bytes which remain to be read after a read of `n_bytes`.
+    pub(crate) fn advance(&mut self, n_bytes: u32) {
+        assert!(n_bytes <= self.len);
+        self.addr += n_bytes as u64;
+        self.len -= n_bytes;
+        self.file_offset += n_bytes as u64;
+        self.required_len = self.required_len.saturating_sub(n_bytes);
+    }
+
+    /// Fill the bytes which remain to be read with zeros (instead of reading them), and update
+    /// `self` to describe an empty read. Used for the bytes beyond the end of the file. See
+    /// [`crate::IoUringBuilder::zero_fill_past_eof`].
+    ///
+    /// # Safety
+    /// `addr` must point to at least `required_len` writable bytes, which the kernel isn't reading
+    /// into.
+    pub(crate) unsafe fn fill_with_zeros(&mut self) {
+        std::ptr::write_bytes(self.addr as *mut u8, 0, self.required_len as usize);
+        self.advance(self.required_len);
+    }
+}
+
+/// Split a read of `len` bytes (from `file_offset` into `ptr`) into `SubRead`s of at most
+/// [`MAX_READ_LEN`] bytes. The first `required_len` bytes must be read to satisfy the user.
+fn split_read(
+    ptr: *mut u8,
+    len: usize,
+    file_offset: u64,
+    required_len: usize,
+    buf_index: Option<u16>,
+) -> Vec<SubRead> {
+    (0..len)
+        .step_by(MAX_READ_LEN)
+        .map(|start| {
+            let sub_read_len = MAX_READ_LEN.min(len - start);
+            SubRead {
+                addr: ptr as u64 + start as u64,
+                len: sub_read_len as u32,
+                file_offset: file_offset + start as u64,
+                required_len: required_len.saturating_sub(start).min(sub_read_len) as u32,
+                buf_index,
+            }
+        })
+        .collect()
+}
+
+/// The alignment (in bytes) of the file offset and length of each read from `file`. `O_DIRECT`
+/// requires aligned reads. Without `O_DIRECT`, reads don't need to be aligned.
+fn read_align(file: &OpenFile) -> isize {
+    direct_io_align(file, file.offset_alignment())
+}
+
+/// The alignment (in bytes) of the address of the buffer of each read from `file`.
+fn mem_align(file: &OpenFile) -> isize {
+    direct_io_align(file, file.mem_alignment())
+}
+
+/// The alignment that `O_DIRECT` requires, given the `alignment` reported by `statx` (if any).
+fn direct_io_align(file: &OpenFile, alignment: Option<u32>) -> isize {
+    match (file.is_direct_io(), alignment) {
+        (false, _) => 1,
+        (true, None) => UNKNOWN_FILE_ALIGN,
+        // `statx` reports an alignment of 0 if the filesystem doesn't support `STATX_DIOALIGN`.
+        (true, Some(0)) => ALIGN,
+        (true, Some(alignment)) => alignment.try_into().unwrap(),
+    }
+}
+
+/// Returns `true` if `resolved_range` of `file` can be read into exactly `resolved_range.len()`
+/// bytes of `destination` by a vectored read. That is, if `file` wasn't opened with `O_DIRECT`, or
+/// if the range's offset, the range's length, and the destination's address are all aligned.
+pub(crate) fn can_read_vectored_into(
+    file: &OpenFile,
+    resolved_range: &Range<isize>,
+    destination: &AlignedBytes,
+) -> bool {
+    let read_align = read_align(file);
+    resolved_range.start % read_align == 0
+        && (resolved_range.len() as isize) % read_align == 0
+        && (destination.as_ptr() as isize) % mem_align(file) == 0
+}
+
+/// Allocate a buffer for reading `range` from `file`, and plan the `SubRead`s. If `fixed_buffers`
+/// has a free buffer which is large enough then we read into that buffer (using `ReadFixed`)
+/// instead of allocating a new buffer. Otherwise, if the read is at least `huge_pages_threshold`
+/// bytes, then we allocate the buffer from huge pages. Otherwise, if there's a `buffer_pool`, then
+/// we take a (possibly recycled) buffer from the pool.
+///
+/// Returns the `SubRead`s, and the buffer (sliced to the `range` requested by the user). Returns an
+/// error if the buffer can't be allocated (e.g. because `range` is larger than the free memory).
+pub(crate) fn plan_read_range(
     file: &OpenFile,
     range: &Range<isize>,
-) -> (squeue::Entry, AlignedBytes) {
-    let filesize: isize = file.size().try_into().unwrap();
-    let start_offset = if range.start >= 0 {
-        range.start
-    } else {
-        // `range.start` is negative. We interpret a negative `range.start`
-        // as an offset from the end of the file.
-        filesize + range.start
+    fixed_buffers: Option<&FixedBuffers>,
+    buffer_pool: Option<&BufferPool>,
+    huge_pages_threshold: Option<usize>,
+) -> Result<(Vec<SubRead>, AlignedBytes), AllocError> {
+    let Range {
+        start: start_offset,
+        end: end_offset,
+    } = file.resolve_range(range);
+
+    // `O_DIRECT` requires that the file offset and the length of each read are aligned. So we
+    // read from the aligned offset at or before `start_offset`, up to the aligned offset at or
+    // after `end_offset`. The kernel stops reading at the end of the file, so it's fine if the
+    // aligned end is beyond the end of the file. Without `O_DIRECT`, we read exactly the range.
+    // The buffer's address must be aligned too, but the filesystem may require a different
+    // alignment for the buffer than for the file offset.
+    let read_align = read_align(file);
+    let buffer_align: usize = mem_align(file).max(ALIGN).try_into().unwrap();
+    let aligned_start_offset = (start_offset / read_align) * read_align;
+    let required_len: usize = (end_offset - aligned_start_offset).try_into().unwrap();
+    assert!(required_len > 0);
+    let read_len = required_len.next_multiple_of(read_align as usize);
+    // Fixed buffers can only be used if they're sufficiently aligned.
+    let fixed_buffer = fixed_buffers
+        .filter(|_| buffer_align <= fixed_buffers::ALIGN)
+        .and_then(|fixed_buffers| fixed_buffers.take(read_len));
+    let (mut buffer, buf_index) = match fixed_buffer {
+        Some((buf_index, buffer)) => (buffer, Some(buf_index)),
+        None => {
+            // Huge pages are aligned to `HUGE_PAGE_SIZE`, which is far larger than `buffer_align`.
+            let use_huge_pages = huge_pages_threshold.is_some_and(|t| required_len >= t);
+            let buffer = match buffer_pool {
+                _ if use_huge_pages => AlignedBytesMut::try_new_huge(read_len)?,
+                Some(buffer_pool) => buffer_pool.try_get(read_len, buffer_align)?,
+                None => AlignedBytesMut::try_new(read_len, buffer_align)?,
+            };
+            let capacity = buffer.capacity();
+            let mut buffer = buffer.freeze().unwrap();
+            buffer.set_slice(0..capacity);
+            (buffer, None)
+        }
     };
-    assert!(start_offset >= 0);
-
-    let end_offset = if range.end >= 0 {
-        range.end
-    } else {
-        // `range.end` is negative. We interpret a negative `range.end`
-        // as an offset from the end of the file, where `range.end = -1` means the last byte.
-        filesize + range.end + 1
+
+    // `buffer` is either newly allocated (or recycled), or a fixed buffer which nothing else is
+    // using. So we can give the kernel a mutable pointer into `buffer`. Fixed buffers and pooled
+    // buffers can be much longer than `required_len`, so we only read `required_len` rounded up to
+    // a multiple of `read_align` (so that the length of the read is aligned).
+    assert!(read_len <= buffer.len());
+    let sub_reads = split_read(
+        buffer.as_ptr() as *mut u8,
+        read_len,
+        aligned_start_offset as u64,
+        required_len,
+        buf_index,
+    );
+
+    // Set the slice to the slice requested by the user. If the `start_offset` is not aligned, then
+    // the start of the buffer will contain data that the user did not request.
+    let start_slice: usize = (start_offset - aligned_start_offset).try_into().unwrap();
+    buffer.set_slice(start_slice..required_len);
+
+    Ok((sub_reads, buffer))
+}
+
+/// Plan the `SubRead`s for reading `range` into `destination`, which has been provided by the
+/// caller. The whole of `destination` is passed to the kernel (so `destination` must be aligned
+/// if `file` was opened with `O_DIRECT`). The returned buffer is `destination`, sliced to the
+/// length of the `range`.
+///
+/// # Panics
+/// If `destination` is shorter than `range`.
+pub(crate) fn plan_read_range_into(
+    file: &OpenFile,
+    range: &Range<isize>,
+    mut destination: AlignedBytes,
+) -> (Vec<SubRead>, AlignedBytes) {
+    let Range {
+        start: start_offset,
+        end: end_offset,
+    } = file.resolve_range(range);
+    let len: usize = (end_offset - start_offset).try_into().unwrap();
+    assert!(len <= destination.len());
+
+    // `destination` is the only view of its underlying buffer, and the user won't get access to
+    // `destination` again until the kernel has finished writing into it.
+    let sub_reads = split_read(
+        destination.as_ptr() as *mut u8,
+        destination.len(),
+        start_offset as u64,
+        len,
+        None,
+    );
+
+    destination.set_slice(0..len);
+    (sub_reads, destination)
+}
+
+/// Build the SQE for one `SubRead`. `sub_index` identifies the `SubRead` within its operation.
+/// Reads into registered buffers use `ReadFixed`.
+pub(crate) fn build_sub_read_sqe(
+    index_of_op: usize,
+    sub_index: u16,
+    file: &OpenFile,
+    sub_read: &SubRead,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    let addr = sub_read.addr as *mut u8;
+    let (entry, opcode) = match sub_read.buf_index {
+        None => (
+            io_uring::opcode::Read::new(fd, addr, sub_read.len)
+                .offset(sub_read.file_offset)
+                .build(),
+            io_uring::opcode::Read::CODE,
+        ),
+        Some(buf_index) => (
+            io_uring::opcode::ReadFixed::new(fd, addr, sub_read.len, buf_index)
+                .offset(sub_read.file_offset)
+                .build(),
+            io_uring::opcode::ReadFixed::CODE,
+        ),
     };
-    assert!(end_offset >= 0);
+    entry
+        .flags(flags)
+        .user_data(UringUserData::new_with_sub_index(index_of_op, sub_index, opcode).into())
+}
 
-    let aligned_start_offset = (start_offset / ALIGN) * ALIGN;
+/// Build a `read` SQE which reads up to `len` bytes into `addr` from the current position of
+/// `file`. Pipes and sockets don't have offsets, so this is how we read streams. The CQE's result
+/// is the number of bytes read, which is zero at the end of the stream.
+pub(crate) fn build_stream_read_sqe(
+    index_of_op: usize,
+    file: &OpenFile,
+    addr: *mut u8,
+    len: u32,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    io_uring::opcode::Read::new(fd, addr, len)
+        // An offset of -1 means "read from the file's current position".
+        .offset(u64::MAX)
+        .build()
+        .flags(flags)
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Read::CODE).into())
+}
 
-    let mut buffer;
-    {
-        let buf_len = end_offset - aligned_start_offset;
-        assert!(buf_len > 0);
+/// Build a `readv` SQE, which reads the bytes starting at `file_offset` into each of `iovecs` in
+/// turn. The kernel may read fewer bytes than requested (e.g. at the end of the file), in which
+/// case the CQE's result is the number of bytes read.
+///
+/// # Safety
+/// `iovecs`, and the buffers that `iovecs` point to, must stay alive (and must not move) until the
+/// CQE for this SQE has been received. (The kernel may read `iovecs` after the SQE has been
+/// submitted.) Nothing else may read or write the buffers until then.
+///
+/// # Documentation about the `readv` operation:
+/// - https://man7.org/linux/man-pages/man2/readv.2.html
+/// - https://man7.org/linux/man-pages/man3/io_uring_prep_readv.3.html
+pub(crate) fn build_readv_sqe(
+    index_of_op: usize,
+    file: &OpenFile,
+    iovecs: &[libc::iovec],
+    file_offset: u64,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    io_uring::opcode::Readv::new(fd, iovecs.as_ptr(), iovecs.len().try_into().unwrap())
+        .offset(file_offset)
+        .build()
+        .flags(flags)
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Readv::CODE).into())
+}
 
-        // Allocate vector. If `buf_len` is not exactly divisible by ALIGN, then
-        // `AlignedBytesMut::new` will extend the length until it is aligned.
-        buffer = AlignedBytesMut::new(buf_len as usize, ALIGN.try_into().unwrap());
-        // From now on, use `buffer.len()` as the correct length!
-        // This code is in its own scope so that `buf_len` cannot be used in subsequent code.
-    }
+/// Build a `LinkTimeout` SQE, which cancels the preceding SQE (which must have the `IO_LINK` flag)
+/// if it hasn't completed within `timespec`. The CQE's result is `-ETIME` if the timeout fired, or
+/// `-ECANCELED` if the linked SQE completed first.
+///
+/// # Safety
+/// `timespec` must stay alive until the SQE has been submitted.
+pub(crate) fn build_link_timeout_sqe(
+    index_of_op: usize,
+    sub_index: u16,
+    timespec: &types::Timespec,
+) -> squeue::Entry {
+    io_uring::opcode::LinkTimeout::new(timespec)
+        .build()
+        .user_data(
+            UringUserData::new_with_sub_index(
+                index_of_op,
+                sub_index,
+                io_uring::opcode::LinkTimeout::CODE,
+            )
+            .into(),
+        )
+}
 
-    // Prepare the "read" opcode:
-    let read_op = io_uring::opcode::Read::new(
-        *file.file_descriptor(),
-        buffer.as_mut_ptr(),
-        buffer.len().try_into().unwrap(),
+/// Build a `Timeout` SQE which completes (with `-ETIME`) once `timespec` has elapsed. Used to wait
+/// before retrying the `SubRead` identified by `sub_index`. `timespec` must stay alive (and must
+/// not move) until the SQE's CQE has arrived.
+pub(crate) fn build_timeout_sqe(
+    index_of_op: usize,
+    sub_index: u16,
+    timespec: &types::Timespec,
+) -> squeue::Entry {
+    io_uring::opcode::Timeout::new(timespec).build().user_data(
+        UringUserData::new_with_sub_index(index_of_op, sub_index, io_uring::opcode::Timeout::CODE)
+            .into(),
     )
-    .offset(aligned_start_offset as _)
-    .build()
-    .user_data(UringUserData::new(index_of_op, io_uring::opcode::Read::CODE).into());
-
-    // If the `start_offset` is not aligned, then the start of the buffer will contain data that
-    // the user did not request.
-    if aligned_start_offset != start_offset {
-        _ = buffer
-            .split_to((start_offset - aligned_start_offset).try_into().unwrap())
-            .unwrap();
-    }
+}
 
-    // `freeze` the buffer, and set the slice to the slice requested by the user:
-    let start_slice: usize = (start_offset - aligned_start_offset).try_into().unwrap();
-    let end_slice: usize = (end_offset - aligned_start_offset).try_into().unwrap();
-    let mut buffer = buffer.freeze().unwrap();
-    buffer.set_slice(start_slice..end_slice);
+/// Write all of `buffer` into `file`, starting at byte `offset`.
+///
+/// # Safety
+/// `buffer` must stay alive until the CQE for this SQE has been received.
+///
+/// # Documentation about the `write` operation:
+/// - https://man7.org/linux/man-pages/man2/pwrite.2.html
+/// - https://man7.org/linux/man-pages/man3/io_uring_prep_write.3.html
+pub(crate) fn build_write_sqe(
+    index_of_op: usize,
+    file: &OpenFile,
+    buffer: &AlignedBytes,
+    offset: u64,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    io_uring::opcode::Write::new(fd, buffer.as_ptr(), buffer.len().try_into().unwrap())
+        .offset(offset)
+        .build()
+        .flags(flags)
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Write::CODE).into())
+}
+
+/// A `nop` SQE does nothing, except produce a CQE.
+///
+/// # Documentation about the `nop` operation:
+/// - https://man7.org/linux/man-pages/man3/io_uring_prep_nop.3.html
+pub(crate) fn build_nop_sqe(index_of_op: usize) -> squeue::Entry {
+    io_uring::opcode::Nop::new()
+        .build()
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Nop::CODE).into())
+}
 
-    (read_op, buffer)
+/// # Documentation about the `fadvise` operation:
+/// - https://man7.org/linux/man-pages/man2/posix_fadvise.2.html
+/// - https://man7.org/linux/man-pages/man3/io_uring_prep_fadvise.3.html
+pub(crate) fn build_fadvise_sqe(
+    index_of_op: usize,
+    file: &OpenFile,
+    offset: i64,
+    len: i64,
+    advice: Advice,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    let advice = nix::fcntl::PosixFadviseAdvice::from(advice) as i32;
+    io_uring::opcode::Fadvise::new(fd, len, advice)
+        .offset(offset.try_into().unwrap())
+        .build()
+        .flags(flags)
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Fadvise::CODE).into())
+}
+
+/// # Documentation about the `fsync` operation:
+/// - https://man7.org/linux/man-pages/man2/fsync.2.html
+/// - https://man7.org/linux/man-pages/man3/io_uring_prep_fsync.3.html
+pub(crate) fn build_fsync_sqe(
+    index_of_op: usize,
+    file: &OpenFile,
+    mode: SyncMode,
+) -> squeue::Entry {
+    let (fd, flags) = file.file_descriptor().fd_and_flags();
+    let fsync_flags = match mode {
+        SyncMode::All => types::FsyncFlags::empty(),
+        SyncMode::Data => types::FsyncFlags::DATASYNC,
+    };
+    io_uring::opcode::Fsync::new(fd)
+        .flags(fsync_flags)
+        .build()
+        .flags(flags)
+        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Fsync::CODE).into())
 }
 
 /// # Documentation about the `close` operation:
 /// - https://man7.org/linux/man-pages/man2/close.2.html
 pub(crate) fn build_close_sqe(
     index_of_op: usize,
-    file_descriptor: io_uring::types::Fd,
+    file_descriptor: FileDescriptor,
 ) -> squeue::Entry {
-    io_uring::opcode::Close::new(file_descriptor)
-        .build()
-        .user_data(UringUserData::new(index_of_op, io_uring::opcode::Close::CODE).into())
+    match file_descriptor {
+        FileDescriptor::Fd(fd) => io_uring::opcode::Close::new(fd),
+        // Closing a fixed file frees its slot in the table of registered files.
+        FileDescriptor::Fixed(fixed) => io_uring::opcode::Close::new(fixed),
+    }
+    .build()
+    .user_data(UringUserData::new(index_of_op, io_uring::opcode::Close::CODE).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_size_cache::FileSize;
+
+    #[test]
+    fn test_split_read() {
+        const PTR: usize = 4096;
+        const FILE_OFFSET: u64 = 512;
+
+        // Reads of up to `MAX_READ_LEN` bytes aren't split:
+        let sub_reads = split_read(
+            PTR as *mut u8,
+            MAX_READ_LEN,
+            FILE_OFFSET,
+            MAX_READ_LEN,
+            None,
+        );
+        assert_eq!(sub_reads.len(), 1);
+        assert_eq!(sub_reads[0].len as usize, MAX_READ_LEN);
+
+        // Larger reads are split into consecutive `SubRead`s:
+        let len = (MAX_READ_LEN * 2) + 10;
+        let sub_reads = split_read(PTR as *mut u8, len, FILE_OFFSET, len - 20, None);
+        assert_eq!(sub_reads.len(), 3);
+        for (i, sub_read) in sub_reads.iter().enumerate() {
+            let start = i * MAX_READ_LEN;
+            assert_eq!(sub_read.addr, (PTR + start) as u64);
+            assert_eq!(sub_read.file_offset, FILE_OFFSET + start as u64);
+        }
+        assert_eq!(sub_reads[2].len, 10);
+        let total_len: usize = sub_reads.iter().map(|sub_read| sub_read.len as usize).sum();
+        assert_eq!(total_len, len);
+
+        // The last 20 bytes aren't required, so they span the last two `SubRead`s:
+        assert_eq!(sub_reads[0].required_len as usize, MAX_READ_LEN);
+        assert_eq!(sub_reads[1].required_len as usize, MAX_READ_LEN - 10);
+        assert_eq!(sub_reads[2].required_len, 0);
+    }
+
+    #[test]
+    fn test_sub_read_advance() {
+        let mut sub_read = SubRead {
+            addr: 4096,
+            len: 1024,
+            file_offset: 512,
+            required_len: 1000,
+            buf_index: None,
+        };
+        sub_read.advance(600);
+        assert_eq!(sub_read.addr, 4096 + 600);
+        assert_eq!(sub_read.len, 1024 - 600);
+        assert_eq!(sub_read.file_offset, 512 + 600);
+        assert_eq!(sub_read.required_len, 400);
+        sub_read.advance(424);
+        assert_eq!(sub_read.len, 0);
+        assert_eq!(sub_read.required_len, 0);
+    }
+
+    #[test]
+    fn test_plan_read_range_uses_file_alignment() {
+        let open_file = |alignment: u32| {
+            let mut builder = OpenFileBuilder::new(std::sync::Arc::new(CString::new("f").unwrap()));
+            builder.set_file_descriptor(types::Fd(-1));
+            builder.set_file_size(FileSize {
+                size: 10_000,
+                mem_alignment: alignment,
+                offset_alignment: alignment,
+            });
+            builder.build()
+        };
+
+        for (alignment, expected_align) in [(4096, 4096), (0, ALIGN as usize)] {
+            let (sub_reads, buffer) =
+                plan_read_range(&open_file(alignment), &(5000..6000), None, None, None).unwrap();
+            assert_eq!(sub_reads.len(), 1);
+            let sub_read = &sub_reads[0];
+            let aligned_start = (5000 / expected_align) * expected_align;
+            assert_eq!(sub_read.file_offset, aligned_start as u64);
+            assert_eq!(sub_read.len as usize % expected_align, 0);
+            assert_eq!(sub_read.required_len as usize, 6000 - aligned_start);
+            assert_eq!(sub_read.addr as usize % expected_align, 0);
+            assert_eq!(buffer.len(), 1000);
+            assert_eq!(
+                buffer.as_ptr() as usize - sub_read.addr as usize,
+                5000 - aligned_start
+            );
+        }
+
+        // Without `O_DIRECT`, we read exactly the requested range:
+        let mut builder = OpenFileBuilder::new(std::sync::Arc::new(CString::new("f").unwrap()));
+        builder.set_direct_io(false);
+        builder.set_file_descriptor(types::Fd(-1));
+        builder.set_file_size(FileSize {
+            size: 10_000,
+            mem_alignment: 4096,
+            offset_alignment: 4096,
+        });
+        let (sub_reads, _) =
+            plan_read_range(&builder.build(), &(5000..6000), None, None, None).unwrap();
+        assert_eq!(sub_reads[0].file_offset, 5000);
+        assert_eq!(sub_reads[0].len, 1000);
+    }
+
+    #[test]
+    fn test_plan_read_range_with_different_mem_and_offset_alignments() {
+        let open_file = |mem_alignment: u32, offset_alignment: u32| {
+            let mut builder = OpenFileBuilder::new(std::sync::Arc::new(CString::new("f").unwrap()));
+            builder.set_file_descriptor(types::Fd(-1));
+            builder.set_file_size(FileSize {
+                size: 100_000,
+                mem_alignment,
+                offset_alignment,
+            });
+            builder.build()
+        };
+
+        // (mem_alignment, offset_alignment, expected file offset, expected read length)
+        for (mem_alignment, offset_alignment, file_offset, len) in
+            [(512, 8192, 0, 8192), (8192, 512, 4608, 1536)]
+        {
+            let file = open_file(mem_alignment, offset_alignment);
+            let (sub_reads, buffer) =
+                plan_read_range(&file, &(5000..6000), None, None, None).unwrap();
+            assert_eq!(sub_reads.len(), 1);
+            let sub_read = &sub_reads[0];
+            assert_eq!(sub_read.file_offset, file_offset);
+            assert_eq!(sub_read.len, len);
+            assert_eq!(sub_read.addr % mem_alignment as u64, 0);
+            assert_eq!(buffer.len(), 1000);
+            assert_eq!(buffer.as_ptr() as u64 - sub_read.addr, 5000 - file_offset);
+
+            // The destination of a vectored read must be aligned to `mem_alignment`, and the
+            // range must be aligned to `offset_alignment`.
+            let offset_align = offset_alignment as isize;
+            let destination = AlignedBytesMut::new(offset_alignment as usize, 8192)
+                .freeze()
+                .unwrap();
+            assert!(can_read_vectored_into(
+                &file,
+                &(offset_align..offset_align * 2),
+                &destination
+            ));
+            assert!(!can_read_vectored_into(
+                &file,
+                &(offset_align / 2..offset_align * 2),
+                &destination
+            ));
+        }
+    }
 }
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+
+/// Statistics about a single worker thread. Each worker thread updates its own `WorkerStats`, and
+/// the user can read the statistics at any time via [`crate::IoUring::worker_stats`].
+///
+/// The counters are updated with `Relaxed` ordering (to keep the worker's hot loop fast), so the
+/// counters are only approximately consistent with each other.
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    sqes_submitted: AtomicU64,
+    cqes_processed: AtomicU64,
+    ops_in_flight: AtomicUsize,
+    fixed_buffer_reads: AtomicU64,
+}
+
+impl WorkerStats {
+    /// The total number of submission queue entries (SQEs) that this worker has pushed onto its
+    /// submission queue (SQ).
+    pub fn sqes_submitted(&self) -> u64 {
+        self.sqes_submitted.load(Relaxed)
+    }
+
+    /// The total number of completion queue entries (CQEs) that this worker has processed.
+    pub fn cqes_processed(&self) -> u64 {
+        self.cqes_processed.load(Relaxed)
+    }
+
+    /// The number of operations that this worker is currently tracking.
+    pub fn ops_in_flight(&self) -> usize {
+        self.ops_in_flight.load(Relaxed)
+    }
+
+    /// The total number of reads into registered buffers (`ReadFixed`) that this worker has
+    /// completed. See [`crate::IoUringBuilder::fixed_buffers`].
+    pub fn fixed_buffer_reads(&self) -> u64 {
+        self.fixed_buffer_reads.load(Relaxed)
+    }
+
+    pub(crate) fn add_sqes_submitted(&self, n: usize) {
+        self.sqes_submitted.fetch_add(n as u64, Relaxed);
+    }
+
+    pub(crate) fn add_cqe_processed(&self) {
+        self.cqes_processed.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn add_fixed_buffer_read(&self) {
+        self.fixed_buffer_reads.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn set_ops_in_flight(&self, n: usize) {
+        self.ops_in_flight.store(n, Relaxed);
+    }
+}
@@ -0,0 +1,27 @@
+/// Emit a `TRACE`-level `tracing` event named `$name`, with each `$field` recorded using its
+/// `Debug` implementation. For example:
+///
+/// ```ignore
+/// trace_event!("read done", path = self.file.path(), cqe_result = cqe_result);
+/// ```
+///
+/// If the `tracing` feature is disabled then this compiles to nothing: The field values are never
+/// evaluated (they're only type-checked, so that variables which are only used by events don't
+/// trigger "unused variable" warnings).
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        tracing::trace!($($field = ?$value,)* $name)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        if false {
+            $(let _ = &$value;)*
+        }
+    };
+}
+
+pub(crate) use trace_event;
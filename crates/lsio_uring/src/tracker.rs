@@ -35,6 +35,18 @@ impl<T> Tracker<T> {
         }
     }
 
+    /// Iterate over the tracked operations, and their indices.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.ops_in_flight
+            .iter()
+            .enumerate()
+            .filter_map(|(index, op)| Some((index, op.as_ref()?)))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
     pub(crate) fn is_empty(&self) -> bool {
         self.len == 0
     }
@@ -42,6 +54,11 @@ impl<T> Tracker<T> {
     pub(crate) fn is_full(&self) -> bool {
         self.next_index.is_empty()
     }
+
+    /// The number of operations that can be added before the tracker is full.
+    pub(crate) fn capacity_remaining(&self) -> usize {
+        self.next_index.len()
+    }
 }
 
 pub(crate) struct TrackerGuard<'a, T> {
@@ -106,6 +123,38 @@ mod tests {
         assert_eq!(tracker.get(i2).unwrap().remove(), s2);
     }
 
+    #[test]
+    fn test_len_and_capacity_remaining() {
+        let mut tracker = Tracker::new(3);
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (0, 3));
+
+        let i0 = tracker.get_next_index().unwrap();
+        tracker.put(i0, 0);
+        let i1 = tracker.get_next_index().unwrap();
+        tracker.put(i1, 1);
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (2, 1));
+
+        tracker.get(i0).unwrap().remove();
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (1, 2));
+
+        // Replacing an op doesn't change the counts.
+        tracker.get(i1).unwrap().replace(10);
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (1, 2));
+
+        for i in 2..4 {
+            let index = tracker.get_next_index().unwrap();
+            tracker.put(index, i);
+        }
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (3, 0));
+        assert!(tracker.is_full());
+
+        for index in 0..3 {
+            tracker.get(index).unwrap().remove();
+        }
+        assert_eq!((tracker.len(), tracker.capacity_remaining()), (0, 3));
+        assert!(tracker.is_empty());
+    }
+
     #[test]
     #[should_panic(expected = "index out of bounds")]
     fn test_panic_if_wrong_index() {
@@ -1,17 +1,26 @@
 use crate::opcode::OpCode;
 
 /// The u64 io_uring user_data represents the index_of_op in the left-most 32 bits,
-/// and represents the io_uring opcode CODE in the right-most 32 bits.
+/// a `sub_index` in bits 16 to 31, and the io_uring opcode CODE in the right-most 8 bits.
+///
+/// The `sub_index` allows a single operation to distinguish between multiple SQEs which share the
+/// same opcode (e.g. when an operation opens two files).
 #[derive(Debug)]
 pub(crate) struct UringUserData {
     index_of_op: u32,
+    sub_index: u16,
     op: OpCode,
 }
 
 impl UringUserData {
     pub(crate) fn new(index_of_op: usize, op: u8) -> Self {
+        Self::new_with_sub_index(index_of_op, 0, op)
+    }
+
+    pub(crate) fn new_with_sub_index(index_of_op: usize, sub_index: u16, op: u8) -> Self {
         Self {
             index_of_op: index_of_op.try_into().unwrap(),
+            sub_index,
             op: OpCode::new(op),
         }
     }
@@ -20,6 +29,10 @@ impl UringUserData {
         self.index_of_op
     }
 
+    pub(crate) const fn sub_index(&self) -> u16 {
+        self.sub_index
+    }
+
     pub(crate) const fn opcode(&self) -> &OpCode {
         &self.op
     }
@@ -28,15 +41,21 @@ impl UringUserData {
 impl From<u64> for UringUserData {
     fn from(value: u64) -> Self {
         let index_of_op: u32 = (value >> 32).try_into().unwrap();
+        let sub_index: u16 = ((value >> 16) & 0xFFFF).try_into().unwrap();
         let op = OpCode::new((value & 0xFF).try_into().unwrap());
-        Self { index_of_op, op }
+        Self {
+            index_of_op,
+            sub_index,
+            op,
+        }
     }
 }
 
 impl Into<u64> for UringUserData {
     fn into(self) -> u64 {
         let index_of_op: u64 = (self.index_of_op as u64) << 32;
-        index_of_op | self.op.value() as u64
+        let sub_index: u64 = (self.sub_index as u64) << 16;
+        index_of_op | sub_index | self.op.value() as u64
     }
 }
 
@@ -52,6 +71,20 @@ mod tests {
         let user_data_u64: u64 = uring_user_data.into();
         let uring_user_data = UringUserData::from(user_data_u64);
         assert_eq!(uring_user_data.index_of_op, INDEX as u32);
+        assert_eq!(uring_user_data.sub_index, 0);
+        assert_eq!(uring_user_data.op, OpCode::new(OPCODE));
+    }
+
+    #[test]
+    fn test_uring_user_data_round_trip_with_sub_index() {
+        const INDEX: usize = u32::MAX as usize;
+        const SUB_INDEX: u16 = u16::MAX;
+        const OPCODE: u8 = io_uring::opcode::OpenAt::CODE;
+        let uring_user_data = UringUserData::new_with_sub_index(INDEX, SUB_INDEX, OPCODE);
+        let user_data_u64: u64 = uring_user_data.into();
+        let uring_user_data = UringUserData::from(user_data_u64);
+        assert_eq!(uring_user_data.index_of_op, INDEX as u32);
+        assert_eq!(uring_user_data.sub_index, SUB_INDEX);
         assert_eq!(uring_user_data.op, OpCode::new(OPCODE));
     }
 }
@@ -1,9 +1,26 @@
-use io_uring::{cqueue, squeue};
-use lsio_io::Output;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Release},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use io_uring::{cqueue, squeue, types};
+use lsio_io::{IoError, Output};
 use lsio_threadpool::WorkerThread;
 
 use crate::{
+    config::{Config, SqPoll, WaitStrategy},
+    fixed_buffers::FixedBuffers,
+    groups::Groups,
+    open_file_limit::OpenFileLimit,
     operation::{NextStep, Operation, UringOperation},
+    spawner::Spawner,
+    stats::WorkerStats,
     tracker::Tracker,
     user_data::UringUserData,
 };
@@ -11,117 +28,475 @@ use crate::{
 /// `MAX_SQ_ENTRIES_PER_ITERATION` describes the most SQEs that will be submitted to the io_uring SQ by
 /// a single iteration of the `run` loop. This constant is used to make sure we have enough
 /// headroom in the SQ before each iteration of the `run` loop.
-const MAX_SQ_ENTRIES_PER_ITERATION: usize = 2;
+/// (`CopyRanges` submits 4 SQEs: `openat` and `statx` for both the source and destination files.
+/// A `GetRange` with a timeout submits up to 4 SQEs: Two `read`s, each linked to a `LinkTimeout`.)
+const MAX_SQ_ENTRIES_PER_ITERATION: usize = 4;
 
 /// Size of the io_uring submission queue (SQ).
-const SQ_RING_SIZE: usize = 64;
+pub(crate) const SQ_RING_SIZE: usize = 64;
 
 /// We keep filling the SQ until we hit the "high water line" before we start draining the
 /// completion queue. This ensures that we allow io_uring to process as many operations in parallel
 /// as possible.
 const HIGH_WATER_LINE: usize = SQ_RING_SIZE / 2;
 
+/// The maximum time that an SQE may sit in the SQ before we force a `submit()`. Whilst we're
+/// "topping up" the SQ, we batch SQEs into a single `submit()` to reduce the number of syscalls.
+/// `MAX_SUBMIT_DELAY` bounds the latency that this batching can add.
+const MAX_SUBMIT_DELAY: Duration = Duration::from_micros(100);
+
+/// The number of slots in each worker's table of registered files (if fixed files are enabled).
+/// When every slot is in use, files are opened with normal file descriptors. The kernel refuses to
+/// register more slots than `RLIMIT_NOFILE`, which is often 1,024.
+const MAX_FILES_TO_REGISTER: u32 = 256;
+
+/// How long to sleep for when the user isn't consuming outputs fast enough, and we have no
+/// operations in flight.
+const BACKPRESSURE_SLEEP: Duration = Duration::from_micros(100);
+
+/// The `user_data` of the `AsyncCancel` SQEs which cancel streams when the `IoUring` is dropped.
+/// Their CQEs aren't associated with any operation, so they're ignored.
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// The longest that `WaitStrategy::Block` (and `WaitStrategy::Hybrid`) block in the kernel whilst
+/// waiting for a CQE. Whilst a worker is blocked, it can't pick up new operations, so this bounds
+/// how long a new operation may wait (e.g. behind a stream which is waiting for data).
+const BLOCK_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// How long `WaitStrategy::Hybrid` polls the CQ before blocking.
+const HYBRID_SPIN_DURATION: Duration = Duration::from_micros(50);
+
 pub struct UringWorker {
     uring: io_uring::IoUring,
+    /// The buffers registered with `uring` (if any). Declared after `uring` so that the buffers
+    /// outlive `uring`.
+    _fixed_buffers: Option<Arc<FixedBuffers>>,
     ops_in_flight: Tracker<Operation>,
     worker_thread: WorkerThread<Operation>,
-    output_tx: crossbeam_channel::Sender<anyhow::Result<Output>>,
+    output_tx: crossbeam_channel::Sender<Result<Output, IoError>>,
+    groups: Arc<Groups>,
+    open_file_limit: Arc<OpenFileLimit>,
+    /// Don't start new operations whilst `output_tx` holds at least this many outputs.
+    output_high_water_mark: usize,
+    stats: Arc<WorkerStats>,
+    /// The number of operations (across all workers) which have been submitted but haven't
+    /// finished. See [`crate::IoUring::shutdown`].
+    n_unfinished_ops: Arc<AtomicUsize>,
+    /// Operations on fixed files which were opened by `uring`. These operations can't run on any
+    /// other worker thread, so they're kept out of `worker_thread`'s queues (which can be stolen
+    /// from). See [`Spawner`].
+    pinned_ops: RefCell<VecDeque<Operation>>,
+
+    /// The time at which the oldest un-submitted SQE was pushed onto the SQ.
+    /// `None` if there are no un-submitted SQEs.
+    oldest_unsubmitted_sqe: Option<Instant>,
+
+    wait_strategy: WaitStrategy,
+    /// The number of consecutive polls of the CQ which found no CQEs.
+    n_empty_polls: u32,
+    /// The time of the first of those polls. `None` if the last poll found CQEs.
+    first_empty_poll: Option<Instant>,
 }
 
 impl UringWorker {
+    #[allow(clippy::too_many_arguments)] // Each argument is state shared with the `IoUring`.
     pub(crate) fn new(
         worker_thread: WorkerThread<Operation>,
-        output_tx: crossbeam_channel::Sender<anyhow::Result<Output>>,
+        output_tx: crossbeam_channel::Sender<Result<Output, IoError>>,
+        groups: Arc<Groups>,
+        open_file_limit: Arc<OpenFileLimit>,
+        stats: Arc<WorkerStats>,
+        n_unfinished_ops: Arc<AtomicUsize>,
+        fixed_buffers: Option<Arc<FixedBuffers>>,
+        config: &Config,
     ) -> Self {
         assert!(MAX_SQ_ENTRIES_PER_ITERATION < SQ_RING_SIZE);
 
-        let ring: io_uring::IoUring<squeue::Entry, cqueue::Entry> = io_uring::IoUring::builder()
-            // TODO: Allow the user to decide whether sqpoll is used.
-            .setup_sqpoll(1000) // The kernel sqpoll thread will sleep after this many milliseconds.
-            .build(SQ_RING_SIZE as _)
-            .expect("Failed to initialise io_uring.");
+        let ring = build_ring(config).expect("Failed to initialise io_uring.");
+
+        assert!(ring.params().cq_entries() >= ring.params().sq_entries() * 2);
+        // Without `NODROP`, the kernel silently drops CQEs when the CQ is full, in which case the
+        // operations waiting for those CQEs would never finish.
+        assert!(
+            ring.params().is_feature_nodrop(),
+            "This kernel's io_uring doesn't support IORING_FEAT_NODROP, so completion queue \
+                entries could be silently dropped if the completion queue overflows. Linux 5.5 or \
+                later is required."
+        );
 
-        assert_eq!(ring.params().cq_entries(), ring.params().sq_entries() * 2);
+        if config.fixed_files {
+            ring.submitter()
+                .register_files_sparse(MAX_FILES_TO_REGISTER)
+                .expect(
+                    "Failed to register a table of fixed files with io_uring. (The table size \
+                        must not exceed RLIMIT_NOFILE.)",
+                );
+        }
+
+        if let Some(fixed_buffers) = &fixed_buffers {
+            // SAFETY: The buffers stay alive (and don't move) until after `ring` has been dropped.
+            unsafe { ring.submitter().register_buffers(&fixed_buffers.iovecs()) }.expect(
+                "Failed to register the fixed buffers with io_uring. (On older kernels, registered \
+                    buffers count towards RLIMIT_MEMLOCK.)",
+            );
+        }
+
+        // Blocking with a timeout requires `IORING_FEAT_EXT_ARG` (Linux 5.11).
+        let wait_strategy = if ring.params().is_feature_ext_arg() {
+            config.wait_strategy
+        } else {
+            WaitStrategy::default()
+        };
 
         Self {
             uring: ring,
+            _fixed_buffers: fixed_buffers,
             ops_in_flight: Tracker::new(SQ_RING_SIZE),
             worker_thread,
             output_tx,
+            groups,
+            open_file_limit,
+            output_high_water_mark: config.output_high_water_mark,
+            stats,
+            n_unfinished_ops,
+            pinned_ops: RefCell::new(VecDeque::new()),
+            oldest_unsubmitted_sqe: None,
+            wait_strategy,
+            n_empty_polls: 0,
+            first_empty_poll: None,
         }
     }
 
     /// The main loop for the thread.
+    ///
+    /// # Error handling
+    ///
+    /// `run` is a long-running loop, so it tries hard not to `panic`. The recoverable conditions
+    /// are:
+    /// - The tracker or the SQ is unexpectedly full when we try to submit a new operation: The
+    ///   operation is pushed back onto this thread's local queue, and will be tried again later.
+    /// - `submit` (or `submit_and_wait`) fails with `EINTR`, `EAGAIN` or `EBUSY`: The kernel is
+    ///   temporarily busy, so we carry on processing the CQ, and try again on the next iteration.
+    /// - A CQE refers to an operation that we are not tracking: The CQE is skipped and an error is
+    ///   sent to the user via the completion channel.
+    ///
+    /// Any other error from `submit` is fatal (because it implies that the io_uring itself is
+    /// broken), and will `panic`. Likewise, if the kernel reports that it has dropped any CQEs
+    /// then `run` panics, because the operations waiting for those CQEs would never finish. (The
+    /// panic is reported to the user, so the user gets an error instead of waiting forever.)
+    /// Conditions which should truly never happen are checked with `debug_assert!`.
+    ///
+    /// # Backpressure
+    ///
+    /// Sending to the user never blocks (because blocking whilst processing the CQ would stall all
+    /// the other operations on this thread). Instead, whilst `output_tx` holds at least
+    /// `output_high_water_mark` outputs, `run` stops pulling new tasks, and just processes the CQ
+    /// for the operations which are already in flight. So the number of outputs waiting in the
+    /// channel is bounded by the high-water mark plus the number of operations in flight.
     pub(crate) fn run(&mut self) {
         while self.worker_thread.keep_running() {
             if self.ops_in_flight.is_full() || self.uring_is_full() {
                 if self.uring.completion().is_empty() {
                     // The SQ is full but no completion events are ready! So we have no choice:
                     // We *have* to wait for some completion events to to complete:
-                    self.uring.submit_and_wait(1).unwrap();
+                    match self.uring.submit_and_wait(1) {
+                        Ok(_) => self.oldest_unsubmitted_sqe = None,
+                        Err(err) => handle_submit_error(err),
+                    }
                 }
                 // The CQ has CQEs for us, so we fall through to the CQ processing loop.
+            } else if self.output_tx.len() >= self.output_high_water_mark {
+                // Backpressure: The user isn't consuming outputs fast enough, so don't start any
+                // new operations. We still process the CQ, so that operations which are already in
+                // flight can finish.
+                if self.ops_in_flight.is_empty() {
+                    thread::sleep(BACKPRESSURE_SLEEP);
+                    continue;
+                }
             } else {
-                match self.worker_thread.find_task() {
-                    Some(mut operation) => {
-                        // Submit first step of `operation`, and track `operation`:
-                        let index_of_op = self
-                            .ops_in_flight
-                            .get_next_index()
-                            .expect("Failed to get_next_task on tracker!");
-                        operation
-                            .submit_first_step(index_of_op, &mut self.uring.submission())
-                            .expect("Failed to submit_first_step of Operation!");
-                        // TODO: Instead of calling `submit()` on every loop, we should keep our
-                        // own check on how long has elapsed since we last submitted to the SQ,
-                        // and only call `submit()` when we know the SQ has gone to sleep.
-                        // See issue #129.
-                        self.uring.submitter().submit().unwrap();
-                        self.ops_in_flight.put(index_of_op, operation);
-                        if self.sq_len_plus_cq_len() < HIGH_WATER_LINE {
-                            // We want to "top up" the SQ before we process any CQEs.
-                            // Without this, we run the risk of submitting one SQE, then draining
-                            // that CQE, then submitting another SQE, and training that CQE, etc.
-                            // In other words, we run the risk of not letting io_uring handle
-                            // multiple SQEs at once!
-                            continue;
-                        }
+                // Operations on this worker's fixed files take priority, because they free
+                // slots for fixed files as they finish. Otherwise, take as many operations as
+                // we can submit in one go (which is cheaper than taking them one at a time).
+                let operations = match self.pinned_ops.get_mut().pop_front() {
+                    Some(operation) => vec![operation],
+                    None => self.worker_thread.find_task_batch(self.max_ops_to_top_up()),
+                };
+                if operations.is_empty() {
+                    // There are no new operations to submit, so let's work out if we need to
+                    // park or process the completion queue.
+                    if self.ops_in_flight.is_empty() {
+                        // There's nothing to do! So we have to sleep:
+                        self.worker_thread.park();
+                        // When we wake, there definitely won't be anything in our uring, so
+                        // continue to the top of the while loop:
+                        continue;
                     }
-                    None => {
-                        // There are no new operations to submit, so let's work out if we need to
-                        // park or process the completion queue.
-                        if self.ops_in_flight.is_empty() {
-                            // There's nothing to do! So we have to sleep:
-                            self.worker_thread.park();
-                            // When we wake, there definitely won't be anything in our uring, so
-                            // continue to the top of the while loop:
-                            continue;
+                    // We can only wait for our operations in flight to complete.
+                    self.wait_for_cqes();
+                } else if self.track_and_submit_first_steps(operations) {
+                    self.oldest_unsubmitted_sqe.get_or_insert_with(Instant::now);
+                    if self.sq_len_plus_cq_len() < HIGH_WATER_LINE {
+                        // We want to "top up" the SQ before we process any CQEs. Without this,
+                        // we run the risk of submitting one SQE, then draining that CQE, then
+                        // submitting another SQE, and draining that CQE, etc. In other words, we
+                        // run the risk of not letting io_uring handle multiple SQEs at once!
+                        if self.submit_is_overdue() {
+                            self.submit();
                         }
+                        continue;
                     }
                 }
             }
 
-            for cqe in unsafe { self.uring.completion_shared() } {
-                let idx_and_opcode = UringUserData::from(cqe.user_data());
-                let idx_of_op = idx_and_opcode.index_of_op() as usize;
-                let mut op_guard = self.ops_in_flight.get(idx_of_op).unwrap();
-                let next_step = op_guard.as_mut().process_opcode_and_submit_next_step(
-                    &idx_and_opcode,
-                    cqe.result(),
-                    &mut unsafe { self.uring.submission_shared() },
-                    &self.worker_thread,
-                    &mut self.output_tx,
-                );
-                match next_step {
-                    NextStep::Pending => (), // By default, op_guard will keep the operation.
-                    NextStep::ReplaceWith(op) => op_guard.replace(op),
-                    NextStep::Done => {
-                        let _ = op_guard.remove();
-                    }
-                };
+            // We're about to process the CQ, so make sure the kernel knows about all our SQEs.
+            if self.oldest_unsubmitted_sqe.is_some() {
+                self.submit();
+            }
+
+            self.process_cq();
+        }
+
+        // The `IoUring` has been dropped, but the kernel may still be using the operations in
+        // flight (e.g. writing into their buffers, or closing their files). So we must wait for
+        // those operations to finish before we drop them. Streams may wait indefinitely for more
+        // data, so we cancel them.
+        self.cancel_streams();
+        while !self.ops_in_flight.is_empty() {
+            match self.uring.submit_and_wait(1) {
+                Ok(_) => self.oldest_unsubmitted_sqe = None,
+                Err(err) => handle_submit_error(err),
+            }
+            self.process_cq();
+        }
+    }
+
+    /// Wait (according to the `WaitStrategy`) for CQEs, when there's nothing else to do.
+    /// Returns straight away if the CQ already holds CQEs, or if the wait strategy is to poll.
+    fn wait_for_cqes(&mut self) {
+        if !self.uring.completion().is_empty() {
+            return;
+        }
+        let first_empty_poll = *self.first_empty_poll.get_or_insert_with(Instant::now);
+        self.n_empty_polls = self.n_empty_polls.saturating_add(1);
+        match self.wait_strategy {
+            WaitStrategy::Block => self.block_until_cqe(),
+            WaitStrategy::BusyPoll { max_spins } if self.n_empty_polls >= max_spins => {
+                self.n_empty_polls = 0;
+                thread::yield_now();
+            }
+            WaitStrategy::BusyPoll { .. } => std::hint::spin_loop(),
+            WaitStrategy::Hybrid if first_empty_poll.elapsed() >= HYBRID_SPIN_DURATION => {
+                self.block_until_cqe()
+            }
+            WaitStrategy::Hybrid => std::hint::spin_loop(),
+        }
+    }
+
+    /// Submit all SQEs in the SQ, and block until the CQ holds at least one CQE, or until
+    /// `BLOCK_TIMEOUT` has passed (so that we can pick up new operations).
+    fn block_until_cqe(&mut self) {
+        let timeout = types::Timespec::from(BLOCK_TIMEOUT);
+        let args = types::SubmitArgs::new().timespec(&timeout);
+        match self.uring.submitter().submit_with_args(1, &args) {
+            Ok(_) => self.oldest_unsubmitted_sqe = None,
+            // We timed out, so go back to the top of the `run` loop to look for new operations.
+            Err(err) if err.raw_os_error() == Some(libc::ETIME) => (),
+            Err(err) => handle_submit_error(err),
+        }
+    }
+
+    /// Cancel the SQE in flight of every `GetStream` operation. Cancelled streams send an
+    /// `ECANCELED` error, and close their files.
+    fn cancel_streams(&mut self) {
+        for (index_of_op, operation) in self.ops_in_flight.iter() {
+            let Operation::GetStream(get_stream) = operation else {
+                continue;
+            };
+            let entry = io_uring::opcode::AsyncCancel::new(
+                get_stream.user_data_of_sqe_in_flight(index_of_op),
+            )
+            .build()
+            .user_data(CANCEL_USER_DATA);
+            while unsafe { self.uring.submission_shared().push(&entry) }.is_err() {
+                // The SQ is full, so make room.
+                if let Err(err) = self.uring.submit() {
+                    handle_submit_error(err);
+                }
+            }
+        }
+    }
+
+    /// Process every CQE in the completion queue, and then release any operations which were
+    /// waiting for those CQEs.
+    fn process_cq(&mut self) {
+        self.check_cq_overflow();
+        if !self.uring.completion().is_empty() {
+            // The wait for CQEs (if any) is over.
+            self.n_empty_polls = 0;
+            self.first_empty_poll = None;
+        }
+        let spawner = Spawner::new(
+            &self.worker_thread,
+            &self.n_unfinished_ops,
+            &self.pinned_ops,
+        );
+        for cqe in unsafe { self.uring.completion_shared() } {
+            self.stats.add_cqe_processed();
+            if cqe.user_data() == CANCEL_USER_DATA {
+                continue;
+            }
+            let idx_and_opcode = UringUserData::from(cqe.user_data());
+            if idx_and_opcode.opcode().value() == io_uring::opcode::ReadFixed::CODE {
+                self.stats.add_fixed_buffer_read();
+            }
+            let idx_of_op = idx_and_opcode.index_of_op() as usize;
+            let Some(mut op_guard) = self.ops_in_flight.get(idx_of_op) else {
+                debug_assert!(false, "CQE for an untracked operation! {idx_and_opcode:?}");
+                let _ = self.output_tx.send(Err(IoError::Internal {
+                    message: format!(
+                        "Received a CQE for an operation which is not being tracked! \
+                            idx_and_opcode: {idx_and_opcode:?}. cqe_result: {}",
+                        cqe.result()
+                    ),
+                }));
+                continue;
+            };
+            let mut sq = unsafe { self.uring.submission_shared() };
+            let sq_len_before = sq.len();
+            let next_step = op_guard.as_mut().process_opcode_and_submit_next_step(
+                &idx_and_opcode,
+                cqe.result(),
+                &mut sq,
+                &spawner,
+                &mut self.output_tx,
+            );
+            self.stats.add_sqes_submitted(sq.len() - sq_len_before);
+            drop(sq);
+            match next_step {
+                NextStep::Pending => (), // By default, op_guard will keep the operation.
+                NextStep::ReplaceWith(op) => op_guard.replace(op),
+                NextStep::Done => {
+                    let _ = op_guard.remove();
+                    // `Release`, so that `IoUring::shutdown` sees everything this operation
+                    // did (including sending its outputs).
+                    self.n_unfinished_ops.fetch_sub(1, Release);
+                }
+                NextStep::Requeue => spawner.requeue(op_guard.remove()),
+            };
+        }
+
+        self.stats.set_ops_in_flight(self.ops_in_flight.len());
+
+        // Processing CQEs may have pushed follow-up SQEs (e.g. `close`) onto the SQ.
+        if !unsafe { self.uring.submission_shared() }.is_empty() {
+            self.oldest_unsubmitted_sqe.get_or_insert_with(Instant::now);
+        }
+
+        // Processing CQEs may have finished a group, in which case the operations in the next
+        // group can start.
+        self.groups.release_ready_ops(&self.worker_thread);
+
+        // Processing CQEs may have closed files, in which case operations which are waiting
+        // to open files can start.
+        self.open_file_limit.release_ready_ops(&self.worker_thread);
+    }
+
+    /// Panic if the kernel has dropped any CQEs. We assert that the kernel supports `NODROP` in
+    /// [`UringWorker::new`], in which case the kernel keeps overflowing CQEs in a backlog instead
+    /// of dropping them. But even with `NODROP`, the kernel drops CQEs (and counts them in the CQ's
+    /// overflow counter) if it can't allocate memory for the backlog.
+    fn check_cq_overflow(&mut self) {
+        assert_no_dropped_cqes(self.uring.completion().overflow());
+    }
+
+    /// Track `operation`, and push its first step onto the SQ. This does _not_ submit the SQ to the
+    /// kernel.
+    ///
+    /// If `operation` can't be submitted then we stop tracking `operation` and return it, so the
+    /// caller can try again later.
+    #[allow(clippy::result_large_err)] // We return `operation` by value so it can be re-queued.
+    fn track_and_submit_first_step(&mut self, operation: Operation) -> Result<(), Operation> {
+        let Some(index_of_op) = self.ops_in_flight.get_next_index() else {
+            debug_assert!(false, "The tracker should never be full at this point!");
+            return Err(operation);
+        };
+
+        // We put `operation` into the tracker _before_ submitting its first step, so that
+        // `operation` doesn't move in memory after the kernel has been given pointers into it
+        // (e.g. the `statx` buffer).
+        self.ops_in_flight.put(index_of_op, operation);
+        let mut op_guard = self
+            .ops_in_flight
+            .get(index_of_op)
+            .expect("We have just put this operation into the tracker!");
+        let mut sq = self.uring.submission();
+        let sq_len_before = sq.len();
+        if let Err(err) = op_guard.as_mut().submit_first_step(index_of_op, &mut sq) {
+            debug_assert!(false, "The SQ should never be full at this point! {err}");
+            return Err(op_guard.remove());
+        }
+        // `sq` caches the head of the SQ, so the change in `len` is the number of SQEs pushed.
+        self.stats.add_sqes_submitted(sq.len() - sq_len_before);
+        drop(sq);
+        self.stats.set_ops_in_flight(self.ops_in_flight.len());
+        Ok(())
+    }
+
+    /// Track and submit the first step of each of `operations`. If an operation can't be
+    /// submitted then it (and the remaining operations) are re-queued, to be tried again after
+    /// we've processed some CQEs. Operations which would exceed the limit on open files are held
+    /// back by the [`OpenFileLimit`]. Returns true if at least one operation was submitted.
+    fn track_and_submit_first_steps(&mut self, operations: Vec<Operation>) -> bool {
+        let mut submitted_any = false;
+        let mut operations = operations.into_iter();
+        for operation in operations.by_ref() {
+            let Some(operation) = self.open_file_limit.acquire_or_hold_back(operation) else {
+                continue;
+            };
+            if let Err(operation) = self.track_and_submit_first_step(operation) {
+                self.spawner().requeue(operation);
+                break;
             }
+            submitted_any = true;
         }
-        assert!(self.ops_in_flight.is_empty());
+        for operation in operations {
+            self.spawner().requeue(operation);
+        }
+        submitted_any
+    }
+
+    /// The number of new operations that we can submit without overfilling the tracker, and
+    /// without pushing the SQ (much) beyond the `HIGH_WATER_LINE`. Always at least 1 (the caller
+    /// has checked that neither the tracker nor the uring is full).
+    fn max_ops_to_top_up(&self) -> usize {
+        let sq_headroom = HIGH_WATER_LINE.saturating_sub(self.sq_len_plus_cq_len());
+        (sq_headroom / MAX_SQ_ENTRIES_PER_ITERATION)
+            .min(self.ops_in_flight.capacity_remaining())
+            .max(1)
+    }
+
+    fn spawner(&self) -> Spawner<'_> {
+        Spawner::new(
+            &self.worker_thread,
+            &self.n_unfinished_ops,
+            &self.pinned_ops,
+        )
+    }
+
+    /// Submit all SQEs in the SQ to the kernel. If SQPOLL is enabled then this only makes a
+    /// syscall if the kernel's SQ polling thread needs to be woken up.
+    fn submit(&mut self) {
+        match self.uring.submitter().submit() {
+            Ok(_) => self.oldest_unsubmitted_sqe = None,
+            // The SQEs are still in the SQ, so they'll be submitted on the next call to `submit`.
+            Err(err) => handle_submit_error(err),
+        }
+    }
+
+    /// Returns true if at least one SQE has been waiting for longer than `MAX_SUBMIT_DELAY`.
+    fn submit_is_overdue(&self) -> bool {
+        self.oldest_unsubmitted_sqe
+            .is_some_and(|t| t.elapsed() >= MAX_SUBMIT_DELAY)
     }
 
     /// io_uring submission queue (SQ) length plus the io_uring completion queue (CQ) length:
@@ -133,3 +508,54 @@ impl UringWorker {
         self.sq_len_plus_cq_len() >= SQ_RING_SIZE - MAX_SQ_ENTRIES_PER_ITERATION
     }
 }
+
+/// Create an io_uring configured by `config` (e.g. with `SQPOLL`).
+pub(crate) fn build_ring(config: &Config) -> std::io::Result<io_uring::IoUring> {
+    let mut builder = io_uring::IoUring::<squeue::Entry, cqueue::Entry>::builder();
+    if let SqPoll::Enabled { idle_ms } = config.sqpoll {
+        // The kernel sqpoll thread will sleep after `idle_ms` milliseconds.
+        builder.setup_sqpoll(idle_ms);
+    }
+    if let Some(cq_size) = config.cq_size {
+        builder.setup_cqsize(cq_size);
+    }
+    builder.build(SQ_RING_SIZE as _)
+}
+
+/// Errors from `submit` which mean "the kernel is temporarily busy" are recoverable.
+/// All other errors are fatal.
+fn handle_submit_error(err: std::io::Error) {
+    match err.raw_os_error() {
+        Some(libc::EINTR | libc::EAGAIN | libc::EBUSY) => (),
+        _ => panic!("Fatal error when submitting SQEs to io_uring: {err}"),
+    }
+}
+
+/// Panics if `n_dropped_cqes` (the CQ's overflow counter) isn't zero. See
+/// [`UringWorker::check_cq_overflow`].
+fn assert_no_dropped_cqes(n_dropped_cqes: u32) {
+    assert_eq!(
+        n_dropped_cqes, 0,
+        "The kernel dropped {n_dropped_cqes} io_uring completion queue entries (CQEs), so the \
+            operations waiting for those CQEs will never finish."
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // We can't make a real io_uring drop CQEs: With `IORING_FEAT_NODROP`, the kernel only drops
+    // CQEs when it can't allocate memory for its backlog. So we test the check on its own.
+    #[test]
+    fn test_assert_no_dropped_cqes() {
+        assert_no_dropped_cqes(0);
+        let result = std::panic::catch_unwind(|| assert_no_dropped_cqes(3));
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("The kernel dropped 3 io_uring completion queue entries"),
+            "{message}"
+        );
+    }
+}
@@ -1,15 +1,48 @@
-use crossbeam_channel::RecvTimeoutError;
-use lsio_aligned_bytes::AlignedBytes;
-use lsio_io::{Completion, Reader};
-use lsio_uring::IoUring;
+// Our API takes a `Vec` of byte ranges, and we often want to request just one byte range.
+#![allow(clippy::single_range_in_vec_init)]
+// Negative range ends are relative to the end of the file, so ranges like `0..-1` aren't empty.
+#![allow(clippy::reversed_empty_ranges)]
+
+use lsio_aligned_bytes::{AlignedBytes, AlignedBytesMut, BufferPool, ExternalMemory};
+use lsio_io::{
+    Advice, AsyncReader, ByteRange, Chunk, Completion, Copier, FileMetadata, IoError, Lister,
+    Output, ReadOp, Reader, RecvTimeoutError, SyncMode, TryRecvError, Writer,
+};
+use lsio_uring::{IoUring, SqPoll, WaitStrategy};
 use rand::Rng;
+use std::ffi::CString;
 use std::fs::File;
 use std::io::Read;
-use std::{io::Write, time::Duration};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::{
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 const KIBIBYTE: usize = 1024;
 const MEBIBYTE: usize = KIBIBYTE * 1024;
 
+/// Write `contents` to a new file in the temporary directory, and return the filename.
+fn create_temp_file(prefix: &str, contents: &[u8]) -> std::io::Result<PathBuf> {
+    let filename =
+        std::env::temp_dir().join(format!("lsio_uring_{prefix}_{}", rand::random::<u32>()));
+    std::fs::write(&filename, contents)?;
+    Ok(filename)
+}
+
+/// Copy `contents` into a new `AlignedBytes`.
+fn aligned_bytes_from(contents: &[u8]) -> AlignedBytes {
+    let mut buffer = AlignedBytesMut::new(contents.len(), 512);
+    unsafe {
+        std::ptr::copy_nonoverlapping(contents.as_ptr(), buffer.as_mut_ptr(), contents.len())
+    };
+    buffer.freeze().unwrap()
+}
+
 #[test]
 fn test_get_ranges() -> anyhow::Result<()> {
     const N_WORKER_THREADS: usize = 4;
@@ -75,10 +108,10 @@ fn test_get_ranges() -> anyhow::Result<()> {
     for i in 0..N_CHUNKS {
         match uring.completion().recv_timeout(Duration::from_millis(500)) {
             Ok(output) => match output {
-                Ok(c) => {
-                    let lsio_io::Output::Chunk(c) = c;
+                Ok(Output::Chunk(c)) => {
                     vec_of_aligned_bytes[c.user_data as usize] = Some(c.buffer);
                 }
+                Ok(output) => panic!("Unexpected output {output:?}"),
                 Err(e) => panic!("Error reading chunk {i}! {e:?}"),
             },
             Err(RecvTimeoutError::Timeout) => panic!("Timed out waiting for chunk {i}!"),
@@ -118,3 +151,2709 @@ fn test_get_ranges() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_get_ranges_saturating_the_submission_queue() -> anyhow::Result<()> {
+    // Far more ranges (in a single call) than the submission queue can hold at once, so the
+    // workers must repeatedly stop submitting when their rings are full, and resume as CQEs
+    // arrive.
+    const N_RANGES: usize = 10_000;
+    const RANGE_SIZE: usize = 100;
+    let file_contents: Vec<u8> = (0..N_RANGES * RANGE_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("saturating_sq", &file_contents)?;
+
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * RANGE_SIZE) as isize..((i + 1) * RANGE_SIZE) as isize)
+        .collect();
+    let user_data = (0..N_RANGES as u64).collect();
+    let mut uring = IoUring::new(2);
+    uring.get_ranges(&filename, ranges, user_data)?;
+
+    let mut chunks: Vec<Option<AlignedBytes>> = (0..N_RANGES).map(|_| None).collect();
+    for i in 0..N_RANGES {
+        match uring.completion().recv_timeout(Duration::from_secs(5)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let chunk = &mut chunks[c.user_data as usize];
+                assert!(chunk.is_none(), "Duplicate chunk {}", c.user_data);
+                *chunk = Some(c.buffer);
+            }
+            output => panic!("Unexpected output whilst waiting for chunk {i}: {output:?}"),
+        }
+    }
+
+    // No completion should be left over.
+    uring.submit_barrier()?;
+    assert!(uring.completion().try_recv().is_err());
+
+    let assembled: Vec<u8> = chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.unwrap().as_slice().to_vec())
+        .collect();
+    assert!(assembled == file_contents);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_copy_ranges() -> anyhow::Result<()> {
+    const N_WORKER_THREADS: usize = 2;
+    const FILE_SIZE: usize = KIBIBYTE * 16;
+
+    let file_contents: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    let src_filename = create_temp_file("copy_src", &file_contents)?;
+    let dst_filename =
+        std::env::temp_dir().join(format!("lsio_uring_copy_dst_{}", rand::random::<u32>()));
+
+    // Copy the second half of `src` to the start of `dst`, and the first half of `src` to the end
+    // of `dst`. The second destination range is not aligned.
+    const HALF: isize = (FILE_SIZE / 2) as isize;
+    let src_ranges = vec![HALF..(FILE_SIZE as isize), 0..HALF];
+    let dst_ranges = vec![0..HALF, (HALF + 1)..(FILE_SIZE as isize + 1)];
+    let user_data = vec![0, 1];
+
+    let mut uring = IoUring::new(N_WORKER_THREADS);
+    uring.copy_ranges(
+        &src_filename,
+        src_ranges,
+        &dst_filename,
+        dst_ranges,
+        user_data,
+    )?;
+
+    let mut nbytes_written = [0; 2];
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { user_data, nbytes })) => {
+                nbytes_written[user_data as usize] = nbytes;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(nbytes_written, [FILE_SIZE / 2, FILE_SIZE / 2]);
+    drop(uring);
+
+    let dst_contents = std::fs::read(&dst_filename)?;
+    assert_eq!(dst_contents.len(), FILE_SIZE + 1);
+    assert_eq!(
+        dst_contents[..HALF as usize],
+        file_contents[HALF as usize..]
+    );
+    assert_eq!(
+        dst_contents[(HALF + 1) as usize..],
+        file_contents[..HALF as usize]
+    );
+
+    // Clean up:
+    std::fs::remove_file(&src_filename)?;
+    std::fs::remove_file(&dst_filename)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_copy_ranges_with_invalid_ranges() -> anyhow::Result<()> {
+    let src_filename = create_temp_file("copy_invalid_src", &[1; 100])?;
+    let dst_filename = std::env::temp_dir().join(format!(
+        "lsio_uring_copy_invalid_dst_{}",
+        rand::random::<u32>()
+    ));
+    let mut uring = IoUring::new(1);
+
+    // Mismatched lengths are rejected before anything is submitted.
+    assert!(uring
+        .copy_ranges(&src_filename, vec![0..1], &dst_filename, vec![], vec![0])
+        .is_err());
+    assert!(uring
+        .copy_ranges(&src_filename, vec![0..1], &dst_filename, vec![0..1], vec![])
+        .is_err());
+
+    // An empty source range, a source range which reaches back beyond the start of the file, and
+    // a destination range which reaches back beyond the start of the (new, empty) destination.
+    let src_ranges = vec![50..50, -513..-1, 0..10, 0..10];
+    let dst_ranges = vec![0..0, 0..512, -200..-190, 10..20];
+    uring.copy_ranges(
+        &src_filename,
+        src_ranges,
+        &dst_filename,
+        dst_ranges,
+        vec![0, 1, 2, 3],
+    )?;
+    let mut n_errors = 0;
+    for _ in 0..4 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { user_data, nbytes })) => {
+                assert_eq!((user_data, nbytes), (3, 10))
+            }
+            Ok(Err(IoError::InvalidRange { .. })) => n_errors += 1,
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_errors, 3);
+
+    // The worker threads are still running.
+    uring.get_ranges(&src_filename, vec![0..1], vec![0])?;
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Ok(Output::Chunk(c))) => assert_eq!(c.buffer.as_slice(), [1]),
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    std::fs::remove_file(&src_filename)?;
+    std::fs::remove_file(&dst_filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_put_ranges() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    let mut uring = IoUring::new(2);
+
+    // Aligned writes (which will use `O_DIRECT`) into a new file:
+    let filename =
+        std::env::temp_dir().join(format!("lsio_uring_put_aligned_{}", rand::random::<u32>()));
+    let ranges = vec![
+        CHUNK_SIZE as isize..(CHUNK_SIZE * 2) as isize,
+        0..CHUNK_SIZE as isize,
+    ];
+    let buffers = vec![
+        aligned_bytes_from(&[1; CHUNK_SIZE]),
+        aligned_bytes_from(&[0; CHUNK_SIZE]),
+    ];
+    uring.put_ranges(&filename, ranges, buffers, vec![1, 0])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { nbytes, .. })) => assert_eq!(nbytes, CHUNK_SIZE),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    let contents = std::fs::read(&filename)?;
+    assert_eq!(contents[..CHUNK_SIZE], [0; CHUNK_SIZE]);
+    assert_eq!(contents[CHUNK_SIZE..], [1; CHUNK_SIZE]);
+    std::fs::remove_file(&filename)?;
+
+    // Unaligned writes (including a negative range) into an existing file:
+    let filename = create_temp_file("put_unaligned", &[0; 16])?;
+    let ranges = vec![2..5, -3..-1];
+    let buffers = vec![aligned_bytes_from(b"abc"), aligned_bytes_from(b"xyz")];
+    uring.put_ranges(&filename, ranges, buffers, vec![0, 1])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { nbytes, .. })) => assert_eq!(nbytes, 3),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(std::fs::read(&filename)?, b"\0\0abc\0\0\0\0\0\0\0\0xyz");
+
+    // A range which reaches back beyond the start of the file is an error, but it doesn't stop
+    // the other ranges (or later operations) from being written.
+    let ranges = vec![-513..-1, 0..1];
+    let buffers = vec![aligned_bytes_from(&[7; 512]), aligned_bytes_from(b"z")];
+    uring.put_ranges(&filename, ranges, buffers, vec![0, 1])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(IoError::InvalidRange { range, .. })) => assert_eq!(range, -513..-1),
+            Ok(Ok(Output::BytesWritten { user_data, nbytes })) => {
+                assert_eq!((user_data, nbytes), (1, 1))
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(std::fs::read(&filename)?, b"z\0abc\0\0\0\0\0\0\0\0xyz");
+    std::fs::remove_file(&filename)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sync() -> anyhow::Result<()> {
+    let filename = create_temp_file("sync", &[0; 16])?;
+    let mut uring = IoUring::new(2);
+    for (user_data, mode) in [(0, SyncMode::All), (1, SyncMode::Data)] {
+        uring.put_ranges(
+            &filename,
+            vec![0..3],
+            vec![aligned_bytes_from(b"abc")],
+            vec![2],
+        )?;
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { nbytes: 3, .. })) => (),
+            output => panic!("Unexpected output {output:?}"),
+        }
+        uring.sync(&filename, mode, user_data)?;
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Synced { user_data: u })) => assert_eq!(u, user_data),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    std::fs::remove_file(&filename)?;
+
+    // Syncing a missing file is an error (and doesn't create the file):
+    uring.sync(&filename, SyncMode::All, 3)?;
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(IoError::NotFound {
+            path,
+            user_data: Some(3),
+            ..
+        })) => assert_eq!(path, filename),
+        output => panic!("Unexpected output {output:?}"),
+    }
+    assert!(!filename.exists());
+    Ok(())
+}
+
+#[test]
+fn test_sync_in_group_waits_for_put_ranges_in_group() -> anyhow::Result<()> {
+    const N_RANGES: usize = 16;
+    const CHUNK_SIZE: usize = KIBIBYTE * 64;
+    const SYNC_USER_DATA: u64 = u64::MAX;
+
+    let filename = create_temp_file("sync_in_group", &[])?;
+    let ranges: Vec<_> = (0..N_RANGES)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    let buffers = (0..N_RANGES)
+        .map(|i| aligned_bytes_from(&[i as u8; CHUNK_SIZE]))
+        .collect();
+    let mut uring = IoUring::new(2);
+    uring.put_ranges_in_group(
+        0,
+        &filename,
+        ranges,
+        buffers,
+        (0..N_RANGES as u64).collect(),
+    )?;
+    uring.sync_in_group(1, &filename, SyncMode::Data, SYNC_USER_DATA)?;
+
+    // Every write must complete before the sync starts.
+    for i in 0..=N_RANGES {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::BytesWritten { nbytes, .. })) if i < N_RANGES => {
+                assert_eq!(nbytes, CHUNK_SIZE)
+            }
+            Ok(Ok(Output::Synced { user_data })) if i == N_RANGES => {
+                assert_eq!(user_data, SYNC_USER_DATA)
+            }
+            output => panic!("Unexpected output {output:?} (output number {i})"),
+        }
+    }
+    assert_eq!(
+        std::fs::metadata(&filename)?.len(),
+        (N_RANGES * CHUNK_SIZE) as u64
+    );
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_into() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const ALIGN: usize = 512;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_ranges_into", &file_contents)?;
+    let mut uring = IoUring::new(1);
+
+    // Buffers which share their underlying memory must be rejected:
+    let mut buffer = AlignedBytesMut::new(CHUNK_SIZE * 2, ALIGN);
+    let other_half = buffer.split_to(CHUNK_SIZE)?;
+    let result = uring.get_ranges_into(
+        &filename,
+        vec![0..CHUNK_SIZE as isize],
+        vec![other_half],
+        vec![0],
+    );
+    assert!(result.is_err());
+    drop(buffer);
+
+    // The second range ends before the end of its (aligned) buffer. The third range is longer
+    // than its buffer.
+    let ranges = vec![
+        0..CHUNK_SIZE as isize,
+        CHUNK_SIZE as isize..(CHUNK_SIZE * 2 - 100) as isize,
+        0..(CHUNK_SIZE * 2) as isize,
+    ];
+    let mut buffers: Vec<AlignedBytesMut> = (0..3)
+        .map(|_| AlignedBytesMut::new(CHUNK_SIZE, ALIGN))
+        .collect();
+    let buffer_ptrs: Vec<*const u8> = buffers
+        .iter_mut()
+        .map(|buffer| buffer.as_mut_ptr() as *const u8)
+        .collect();
+    uring.get_ranges_into(&filename, ranges, buffers, vec![0, 1, 2])?;
+
+    let mut n_errors = 0;
+    for _ in 0..3 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                // The chunk must be a view into the buffer that we provided:
+                assert_eq!(c.buffer.as_ptr(), buffer_ptrs[c.user_data as usize]);
+                let expected = match c.user_data {
+                    0 => &file_contents[..CHUNK_SIZE],
+                    1 => &file_contents[CHUNK_SIZE..CHUNK_SIZE * 2 - 100],
+                    _ => panic!("Unexpected chunk {c:?}"),
+                };
+                assert_eq!(c.buffer.as_slice(), expected);
+            }
+            Ok(Err(_)) => n_errors += 1,
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_errors, 1);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_into_with_vectored_reads() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const ALIGN: usize = 512;
+
+    // The file ends 100 bytes before the end of the last chunk.
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * 8 - 100).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_ranges_into_vectored", &file_contents)?;
+    // With `max_gap`, adjacent ranges are read by a single `readv`.
+    let mut uring = IoUring::builder(1).max_gap(0).build();
+
+    // Chunks 0 to 3 are adjacent (but not in order). Chunks 5 to 7 are adjacent, but chunk 7
+    // extends beyond the end of the file.
+    let chunk_indices = [2, 0, 3, 1, 5, 6, 7];
+    let ranges = chunk_indices
+        .iter()
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    let buffers: Vec<AlignedBytesMut> = chunk_indices
+        .iter()
+        .map(|_| AlignedBytesMut::new(CHUNK_SIZE, ALIGN))
+        .collect();
+    let user_data = chunk_indices.iter().map(|&i| i as u64).collect();
+    uring.get_ranges_into(&filename, ranges, buffers, user_data)?;
+
+    let mut user_data_of_chunks = Vec::new();
+    let mut n_errors = 0;
+    for _ in 0..chunk_indices.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data as usize * CHUNK_SIZE;
+                assert_eq!(c.range, Some(start..start + CHUNK_SIZE));
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[start..start + CHUNK_SIZE]
+                );
+                user_data_of_chunks.push(c.user_data);
+            }
+            Ok(Err(IoError::ShortRead { range, got, .. })) => {
+                assert_eq!(range.start as usize, CHUNK_SIZE * 7);
+                assert_eq!(got, CHUNK_SIZE - 100);
+                n_errors += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    user_data_of_chunks.sort();
+    assert_eq!(user_data_of_chunks, [0, 1, 2, 3, 5, 6]);
+    assert_eq!(n_errors, 1);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_into_external_memory() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE;
+    const N_CHUNKS: usize = 4;
+    const ALIGN: usize = 512;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * N_CHUNKS)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("external_memory", &file_contents)?;
+    let mut uring = IoUring::new(1);
+
+    // The user's "final array". We read the chunks in reverse order, so the final array holds the
+    // chunks in reverse order.
+    let mut final_array = AlignedBytesMut::new(CHUNK_SIZE * N_CHUNKS, ALIGN);
+    let memory =
+        Arc::new(unsafe { ExternalMemory::new(final_array.as_mut_ptr(), final_array.len(), ()) });
+    let buffers = (0..N_CHUNKS)
+        .map(|i| unsafe {
+            AlignedBytesMut::from_external_memory(
+                &memory,
+                i * CHUNK_SIZE..(i + 1) * CHUNK_SIZE,
+                ALIGN,
+            )
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let ranges = (0..N_CHUNKS)
+        .rev()
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    uring.get_ranges_into(&filename, ranges, buffers, (0..N_CHUNKS as u64).collect())?;
+
+    for _ in 0..N_CHUNKS {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => drop(c),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    let final_array = final_array.freeze().unwrap();
+    let expected: Vec<u8> = file_contents
+        .chunks(CHUNK_SIZE)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    assert_eq!(final_array.as_slice(), expected);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_into_vecs() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * 4).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_ranges_into_vecs", &file_contents)?;
+
+    // With `O_DIRECT`, buffers which aren't aligned are rejected before anything is submitted.
+    let mut uring = IoUring::new(1);
+    let mut memory = AlignedBytesMut::new(CHUNK_SIZE * 2, 512);
+    let memory =
+        Arc::new(unsafe { ExternalMemory::new(memory.as_mut_ptr(), memory.len(), memory) });
+    let misaligned =
+        unsafe { AlignedBytesMut::from_external_memory(&memory, 1..CHUNK_SIZE + 1, 1) }?;
+    let result = uring.get_ranges_into(
+        &filename,
+        vec![0..CHUNK_SIZE as isize],
+        vec![misaligned],
+        vec![0],
+    );
+    assert!(result.is_err());
+
+    // Empty `Vec`s are rejected.
+    let mut uring = IoUring::builder(1).direct_io(false).build();
+    let result = uring.get_ranges_into_vecs(
+        &filename,
+        vec![0..CHUNK_SIZE as isize],
+        vec![Vec::new()],
+        vec![0],
+    );
+    assert!(result.is_err());
+
+    // The `Vec`s aren't aligned, and the second range isn't aligned either.
+    let ranges = vec![
+        0..CHUNK_SIZE as isize,
+        (CHUNK_SIZE + 10) as isize..(CHUNK_SIZE * 3) as isize,
+    ];
+    let buffers = vec![vec![0u8; CHUNK_SIZE], vec![0u8; CHUNK_SIZE * 2]];
+    let buffer_ptrs: Vec<*const u8> = buffers.iter().map(|buffer| buffer.as_ptr()).collect();
+    uring.get_ranges_into_vecs(&filename, ranges, buffers, vec![0, 1])?;
+
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                // The chunk must be a view into the `Vec` that we provided:
+                assert_eq!(c.buffer.as_ptr(), buffer_ptrs[c.user_data as usize]);
+                let expected = match c.user_data {
+                    0 => &file_contents[..CHUNK_SIZE],
+                    1 => &file_contents[CHUNK_SIZE + 10..CHUNK_SIZE * 3],
+                    _ => panic!("Unexpected chunk {c:?}"),
+                };
+                assert_eq!(c.buffer.as_slice(), expected);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_request_builder() -> anyhow::Result<()> {
+    let contents_a: Vec<u8> = (0..KIBIBYTE).map(|i| (i % 251) as u8).collect();
+    let contents_b: Vec<u8> = (0..KIBIBYTE).map(|i| (i % 13) as u8).collect();
+    let filename_a = create_temp_file("read_request_a", &contents_a)?;
+    let filename_b = create_temp_file("read_request_b", &contents_b)?;
+
+    // The ranges start at aligned offsets, and are aligned in length (unless they end at the end
+    // of the file), because we read using `O_DIRECT`.
+    let mut uring = IoUring::new(2);
+    uring
+        .read()
+        .file(&filename_a)
+        .ranges(&[0..512, 512..-1])
+        .file(&filename_b)
+        .range(0..-1)
+        .range_with_user_data(512..1024, 42)
+        .range_with_user_data(ByteRange::Last(512), 43)
+        .range_with_user_data(.., 44)
+        .submit()?;
+
+    let mut chunks = std::collections::HashMap::new();
+    for _ in 0..6 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                chunks.insert(c.user_data, c.buffer);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(chunks[&0].as_slice(), &contents_a[..512]);
+    assert_eq!(chunks[&1].as_slice(), &contents_a[512..]);
+    assert_eq!(chunks[&2].as_slice(), &contents_b);
+    assert_eq!(chunks[&42].as_slice(), &contents_b[512..]);
+    assert_eq!(chunks[&43].as_slice(), &contents_b[512..]);
+    assert_eq!(chunks[&44].as_slice(), &contents_b);
+
+    std::fs::remove_file(&filename_a)?;
+    std::fs::remove_file(&filename_b)?;
+    Ok(())
+}
+
+#[test]
+fn test_submit_batch() -> anyhow::Result<()> {
+    const N_FILES: usize = 3;
+    let contents: Vec<Vec<u8>> = (0..N_FILES)
+        .map(|f| (0..KIBIBYTE).map(|i| ((i + f) % 251) as u8).collect())
+        .collect();
+    let filenames = contents
+        .iter()
+        .enumerate()
+        .map(|(f, contents)| create_temp_file(&format!("submit_batch_{f}"), contents))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let mut uring = IoUring::new(2);
+    let ops = filenames
+        .iter()
+        .enumerate()
+        .map(|(f, filename)| {
+            let user_data = (f as u64) * 2;
+            ReadOp::new(
+                filename,
+                vec![0..512, 512..-1],
+                vec![user_data, user_data + 1],
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // If any op is invalid, then nothing is submitted.
+    let mut invalid_ops = ops.clone();
+    invalid_ops.push(ReadOp::new(&filenames[0], vec![0..512], vec![]));
+    assert!(uring.submit_batch(invalid_ops).is_err());
+    uring.submit_barrier()?;
+    assert!(uring.completion().try_recv().is_err());
+
+    uring.submit_batch(ops)?;
+    uring.submit_batch(Vec::new())?;
+    let mut chunks = std::collections::HashMap::new();
+    for _ in 0..N_FILES * 2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                chunks.insert(c.user_data, c.buffer);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    for (f, contents) in contents.iter().enumerate() {
+        let user_data = (f as u64) * 2;
+        assert_eq!(chunks[&user_data].as_slice(), &contents[..512]);
+        assert_eq!(chunks[&(user_data + 1)].as_slice(), &contents[512..]);
+    }
+    uring.submit_barrier()?;
+    assert!(uring.completion().try_recv().is_err());
+
+    for filename in &filenames {
+        std::fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_reports_failed_allocations() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("failed_allocation", &file_contents)?;
+    let mut uring = IoUring::new(1);
+
+    // The buffer for the first range is far larger than the address space, so it can't be
+    // allocated. The second range is unaffected.
+    uring.get_ranges(&filename, vec![0..1 << 60, 0..512], vec![0, 1])?;
+    let (chunks, errors) = uring.drain_errors(2, Duration::from_secs(5));
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].user_data, 1);
+    assert_eq!(chunks[0].buffer.as_slice(), &file_contents[..512]);
+    match &errors[..] {
+        [IoError::Nix {
+            errno: nix::Error::ENOMEM,
+            user_data: Some(0),
+            range: Some(range),
+            ..
+        }] => assert_eq!(range, &(0..1 << 60)),
+        errors => panic!("Unexpected errors {errors:?}"),
+    }
+
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_without_sqpoll() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("no_sqpoll", &file_contents)?;
+    let mut uring = IoUring::builder(2).sqpoll(SqPoll::Disabled).build();
+    uring.get_ranges(
+        &filename,
+        vec![0..CHUNK_SIZE as isize, CHUNK_SIZE as isize..-1],
+        vec![0, 1],
+    )?;
+
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data as usize * CHUNK_SIZE;
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[start..start + CHUNK_SIZE]
+                );
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_larger_cq() -> anyhow::Result<()> {
+    const N_RANGES: usize = 256;
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * N_RANGES)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("larger_cq", &file_contents)?;
+    let mut uring = IoUring::builder(1).setup_cqsize(1024).build();
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES as u64).collect())?;
+
+    for _ in 0..N_RANGES {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data as usize * CHUNK_SIZE;
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[start..start + CHUNK_SIZE]
+                );
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "The CQ must have at least")]
+fn test_cq_smaller_than_default_panics() {
+    IoUring::builder(1).setup_cqsize(16);
+}
+
+#[test]
+fn test_get_ranges_with_open_flags() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 8).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("open_flags", &file_contents)?;
+
+    for direct_io in [true, false] {
+        let mut uring = IoUring::builder(1)
+            .direct_io(direct_io)
+            .open_flags(libc::O_NOATIME | libc::O_CLOEXEC)
+            .build();
+        uring.get_ranges(&filename, vec![0..-1, 100..200], vec![0, 1])?;
+        let mut chunks = std::collections::HashMap::new();
+        for _ in 0..2 {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(c))) => {
+                    chunks.insert(c.user_data, c.buffer);
+                }
+                output => panic!("Unexpected output {output:?}"),
+            }
+        }
+        assert_eq!(chunks[&0].as_slice(), &file_contents);
+        assert_eq!(chunks[&1].as_slice(), &file_contents[100..200]);
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_zero_fill_past_eof() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const N_CHUNKS: usize = 4;
+    // The third chunk straddles the end of the file, and the fourth chunk is beyond it.
+    let file_contents: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("zero_fill_past_eof", &file_contents)?;
+    let mut padded_contents = file_contents.clone();
+    padded_contents.resize(CHUNK_SIZE * N_CHUNKS, 0);
+    let ranges: Vec<_> = (0..N_CHUNKS)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+
+    for (direct_io, max_gap) in [(true, None), (false, None), (true, Some(0))] {
+        let mut builder = IoUring::builder(1)
+            .direct_io(direct_io)
+            .zero_fill_past_eof(true);
+        if let Some(max_gap) = max_gap {
+            builder = builder.max_gap(max_gap);
+        }
+        let mut uring = builder.build();
+        uring.get_ranges(&filename, ranges.clone(), (0..N_CHUNKS as u64).collect())?;
+        for _ in 0..N_CHUNKS {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(chunk))) => {
+                    let start = chunk.user_data as usize * CHUNK_SIZE;
+                    assert_eq!(
+                        chunk.buffer.as_slice(),
+                        &padded_contents[start..start + CHUNK_SIZE],
+                        "direct_io={direct_io}, max_gap={max_gap:?}",
+                    );
+                }
+                output => panic!("Unexpected output {output:?}"),
+            }
+        }
+    }
+
+    // Without `zero_fill_past_eof`, the reads beyond the end of the file fail.
+    let mut uring = IoUring::new(1);
+    uring.get_ranges(&filename, ranges[2..].to_vec(), vec![2, 3])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(_)) => (),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "can't be used to open files for reading")]
+fn test_write_open_flags_panic() {
+    IoUring::builder(1).open_flags(libc::O_RDWR | libc::O_CLOEXEC);
+}
+
+#[test]
+fn test_get_ranges_in_group() -> anyhow::Result<()> {
+    const N_RANGES_PER_GROUP: usize = 8;
+    const CHUNK_SIZE: usize = KIBIBYTE * 64;
+    const N_GROUPS: u64 = 3;
+
+    let filename = create_temp_file("groups", &vec![0; CHUNK_SIZE * N_RANGES_PER_GROUP])?;
+    let ranges: Vec<_> = (0..N_RANGES_PER_GROUP)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    let mut uring = IoUring::new(2);
+
+    // Submit each group as two `get_ranges_in_group` calls. Each chunk's `user_data` is its group.
+    let (first_half, second_half) = ranges.split_at(N_RANGES_PER_GROUP / 2);
+    for group_id in 0..N_GROUPS {
+        for half in [first_half, second_half] {
+            let user_data = vec![group_id; half.len()];
+            uring.get_ranges_in_group(group_id, &filename, half.to_vec(), user_data)?;
+        }
+    }
+
+    // Every chunk from group n must arrive before any chunk from group n+1.
+    let mut n_chunks_per_group = vec![0; N_GROUPS as usize];
+    for _ in 0..N_RANGES_PER_GROUP * N_GROUPS as usize {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let group_id = c.user_data as usize;
+                assert!(
+                    n_chunks_per_group[..group_id]
+                        .iter()
+                        .all(|&n| n == N_RANGES_PER_GROUP),
+                    "Received a chunk from group {group_id} before all earlier groups had \
+                        finished! {n_chunks_per_group:?}"
+                );
+                n_chunks_per_group[group_id] += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_max_gap() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 8).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("max_gap", &file_contents)?;
+    let mut uring = IoUring::builder(1).max_gap(512).build();
+
+    // The first three ranges (overlapping and nearly-adjacent) should be merged into one read.
+    // The last range is too far away to be merged.
+    let ranges = vec![0..100, 50..512, 600..1024, 4096..-1];
+    uring.get_ranges(&filename, ranges.clone(), vec![0, 1, 2, 3])?;
+
+    let mut n_chunks = 0;
+    while n_chunks < ranges.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let range = &ranges[c.user_data as usize];
+                let end = if range.end < 0 {
+                    file_contents.len()
+                } else {
+                    range.end as usize
+                };
+                assert_eq!(c.range, Some(range.start as usize..end));
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[range.start as usize..end],
+                    "user_data={}",
+                    c.user_data
+                );
+                n_chunks += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_merged_ranges_which_fail_send_one_output_per_range() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("merged_short_read", &file_contents)?;
+    let mut uring = IoUring::builder(1).max_gap(100).build();
+
+    // The second range runs past the end of the file. Every range is non-negative, so the file
+    // isn't `statx`ed, and the ranges are merged into one read, which hits the end of the file.
+    // With a negative range, the file is `statx`ed, so the range past the end of the file isn't
+    // merged with its neighbours.
+    for ranges in [
+        vec![900..950, 960..1100],
+        vec![900..950, 960..1100, -10..-1],
+    ] {
+        let n_ranges = ranges.len();
+        uring.get_ranges(&filename, ranges, (0..n_ranges as u64).collect())?;
+        let mut user_data_of_chunks = Vec::new();
+        for _ in 0..n_ranges {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(c))) => {
+                    let range = match c.user_data {
+                        0 => 900..950,
+                        2 => 990..1000,
+                        _ => panic!("Unexpected chunk {c:?}"),
+                    };
+                    assert_eq!(c.range, Some(range.clone()));
+                    assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+                    user_data_of_chunks.push(c.user_data);
+                }
+                Ok(Err(IoError::ShortRead {
+                    range, got, wanted, ..
+                })) => {
+                    assert_eq!(range, 960..1100);
+                    assert_eq!((got, wanted), (40, 140));
+                }
+                output => panic!("Unexpected output {output:?}"),
+            }
+        }
+        user_data_of_chunks.sort();
+        let expected_user_data: Vec<u64> = (0..n_ranges as u64).filter(|&i| i != 1).collect();
+        assert_eq!(user_data_of_chunks, expected_user_data);
+        assert!(uring
+            .completion()
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_coalesce_window() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 8).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("coalesce_window", &file_contents)?;
+
+    // Adjacent ranges requested by separate calls within the window should be merged into one
+    // read, so the chunks are consecutive views into the same buffer.
+    let mut uring = IoUring::builder(1)
+        .coalesce_window(Duration::from_millis(50), usize::MAX)
+        .build();
+    for i in 0..4 {
+        let start = (i * KIBIBYTE) as isize;
+        uring.get_ranges(
+            &filename,
+            vec![start..start + KIBIBYTE as isize],
+            vec![i as u64],
+        )?;
+    }
+    let mut chunks = Vec::new();
+    while chunks.len() < 4 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => chunks.push(c),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    chunks.sort_by_key(|c| c.user_data);
+    for (i, c) in chunks.iter().enumerate() {
+        let range = i * KIBIBYTE..(i + 1) * KIBIBYTE;
+        assert_eq!(c.range, Some(range.clone()));
+        assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+    }
+    for pair in chunks.windows(2) {
+        assert_eq!(
+            pair[1].buffer.as_ptr(),
+            pair[0].buffer.as_ptr().wrapping_add(KIBIBYTE)
+        );
+    }
+
+    // Reaching `max_bytes` submits the ranges without waiting for the (very long) window.
+    let mut uring = IoUring::builder(1)
+        .coalesce_window(Duration::from_secs(3600), 2 * KIBIBYTE)
+        .build();
+    uring.get_ranges(&filename, vec![0..KIBIBYTE as isize], vec![0])?;
+    uring.get_ranges(&filename, vec![4096..5120], vec![1])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(_))) => (),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    // `submit_barrier` submits the ranges which are still accumulating.
+    uring.get_ranges(&filename, vec![0..100], vec![2])?;
+    uring.submit_barrier()?;
+    match uring.completion().try_recv() {
+        Ok(Ok(Output::Chunk(c))) => assert_eq!(c.user_data, 2),
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_whole_files() -> anyhow::Result<()> {
+    let file_contents: Vec<Vec<u8>> = [100, KIBIBYTE * 4, 5_000]
+        .iter()
+        .enumerate()
+        .map(|(i, &len)| vec![i as u8; len])
+        .collect();
+    let filenames = file_contents
+        .iter()
+        .map(|contents| create_temp_file("whole_files", contents))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut uring = IoUring::new(1);
+    uring.get_whole_files(&filenames)?;
+
+    for _ in 0..filenames.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                assert_eq!(c.buffer.as_slice(), file_contents[c.user_data as usize]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    for filename in filenames {
+        std::fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_range_from_many() -> anyhow::Result<()> {
+    const N_FILES: usize = 20;
+    // Each file ends with a 16-byte "index", and the files have different lengths.
+    let file_contents: Vec<Vec<u8>> = (0..N_FILES)
+        .map(|i| (0..1000 + i * 10).map(|j| (i + j) as u8).collect())
+        .collect();
+    let filenames = file_contents
+        .iter()
+        .map(|contents| create_temp_file("range_from_many", contents))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let user_data: Vec<u64> = (0..N_FILES as u64).map(|i| i * 100).collect();
+    let mut uring = IoUring::builder(2)
+        .max_open_files(2)
+        .sqpoll(SqPoll::Disabled)
+        .build();
+    assert!(uring
+        .get_range_from_many(&filenames, -16..-1, &user_data[1..])
+        .is_err());
+    uring.get_range_from_many(&filenames, -16..-1, &user_data)?;
+
+    let mut received = [false; N_FILES];
+    for _ in 0..N_FILES {
+        match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = (c.user_data / 100) as usize;
+                let contents = &file_contents[i];
+                assert_eq!(c.buffer.as_slice(), &contents[contents.len() - 16..]);
+                assert!(!received[i]);
+                received[i] = true;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+
+    for filename in filenames {
+        std::fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_contiguous() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("get_contiguous", &file_contents)?;
+    let mut uring = IoUring::builder(2).sqpoll(SqPoll::Disabled).build();
+
+    // An unaligned range which spans many filesystem blocks, and a range relative to the end.
+    uring.get_contiguous(&filename, 100..900_100, 0)?;
+    uring.get_contiguous(&filename, -1000..-1, 1)?;
+
+    let mut received = [false; 2];
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let expected = match c.user_data {
+                    0 => &file_contents[100..900_100],
+                    1 => &file_contents[999_000..],
+                    _ => panic!("Unexpected user_data {}", c.user_data),
+                };
+                assert_eq!(c.buffer.as_slice(), expected);
+                assert!(!received[c.user_data as usize]);
+                received[c.user_data as usize] = true;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+
+    // Ranges requested by separate calls (which overlap, or which are separated by less than
+    // `max_gap`) are merged into a single read. But each call still receives exactly one chunk,
+    // which covers exactly its range.
+    let mut uring = IoUring::builder(1)
+        .max_gap(4096)
+        .coalesce_window(Duration::from_millis(50), usize::MAX)
+        .build();
+    let ranges = [0..10_000, 5_000..20_000, 21_000..30_000];
+    for (user_data, range) in ranges.iter().enumerate() {
+        uring.get_contiguous(&filename, range.clone(), user_data as u64)?;
+    }
+    let mut chunks: Vec<Option<Chunk>> = vec![None, None, None];
+    for _ in 0..ranges.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = c.user_data as usize;
+                let range = ranges[i].start as usize..ranges[i].end as usize;
+                assert_eq!(c.range, Some(range.clone()));
+                assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+                assert!(chunks[i].is_none(), "Received two chunks for user_data {i}");
+                chunks[i] = Some(c);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+    // The chunks are views into the same buffer, so the ranges really were merged.
+    let ptrs: Vec<*const u8> = chunks
+        .iter()
+        .map(|c| c.as_ref().unwrap().buffer.as_ptr())
+        .collect();
+    assert_eq!(ptrs[1], ptrs[0].wrapping_add(5_000));
+    assert_eq!(ptrs[2], ptrs[0].wrapping_add(21_000));
+
+    std::fs::remove_file(filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_accepts_any_path_like_location() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..100).collect();
+    let filename = create_temp_file("path_like", &file_contents)?;
+    let filename_str = filename.to_str().unwrap();
+    let mut uring = IoUring::new(1);
+    uring.get_ranges(filename_str, vec![0..10], vec![0])?;
+    let filename_string: String = filename_str.into();
+    uring.get_ranges(filename_string, vec![10..20], vec![1])?;
+    uring.get_ranges(filename.clone(), vec![20..30], vec![2])?;
+    // `Reader` can still be used as a trait object, whose `get_ranges` takes a `&Path`.
+    let reader: &mut dyn Reader = &mut uring;
+    reader.get_ranges(&filename, vec![30..40], vec![3])?;
+
+    let mut received = [false; 4];
+    for _ in 0..4 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data as usize * 10;
+                assert_eq!(c.buffer.as_slice(), &file_contents[start..start + 10]);
+                received[c.user_data as usize] = true;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(received, [true; 4]);
+
+    // A path containing a NUL byte can't be passed to the kernel, so it's rejected up front.
+    assert!(uring.get_ranges("foo\0bar", vec![0..10], vec![4]).is_err());
+    assert!(uring.exists(Path::new("foo\0bar"), 5).is_err());
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_paths_containing_nul_return_errors() -> anyhow::Result<()> {
+    let path = Path::new("lsio_uring\0nul");
+    let mut uring = IoUring::builder(1).sqpoll(SqPoll::Disabled).build();
+    let errors = [
+        uring.get_ranges(path, vec![0..10], vec![0]),
+        uring.get_ranges_with_timeout(path, vec![0..10], vec![1], Duration::from_secs(1)),
+        uring.get_ranges_in_group(0, path, vec![0..10], vec![2]),
+        uring.get_ranges_into(
+            path,
+            vec![0..512],
+            vec![AlignedBytesMut::new(512, 512)],
+            vec![3],
+        ),
+        uring.submit_batch(vec![ReadOp::new(path, vec![0..10], vec![4])]),
+        uring.exists(path, 5),
+        uring.metadata(path, 6),
+        uring.advise(path, 0..10, Advice::WillNeed, Some(7)),
+        uring.put_ranges(path, vec![0..1], vec![aligned_bytes_from(&[0])], vec![8]),
+        uring.copy_ranges(path, vec![0..1], path, vec![0..1], vec![9]),
+        uring.get_stream(path, 512, 10),
+    ];
+    for error in errors {
+        let error = error.expect_err("A path containing a NUL byte should be rejected");
+        assert!(
+            error.to_string().contains(r#""lsio_uring\0nul""#),
+            "The error should name the path: {error}"
+        );
+    }
+
+    // Nothing was submitted.
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_ordered_completion() -> anyhow::Result<()> {
+    const N_SMALL_RANGES: usize = 64;
+    let file_contents: Vec<u8> = (0..MEBIBYTE * 8).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("ordered_completion", &file_contents)?;
+    let missing = std::env::temp_dir().join("lsio_uring_ordered_completion_does_not_exist");
+    let mut uring = IoUring::builder(4).ordered_completion(true).build();
+    assert!(IoUring::new(1).ordered_completion().is_none());
+
+    // The first range is much larger than the others, so it's likely to complete last. The
+    // `user_data` counts down, so that the submission order isn't the order of the `user_data`.
+    let mut expected_order = Vec::new();
+    uring.get_ranges(&filename, vec![0..-1], vec![1000])?;
+    expected_order.push(1000);
+    uring.get_ranges(&missing, vec![0..10, 10..20], vec![999, 998])?;
+    expected_order.extend([999, 998]);
+    let small_ranges = (0..N_SMALL_RANGES)
+        .map(|i| (i * 4096) as isize..(i * 4096 + 100) as isize)
+        .collect();
+    let small_user_data: Vec<u64> = (0..N_SMALL_RANGES as u64).rev().collect();
+    uring.get_ranges(&filename, small_ranges, small_user_data.clone())?;
+    expected_order.extend(small_user_data);
+
+    let ordered = uring.ordered_completion().unwrap();
+    let received_order: Vec<u64> = (0..expected_order.len())
+        .map(
+            |_| match ordered.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Ok(Output::Chunk(c)) => {
+                    let start = if c.user_data == 1000 {
+                        0
+                    } else {
+                        (N_SMALL_RANGES - 1 - c.user_data as usize) * 4096
+                    };
+                    assert_eq!(
+                        c.buffer.as_slice(),
+                        &file_contents[start..start + c.buffer.len()]
+                    );
+                    c.user_data
+                }
+                Err(IoError::NotFound { user_data, .. }) => user_data.unwrap(),
+                output => panic!("Unexpected output {output:?}"),
+            },
+        )
+        .collect();
+    assert_eq!(received_order, expected_order);
+
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_drain_errors() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("drain_errors", &file_contents)?;
+    let missing = std::env::temp_dir().join("lsio_uring_drain_errors_does_not_exist");
+    let mut uring = IoUring::builder(2).file_complete_outputs(true).build();
+    uring.get_ranges(&filename, vec![0..512, 512..-1], vec![0, 1])?;
+    uring.get_ranges(&missing, vec![0..512, 512..-1], vec![2, 3])?;
+
+    let (chunks, errors) = uring.drain_errors(4, Duration::from_secs(5));
+    let mut chunk_user_data: Vec<u64> = chunks.iter().map(|c| c.user_data).collect();
+    chunk_user_data.sort();
+    assert_eq!(chunk_user_data, [0, 1]);
+    let mut error_user_data: Vec<u64> = errors
+        .iter()
+        .map(|err| match err {
+            IoError::NotFound { user_data, .. } => user_data.unwrap(),
+            err => panic!("Unexpected error {err:?}"),
+        })
+        .collect();
+    error_user_data.sort();
+    assert_eq!(error_user_data, [2, 3]);
+
+    // Nothing else arrives, so a zero timeout returns immediately with nothing.
+    uring.submit_barrier()?;
+    let (chunks, errors) = uring.drain_errors(4, Duration::ZERO);
+    assert!(chunks.is_empty() && errors.is_empty());
+
+    std::fs::remove_file(filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_reading_more_files_than_max_open_files() -> anyhow::Result<()> {
+    const N_FILES: usize = 200;
+    let filenames = (0..N_FILES)
+        .map(|i| create_temp_file("max_open_files", &vec![i as u8; 100 + i]))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Only 2 files can be open at once, so most files are read one after another. Without SQPOLL,
+    // the worker threads don't compete with SQPOLL kernel threads for the CPU (which is slow on
+    // machines with few cores).
+    let mut uring = IoUring::builder(3)
+        .max_open_files(2)
+        .sqpoll(SqPoll::Disabled)
+        .build();
+    for (i, filename) in filenames.iter().enumerate() {
+        let user_data = (i * 3) as u64;
+        uring.get_ranges(
+            filename,
+            vec![0..10, 10..20, -10..-1],
+            vec![user_data, user_data + 1, user_data + 2],
+        )?;
+    }
+
+    // Every file is read, even though at most 2 files can be open at once.
+    let mut n_chunks_per_file = vec![0; N_FILES];
+    for _ in 0..N_FILES * 3 {
+        match uring.completion().recv_timeout(Duration::from_secs(2)) {
+            Ok(Ok(Output::Chunk(chunk))) => {
+                let i = chunk.user_data as usize / 3;
+                assert_eq!(chunk.buffer.as_slice(), [i as u8; 10]);
+                n_chunks_per_file[i] += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert!(n_chunks_per_file.iter().all(|&n| n == 3));
+    uring.shutdown()?;
+
+    for filename in filenames {
+        std::fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_worker_stats() -> anyhow::Result<()> {
+    const N_WORKER_THREADS: usize = 2;
+    const N_RANGES: usize = 16;
+    let filename = create_temp_file("worker_stats", &[42; KIBIBYTE * N_RANGES])?;
+    let mut uring = IoUring::new(N_WORKER_THREADS);
+    assert_eq!(uring.worker_stats().len(), N_WORKER_THREADS);
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES as u64).collect())?;
+    for _ in 0..N_RANGES {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(_))) => (),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    // Wait for the `close` operation to finish.
+    let started = Instant::now();
+    while uring.worker_stats().iter().any(|s| s.ops_in_flight() > 0) {
+        assert!(started.elapsed() < Duration::from_millis(500));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    // `openat`, one `read` per range, and `close`. (Every range is non-negative, so the file isn't
+    // `statx`ed.)
+    let sqes_submitted: u64 = uring
+        .worker_stats()
+        .iter()
+        .map(|s| s.sqes_submitted())
+        .sum();
+    let cqes_processed: u64 = uring
+        .worker_stats()
+        .iter()
+        .map(|s| s.cqes_processed())
+        .sum();
+    assert_eq!(sqes_submitted, N_RANGES as u64 + 2);
+    assert_eq!(cqes_processed, sqes_submitted);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_prepared() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 4).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("prepared", &file_contents)?;
+    let mut uring = IoUring::new(1);
+
+    // Share one `CString` between several calls.
+    let location = Arc::new(CString::new(filename.as_os_str().as_bytes())?);
+    const N_CALLS: usize = 4;
+    for i in 0..N_CALLS {
+        let start = (i * KIBIBYTE) as isize;
+        uring.get_ranges_prepared(
+            Arc::clone(&location),
+            vec![start..start + KIBIBYTE as isize],
+            vec![i as u64],
+        )?;
+    }
+
+    for _ in 0..N_CALLS {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let start = c.user_data as usize * KIBIBYTE;
+                assert_eq!(c.buffer.as_slice(), &file_contents[start..start + KIBIBYTE]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_completion_helpers() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 4).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("completion_helpers", &file_contents)?;
+    let mut uring = IoUring::new(1);
+    assert!(matches!(uring.try_recv(), Err(TryRecvError::Empty)));
+    // The helpers (other than `outputs`) can be called on a trait object, so that backends can
+    // be swapped at runtime.
+    let completion: &dyn Completion = &uring;
+    assert!(matches!(
+        completion.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    ));
+
+    const N_RANGES: usize = 4;
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES as u64).collect())?;
+
+    let mut user_data: Vec<u64> = uring
+        .outputs()
+        .take(N_RANGES)
+        .map(|output| match output? {
+            Output::Chunk(c) => {
+                let start = c.user_data as usize * KIBIBYTE;
+                assert_eq!(c.buffer.as_slice(), &file_contents[start..start + KIBIBYTE]);
+                Ok(c.user_data)
+            }
+            output => panic!("Unexpected output {output:?}"),
+        })
+        .collect::<anyhow::Result<_>>()?;
+    user_data.sort();
+    assert_eq!(user_data, (0..N_RANGES as u64).collect::<Vec<_>>());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_negative_ranges() -> anyhow::Result<()> {
+    // An odd file size, so that the ends of the file aren't aligned.
+    const FILE_SIZE: usize = 10_007;
+    let file_contents: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("negative_ranges", &file_contents)?;
+    let mut uring = IoUring::new(1);
+
+    // Each range, and the bytes that it should resolve to. Negative numbers are relative to the
+    // end of the file, where an `end` of -1 means "up to and including the last byte".
+    let ranges = vec![0..-1, -100..-1, -500..-100, 0..100, 700..1_000];
+    let expected = [
+        0..FILE_SIZE,
+        FILE_SIZE - 100..FILE_SIZE,
+        FILE_SIZE - 500..FILE_SIZE - 99,
+        0..100,
+        700..1_000,
+    ];
+    uring.get_ranges(
+        &filename,
+        ranges.clone(),
+        (0..ranges.len() as u64).collect(),
+    )?;
+
+    for _ in 0..ranges.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = c.user_data as usize;
+                assert_eq!(c.buffer.len(), expected[i].len(), "range={:?}", ranges[i]);
+                assert_eq!(c.range, Some(expected[i].clone()));
+                assert_eq!(
+                    c.buffer.as_slice(),
+                    &file_contents[expected[i].clone()],
+                    "range={:?}",
+                    ranges[i]
+                );
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_slow_consumer_applies_backpressure() -> anyhow::Result<()> {
+    const N_RANGES: usize = 256;
+    const HIGH_WATER_MARK: usize = 4;
+    let filename = create_temp_file("backpressure", &vec![42; KIBIBYTE * N_RANGES])?;
+    let mut uring = IoUring::builder(1)
+        .output_high_water_mark(HIGH_WATER_MARK)
+        .build();
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES as u64).collect())?;
+
+    // Consume the outputs slowly. The workers must not deadlock, and must not flood the channel.
+    let mut max_queued_outputs = 0;
+    for _ in 0..N_RANGES {
+        std::thread::sleep(Duration::from_millis(1));
+        max_queued_outputs = max_queued_outputs.max(uring.completion().len());
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => assert_eq!(c.buffer.len(), KIBIBYTE),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    // The channel can exceed the high-water mark by the number of operations in flight.
+    assert!(
+        max_queued_outputs < N_RANGES / 2,
+        "max_queued_outputs={max_queued_outputs}"
+    );
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_beyond_end_of_file_is_an_error() -> anyhow::Result<()> {
+    let filename = create_temp_file("beyond_eof", &[42; KIBIBYTE])?;
+    let mut uring = IoUring::new(1);
+
+    // The first `read` will return fewer bytes than requested. The retry will read zero bytes.
+    uring.get_ranges(&filename, vec![0..(KIBIBYTE * 4) as isize], vec![0])?;
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(err @ IoError::ShortRead { .. })) => {
+            assert!(err.to_string().contains("end of the file"), "{err}");
+            let IoError::ShortRead {
+                path, got, wanted, ..
+            } = err
+            else {
+                unreachable!()
+            };
+            assert_eq!(path, filename);
+            assert_eq!(got, KIBIBYTE);
+            assert_eq!(wanted, KIBIBYTE * 4);
+        }
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_after_file_is_truncated() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 8).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("truncated", &file_contents)?;
+    let mut uring = IoUring::new(1);
+    let completion = uring.completion().clone();
+    let recv = || completion.recv_timeout(Duration::from_millis(500));
+
+    // Cache the file size. (The range must be negative, otherwise the file isn't `statx`ed.)
+    uring.get_ranges(&filename, vec![-2..-1], vec![0])?;
+    assert!(matches!(recv(), Ok(Ok(Output::Chunk(_)))));
+
+    // Truncate the file behind the `IoUring`'s back. The cached file size is now stale, so the
+    // read lands beyond the end of the file.
+    File::options()
+        .write(true)
+        .open(&filename)?
+        .set_len(KIBIBYTE as u64)?;
+    uring.get_ranges(&filename, vec![-100..-1], vec![1])?;
+    match recv() {
+        Ok(Err(IoError::ShortRead {
+            got,
+            wanted,
+            file_size,
+            ..
+        })) => {
+            assert!(got < wanted);
+            assert_eq!(file_size, Some((KIBIBYTE * 8) as u64));
+        }
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    // The short read removed the stale size from the cache, so the file is `statx`ed again.
+    uring.get_ranges(&filename, vec![-100..-1], vec![2])?;
+    match recv() {
+        Ok(Ok(Output::Chunk(chunk))) => {
+            assert_eq!(
+                chunk.buffer.as_slice(),
+                &file_contents[KIBIBYTE - 100..KIBIBYTE]
+            );
+        }
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    // A negative range which reaches back beyond the start of the file is an error (not a panic),
+    // and doesn't stop the other ranges from being read.
+    uring.get_ranges(
+        &filename,
+        vec![-(KIBIBYTE as isize) * 2..-1, 0..10],
+        vec![3, 4],
+    )?;
+    let mut n_invalid_ranges = 0;
+    for _ in 0..2 {
+        match recv() {
+            Ok(Err(IoError::InvalidRange { range, .. })) => {
+                assert_eq!(range, -(KIBIBYTE as isize) * 2..-1);
+                n_invalid_ranges += 1;
+            }
+            Ok(Ok(Output::Chunk(chunk))) => assert_eq!(chunk.user_data, 4),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_invalid_ranges, 1);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_fixed_buffers() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const N_CHUNKS: usize = 64;
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * N_CHUNKS)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("fixed_buffers", &file_contents)?;
+    // Fewer registered buffers than chunks, so some reads have to wait for buffers to be
+    // recycled, or fall back to allocating new buffers.
+    let mut uring = IoUring::builder(2).fixed_buffers(8, CHUNK_SIZE).build();
+
+    // The last range is too large for a registered buffer, and isn't aligned.
+    let mut ranges: Vec<_> = (0..N_CHUNKS)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    ranges.push(100..(CHUNK_SIZE * 3) as isize);
+    uring.get_ranges(
+        &filename,
+        ranges.clone(),
+        (0..ranges.len() as u64).collect(),
+    )?;
+
+    for _ in 0..ranges.len() {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let range = &ranges[c.user_data as usize];
+                let range = range.start as usize..range.end as usize;
+                assert_eq!(c.range, Some(range.clone()));
+                assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+                // Dropping `c` returns its buffer to the pool of registered buffers.
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    // Some reads used the registered buffers. The last range can't.
+    let fixed_buffer_reads: u64 = uring
+        .worker_stats()
+        .iter()
+        .map(|s| s.fixed_buffer_reads())
+        .sum();
+    assert!(
+        fixed_buffer_reads > 0 && fixed_buffer_reads <= N_CHUNKS as u64,
+        "fixed_buffer_reads={fixed_buffer_reads}"
+    );
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_buffer_pool() -> anyhow::Result<()> {
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const N_CHUNKS: usize = 64;
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * N_CHUNKS)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("buffer_pool", &file_contents)?;
+    let buffer_pool = BufferPool::new(16);
+    let mut uring = IoUring::builder(2).buffer_pool(buffer_pool.clone()).build();
+
+    // Read the file twice. The second pass should reuse the buffers freed by the first pass.
+    for _ in 0..2 {
+        // The last range isn't aligned, and is in a different size class.
+        let mut ranges: Vec<_> = (0..N_CHUNKS)
+            .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+            .collect();
+        ranges.push(100..(CHUNK_SIZE * 3) as isize);
+        uring.get_ranges(
+            &filename,
+            ranges.clone(),
+            (0..ranges.len() as u64).collect(),
+        )?;
+
+        for _ in 0..ranges.len() {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(c))) => {
+                    let range = &ranges[c.user_data as usize];
+                    let range = range.start as usize..range.end as usize;
+                    assert_eq!(c.range, Some(range.clone()));
+                    assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+                    // Dropping `c` returns its buffer to the pool.
+                }
+                output => panic!("Unexpected output {output:?}"),
+            }
+        }
+    }
+    assert!(buffer_pool.n_recycled() > 0);
+    // No buffers were registered, so no reads used `ReadFixed`.
+    assert!(uring
+        .worker_stats()
+        .iter()
+        .all(|s| s.fixed_buffer_reads() == 0));
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_get_ranges_with_checksums() -> anyhow::Result<()> {
+    use lsio_io::Checksum;
+
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 16).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("checksums", &file_contents)?;
+    // Merging must not interfere with the checksums.
+    let mut uring = IoUring::builder(1).max_gap(KIBIBYTE).build();
+
+    let ranges = vec![0..1000, 1000..4096, -100..-1];
+    let checksums = vec![
+        Checksum::crc32c(&file_contents[0..1000]),
+        Checksum::xxhash64(&file_contents[1000..4096]),
+        // The wrong range, so the checksum won't match.
+        Checksum::xxhash64(&file_contents[..100]),
+    ];
+    assert!(uring
+        .get_ranges_with_checksums(&filename, ranges.clone(), vec![0, 1, 2], vec![])
+        .is_err());
+    uring.get_ranges_with_checksums(&filename, ranges, vec![0, 1, 2], checksums.clone())?;
+
+    let mut n_chunks = 0;
+    for _ in 0..3 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                assert!(checksums[c.user_data as usize]
+                    .verify(c.buffer.as_slice())
+                    .is_ok());
+                n_chunks += 1;
+            }
+            Ok(Err(IoError::ChecksumMismatch {
+                user_data: 2,
+                expected,
+                actual,
+                ..
+            })) => {
+                assert_eq!(expected, checksums[2]);
+                assert_eq!(
+                    actual,
+                    Checksum::xxhash64(&file_contents[file_contents.len() - 100..])
+                );
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!(n_chunks, 2);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_huge_pages() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..MEBIBYTE * 5).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("huge_pages", &file_contents)?;
+    let mut uring = IoUring::builder(1).huge_pages(MEBIBYTE).build();
+
+    // Only the first range is above the threshold.
+    let ranges = vec![0..-1, 0..KIBIBYTE as isize];
+    uring.get_ranges(&filename, ranges, vec![0, 1])?;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Ok(Output::Chunk(c))) if c.user_data == 0 => {
+                assert_eq!(c.buffer.as_slice(), &file_contents[..]);
+                assert_eq!(
+                    c.buffer.as_ptr() as usize % lsio_aligned_bytes::HUGE_PAGE_SIZE,
+                    0
+                );
+            }
+            Ok(Ok(Output::Chunk(c))) => {
+                assert_eq!(c.buffer.as_slice(), &file_contents[..KIBIBYTE]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_file_complete_outputs() -> anyhow::Result<()> {
+    const N_FILES: usize = 2;
+    const N_CHUNKS_PER_FILE: usize = 3;
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    let filenames: Vec<PathBuf> = (0..N_FILES)
+        .map(|i| create_temp_file(&format!("file_complete_{i}"), &[i as u8; CHUNK_SIZE * 4]))
+        .collect::<std::io::Result<_>>()?;
+    let mut uring = IoUring::builder(2).file_complete_outputs(true).build();
+    for (i, filename) in filenames.iter().enumerate() {
+        let ranges: Vec<_> = (0..N_CHUNKS_PER_FILE)
+            .map(|j| (j * CHUNK_SIZE) as isize..((j + 1) * CHUNK_SIZE) as isize)
+            .collect();
+        let user_data = (0..N_CHUNKS_PER_FILE)
+            .map(|j| (i * N_CHUNKS_PER_FILE + j) as u64)
+            .collect();
+        uring.get_ranges(filename, ranges, user_data)?;
+    }
+
+    // Each file's `FileComplete` must arrive after all of that file's chunks.
+    let mut n_chunks_received = [0; N_FILES];
+    let mut last_user_data = [None; N_FILES];
+    let mut n_files_complete = 0;
+    while n_files_complete < N_FILES {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let i = c.user_data as usize / N_CHUNKS_PER_FILE;
+                n_chunks_received[i] += 1;
+                last_user_data[i] = Some(c.user_data);
+            }
+            Ok(Ok(Output::FileComplete {
+                location,
+                user_data_of_last,
+            })) => {
+                let i = filenames.iter().position(|f| *f == location).unwrap();
+                assert_eq!(n_chunks_received[i], N_CHUNKS_PER_FILE);
+                assert_eq!(last_user_data[i], Some(user_data_of_last));
+                n_files_complete += 1;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert!(uring.completion().try_recv().is_err());
+
+    for filename in &filenames {
+        std::fs::remove_file(filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_retries_transient_errors() -> anyhow::Result<()> {
+    // Reading this process's memory at an address which isn't mapped (address 0) always fails
+    // with `EIO`, which is a transient error. `/proc/self/mem` doesn't support `O_DIRECT`.
+    const BACKOFF: Duration = Duration::from_millis(50);
+    let filename = PathBuf::from("/proc/self/mem");
+
+    // Returns the error, and the number of CQEs processed by the `IoUring`.
+    let read_unmapped_memory = |uring: &mut IoUring| -> anyhow::Result<(IoError, u64)> {
+        uring.get_ranges(&filename, vec![0..4096], vec![0])?;
+        let err = match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Err(err)) => err,
+            output => panic!("Unexpected output {output:?}"),
+        };
+        uring.submit_barrier()?;
+        // Each range produces exactly one error, however many times its read is retried.
+        assert!(uring.completion().is_empty());
+        let n_cqes = uring
+            .worker_stats()
+            .iter()
+            .map(|s| s.cqes_processed())
+            .sum();
+        Ok((err, n_cqes))
+    };
+
+    let mut uring = IoUring::builder(1).direct_io(false).build();
+    let (err, n_cqes_without_retries) = read_unmapped_memory(&mut uring)?;
+    assert!(
+        matches!(
+            err,
+            IoError::Nix {
+                errno: nix::Error::EIO,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+
+    let mut uring = IoUring::builder(1)
+        .direct_io(false)
+        .retries(2)
+        .backoff(BACKOFF)
+        .build();
+    let start = Instant::now();
+    let (err, n_cqes_with_retries) = read_unmapped_memory(&mut uring)?;
+    // The retries wait for 50 ms and then 100 ms.
+    assert!(start.elapsed() >= BACKOFF * 3);
+    assert!(
+        matches!(
+            err,
+            IoError::Nix {
+                errno: nix::Error::EIO,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+    // Each retry adds a `Timeout` CQE and a `read` CQE.
+    assert_eq!(n_cqes_with_retries, n_cqes_without_retries + 4);
+
+    // `ENOENT` is never retried, so the error arrives long before the first backoff would expire.
+    let mut uring = IoUring::builder(1)
+        .retries(2)
+        .backoff(Duration::from_secs(10))
+        .build();
+    uring.get_ranges(
+        PathBuf::from("/tmp/lsio_uring_missing_file"),
+        vec![0..100],
+        vec![0],
+    )?;
+    match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+        Ok(Err(IoError::NotFound { user_data, .. })) => assert_eq!(user_data, Some(0)),
+        output => panic!("Unexpected output {output:?}"),
+    }
+    uring.submit_barrier()?;
+    assert!(uring.completion().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_timeout() -> anyhow::Result<()> {
+    // Files opened with `O_DIRECT` can't be made to hang, so this test checks that reads which
+    // complete within the timeout are unaffected (and that each read's `LinkTimeout` CQE is
+    // accounted for, so the operations finish).
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const N_CHUNKS: usize = 32;
+    let file_contents: Vec<u8> = (0..CHUNK_SIZE * N_CHUNKS)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let filename = create_temp_file("timeout", &file_contents)?;
+    let mut uring = IoUring::new(2);
+    let ranges: Vec<_> = (0..N_CHUNKS)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE - 100) as isize)
+        .collect();
+    uring.get_ranges_with_timeout(
+        &filename,
+        ranges.clone(),
+        (0..N_CHUNKS as u64).collect(),
+        Duration::from_secs(10),
+    )?;
+
+    for _ in 0..N_CHUNKS {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let range = &ranges[c.user_data as usize];
+                let range = range.start as usize..range.end as usize;
+                assert_eq!(c.buffer.as_slice(), &file_contents[range]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert!(uring.completion().try_recv().is_err());
+    uring.shutdown()?;
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_and_without_direct_io() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..KIBIBYTE * 16).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("direct_io", &file_contents)?;
+    // Neither the offsets nor the lengths are aligned.
+    let ranges = vec![0..1, 100..5000, 513..1023, 4096..8192, -1000..-1];
+
+    let read_chunks = |direct_io: bool| -> Vec<Vec<u8>> {
+        let mut uring = IoUring::builder(2).direct_io(direct_io).build();
+        uring
+            .get_ranges(
+                &filename,
+                ranges.clone(),
+                (0..ranges.len() as u64).collect(),
+            )
+            .unwrap();
+        let mut chunks = vec![Vec::new(); ranges.len()];
+        for _ in 0..ranges.len() {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(c))) => {
+                    chunks[c.user_data as usize] = c.buffer.as_slice().to_vec();
+                }
+                output => panic!("Unexpected output {output:?}"),
+            }
+        }
+        chunks
+    };
+
+    let direct = read_chunks(true);
+    let buffered = read_chunks(false);
+    assert_eq!(direct, buffered);
+    assert_eq!(buffered[1], &file_contents[100..5000]);
+    assert_eq!(buffered[4], &file_contents[file_contents.len() - 1000..]);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_fixed_files() -> anyhow::Result<()> {
+    const N_FILES: usize = 32;
+    const N_CHUNKS_PER_FILE: usize = 4;
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    let files: Vec<(PathBuf, Vec<u8>)> = (0..N_FILES)
+        .map(|i| {
+            let contents: Vec<u8> = (0..CHUNK_SIZE * N_CHUNKS_PER_FILE)
+                .map(|j| ((i + j) % 251) as u8)
+                .collect();
+            let filename = create_temp_file("fixed_files", &contents).unwrap();
+            (filename, contents)
+        })
+        .collect();
+    let mut uring = IoUring::builder(2).fixed_files(true).build();
+
+    let ranges: Vec<_> = (0..N_CHUNKS_PER_FILE)
+        .map(|i| (i * CHUNK_SIZE) as isize..((i + 1) * CHUNK_SIZE) as isize)
+        .collect();
+    for (file_i, (filename, _)) in files.iter().enumerate() {
+        let user_data = (0..N_CHUNKS_PER_FILE)
+            .map(|chunk_i| (file_i * N_CHUNKS_PER_FILE + chunk_i) as u64)
+            .collect();
+        uring.get_ranges(filename, ranges.clone(), user_data)?;
+    }
+
+    for _ in 0..N_FILES * N_CHUNKS_PER_FILE {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                let file_i = c.user_data as usize / N_CHUNKS_PER_FILE;
+                let range = &ranges[c.user_data as usize % N_CHUNKS_PER_FILE];
+                let range = range.start as usize..range.end as usize;
+                assert_eq!(c.buffer.as_slice(), &files[file_i].1[range]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    // Every file (and its slot in the table of fixed files) is closed.
+    uring.shutdown()?;
+    for (filename, _) in files {
+        std::fs::remove_file(&filename)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shutdown_waits_for_operations_in_flight() -> anyhow::Result<()> {
+    const N_RANGES: usize = 256;
+    let filename = create_temp_file("shutdown", &vec![42; KIBIBYTE * N_RANGES])?;
+    let mut uring = IoUring::new(2);
+    let ranges = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES as u64).collect())?;
+    uring.get_ranges_in_group(0, &filename, vec![0..-1], vec![N_RANGES as u64])?;
+    uring.get_ranges_in_group(1, &filename, vec![0..-1], vec![N_RANGES as u64 + 1])?;
+
+    // Shut down immediately, whilst the operations are still in flight.
+    let completion = uring.completion().clone();
+    uring.shutdown()?;
+
+    // Every output must already be in the channel.
+    let mut n_chunks = 0;
+    while let Ok(output) = completion.try_recv() {
+        assert!(matches!(output, Ok(Output::Chunk(_))), "{output:?}");
+        n_chunks += 1;
+    }
+    assert_eq!(n_chunks, N_RANGES + 2);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_submit_barrier() -> anyhow::Result<()> {
+    const N_RANGES: usize = 64;
+    let filename = create_temp_file("barrier", &vec![42; KIBIBYTE * N_RANGES])?;
+    let mut uring = IoUring::builder(2).max_open_files(1).build();
+    let ranges: Vec<_> = (0..N_RANGES)
+        .map(|i| (i * KIBIBYTE) as isize..((i + 1) * KIBIBYTE) as isize)
+        .collect();
+
+    // The barrier covers held-back operations (in groups, and waiting for a file to be closed).
+    for round in 0..2u64 {
+        let first_user_data = round * N_RANGES as u64;
+        let user_data = (first_user_data..first_user_data + N_RANGES as u64).collect();
+        uring.get_ranges(&filename, ranges.clone(), user_data)?;
+        uring.get_ranges_in_group(round, &filename, vec![0..-1], vec![u64::MAX])?;
+        uring.submit_barrier()?;
+
+        // Every output of this round must already be in the channel.
+        let mut n_chunks = 0;
+        while let Ok(output) = uring.try_recv() {
+            assert!(matches!(output, Ok(Output::Chunk(_))), "{output:?}");
+            n_chunks += 1;
+        }
+        assert_eq!(n_chunks, N_RANGES + 1);
+    }
+    assert!(uring.worker_stats().iter().all(|s| s.ops_in_flight() == 0));
+
+    // A barrier with nothing in flight returns immediately.
+    uring.submit_barrier()?;
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_worker_thread_panic_is_reported() -> anyhow::Result<()> {
+    // The kernel refuses to create a CQ this large, so the worker thread panics on startup.
+    let mut uring = IoUring::builder(1).setup_cqsize(u32::MAX).build();
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(IoError::Internal { message })) => {
+            assert!(message.contains("Worker thread 0 panicked"), "{message}");
+            assert!(
+                message.contains("Failed to initialise io_uring"),
+                "{message}"
+            );
+        }
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    let err = uring.get_ranges(PathBuf::from("/tmp/foo"), vec![0..-1], vec![0]);
+    let err = err.unwrap_err().to_string();
+    assert!(err.contains("Worker thread 0 panicked"), "{err}");
+    assert!(uring.submit_barrier().is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_read_errors_identify_their_range() -> anyhow::Result<()> {
+    // A directory can be opened, but `read` fails with `EISDIR`, so each range's `read` fails.
+    let directory =
+        std::env::temp_dir().join(format!("lsio_uring_read_errors_{}", rand::random::<u32>()));
+    std::fs::create_dir(&directory)?;
+    let mut uring = IoUring::builder(1).direct_io(false).build();
+    uring.get_ranges(&directory, vec![0..10, 0..10, 20..30], vec![0, 1, 2])?;
+
+    // The ranges overlap, so only `user_data` can tell the user which range failed.
+    let mut user_data_of_errors = Vec::new();
+    for _ in 0..3 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(err @ IoError::Nix { .. })) => {
+                user_data_of_errors.push(err.user_data().unwrap())
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    user_data_of_errors.sort();
+    assert_eq!(user_data_of_errors, [0, 1, 2]);
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+
+    std::fs::remove_dir(&directory)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_from_missing_file_is_not_found() -> anyhow::Result<()> {
+    let filename = std::env::temp_dir().join("lsio_uring_this_file_does_not_exist");
+    let mut uring = IoUring::new(1);
+    const N_RANGES: u64 = 10;
+    let ranges = (0..N_RANGES as isize).map(|i| i..i + 1).collect();
+    uring.get_ranges(&filename, ranges, (0..N_RANGES).collect())?;
+
+    // Both the `openat` and the `statx` fail, but we expect exactly one error per range.
+    let mut user_data_of_errors = Vec::new();
+    for _ in 0..N_RANGES {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(IoError::NotFound {
+                path,
+                user_data: Some(user_data),
+                ..
+            })) => {
+                assert_eq!(path, filename);
+                user_data_of_errors.push(user_data);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    user_data_of_errors.sort();
+    assert_eq!(user_data_of_errors, (0..N_RANGES).collect::<Vec<_>>());
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_get_ranges_with_mismatched_user_data_is_an_error() {
+    let filename = std::env::temp_dir().join("lsio_uring_mismatched_user_data");
+    let mut uring = IoUring::new(1);
+    let err = uring
+        .get_ranges(&filename, vec![0..1, 1..2], vec![0])
+        .unwrap_err();
+    assert!(err.to_string().contains("1 user_data instances"), "{err}");
+    assert!(uring
+        .get_ranges_in_group(0, &filename, vec![0..1], vec![0, 1])
+        .is_err());
+    assert!(uring
+        .get_ranges_with_timeout(&filename, vec![0..1], vec![], Duration::from_secs(1))
+        .is_err());
+
+    // Nothing was submitted, so the file wasn't opened and no errors were sent.
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+}
+
+#[test]
+fn test_get_ranges_with_no_ranges_is_an_error() -> anyhow::Result<()> {
+    let filename = create_temp_file("no_ranges", b"hello")?;
+    let mut uring = IoUring::new(1);
+    let err = uring.get_ranges(&filename, vec![], vec![]).unwrap_err();
+    assert!(err.to_string().contains("No ranges"), "{err}");
+    assert!(uring
+        .get_ranges_in_group(0, &filename, vec![], vec![])
+        .is_err());
+    assert!(uring
+        .get_ranges_into(&filename, vec![], vec![], vec![])
+        .is_err());
+
+    // Nothing was submitted, so the file was never opened (and so can't have been leaked).
+    uring.submit_barrier()?;
+    assert!(uring.completion().try_recv().is_err());
+    let n_open_descriptors_of_file = std::fs::read_dir("/proc/self/fd")?
+        .filter_map(|entry| std::fs::read_link(entry.ok()?.path()).ok())
+        .filter(|target| *target == filename)
+        .count();
+    assert_eq!(n_open_descriptors_of_file, 0);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_list() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("lsio_uring_list_{}", rand::random::<u32>()));
+    std::fs::create_dir(&dir)?;
+    std::fs::write(dir.join("a"), [0; 10])?;
+    std::fs::write(dir.join("b"), [0; 20])?;
+    std::fs::create_dir(dir.join("c"))?;
+
+    // Listing a directory which doesn't exist is an error.
+    let mut uring = IoUring::new(1);
+    uring.list(&dir)?;
+    uring.list(&dir.join("does_not_exist"))?;
+
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Listing(mut listing))) => {
+                listing.sort_by(|a, b| a.path.cmp(&b.path));
+                assert_eq!(listing.len(), 3);
+                let file = |name, size| {
+                    let metadata = std::fs::metadata(dir.join(name)).unwrap();
+                    FileMetadata {
+                        path: dir.join(name),
+                        size,
+                        is_dir: false,
+                        mtime: metadata.modified().ok(),
+                        mode: metadata.mode(),
+                    }
+                };
+                assert_eq!(listing[0], file("a", 10));
+                assert_eq!(listing[1], file("b", 20));
+                assert_eq!(listing[2].path, dir.join("c"));
+                assert!(listing[2].is_dir);
+            }
+            Ok(Err(err)) => assert!(err.to_string().contains("does_not_exist"), "{err}"),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_directory() -> anyhow::Result<()> {
+    // Create a directory tree like an unsharded Zarr array: `root/c/<i>/<j>`.
+    let root = std::env::temp_dir().join(format!(
+        "lsio_uring_get_directory_{}",
+        rand::random::<u32>()
+    ));
+    let mut relative_paths = Vec::new();
+    for i in 0..4 {
+        std::fs::create_dir_all(root.join("c").join(i.to_string()))?;
+        for j in 0..5 {
+            relative_paths.push(PathBuf::from(format!("c/{i}/{j}")));
+        }
+    }
+    relative_paths.push(PathBuf::from("zarr.json"));
+    for (i, relative_path) in relative_paths.iter().enumerate() {
+        std::fs::write(root.join(relative_path), vec![i as u8; 100 + i])?;
+    }
+
+    let mut uring = IoUring::new(2);
+    let mut n_chunks = 0;
+    for output in uring
+        .get_directory(&root, |relative_path| {
+            relative_paths
+                .iter()
+                .position(|p| p == relative_path)
+                .unwrap() as u64
+        })?
+        .with_max_open_files(3)
+    {
+        let Output::Chunk(chunk) = output? else {
+            panic!("Expected a chunk");
+        };
+        let i = chunk.user_data as usize;
+        assert_eq!(chunk.buffer.as_slice(), vec![i as u8; 100 + i]);
+        n_chunks += 1;
+    }
+    assert_eq!(n_chunks, relative_paths.len());
+    assert!(uring
+        .completion()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+
+    // A missing root produces a single error.
+    let outputs: Vec<_> = uring
+        .get_directory(&root.join("does_not_exist"), |_| 0)?
+        .collect();
+    assert!(
+        matches!(outputs[..], [Err(IoError::ReadDir { .. })]),
+        "{outputs:?}"
+    );
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_exists() -> anyhow::Result<()> {
+    let filename = create_temp_file("exists", &[0; 1234])?;
+    let missing = filename.with_extension("missing");
+    let mut uring = IoUring::new(1);
+    uring.exists(&filename, 1)?;
+    uring.exists(&missing, 2)?;
+
+    let mut outputs = Vec::new();
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Exists {
+                user_data,
+                exists,
+                size,
+            })) => outputs.push((user_data, exists, size)),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    outputs.sort();
+    assert_eq!(outputs, [(1, true, Some(1234)), (2, false, None)]);
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_metadata() -> anyhow::Result<()> {
+    let filename = create_temp_file("metadata", &[0; 1234])?;
+    let mtime = std::time::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    std::fs::File::options()
+        .write(true)
+        .open(&filename)?
+        .set_modified(mtime)?;
+    std::fs::set_permissions(&filename, std::fs::Permissions::from_mode(0o640))?;
+    let dir = filename.parent().unwrap();
+    let missing = filename.with_extension("missing");
+    let mut uring = IoUring::new(1);
+    uring.metadata(&filename, 1)?;
+    uring.metadata(dir, 2)?;
+    uring.metadata(&missing, 3)?;
+
+    for _ in 0..3 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Metadata {
+                user_data: 1,
+                metadata,
+            })) => {
+                assert_eq!(metadata.path, filename);
+                assert_eq!(metadata.size, 1234);
+                assert!(!metadata.is_dir);
+                assert_eq!(metadata.mtime, Some(mtime));
+                assert_eq!(metadata.permissions(), 0o640);
+            }
+            Ok(Ok(Output::Metadata {
+                user_data: 2,
+                metadata,
+            })) => assert!(metadata.is_dir),
+            Ok(Err(IoError::NotFound {
+                path,
+                user_data: Some(3),
+                ..
+            })) => assert_eq!(path, missing),
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_advise() -> anyhow::Result<()> {
+    let filename = create_temp_file("advise", &[0; 1234])?;
+    let missing = filename.with_extension("missing");
+    let mut uring = IoUring::new(1);
+    assert!(uring
+        .advise(&filename, -100..-1, Advice::WillNeed, None)
+        .is_err());
+
+    // Fire-and-forget, so there's no output (not even an error).
+    uring.advise(&missing, 0..-1, Advice::Sequential, None)?;
+    uring.advise(&filename, 0..-1, Advice::WillNeed, Some(1))?;
+    uring.advise(&missing, 0..100, Advice::DontNeed, Some(2))?;
+    let mut n_advised = 0;
+    let mut n_not_found = 0;
+    for _ in 0..2 {
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Advised { user_data: 1 })) => n_advised += 1,
+            Ok(Err(IoError::NotFound {
+                user_data: Some(2), ..
+            })) => n_not_found += 1,
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+    assert_eq!((n_advised, n_not_found), (1, 1));
+
+    // Wait for the file to be closed.
+    let started = Instant::now();
+    while uring.worker_stats().iter().any(|s| s.ops_in_flight() > 0) {
+        assert!(started.elapsed() < Duration::from_millis(500));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(uring.completion().is_empty());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+/// Receive the outputs of the stream `user_data` until its `StreamEnd`, and return the bytes read.
+fn recv_stream(uring: &IoUring, user_data: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    loop {
+        match uring.completion().recv_timeout(Duration::from_millis(1000)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                assert_eq!(c.user_data, user_data);
+                assert_eq!(
+                    c.range,
+                    Some(contents.len()..contents.len() + c.buffer.len())
+                );
+                contents.extend_from_slice(c.buffer.as_slice());
+            }
+            Ok(Ok(Output::StreamEnd {
+                user_data: u,
+                nbytes,
+            })) => {
+                assert_eq!(u, user_data);
+                assert_eq!(nbytes, contents.len());
+                return contents;
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_get_stream_from_pipe() -> anyhow::Result<()> {
+    let contents: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    let (read_fd, write_fd) = nix::unistd::pipe()?;
+    let mut uring = IoUring::new(1);
+    assert!(uring.get_stream(read_fd.try_clone()?, 0, 0).is_err());
+    uring.get_stream(read_fd, 4096, 7)?;
+
+    // Write in small pieces, so the reads return fewer than `chunk_size` bytes.
+    let expected = contents.clone();
+    let writer = std::thread::spawn(move || {
+        let mut pipe = File::from(write_fd);
+        for piece in contents.chunks(1000) {
+            pipe.write_all(piece).unwrap();
+            std::thread::sleep(Duration::from_micros(100));
+        }
+        // Dropping `pipe` closes the write end, which ends the stream.
+    });
+    assert_eq!(recv_stream(&uring, 7), expected);
+    writer.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_get_stream_from_fifo() -> anyhow::Result<()> {
+    let filename = std::env::temp_dir().join(format!("lsio_uring_fifo_{}", rand::random::<u32>()));
+    nix::unistd::mkfifo(&filename, nix::sys::stat::Mode::S_IRWXU)?;
+    let mut uring = IoUring::builder(1).direct_io(true).build();
+    // Opening the FIFO waits until the writer opens it.
+    uring.get_stream(filename.as_path(), 1024, 3)?;
+
+    let contents: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    let writer = {
+        let filename = filename.clone();
+        let contents = contents.clone();
+        std::thread::spawn(move || File::create(filename)?.write_all(&contents))
+    };
+    assert_eq!(recv_stream(&uring, 3), contents);
+    writer.join().unwrap()?;
+
+    uring.get_stream(filename.with_extension("missing"), 1024, 4)?;
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Err(IoError::NotFound {
+            user_data: Some(4), ..
+        })) => (),
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_dropping_io_uring_cancels_streams() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = nix::unistd::pipe()?;
+    let mut uring = IoUring::new(1);
+    uring.get_stream(read_fd, 1024, 0)?;
+    // Wait for the read to be submitted. Nothing is ever written, so the read would wait forever.
+    let started = Instant::now();
+    while uring.worker_stats()[0].ops_in_flight() == 0 {
+        assert!(started.elapsed() < Duration::from_millis(500));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let completion = uring.completion().clone();
+    drop(uring);
+    match completion.recv() {
+        Ok(Err(IoError::Nix {
+            errno: nix::Error::ECANCELED,
+            user_data: Some(0),
+            ..
+        })) => (),
+        output => panic!("Unexpected output {output:?}"),
+    }
+    assert!(completion.recv().is_err());
+    drop(write_fd);
+    Ok(())
+}
+
+#[test]
+fn test_probe_and_try_new() -> anyhow::Result<()> {
+    let capabilities = IoUring::probe()?;
+    assert!(capabilities.is_supported(), "{capabilities:?}");
+    assert!(capabilities.missing().is_empty());
+
+    let mut uring = IoUring::try_new(1)?;
+    let filename = create_temp_file("try_new", &[1, 2, 3])?;
+    uring.get_ranges(&filename, vec![0..3], vec![0])?;
+    match uring.completion().recv_timeout(Duration::from_millis(500)) {
+        Ok(Ok(Output::Chunk(c))) => assert_eq!(c.buffer.as_slice(), [1, 2, 3]),
+        output => panic!("Unexpected output {output:?}"),
+    }
+
+    // The kernel refuses to create a CQ this large, so `try_build` returns an error.
+    assert!(IoUring::builder(1)
+        .setup_cqsize(u32::MAX)
+        .try_build()
+        .is_err());
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_wait_strategies() -> anyhow::Result<()> {
+    let file_contents: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    let filename = create_temp_file("wait_strategies", &file_contents)?;
+    for wait_strategy in [
+        WaitStrategy::Block,
+        WaitStrategy::BusyPoll { max_spins: 1 },
+        WaitStrategy::BusyPoll { max_spins: 1_000 },
+        WaitStrategy::Hybrid,
+    ] {
+        let mut uring = IoUring::builder(1)
+            .sqpoll(SqPoll::Disabled)
+            .wait_strategy(wait_strategy)
+            .build();
+
+        // A stream which waits for data mustn't stop the worker from picking up new operations.
+        let (read_fd, write_fd) = nix::unistd::pipe()?;
+        uring.get_stream(read_fd, 1024, 100)?;
+        let started = Instant::now();
+        while uring.worker_stats()[0].ops_in_flight() == 0 {
+            assert!(started.elapsed() < Duration::from_millis(500));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let ranges: Vec<Range<isize>> = (0..10).map(|i| i * 10_000..i * 10_000 + 100).collect();
+        uring.get_ranges(&filename, ranges.clone(), (0..10).collect())?;
+        for _ in 0..ranges.len() {
+            match uring.completion().recv_timeout(Duration::from_millis(500)) {
+                Ok(Ok(Output::Chunk(c))) => {
+                    let range = &ranges[c.user_data as usize];
+                    assert_eq!(
+                        c.buffer.as_slice(),
+                        &file_contents[range.start as usize..range.end as usize],
+                        "{wait_strategy:?}"
+                    );
+                }
+                output => panic!("Unexpected output {output:?} with {wait_strategy:?}"),
+            }
+        }
+
+        drop(write_fd);
+        assert_eq!(recv_stream(&uring, 100), Vec::<u8>::new());
+    }
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_async_reader() -> anyhow::Result<()> {
+    let contents: Vec<u8> = (0..200).collect();
+    let filename = create_temp_file("async_reader", &contents)?;
+    let missing = filename.with_extension("missing");
+    let reader = AsyncReader::new(IoUring::new(2));
+
+    // Poll two requests concurrently, without an async runtime.
+    let (results, missing_results) = futures::executor::block_on(futures::future::join(
+        reader.get_ranges(&filename, vec![0..10, 100..150, -50..-1]),
+        reader.get_ranges(&missing, vec![0..1, 1..2]),
+    ));
+
+    let expected_ranges = [0..10, 100..150, 150..200];
+    assert_eq!(results.len(), expected_ranges.len());
+    for (index, (result, expected_range)) in results.into_iter().zip(expected_ranges).enumerate() {
+        let chunk = result?;
+        assert_eq!(chunk.user_data, index as u64);
+        assert_eq!(chunk.buffer.as_slice(), &contents[expected_range]);
+    }
+
+    assert_eq!(missing_results.len(), 2);
+    for result in missing_results {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(IoError::NotFound { .. })),
+            "{err:?}"
+        );
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_trickle_submissions_have_low_latency() -> anyhow::Result<()> {
+    const N_SUBMISSIONS: u64 = 5;
+    const CHUNK_SIZE: usize = KIBIBYTE * 4;
+    const MAX_LATENCY: Duration = Duration::from_millis(100);
+
+    let filename = create_temp_file("trickle", &[42; CHUNK_SIZE])?;
+    let mut uring = IoUring::new(1);
+
+    for user_data in 0..N_SUBMISSIONS {
+        // Give the worker thread time to go idle.
+        std::thread::sleep(Duration::from_millis(50));
+
+        let submitted = Instant::now();
+        uring.get_ranges(&filename, vec![0..CHUNK_SIZE as isize], vec![user_data])?;
+        match uring.completion().recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(Output::Chunk(c))) => {
+                assert_eq!(c.user_data, user_data);
+                assert_eq!(c.buffer.as_slice(), [42; CHUNK_SIZE]);
+            }
+            output => panic!("Unexpected output {output:?}"),
+        }
+        let latency = submitted.elapsed();
+        assert!(
+            latency < MAX_LATENCY,
+            "Submission {user_data} took {latency:?}, which is longer than {MAX_LATENCY:?}"
+        );
+    }
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "object_store")]
+
+use std::path::PathBuf;
+
+use futures::executor::block_on;
+use lsio_uring::{IoUring, ObjectStoreAdapter};
+use object_store::{path::Path, GetOptions, GetRange, ObjectStore};
+
+/// Write `contents` to a new file in the temporary directory, and return the filename.
+fn create_temp_file(prefix: &str, contents: &[u8]) -> std::io::Result<PathBuf> {
+    let filename =
+        std::env::temp_dir().join(format!("lsio_uring_{prefix}_{}", rand::random::<u32>()));
+    std::fs::write(&filename, contents)?;
+    Ok(filename)
+}
+
+fn to_object_store_path(filename: &std::path::Path) -> Path {
+    Path::from(filename.to_str().unwrap())
+}
+
+#[test]
+fn test_get_and_get_ranges() -> anyhow::Result<()> {
+    let contents: Vec<u8> = (0..=255).collect();
+    let filename = create_temp_file("object_store_adapter", &contents)?;
+    let location = to_object_store_path(&filename);
+    let store = ObjectStoreAdapter::new(IoUring::new(2));
+
+    block_on(async {
+        let result = store.get(&location).await?;
+        assert_eq!(result.meta.size, contents.len());
+        assert_eq!(result.range, 0..contents.len());
+        assert_eq!(result.bytes().await?, &contents[..]);
+
+        let ranges = [0..10, 10..10, 100..164, 250..256];
+        let bytes = store.get_ranges(&location, &ranges).await?;
+        assert_eq!(bytes.len(), ranges.len());
+        for (bytes, range) in bytes.iter().zip(ranges) {
+            assert_eq!(bytes, &contents[range]);
+        }
+
+        assert_eq!(store.get_range(&location, 5..7).await?, &contents[5..7]);
+
+        let options = GetOptions {
+            range: Some(GetRange::Suffix(6)),
+            ..Default::default()
+        };
+        let result = store.get_opts(&location, options).await?;
+        assert_eq!(result.range, 250..256);
+        assert_eq!(result.bytes().await?, &contents[250..]);
+
+        assert_eq!(store.head(&location).await?.size, contents.len());
+        anyhow::Ok(())
+    })?;
+
+    std::fs::remove_file(&filename)?;
+    Ok(())
+}
+
+#[test]
+fn test_missing_file_is_not_found() {
+    let filename = std::env::temp_dir().join(format!(
+        "lsio_uring_object_store_adapter_missing_{}",
+        rand::random::<u32>()
+    ));
+    let location = to_object_store_path(&filename);
+    let store = ObjectStoreAdapter::new(IoUring::new(1));
+
+    let err = block_on(store.get_range(&location, 0..5)).unwrap_err();
+    assert!(
+        matches!(err, object_store::Error::NotFound { .. }),
+        "{err:?}"
+    );
+    let err = block_on(store.head(&location)).unwrap_err();
+    assert!(
+        matches!(err, object_store::Error::NotFound { .. }),
+        "{err:?}"
+    );
+}